@@ -0,0 +1,128 @@
+// Local push service stub for web push tests on the local replica.
+// Acts as both the push service and the browser: it creates the subscription keys, checks the VAPID
+// Authorization header and decrypts the aes128gcm payload. Every replica node sends the request, each
+// push is logged once.
+//
+//   VAPID_PUBLIC_KEY=<key from setup_web_push> SUBSCRIPTION_FILE=/tmp/sub node scripts/mock-push-service.js
+//
+// The subscription (endpoint, p256dh and auth, base64url) is written to SUBSCRIPTION_FILE on start.
+// GONE=1 answers 410 to every push, like a push service for an expired subscription.
+
+const http = require("http");
+const crypto = require("crypto");
+const fs = require("fs");
+
+const port = Number(process.env.PORT || 8788);
+const vapidPublicKey = process.env.VAPID_PUBLIC_KEY || "";
+const gone = process.env.GONE === "1";
+const seen = new Map(); // push body to the status answered, so every node gets the same response
+
+const browser = crypto.createECDH("prime256v1");
+browser.generateKeys();
+const auth = crypto.randomBytes(16);
+const endpoint = `http://localhost:${port}/push/device-1`;
+
+function verifyVapid(header, audience) {
+  const match = /^vapid t=([^,]+), k=(.+)$/.exec(header || "");
+  if (!match || match[2] !== vapidPublicKey) {
+    return "bad key";
+  }
+  const [head, claims, signature] = match[1].split(".");
+  const key = Buffer.from(vapidPublicKey, "base64url");
+  const publicKey = crypto.createPublicKey({
+    key: {
+      kty: "EC",
+      crv: "P-256",
+      x: key.subarray(1, 33).toString("base64url"),
+      y: key.subarray(33, 65).toString("base64url"),
+    },
+    format: "jwk",
+  });
+  const valid = crypto.verify(
+    "sha256",
+    Buffer.from(`${head}.${claims}`),
+    { key: publicKey, dsaEncoding: "ieee-p1363" },
+    Buffer.from(signature, "base64url")
+  );
+  if (!valid) {
+    return "bad signature";
+  }
+  const { aud, exp } = JSON.parse(Buffer.from(claims, "base64url"));
+  if (aud !== audience || exp * 1000 < Date.now()) {
+    return "bad claims";
+  }
+  return null;
+}
+
+// RFC 8291, one aes128gcm record
+function decrypt(body) {
+  const salt = body.subarray(0, 16);
+  const idLen = body[20];
+  const serverPublic = body.subarray(21, 21 + idLen);
+  const ciphertext = body.subarray(21 + idLen);
+
+  const shared = browser.computeSecret(serverPublic);
+  const keyInfo = Buffer.concat([
+    Buffer.from("WebPush: info\0"),
+    browser.getPublicKey(),
+    serverPublic,
+  ]);
+  const ikm = Buffer.from(crypto.hkdfSync("sha256", shared, auth, keyInfo, 32));
+  const cek = Buffer.from(
+    crypto.hkdfSync("sha256", ikm, salt, Buffer.from("Content-Encoding: aes128gcm\0"), 16)
+  );
+  const nonce = Buffer.from(
+    crypto.hkdfSync("sha256", ikm, salt, Buffer.from("Content-Encoding: nonce\0"), 12)
+  );
+
+  const decipher = crypto.createDecipheriv("aes-128-gcm", cek, nonce);
+  decipher.setAuthTag(ciphertext.subarray(ciphertext.length - 16));
+  const record = Buffer.concat([
+    decipher.update(ciphertext.subarray(0, ciphertext.length - 16)),
+    decipher.final(),
+  ]);
+  return record.subarray(0, record.lastIndexOf(2)).toString();
+}
+
+if (process.env.SUBSCRIPTION_FILE) {
+  fs.writeFileSync(
+    process.env.SUBSCRIPTION_FILE,
+    `${endpoint} ${browser.getPublicKey().toString("base64url")} ${auth.toString("base64url")}\n`
+  );
+}
+
+http
+  .createServer((req, res) => {
+    const chunks = [];
+    req.on("data", (chunk) => chunks.push(chunk));
+    req.on("end", () => {
+      const body = Buffer.concat(chunks);
+      const key = body.toString("base64");
+
+      if (!seen.has(key)) {
+        let status = gone ? 410 : 201;
+        let payload = "";
+        const error = verifyVapid(req.headers["authorization"], `http://localhost:${port}`);
+        if (error) {
+          status = 401;
+          payload = error;
+        } else if (req.headers["content-encoding"] !== "aes128gcm") {
+          status = 400;
+          payload = "bad encoding";
+        } else {
+          try {
+            payload = decrypt(body);
+          } catch (err) {
+            status = 400;
+            payload = `decryption failed ${err.message}`;
+          }
+        }
+        seen.set(key, status);
+        console.log(`${status} ${req.headers["topic"]} ${payload}`);
+      }
+
+      res.writeHead(seen.get(key));
+      res.end();
+    });
+  })
+  .listen(port, () => console.log(`Mock push service on http://localhost:${port}`));
@@ -0,0 +1,55 @@
+// Mock merchant endpoint for webhook tests on the local replica.
+// Verifies the X-Rapido-Signature header and logs every delivery once, each replica node sends the request.
+//
+//   WEBHOOK_SECRET=<secret from add_webhook> FAIL_FIRST=2 node scripts/mock-webhook-server.js
+//
+// FAIL_FIRST answers 500 to the first N deliveries to exercise the retries.
+
+const http = require("http");
+const crypto = require("crypto");
+
+const port = Number(process.env.PORT || 8787);
+const secret = process.env.WEBHOOK_SECRET || "";
+let failFirst = Number(process.env.FAIL_FIRST || 0);
+const seen = new Map(); // delivery id to the status answered, so every node gets the same response
+
+function verify(header, body) {
+  const parts = Object.fromEntries(
+    (header || "").split(",").map((part) => part.split("=", 2))
+  );
+  const expected = crypto
+    .createHmac("sha256", secret)
+    .update(`${parts.t}.${body}`)
+    .digest("hex");
+  return (
+    parts.v1 !== undefined &&
+    parts.v1.length === expected.length &&
+    crypto.timingSafeEqual(Buffer.from(parts.v1), Buffer.from(expected))
+  );
+}
+
+http
+  .createServer((req, res) => {
+    let body = "";
+    req.on("data", (chunk) => (body += chunk));
+    req.on("end", () => {
+      const delivery = req.headers["x-rapido-delivery"];
+      const key = `${delivery}/${req.headers["x-rapido-signature"]}`;
+
+      if (!seen.has(key)) {
+        let status = 200;
+        if (secret && !verify(req.headers["x-rapido-signature"], body)) {
+          status = 401;
+        } else if (failFirst > 0) {
+          failFirst -= 1;
+          status = 500;
+        }
+        seen.set(key, status);
+        console.log(`${status} ${req.headers["x-rapido-event"]} ${delivery} ${body}`);
+      }
+
+      res.writeHead(seen.get(key));
+      res.end();
+    });
+  })
+  .listen(port, () => console.log(`Mock webhook server on http://localhost:${port}`));
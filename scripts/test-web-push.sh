@@ -0,0 +1,46 @@
+#!/bin/bash
+
+# Web push deliveries against scripts/mock-push-service.js, needs a local replica with backend deployed
+# (scripts/deploy-local.sh) and node.
+
+PORT=8788
+MOCK_LOG=$(mktemp)
+SUBSCRIPTION_FILE=$(mktemp)
+
+echo "Creating the VAPID key..."
+SETUP=$(dfx canister call backend setup_web_push '("mailto:dev@localhost")')
+echo "$SETUP"
+VAPID_PUBLIC_KEY=$(echo "$SETUP" | grep -o 'Ok = "[A-Za-z0-9_-]*"' | cut -d '"' -f 2)
+if [ -z "$VAPID_PUBLIC_KEY" ]; then
+    echo "setup_web_push failed"
+    exit 1
+fi
+
+echo "Starting the mock push service..."
+VAPID_PUBLIC_KEY=$VAPID_PUBLIC_KEY SUBSCRIPTION_FILE=$SUBSCRIPTION_FILE PORT=$PORT \
+    node scripts/mock-push-service.js > "$MOCK_LOG" 2>&1 &
+MOCK_PID=$!
+trap 'kill $MOCK_PID' EXIT
+sleep 1
+read -r ENDPOINT P256DH AUTH < "$SUBSCRIPTION_FILE"
+
+echo "Signing up a user..."
+dfx canister call backend sign_up '(variant {User = record {name="Push User"; pay_id="push_user"; profile_pic=""}})'
+
+echo "Subscribing the mock device..."
+dfx canister call backend add_push_subscription "(record {endpoint=\"$ENDPOINT\"; p256dh=\"$P256DH\"; auth=\"$AUTH\"})"
+
+echo "Sending a test push..."
+dfx canister call backend send_test_push '()'
+
+echo "Waiting for the delivery..."
+sleep 15
+
+cat "$MOCK_LOG"
+
+if grep -q '^201 test {"title":"Rapido"' "$MOCK_LOG"; then
+    echo "Push delivered, VAPID signature checked and payload decrypted"
+else
+    echo "Push not delivered"
+    exit 1
+fi
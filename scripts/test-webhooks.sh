@@ -0,0 +1,42 @@
+#!/bin/bash
+
+# Webhook deliveries against scripts/mock-webhook-server.js, needs a local replica with backend deployed
+# (scripts/deploy-local.sh) and node.
+
+PORT=8787
+MOCK_LOG=$(mktemp)
+
+echo "Signing up a business..."
+dfx canister call backend sign_up '(variant {Business = record {logo="logo"; name="Webhook Shop"; pay_id="webhook_shop"; category=variant {Food}}})'
+
+echo "Adding a webhook to the mock server..."
+ADDED=$(dfx canister call backend add_webhook "(\"http://localhost:$PORT/webhook\", vec {variant {PaymentRecorded}; variant {CheckoutPaid}; variant {Refund}}, null)")
+echo "$ADDED"
+SECRET=$(echo "$ADDED" | grep -o 'secret = "[0-9a-f]*"' | cut -d '"' -f 2)
+if [ -z "$SECRET" ]; then
+    echo "add_webhook failed"
+    exit 1
+fi
+
+echo "Starting the mock server, failing the first delivery..."
+WEBHOOK_SECRET=$SECRET FAIL_FIRST=1 PORT=$PORT node scripts/mock-webhook-server.js > "$MOCK_LOG" 2>&1 &
+MOCK_PID=$!
+trap 'kill $MOCK_PID' EXIT
+sleep 1
+
+echo "Sending a test webhook..."
+dfx canister call backend send_test_webhook '(0, null)'
+
+# First attempt answers 500, the retry comes 30 seconds later
+echo "Waiting for the delivery and its retry..."
+sleep 45
+
+dfx canister call backend get_webhook_deliveries '(0, null)'
+cat "$MOCK_LOG"
+
+if grep -q "^200 ping" "$MOCK_LOG" && grep -q "^500 ping" "$MOCK_LOG"; then
+    echo "Webhook delivered after a retry"
+else
+    echo "Webhook not delivered"
+    exit 1
+fi
@@ -0,0 +1,269 @@
+use crate::business;
+use crate::error::AnalyticsError;
+use crate::staff::{self, AuditAction, Permission};
+use crate::{TransactionEntry, ANALYTICS_MAP};
+use candid::{Decode, Encode, Nat, Principal};
+use ic_stable_structures::storable::{Bound, Storable};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+const DAY: u64 = 86_400_000_000_000; // 1 day in nanoseconds
+const MAX_RANGE_DAYS: u64 = 731; // 2 years of daily buckets
+const TOP_CUSTOMERS: usize = 5;
+
+// Key is "{principal}/{day since epoch}", days are zero padded so they sort in order
+fn day_key(principal: &Principal, day: u64) -> String {
+    format!("{}/{:08}", principal.to_text(), day)
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize, Default)]
+pub struct CustomerStats {
+    name: String,
+    paid: Nat,
+    tx_count: u64,
+}
+
+// Totals of one business for one UTC day, updated every time a transaction is recorded
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize, Default)]
+pub struct DayStats {
+    revenue_in: Nat,
+    revenue_out: Nat,
+    tx_count_in: u64,
+    tx_count_out: u64,
+    customers: BTreeMap<String, CustomerStats>, // pay_id of whoever paid the business
+}
+
+impl Storable for DayStats {
+    const BOUND: Bound = Bound::Unbounded;
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl DayStats {
+    fn get(key: &String) -> Option<DayStats> {
+        ANALYTICS_MAP.with(|p| p.borrow().get(key))
+    }
+
+    fn insert(key: String, value: DayStats) -> Option<DayStats> {
+        ANALYTICS_MAP.with(|p| p.borrow_mut().insert(key, value))
+    }
+
+    fn remove(key: &String) -> Option<DayStats> {
+        ANALYTICS_MAP.with(|p| p.borrow_mut().remove(key))
+    }
+
+    // (day, stats) of every day of principal in [from_day, to_day]
+    fn in_range(principal: &Principal, from_day: u64, to_day: u64) -> Vec<(u64, DayStats)> {
+        let start = day_key(principal, from_day);
+        let end = day_key(principal, to_day);
+        let day_start = start.len() - 8;
+
+        ANALYTICS_MAP.with(|p| {
+            p.borrow()
+                .range(start..=end)
+                .filter_map(|(key, stats)| Some((key[day_start..].parse().ok()?, stats)))
+                .collect()
+        })
+    }
+
+    fn add(&mut self, entry: &TransactionEntry) {
+        if !entry.kind.is_transfer() {
+            return;
+        }
+        if entry.kind.is_outgoing() {
+            self.revenue_out += entry.amount.clone();
+            self.tx_count_out += 1;
+        } else {
+            self.revenue_in += entry.amount.clone();
+            self.tx_count_in += 1;
+
+            let customer = self.customers.entry(entry.pay_id.clone()).or_default();
+            customer.name = entry.name.clone();
+            customer.paid += entry.amount.clone();
+            customer.tx_count += 1;
+        }
+    }
+
+    fn merge(&mut self, other: DayStats) {
+        self.revenue_in += other.revenue_in;
+        self.revenue_out += other.revenue_out;
+        self.tx_count_in += other.tx_count_in;
+        self.tx_count_out += other.tx_count_out;
+
+        for (pay_id, stats) in other.customers {
+            let customer = self.customers.entry(pay_id).or_default();
+            customer.name = stats.name;
+            customer.paid += stats.paid;
+            customer.tx_count += stats.tx_count;
+        }
+    }
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub enum AnalyticsBucket {
+    Day,
+    Week,  // weeks start on monday
+    Month, // calendar months in UTC
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct AnalyticsRange {
+    from: u64, // timestamps in nanoseconds, both inclusive
+    to: u64,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct TopCustomer {
+    pay_id: String,
+    name: String,
+    paid: Nat,
+    tx_count: u64,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct AnalyticsPeriod {
+    start: u64, // timestamp of the first day of the bucket
+    revenue_in: Nat,
+    revenue_out: Nat,
+    tx_count_in: u64,
+    tx_count_out: u64,
+    unique_customers: u64,
+    average_ticket: Nat, // revenue_in / tx_count_in
+    top_customers: Vec<TopCustomer>,
+}
+
+impl AnalyticsPeriod {
+    fn new(start_day: u64, stats: DayStats) -> Self {
+        let average_ticket = if stats.tx_count_in == 0 {
+            Nat::from(0u64)
+        } else {
+            stats.revenue_in.clone() / stats.tx_count_in
+        };
+
+        let mut top_customers = stats
+            .customers
+            .into_iter()
+            .map(|(pay_id, customer)| TopCustomer {
+                pay_id,
+                name: customer.name,
+                paid: customer.paid,
+                tx_count: customer.tx_count,
+            })
+            .collect::<Vec<_>>();
+        let unique_customers = top_customers.len() as u64;
+        top_customers.sort_by(|a, b| b.paid.cmp(&a.paid));
+        top_customers.truncate(TOP_CUSTOMERS);
+
+        AnalyticsPeriod {
+            start: start_day * DAY,
+            revenue_in: stats.revenue_in,
+            revenue_out: stats.revenue_out,
+            tx_count_in: stats.tx_count_in,
+            tx_count_out: stats.tx_count_out,
+            unique_customers,
+            average_ticket,
+            top_customers,
+        }
+    }
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct BusinessAnalytics {
+    total: AnalyticsPeriod, // the whole range
+    buckets: Vec<AnalyticsPeriod>,
+}
+
+// Called for every entry pushed to Business.transactions
+pub fn record_transaction(principal: &Principal, entry: &TransactionEntry) {
+    let key = day_key(principal, entry.timestamp / DAY);
+    let mut stats = DayStats::get(&key).unwrap_or_default();
+    stats.add(entry);
+    DayStats::insert(key, stats);
+}
+
+// Day since epoch of the 1st of the month, the day of month part of Howard Hinnant's civil_from_days
+pub fn month_start(day: u64) -> u64 {
+    let doe = (day + 719_468) % 146_097; // day of the 400 year era, starting on 1 March
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day_of_month = doy - (153 * mp + 2) / 5; // 0 based
+    day - day_of_month
+}
+
+fn bucket_start(day: u64, bucket: &AnalyticsBucket) -> u64 {
+    match bucket {
+        AnalyticsBucket::Day => day,
+        AnalyticsBucket::Week => day.saturating_sub((day + 3) % 7), // 1 January 1970 was a thursday
+        AnalyticsBucket::Month => month_start(day),
+    }
+}
+
+pub fn get_business_analytics(
+    range: AnalyticsRange,
+    bucket: AnalyticsBucket,
+    profile: Option<Principal>,
+) -> Result<BusinessAnalytics, AnalyticsError> {
+    let business = staff::actor(profile, Permission::ViewAnalytics)?.business;
+
+    if range.from > range.to {
+        return Err(AnalyticsError::InvalidRange);
+    }
+
+    let from_day = range.from / DAY;
+    let to_day = range.to / DAY;
+    if to_day - from_day >= MAX_RANGE_DAYS {
+        return Err(AnalyticsError::RangeTooLong {
+            max_days: MAX_RANGE_DAYS,
+        });
+    }
+
+    let mut total = DayStats::default();
+    let mut buckets: BTreeMap<u64, DayStats> = BTreeMap::new();
+
+    for (day, stats) in DayStats::in_range(&business, from_day, to_day) {
+        total.merge(stats.clone());
+        buckets
+            .entry(bucket_start(day, &bucket))
+            .or_default()
+            .merge(stats);
+    }
+
+    Ok(BusinessAnalytics {
+        total: AnalyticsPeriod::new(from_day, total),
+        buckets: buckets
+            .into_iter()
+            .map(|(start_day, stats)| AnalyticsPeriod::new(start_day, stats))
+            .collect(),
+    })
+}
+
+// Recomputes every day of the business from Business.transactions, for transactions recorded before analytics existed
+pub fn rebuild_business_analytics(profile: Option<Principal>) -> Result<(), AnalyticsError> {
+    let actor = staff::actor(profile, Permission::ManageSettings)?;
+
+    let start = format!("{}/", actor.business.to_text());
+    let keys = ANALYTICS_MAP.with(|p| {
+        p.borrow()
+            .range(start.clone()..)
+            .take_while(|(key, _)| key.starts_with(&start))
+            .map(|(key, _)| key)
+            .collect::<Vec<_>>()
+    });
+    for key in keys {
+        DayStats::remove(&key);
+    }
+
+    for entry in business::get_transactions(&actor.business).iter() {
+        record_transaction(&actor.business, entry);
+    }
+
+    actor.audit(AuditAction::AnalyticsRebuilt);
+    Ok(())
+}
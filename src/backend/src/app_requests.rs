@@ -0,0 +1,435 @@
+use crate::ck_btc_ledger::Account;
+use crate::error::AppRequestError;
+use crate::notifications;
+use crate::safety;
+use crate::sync::{self, Change};
+use crate::user::{is_user, user_caller};
+use crate::{checkout, PayIds, ALLOWED_APPS_MAP, APP_REQUESTS_MAP, USER_APP_REQUESTS_MAP};
+use candid::{Decode, Encode, Nat, Principal};
+use ic_cdk::{
+    api::{is_controller, time},
+    caller,
+};
+use ic_stable_structures::storable::{Bound, Storable};
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+use std::borrow::Cow;
+
+const REQUEST_TTL: u64 = 86_400_000_000_000; // 24 hours, like chat payment requests
+const MAX_MEMO_LEN: usize = 256;
+const MAX_CALLBACK_LEN: usize = 64;
+const MAX_APP_NAME_LEN: usize = 64;
+const MAX_PENDING_PER_USER: usize = 5; // pending requests of one app to one user
+const MEMO_PREFIX: &[u8] = b"APP"; // memo is the prefix followed by the request id in big endian
+const REQUESTS_PAGE_SIZE: usize = 20;
+
+// Canister allowed to call request_payment_from, set by the controllers
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct AllowedApp {
+    canister: Principal,
+    name: String, // shown to users instead of the canister id
+    added_at: u64,
+}
+
+impl Storable for AllowedApp {
+    const BOUND: Bound = Bound::Unbounded;
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl AllowedApp {
+    fn get(key: &Principal) -> Option<AllowedApp> {
+        ALLOWED_APPS_MAP.with(|p| p.borrow().get(key))
+    }
+
+    fn insert(key: Principal, value: AllowedApp) -> Option<AllowedApp> {
+        ALLOWED_APPS_MAP.with(|p| p.borrow_mut().insert(key, value))
+    }
+
+    fn remove(key: &Principal) -> Option<AllowedApp> {
+        ALLOWED_APPS_MAP.with(|p| p.borrow_mut().remove(key))
+    }
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize, PartialEq)]
+pub enum AppRequestStatus {
+    Pending,
+    Paid { tx_id: Nat, paid_at: u64 },
+    Declined { declined_at: u64 },
+    Expired, // only returned by the queries, never stored
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct AppPaymentRequest {
+    id: u64,
+    app: Principal,
+    app_name: String,
+    user: Principal,
+    pay_id: String,
+    amount: Nat,
+    memo: Option<String>, // shown to the user
+    callback: String,     // method of app notified when the request is paid or declined
+    payment_memo: ByteBuf,
+    payment_uri: String, // ICRC-22, pays app directly
+    requested_at: u64,
+    expires_at: u64,
+    status: AppRequestStatus,
+    callback_error: Option<String>, // the notification couldn't be sent
+}
+
+impl Storable for AppPaymentRequest {
+    const BOUND: Bound = Bound::Unbounded;
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl AppPaymentRequest {
+    fn get(key: u64) -> Option<AppPaymentRequest> {
+        APP_REQUESTS_MAP.with(|p| p.borrow().get(&key))
+    }
+
+    fn insert(key: u64, value: AppPaymentRequest) -> Option<AppPaymentRequest> {
+        APP_REQUESTS_MAP.with(|p| p.borrow_mut().insert(key, value))
+    }
+
+    fn next_id() -> u64 {
+        APP_REQUESTS_MAP.with(|p| p.borrow().last_key_value().map_or(0, |(id, _)| id + 1))
+    }
+
+    fn is_pending(&self, now: u64) -> bool {
+        self.status == AppRequestStatus::Pending && now <= self.expires_at
+    }
+
+    // Status as shown to the user and the app
+    fn with_status(mut self, now: u64) -> Self {
+        if self.status == AppRequestStatus::Pending && now > self.expires_at {
+            self.status = AppRequestStatus::Expired;
+        }
+        self
+    }
+
+    // Syncs the user's devices and notifies the user once the request is paid, declined or expired
+    fn closed(&self) {
+        sync::record_change(self.user, Change::AppRequest(self.id));
+        notifications::app_request_closed(
+            self.user,
+            self.id,
+            &self.app_name,
+            &self.amount,
+            self.status.clone(),
+        );
+    }
+}
+
+/*
+ * Argument of the callback, apps implement the method passed to request_payment_from as
+ * `(AppPaymentNotification) -> ()`. It's sent as a one way call once, apps can poll
+ * get_app_payment_request to catch up on missed notifications.
+ */
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct AppPaymentNotification {
+    request_id: u64,
+    pay_id: String,
+    amount: Nat,
+    memo: Option<String>,
+    status: AppRequestStatus,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct AppRequestPage {
+    requests: Vec<AppPaymentRequest>, // latest first
+    next_page: Option<u64>,
+}
+
+// Key is "{user principal}/{id}", id zero padded so requests of a user are in order
+fn user_key(user: &Principal, id: u64) -> String {
+    format!("{}/{:020}", user.to_text(), id)
+}
+
+fn request_memo(id: u64) -> Vec<u8> {
+    [MEMO_PREFIX, &id.to_be_bytes()].concat()
+}
+
+fn request_id(memo: &[u8]) -> Option<u64> {
+    let id = memo.strip_prefix(MEMO_PREFIX)?;
+    Some(u64::from_be_bytes(id.try_into().ok()?))
+}
+
+// Ids of the requests to user, oldest first
+fn user_request_ids(user: &Principal) -> Vec<u64> {
+    let prefix = format!("{}/", user.to_text());
+    USER_APP_REQUESTS_MAP.with(|p| {
+        p.borrow()
+            .range(prefix.clone()..)
+            .take_while(|(key, _)| key.starts_with(&prefix))
+            .map(|(_, id)| id)
+            .collect()
+    })
+}
+
+pub fn allow_payment_requester(canister: Principal, name: String) -> Result<(), AppRequestError> {
+    if !is_controller(&caller()) {
+        return Err(AppRequestError::NotAController);
+    }
+    if name.trim().is_empty() || name.chars().count() > MAX_APP_NAME_LEN {
+        return Err(AppRequestError::InvalidName {
+            max: MAX_APP_NAME_LEN,
+        });
+    }
+
+    AllowedApp::insert(
+        canister,
+        AllowedApp {
+            canister,
+            name,
+            added_at: time(),
+        },
+    );
+    Ok(())
+}
+
+// Pending requests of the canister can still be paid, it just can't post new ones
+pub fn remove_payment_requester(canister: Principal) -> Result<(), AppRequestError> {
+    if !is_controller(&caller()) {
+        return Err(AppRequestError::NotAController);
+    }
+    match AllowedApp::remove(&canister) {
+        Some(_) => Ok(()),
+        None => Err(AppRequestError::NotAllowed),
+    }
+}
+
+pub fn list_payment_requesters() -> Vec<AllowedApp> {
+    ALLOWED_APPS_MAP.with(|p| p.borrow().iter().map(|(_, app)| app).collect())
+}
+
+/*
+ * Posts a request to pay amount to the calling canister to the user of pay_id. The user pays the
+ * payment_uri from the wallet, or declines it, and callback is called on the app either way.
+ */
+pub fn request_payment_from(
+    pay_id: String,
+    amount: Nat,
+    memo: Option<String>,
+    callback: String,
+) -> Result<AppPaymentRequest, AppRequestError> {
+    let app = caller();
+    let allowed = AllowedApp::get(&app).ok_or(AppRequestError::NotAllowed)?;
+
+    if amount == 0u64 {
+        return Err(AppRequestError::InvalidAmount);
+    }
+    if memo
+        .as_ref()
+        .is_some_and(|memo| memo.chars().count() > MAX_MEMO_LEN)
+    {
+        return Err(AppRequestError::MemoTooLong { max: MAX_MEMO_LEN });
+    }
+    if callback.is_empty()
+        || callback.len() > MAX_CALLBACK_LEN
+        || !callback
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_')
+    {
+        return Err(AppRequestError::InvalidCallback);
+    }
+
+    let user = match PayIds::get(&pay_id) {
+        Some(user) if is_user(&user) => user,
+        _ => return Err(AppRequestError::AccountNotFound),
+    };
+    if safety::is_blocked(&app, &user) {
+        return Err(AppRequestError::Blocked);
+    }
+
+    let now = time();
+    let pending = user_request_ids(&user)
+        .into_iter()
+        .filter_map(AppPaymentRequest::get)
+        .filter(|request| request.app == app && request.is_pending(now))
+        .count();
+    if pending >= MAX_PENDING_PER_USER {
+        return Err(AppRequestError::TooManyPending {
+            max: MAX_PENDING_PER_USER,
+        });
+    }
+
+    let id = AppPaymentRequest::next_id();
+    let payment_memo = request_memo(id);
+    let request = AppPaymentRequest {
+        id,
+        app,
+        app_name: allowed.name,
+        user,
+        pay_id,
+        payment_uri: checkout::payment_uri(
+            &Account {
+                owner: app,
+                subaccount: None,
+            },
+            Some(&amount),
+            &payment_memo,
+        ),
+        amount,
+        memo,
+        callback,
+        payment_memo: ByteBuf::from(payment_memo),
+        requested_at: now,
+        expires_at: now + REQUEST_TTL,
+        status: AppRequestStatus::Pending,
+        callback_error: None,
+    };
+    AppPaymentRequest::insert(id, request.clone());
+    USER_APP_REQUESTS_MAP.with(|p| p.borrow_mut().insert(user_key(&user, id), id));
+    sync::record_change(user, Change::AppRequest(id));
+    notifications::app_request_received(
+        user,
+        id,
+        &request.app_name,
+        &request.amount,
+        request.memo.clone(),
+        request.expires_at,
+    );
+
+    Ok(request)
+}
+
+// Request as seen by the app that posted it or the user it was posted to
+pub fn get_app_payment_request(id: u64) -> Result<AppPaymentRequest, AppRequestError> {
+    let caller = caller();
+    let user = user_caller();
+    match AppPaymentRequest::get(id) {
+        Some(request) if request.app == caller || request.user == user => {
+            Ok(request.with_status(time()))
+        }
+        _ => Err(AppRequestError::RequestNotFound),
+    }
+}
+
+// Requests posted to the caller, latest first
+pub fn get_app_payment_requests(page: u64) -> Result<AppRequestPage, AppRequestError> {
+    let caller = user_caller();
+    if !is_user(&caller) {
+        return Err(AppRequestError::AccountNotFound);
+    }
+
+    let ids = user_request_ids(&caller);
+    let now = time();
+    let end = ids.len().saturating_sub(page as usize * REQUESTS_PAGE_SIZE);
+    let start = end.saturating_sub(REQUESTS_PAGE_SIZE);
+    let requests = ids[start..end]
+        .iter()
+        .rev()
+        .filter_map(|id| AppPaymentRequest::get(*id))
+        .map(|request| request.with_status(now))
+        .collect();
+
+    Ok(AppRequestPage {
+        requests,
+        next_page: if start > 0 { Some(page + 1) } else { None },
+    })
+}
+
+pub fn decline_app_payment_request(id: u64) -> Result<AppPaymentRequest, AppRequestError> {
+    let mut request = match AppPaymentRequest::get(id) {
+        Some(request) if request.user == user_caller() => request,
+        _ => return Err(AppRequestError::RequestNotFound),
+    };
+    if !request.is_pending(time()) {
+        return Err(AppRequestError::NotPending);
+    }
+
+    request.status = AppRequestStatus::Declined {
+        declined_at: time(),
+    };
+    notify_app(&mut request);
+    AppPaymentRequest::insert(id, request.clone());
+    request.closed();
+    Ok(request)
+}
+
+/*
+ * Marks the request in memo as paid if the transfer is from its user to its app and covers its
+ * amount. Called by record_xfer_transaction, returns true if a request was paid.
+ */
+pub fn record_payment(
+    tx_id: &Nat,
+    from: Principal,
+    to: Principal,
+    amount: &Nat,
+    memo: Option<&ByteBuf>,
+    timestamp: u64,
+) -> bool {
+    let id = match memo.and_then(|memo| request_id(memo)) {
+        Some(id) => id,
+        None => return false,
+    };
+    let mut request = match AppPaymentRequest::get(id) {
+        Some(request) => request,
+        None => return false,
+    };
+
+    // A late record still counts if the transfer was made before the request expired
+    if request.status != AppRequestStatus::Pending
+        || request.user != from
+        || request.app != to
+        || amount < &request.amount
+        || timestamp > request.expires_at
+    {
+        return false;
+    }
+
+    request.status = AppRequestStatus::Paid {
+        tx_id: tx_id.clone(),
+        paid_at: timestamp,
+    };
+    notify_app(&mut request);
+    AppPaymentRequest::insert(id, request.clone());
+    request.closed();
+    true
+}
+
+// Tells the user a request expired unpaid, called by the timer after it expires
+pub fn expired(id: u64) {
+    if let Some(request) = AppPaymentRequest::get(id)
+        .map(|request| request.with_status(time()))
+        .filter(|request| request.status == AppRequestStatus::Expired)
+    {
+        request.closed();
+    }
+}
+
+// Requests posted to principal, for sync
+pub fn get_app_requests_by_id(principal: &Principal, ids: &[u64]) -> Vec<AppPaymentRequest> {
+    let now = time();
+    ids.iter()
+        .filter_map(|id| AppPaymentRequest::get(*id))
+        .filter(|request| &request.user == principal)
+        .map(|request| request.with_status(now))
+        .collect()
+}
+
+// One way call, the app can't hold up or fail the user's update
+fn notify_app(request: &mut AppPaymentRequest) {
+    let notification = AppPaymentNotification {
+        request_id: request.id,
+        pay_id: request.pay_id.clone(),
+        amount: request.amount.clone(),
+        memo: request.memo.clone(),
+        status: request.status.clone(),
+    };
+
+    request.callback_error = ic_cdk::notify(request.app, &request.callback, (notification,))
+        .err()
+        .map(|code| format!("{:?}", code));
+}
@@ -1,5 +1,12 @@
+use crate::analytics;
+use crate::customers;
 use crate::error::{GetBusinessError, SignUpError};
+use crate::profiles;
+use crate::search;
+use crate::staff::{self, Permission};
+use crate::sync::{self, Change};
 use crate::user::{self, is_user};
+use crate::webhooks;
 use crate::{is_payid_exist, PayIds, TransactionEntry, TxHistory, TxKind, BUSINESS_MAP};
 use candid::{Decode, Encode, Nat, Principal};
 use ic_cdk::{api::time, caller};
@@ -8,7 +15,7 @@ use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
 use std::vec::Vec;
 
-#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize, PartialEq)]
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize, PartialEq, Default)]
 pub enum BusinessCategory {
     Agriculture,    // Farming, forestry, fishing
     Manufacturing,  // Production of goods
@@ -25,13 +32,8 @@ pub enum BusinessCategory {
     Professional,   // Consulting, legal, accounting
     Hospitality,    // Hotels, tourism
     Energy,         // Oil, gas, renewable energy
-    Other,          // Miscellaneous
-}
-
-impl Default for BusinessCategory {
-    fn default() -> Self {
-        BusinessCategory::Other
-    }
+    #[default]
+    Other, // Miscellaneous
 }
 
 #[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize, Default)]
@@ -50,7 +52,7 @@ pub struct Business {
 
 impl Storable for Business {
     const BOUND: Bound = Bound::Unbounded;
-    fn to_bytes(&self) -> Cow<[u8]> {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
         Cow::Owned(Encode!(self).unwrap())
     }
 
@@ -63,26 +65,30 @@ struct BusinessMap;
 
 impl BusinessMap {
     fn get(key: &Principal) -> Option<Business> {
-        BUSINESS_MAP.with(|p| p.borrow().get(&key))
-    }
-
-    fn get_mut(key: &Principal) -> Option<Business> {
-        BUSINESS_MAP.with(|p| p.borrow_mut().get(&key))
+        BUSINESS_MAP.with(|p| p.borrow().get(key))
     }
 
     fn insert(key: Principal, value: Business) -> Option<Business> {
         BUSINESS_MAP.with(|p| p.borrow_mut().insert(key, value))
     }
 
-    fn remove(key: Principal) -> Option<Business> {
-        BUSINESS_MAP.with(|p| p.borrow_mut().remove(&key))
-    }
-
     fn contains_key(key: &Principal) -> bool {
         BUSINESS_MAP.with(|p| p.borrow().contains_key(key))
     }
 }
 
+impl Business {
+    // Appends a transaction and records it for search, sync and analytics, the caller still has to insert the business
+    fn push_transaction(&mut self, principal: Principal, entry: TransactionEntry) {
+        self.transactions.push(entry);
+        let index = self.transactions.len() - 1;
+        search::index_history(&principal, index, &self.transactions[index]);
+        analytics::record_transaction(&principal, &self.transactions[index]);
+        sync::record_change(principal, Change::History(index));
+        webhooks::record_entry(&principal, &self.transactions[index]);
+    }
+}
+
 #[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
 pub struct BusinessSignUpArgs {
     name: String,
@@ -96,7 +102,6 @@ pub struct BusinessMetadata {
     pub pay_id: String,
     pub logo: String,
     pub category: BusinessCategory,
-    pub created_at: u64,
 }
 pub struct BusinessTxArg {
     pub b_principal: Principal,
@@ -105,6 +110,7 @@ pub struct BusinessTxArg {
     pub amount: Nat,
     pub timestamp: u64,
     pub tx_id: Nat,
+    pub fee: Option<Nat>,
 }
 
 pub struct BtoBTxArg {
@@ -114,6 +120,8 @@ pub struct BtoBTxArg {
     pub note: Option<String>,
     pub amount: Nat,
     pub tx_id: Nat,
+    pub tx_kind: TxKind, // Based on from
+    pub fee: Option<Nat>,
 }
 
 pub struct BusinessUnknownTxArg {
@@ -124,6 +132,7 @@ pub struct BusinessUnknownTxArg {
     pub timestamp: u64,
     pub note: Option<String>,
     pub tx_kind: TxKind, // Based on User (from or to)
+    pub fee: Option<Nat>,
 }
 
 // pub fn get_business(princpal: &Principal) -> Option<Business> {
@@ -137,7 +146,6 @@ pub fn get_metadata(princpal: &Principal) -> Option<BusinessMetadata> {
             pay_id: b.pay_id,
             logo: b.logo,
             category: b.category,
-            created_at: b.created_at,
         }),
         None => None,
     }
@@ -147,19 +155,36 @@ pub fn is_business(princpal: &Principal) -> bool {
     BusinessMap::contains_key(princpal)
 }
 
+/*
+ * The first account of an identity is on its own principal. An identity that already has a user or
+ * business account gets another business profile on a subaccount, see profiles.rs.
+ */
 pub fn sign_up_business(arg: BusinessSignUpArgs) -> Result<(), SignUpError> {
     let caller: Principal = caller();
 
     if caller == Principal::anonymous() {
         return Err(SignUpError::AnonymousCaller);
     }
-    if is_business(&caller) || is_user(&caller) {
+    if staff::member_of(&caller).is_some() {
         return Err(SignUpError::AccountExist);
     }
     if is_payid_exist(&arg.pay_id) {
         return Err(SignUpError::PayIdExist);
     }
 
+    let principal = if is_business(&caller) || is_user(&caller) {
+        match profiles::new_business_profile(&caller) {
+            Some(profile) => profile,
+            None => {
+                return Err(SignUpError::TooManyProfiles {
+                    max: profiles::MAX_BUSINESS_PROFILES,
+                })
+            }
+        }
+    } else {
+        caller
+    };
+
     let pay_id = arg.pay_id.to_string();
     let business = Business {
         name: arg.name,
@@ -169,13 +194,16 @@ pub fn sign_up_business(arg: BusinessSignUpArgs) -> Result<(), SignUpError> {
         transactions: Vec::new(),
         created_at: time(),
     };
-    BusinessMap::insert(caller, business);
-    PayIds::insert(pay_id, caller);
+    BusinessMap::insert(principal, business);
+    PayIds::insert(pay_id, principal);
     Ok(())
 }
 
-pub fn ic_get_business() -> Result<Business, GetBusinessError> {
-    match BusinessMap::get(&caller()) {
+// Business of the caller, the one it works for if the caller is a member, or the given profile
+pub fn ic_get_business(profile: Option<Principal>) -> Result<Business, GetBusinessError> {
+    let business = staff::actor(profile, Permission::ViewBusiness)?.business;
+
+    match BusinessMap::get(&business) {
         Some(business) => Ok(business),
         None => Err(GetBusinessError::AccountNotFound),
     }
@@ -189,12 +217,21 @@ pub fn add_business_transaction(
         amount,
         timestamp,
         tx_id,
+        fee,
     }: BusinessTxArg,
 ) {
     // No need for second checking of Accounts, already checked in record_xfer_transaction function
     let mut business = BusinessMap::get(&b_principal).unwrap();
     let user = user::get_metadata(&u_principal).unwrap();
 
+    customers::record_transaction(
+        &b_principal,
+        &u_principal,
+        &tx_kind,
+        amount.clone(),
+        timestamp,
+    );
+
     let tx_entry = TransactionEntry {
         kind: tx_kind,
         name: user.name,
@@ -203,9 +240,12 @@ pub fn add_business_transaction(
         amount,
         tx_id,
         note: None,
+        fee,
+        refund: None,
+        fiat: None,
     };
 
-    business.transactions.push(tx_entry);
+    business.push_transaction(b_principal, tx_entry);
     BusinessMap::insert(b_principal, business);
 }
 
@@ -217,6 +257,8 @@ pub fn add_business_to_business_transaction(
         note,
         amount,
         tx_id,
+        tx_kind,
+        fee,
     }: BtoBTxArg,
 ) {
     // No need for second checking of Accounts, already checked in record_xfer_transaction function
@@ -224,26 +266,32 @@ pub fn add_business_to_business_transaction(
     let mut to_business = BusinessMap::get(&to).unwrap();
 
     let tx_entry_from_business = TransactionEntry {
-        kind: TxKind::Sends,                // from sends it
+        kind: tx_kind.clone(),              // from sends it
         name: to_business.name.clone(),     // to who
         pay_id: to_business.pay_id.clone(), // to who
         tx_id: tx_id.clone(),
         timestamp,
         amount: amount.clone(),
         note: note.clone(),
+        fee: fee.clone(),
+        refund: None,
+        fiat: None,
     };
-    from_business.transactions.push(tx_entry_from_business);
+    from_business.push_transaction(from, tx_entry_from_business);
 
     let tx_entry_to_business = TransactionEntry {
-        kind: TxKind::Receive,                // to receive it
+        kind: tx_kind.counterpart(),          // to receive it
         name: from_business.name.clone(),     // from who
         pay_id: from_business.pay_id.clone(), // from who
         tx_id,
         timestamp,
         amount,
         note,
+        fee,
+        refund: None,
+        fiat: None,
     };
-    to_business.transactions.push(tx_entry_to_business);
+    to_business.push_transaction(to, tx_entry_to_business);
 
     BusinessMap::insert(from, from_business);
     BusinessMap::insert(to, to_business);
@@ -258,6 +306,7 @@ pub fn add_unknown_transaction(
         timestamp,
         note,
         tx_kind,
+        fee,
     }: BusinessUnknownTxArg,
 ) {
     // No need for second checking of Accounts, already checked in record_xfer_transaction function
@@ -271,14 +320,17 @@ pub fn add_unknown_transaction(
         timestamp,
         amount,
         note,
+        fee,
+        refund: None,
+        fiat: None,
     };
 
-    business.transactions.push(tx_entry);
+    business.push_transaction(b_principal, tx_entry);
     BusinessMap::insert(b_principal, business);
 }
 
-pub fn fetch_init_business_data() -> Business {
-    let mut business = BusinessMap::get(&caller()).unwrap();
+pub fn fetch_init_business_data(principal: &Principal) -> Business {
+    let mut business = BusinessMap::get(principal).unwrap();
 
     let length = business.transactions.len();
 
@@ -286,17 +338,15 @@ pub fn fetch_init_business_data() -> Business {
     business.transactions = business
         .transactions
         .into_iter()
-        .skip(if length > 50 { length - 50 } else { 0 })
+        .skip(length.saturating_sub(50))
         .rev()
         .collect::<Vec<_>>();
 
     business
 }
 
-pub fn fetch_business_data() -> Business {
-    let mut business = BusinessMap::get(&caller()).unwrap();
-
-    let length = business.transactions.len();
+pub fn fetch_business_data(principal: &Principal) -> Business {
+    let mut business = BusinessMap::get(principal).unwrap();
 
     // latest first order
     business.transactions = business.transactions.into_iter().rev().collect::<Vec<_>>();
@@ -305,16 +355,74 @@ pub fn fetch_business_data() -> Business {
 }
 
 // Here length argument is the lenghth of transaction history array fetch already in frontend
-pub fn get_new_business_transactions(length: usize) -> Vec<TransactionEntry> {
-    let business = match BusinessMap::get(&caller()) {
+pub fn get_new_business_transactions(
+    length: usize,
+    profile: Option<Principal>,
+) -> Vec<TransactionEntry> {
+    let business = match staff::actor(profile, Permission::ViewBusiness)
+        .ok()
+        .and_then(|actor| BusinessMap::get(&actor.business))
+    {
         Some(b) => b,
         None => return Vec::new(),
     };
 
     // latest first order
     business.transactions[length..]
-        .to_vec()
-        .into_iter()
+        .iter()
         .rev()
+        .cloned()
         .collect::<Vec<_>>()
 }
+
+// Appends an entry that isn't tied to a user or business counterpart, like mints and burns
+pub fn push_entry(principal: Principal, entry: TransactionEntry) {
+    if let Some(mut business) = BusinessMap::get(&principal) {
+        business.push_transaction(principal, entry);
+        BusinessMap::insert(principal, business);
+    }
+}
+
+// Updates the entry of tx_id in the transactions of principal and records the change for sync
+pub fn update_entry(
+    principal: &Principal,
+    tx_id: &Nat,
+    update: impl FnOnce(&mut TransactionEntry),
+) {
+    let mut business = match BusinessMap::get(principal) {
+        Some(b) => b,
+        None => return,
+    };
+
+    let index = match business
+        .transactions
+        .iter()
+        .rposition(|entry| &entry.tx_id == tx_id)
+    {
+        Some(index) => index,
+        None => return,
+    };
+
+    update(&mut business.transactions[index]);
+    sync::record_change(*principal, Change::History(index));
+    BusinessMap::insert(*principal, business);
+}
+
+pub fn get_transactions(principal: &Principal) -> TxHistory {
+    match BusinessMap::get(principal) {
+        Some(b) => b.transactions,
+        None => Vec::new(),
+    }
+}
+
+pub fn get_transactions_at(principal: &Principal, indexes: &[usize]) -> Vec<TransactionEntry> {
+    let business = match BusinessMap::get(principal) {
+        Some(b) => b,
+        None => return Vec::new(),
+    };
+
+    indexes
+        .iter()
+        .filter_map(|index| business.transactions.get(*index).cloned())
+        .collect()
+}
@@ -0,0 +1,113 @@
+use crate::error::ChatEncryptionError;
+use crate::user::{self, ChatId};
+use crate::vetkd::{self, VetKdDeriveKeyArgs, VetKdPublicKeyArgs};
+use candid::Principal;
+use ic_cdk::{api::time, caller};
+use serde_bytes::ByteBuf;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+const CHAT_KEY_CONTEXT: &[u8] = b"rapido_chat_key";
+const TRANSPORT_KEY_LEN: usize = 48; // compressed BLS12-381 G1 point
+const KEY_WINDOW: u64 = 3_600_000_000_000; // 1 hour in nanoseconds
+const MAX_KEYS_PER_CHAT: usize = 10; // chat keys one caller can derive for a chat per window
+const MAX_KEYS_PER_CALLER: usize = 50; // across all chats
+
+thread_local! {
+    // Not kept over upgrades, the public key is fetched again and the limits start over
+    static PUBLIC_KEY: RefCell<Option<ByteBuf>> = const { RefCell::new(None) };
+    static RECENT_KEYS: RefCell<BTreeMap<Principal, Vec<(u64, ChatId)>>> = const { RefCell::new(BTreeMap::new()) };
+}
+
+/*
+ * Chat keys are vetKeys derived for the chat id, so every chat has its own key and the canister never
+ * sees it: the key is returned encrypted under a transport key the client generated for the request.
+ * Clients decrypt it, verify it against the public key below and the chat id, and use it to encrypt and
+ * decrypt the messages. Payment requests and transfers in the chat stay in the clear for settlement.
+ */
+
+// Same for every chat, the chat id is the derivation input. Fetched once, it never changes
+pub async fn get_chat_key_public_key() -> Result<ByteBuf, ChatEncryptionError> {
+    if let Some(public_key) = PUBLIC_KEY.with(|p| p.borrow().clone()) {
+        return Ok(public_key);
+    }
+
+    let args = VetKdPublicKeyArgs {
+        canister_id: None,
+        context: ByteBuf::from(CHAT_KEY_CONTEXT),
+        key_id: vetkd::key_id(),
+    };
+    match vetkd::vetkd_public_key(args).await {
+        Ok((result,)) => {
+            PUBLIC_KEY.with(|p| *p.borrow_mut() = Some(result.public_key.clone()));
+            Ok(result.public_key)
+        }
+        Err(err) => Err(ChatEncryptionError::InterCanisterCall(format!(
+            "vetkd_public_key failed {:?}",
+            err
+        ))),
+    }
+}
+
+/*
+ * Every derivation costs the canister cycles, so a caller gets MAX_KEYS_PER_CHAT keys of a chat and
+ * MAX_KEYS_PER_CALLER keys overall per KEY_WINDOW. Counted before the call, so concurrent calls can't
+ * go past the limits.
+ */
+fn record_key_request(principal: Principal, chat_id: &ChatId) -> Result<(), ChatEncryptionError> {
+    let now = time();
+    RECENT_KEYS.with(|p| {
+        let mut recent_keys = p.borrow_mut();
+        // oldest first, as requests are appended
+        let sent = recent_keys.entry(principal).or_default();
+        sent.retain(|(requested_at, _)| requested_at + KEY_WINDOW > now);
+
+        if sent.len() >= MAX_KEYS_PER_CALLER {
+            return Err(ChatEncryptionError::RateLimited {
+                retry_at: sent[sent.len() - MAX_KEYS_PER_CALLER].0 + KEY_WINDOW,
+            });
+        }
+        let sent_for_chat = sent
+            .iter()
+            .filter(|(_, id)| id == chat_id)
+            .map(|(requested_at, _)| *requested_at)
+            .collect::<Vec<_>>();
+        if sent_for_chat.len() >= MAX_KEYS_PER_CHAT {
+            return Err(ChatEncryptionError::RateLimited {
+                retry_at: sent_for_chat[sent_for_chat.len() - MAX_KEYS_PER_CHAT] + KEY_WINDOW,
+            });
+        }
+
+        sent.push((now, chat_id.clone()));
+        Ok(())
+    })
+}
+
+// Only participants of a chat with encryption turned on get its key
+pub async fn get_encrypted_chat_key(
+    chat_id: ChatId,
+    transport_public_key: ByteBuf,
+) -> Result<ByteBuf, ChatEncryptionError> {
+    let chat = user::get_chat_for_encryption(&chat_id)?;
+    if !chat.is_encrypted() {
+        return Err(ChatEncryptionError::ChatNotEncrypted);
+    }
+    if transport_public_key.len() != TRANSPORT_KEY_LEN {
+        return Err(ChatEncryptionError::InvalidTransportKey);
+    }
+    record_key_request(caller(), &chat_id)?;
+
+    let args = VetKdDeriveKeyArgs {
+        input: ByteBuf::from(chat_id.into_bytes()),
+        context: ByteBuf::from(CHAT_KEY_CONTEXT),
+        transport_public_key,
+        key_id: vetkd::key_id(),
+    };
+    match vetkd::vetkd_derive_key(args).await {
+        Ok((result,)) => Ok(result.encrypted_key),
+        Err(err) => Err(ChatEncryptionError::InterCanisterCall(format!(
+            "vetkd_derive_key failed {:?}",
+            err
+        ))),
+    }
+}
@@ -0,0 +1,363 @@
+use crate::ck_btc_ledger::{self, Account, CK_BTC_LEDGER};
+use crate::error::CheckoutError;
+use crate::export::{hex, json_string, token_units};
+use crate::profiles;
+use crate::staff::{self, AuditAction, Permission};
+use crate::webhooks;
+use crate::{timer, CHECKOUTS_MAP, OPEN_CHECKOUTS_MAP};
+use candid::{Decode, Encode, Nat, Principal};
+use ic_cdk::api::time;
+use ic_stable_structures::storable::{Bound, Storable};
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+use std::borrow::Cow;
+use std::cell::Cell;
+
+const DEFAULT_TTL: u64 = 900; // seconds
+const MAX_TTL: u64 = 86_400;
+const MAX_REFERENCE_LEN: usize = 64;
+const MEMO_PREFIX: &[u8] = b"POS"; // memo is the prefix followed by the session id in big endian
+const SCAN_BATCH: u64 = 100; // ledger blocks read per timer tick
+
+thread_local! {
+    // Next ledger block to scan, not kept over upgrades, scanning restarts from the oldest open session
+    static SCAN_CURSOR: Cell<Option<u64>> = const { Cell::new(None) };
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize, PartialEq)]
+pub enum CheckoutStatus {
+    Open,
+    Paid {
+        tx_id: Nat,
+        from: Principal,
+        paid_at: u64,
+    },
+    Expired,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct CheckoutSession {
+    id: u64,
+    business: Principal,
+    amount: Nat,
+    reference: String,
+    memo: ByteBuf,
+    payment_uri: String, // ICRC-22, shown as QR code by the POS
+    created_by: Principal,
+    created_at: u64,
+    expires_at: u64,
+    start_block: u64, // ledger length when the session was created
+    status: CheckoutStatus,
+}
+
+impl Storable for CheckoutSession {
+    const BOUND: Bound = Bound::Unbounded;
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl CheckoutSession {
+    fn get(key: u64) -> Option<CheckoutSession> {
+        CHECKOUTS_MAP.with(|p| p.borrow().get(&key))
+    }
+
+    fn insert(key: u64, value: CheckoutSession) -> Option<CheckoutSession> {
+        CHECKOUTS_MAP.with(|p| p.borrow_mut().insert(key, value))
+    }
+
+    fn next_id() -> u64 {
+        CHECKOUTS_MAP.with(|p| p.borrow().last_key_value().map_or(0, |(id, _)| id + 1))
+    }
+
+    // Still payable, a paid session isn't open even before expires_at
+    fn is_open(&self, now: u64) -> bool {
+        self.status == CheckoutStatus::Open && now <= self.expires_at
+    }
+}
+
+fn session_memo(id: u64) -> Vec<u8> {
+    [MEMO_PREFIX, &id.to_be_bytes()].concat()
+}
+
+fn session_id(memo: &[u8]) -> Option<u64> {
+    let id = memo.strip_prefix(MEMO_PREFIX)?;
+    Some(u64::from_be_bytes(id.try_into().ok()?))
+}
+
+// CRC-32 (IEEE) used by the ICRC-1 account checksum
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+// RFC 4648 base32, lowercase without padding
+fn base32(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"abcdefghijklmnopqrstuvwxyz234567";
+    let mut text = String::new();
+    let (mut buffer, mut bits) = (0u32, 0);
+    for byte in bytes {
+        buffer = (buffer << 8) | *byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            text.push(ALPHABET[((buffer >> bits) & 31) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        text.push(ALPHABET[((buffer << (5 - bits)) & 31) as usize] as char);
+    }
+    text
+}
+
+// ICRC-1 textual encoding, "{owner}-{checksum}.{subaccount hex without leading zeros}"
+fn account_text(account: &Account) -> String {
+    let subaccount = match &account.subaccount {
+        Some(subaccount) if subaccount.iter().any(|byte| *byte != 0) => subaccount,
+        _ => return account.owner.to_text(),
+    };
+
+    let checksum = crc32(&[account.owner.as_slice(), subaccount.as_slice()].concat());
+    format!(
+        "{}-{}.{}",
+        account.owner.to_text(),
+        base32(&checksum.to_be_bytes()),
+        hex(subaccount).trim_start_matches('0')
+    )
+}
+
+// Amount is left out for open amounts, the wallet asks the payer for it
+pub fn payment_uri(account: &Account, amount: Option<&Nat>, memo: &[u8]) -> String {
+    let amount = amount
+        .map(|amount| format!("&amount={}", token_units(amount)))
+        .unwrap_or_default();
+    format!(
+        "icp:{}/transfer?to={}{}&memo={}",
+        CK_BTC_LEDGER,
+        account_text(account),
+        amount,
+        hex(memo)
+    )
+}
+
+/*
+ * Opens a session for a walk-in customer to pay amount, ttl in seconds. The customer wallet pays the
+ * payment_uri and the session turns Paid once a transfer with its memo is seen on the ledger or recorded.
+ */
+pub async fn create_checkout_session(
+    amount: Nat,
+    reference: String,
+    ttl: Option<u64>,
+    profile: Option<Principal>,
+) -> Result<CheckoutSession, CheckoutError> {
+    let actor = staff::actor(profile, Permission::CreateInvoice)?;
+
+    if amount == 0u64 {
+        return Err(CheckoutError::InvalidAmount);
+    }
+    if reference.chars().count() > MAX_REFERENCE_LEN {
+        return Err(CheckoutError::ReferenceTooLong {
+            max: MAX_REFERENCE_LEN,
+        });
+    }
+    let ttl = ttl.unwrap_or(DEFAULT_TTL);
+    if ttl == 0 || ttl > MAX_TTL {
+        return Err(CheckoutError::InvalidTtl { max: MAX_TTL });
+    }
+
+    let (response,) = ck_btc_ledger::get_transaction_range(Nat::from(0u64), Nat::from(0u64))
+        .await
+        .map_err(|err| {
+            CheckoutError::InterCanisterCall(format!("get_transactions failed {:?}", err))
+        })?;
+    let start_block = u64::try_from(response.log_length.0).unwrap_or(u64::MAX);
+
+    // Taken after the call so sessions created meanwhile don't get the same id
+    let id = CheckoutSession::next_id();
+    let memo = session_memo(id);
+    let now = time();
+    let session = CheckoutSession {
+        id,
+        business: actor.business,
+        payment_uri: payment_uri(
+            &profiles::ledger_account(&actor.business),
+            Some(&amount),
+            &memo,
+        ),
+        amount: amount.clone(),
+        reference,
+        memo: ByteBuf::from(memo),
+        created_by: actor.member,
+        created_at: now,
+        expires_at: now + ttl * 1_000_000_000,
+        start_block,
+        status: CheckoutStatus::Open,
+    };
+    CheckoutSession::insert(id, session.clone());
+    OPEN_CHECKOUTS_MAP.with(|p| p.borrow_mut().insert(id, session.expires_at));
+    actor.audit(AuditAction::CheckoutSessionCreated { id, amount });
+
+    timer::schedule();
+    Ok(session)
+}
+
+// Polled by the POS screen, open sessions past expires_at are shown as Expired
+pub fn get_checkout_status(
+    id: u64,
+    profile: Option<Principal>,
+) -> Result<CheckoutSession, CheckoutError> {
+    let actor = staff::actor(profile, Permission::ViewBusiness)?;
+
+    let mut session = match CheckoutSession::get(id) {
+        Some(session) if session.business == actor.business => session,
+        _ => return Err(CheckoutError::SessionNotFound),
+    };
+
+    if session.status == CheckoutStatus::Open && time() > session.expires_at {
+        session.status = CheckoutStatus::Expired;
+    }
+    Ok(session)
+}
+
+/*
+ * Marks the session in memo as paid if the transfer is to its business and covers its amount.
+ * Called by record_xfer_transaction and the ledger scan, returns true if a session was paid.
+ */
+pub fn record_payment(
+    tx_id: &Nat,
+    from: Principal,
+    to: Principal,
+    amount: &Nat,
+    memo: Option<&ByteBuf>,
+    timestamp: u64,
+) -> bool {
+    let id = match memo.and_then(|memo| session_id(memo)) {
+        Some(id) => id,
+        None => return false,
+    };
+    let mut session = match CheckoutSession::get(id) {
+        Some(session) => session,
+        None => return false,
+    };
+
+    // A late payment still counts if it was made before the session expired
+    if session.status != CheckoutStatus::Open
+        || session.business != to
+        || amount < &session.amount
+        || timestamp > session.expires_at
+    {
+        return false;
+    }
+
+    session.status = CheckoutStatus::Paid {
+        tx_id: tx_id.clone(),
+        from,
+        paid_at: timestamp,
+    };
+    webhooks::record_checkout_paid(
+        &session.business,
+        &format!(
+            "{{\"session_id\":{},\"reference\":{},\"amount\":{},\"tx_id\":{},\"from\":{},\"paid_at\":{}}}",
+            id,
+            json_string(&session.reference),
+            json_string(&token_units(&session.amount)),
+            json_string(&tx_id.0.to_string()),
+            json_string(&from.to_text()),
+            timestamp
+        ),
+    );
+    CheckoutSession::insert(id, session);
+    OPEN_CHECKOUTS_MAP.with(|p| p.borrow_mut().remove(&id));
+    true
+}
+
+// Open sessions, the expired ones are dropped from OPEN_CHECKOUTS_MAP
+fn open_sessions(now: u64) -> Vec<CheckoutSession> {
+    let ids = OPEN_CHECKOUTS_MAP.with(|p| p.borrow().iter().collect::<Vec<_>>());
+
+    let mut open = Vec::new();
+    for (id, expires_at) in ids {
+        match CheckoutSession::get(id) {
+            Some(session) if session.is_open(now) => open.push(session),
+            _ if now > expires_at => {
+                OPEN_CHECKOUTS_MAP.with(|p| p.borrow_mut().remove(&id));
+            }
+            _ => {}
+        }
+    }
+    open
+}
+
+pub fn has_open_sessions() -> bool {
+    !open_sessions(time()).is_empty()
+}
+
+/*
+ * Reads the next ledger blocks and pays the sessions they match, then records the transfers in the
+ * histories. Called by the timer while sessions are open, returns true if it should run again.
+ */
+pub async fn scan_ledger() -> bool {
+    let now = time();
+    let open = open_sessions(now);
+    if open.is_empty() {
+        SCAN_CURSOR.set(None);
+        return false;
+    }
+
+    let start = SCAN_CURSOR
+        .get()
+        .unwrap_or_else(|| open.iter().map(|s| s.start_block).min().unwrap_or(0));
+
+    // Archived blocks are read from their archive, the cursor can be behind the ledger's own blocks
+    let blocks = match ck_btc_ledger::get_blocks(start, SCAN_BATCH).await {
+        Ok((blocks, _)) => blocks,
+        // Same range again on the next tick
+        Err(_) => return true,
+    };
+
+    let next = blocks
+        .last()
+        .map_or(start, |(index, _)| index + 1)
+        .max(start);
+    let mut paid = Vec::new();
+    for (index, transaction) in blocks {
+        let transfer = match transaction.transfer {
+            Some(transfer) => transfer,
+            None => continue,
+        };
+        let tx_id = Nat::from(index);
+        if record_payment(
+            &tx_id,
+            profiles::account_principal(&transfer.from),
+            profiles::account_principal(&transfer.to),
+            &transfer.amount,
+            transfer.memo.as_ref(),
+            transaction.timestamp,
+        ) {
+            paid.push(tx_id);
+        }
+    }
+    SCAN_CURSOR.set(Some(next));
+
+    // The sessions are paid either way, a transfer that fails to record here can still be recorded
+    // with record_xfer_transaction like any other
+    for tx_id in paid {
+        let _ = crate::record_xfer_transaction(tx_id, None).await;
+    }
+
+    has_open_sessions()
+}
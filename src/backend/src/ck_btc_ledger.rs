@@ -1,7 +1,8 @@
-use candid::{self, CandidType, Decode, Deserialize, Encode, Principal};
+use candid::{self, CandidType, Deserialize, Principal};
 use ic_cdk::api::call::CallResult as Result;
+use serde::Serialize;
 
-#[derive(CandidType, Deserialize, Debug, Clone)]
+#[derive(CandidType, Deserialize, Serialize, Debug, Clone)]
 pub struct Account {
     pub owner: Principal,
     pub subaccount: Option<serde_bytes::ByteBuf>,
@@ -86,15 +87,177 @@ pub struct GetTransactionsResponse {
     pub archived_transactions: Vec<ArchivedRange1>,
 }
 
-pub async fn get_transactions(
-    tx_id: candid::Nat,
+pub const CK_BTC_LEDGER: &str = "mxzaz-hqaaa-aaaar-qaada-cai";
+
+pub async fn get_transactions(tx_id: candid::Nat) -> Result<(GetTransactionsResponse,)> {
+    get_transaction_range(tx_id, candid::Nat::from(1u8)).await
+}
+
+// Transactions from start, archived ones are only returned as archived_transactions ranges
+pub async fn get_transaction_range(
+    start: candid::Nat,
+    length: candid::Nat,
 ) -> Result<(GetTransactionsResponse,)> {
-    let args = GetBlocksRequest {
-        start: tx_id,
-        length: candid::Nat::from(1u8),
-    };
- 
-    let ck_btc_ledger = Principal::from_text("mxzaz-hqaaa-aaaar-qaada-cai").unwrap();
+    let args = GetBlocksRequest { start, length };
+
+    let ck_btc_ledger = Principal::from_text(CK_BTC_LEDGER).unwrap();
 
     ic_cdk::call(ck_btc_ledger, "get_transactions", (args,)).await
 }
+
+/*
+ * Transactions from start with their block index, the archived ones read from their archive canister.
+ * Fewer than length at the end of the log, the log length comes with them.
+ */
+pub async fn get_blocks(
+    start: u64,
+    length: u64,
+) -> std::result::Result<(Vec<(u64, Transaction)>, u64), String> {
+    let (response,) = get_transaction_range(candid::Nat::from(start), candid::Nat::from(length))
+        .await
+        .map_err(|err| format!("get_transactions failed {:?}", err))?;
+    let index = |nat: &candid::Nat| u64::try_from(nat.0.clone()).unwrap_or(u64::MAX);
+
+    let mut blocks = Vec::new();
+    for range in response.archived_transactions {
+        let first = index(&range.start);
+        let args = GetBlocksRequest {
+            start: range.start,
+            length: range.length,
+        };
+        let (archived,): (TransactionRange,) = ic_cdk::call(
+            range.callback.0.principal,
+            &range.callback.0.method,
+            (args,),
+        )
+        .await
+        .map_err(|err| format!("archived get_transactions failed {:?}", err))?;
+        blocks.extend((first..).zip(archived.transactions));
+    }
+    blocks.extend((index(&response.first_index)..).zip(response.transactions));
+    blocks.sort_by_key(|(index, _)| *index);
+
+    Ok((blocks, index(&response.log_length)))
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct AllowanceArgs {
+    pub account: Account,
+    pub spender: Account,
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct Allowance {
+    pub allowance: candid::Nat,
+    pub expires_at: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct TransferFromArgs {
+    pub spender_subaccount: Option<serde_bytes::ByteBuf>,
+    pub from: Account,
+    pub to: Account,
+    pub amount: candid::Nat,
+    pub fee: Option<candid::Nat>,
+    pub memo: Option<serde_bytes::ByteBuf>,
+    pub created_at_time: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub enum TransferFromError {
+    BadFee {
+        expected_fee: candid::Nat,
+    },
+    BadBurn {
+        min_burn_amount: candid::Nat,
+    },
+    InsufficientFunds {
+        balance: candid::Nat,
+    },
+    InsufficientAllowance {
+        allowance: candid::Nat,
+    },
+    TooOld,
+    CreatedInFuture {
+        ledger_time: u64,
+    },
+    Duplicate {
+        duplicate_of: candid::Nat,
+    },
+    TemporarilyUnavailable,
+    GenericError {
+        error_code: candid::Nat,
+        message: String,
+    },
+}
+
+pub async fn allowance(args: AllowanceArgs) -> Result<(Allowance,)> {
+    let ck_btc_ledger = Principal::from_text(CK_BTC_LEDGER).unwrap();
+
+    ic_cdk::call(ck_btc_ledger, "icrc2_allowance", (args,)).await
+}
+
+// Block index of the transfer, the canister is the spender
+pub async fn transfer_from(
+    args: TransferFromArgs,
+) -> Result<(std::result::Result<candid::Nat, TransferFromError>,)> {
+    let ck_btc_ledger = Principal::from_text(CK_BTC_LEDGER).unwrap();
+
+    ic_cdk::call(ck_btc_ledger, "icrc2_transfer_from", (args,)).await
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct TransferArg {
+    pub from_subaccount: Option<serde_bytes::ByteBuf>,
+    pub to: Account,
+    pub amount: candid::Nat,
+    pub fee: Option<candid::Nat>,
+    pub memo: Option<serde_bytes::ByteBuf>,
+    pub created_at_time: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub enum TransferError {
+    BadFee {
+        expected_fee: candid::Nat,
+    },
+    BadBurn {
+        min_burn_amount: candid::Nat,
+    },
+    InsufficientFunds {
+        balance: candid::Nat,
+    },
+    TooOld,
+    CreatedInFuture {
+        ledger_time: u64,
+    },
+    Duplicate {
+        duplicate_of: candid::Nat,
+    },
+    TemporarilyUnavailable,
+    GenericError {
+        error_code: candid::Nat,
+        message: String,
+    },
+}
+
+pub async fn balance_of(account: Account) -> Result<(candid::Nat,)> {
+    let ck_btc_ledger = Principal::from_text(CK_BTC_LEDGER).unwrap();
+
+    ic_cdk::call(ck_btc_ledger, "icrc1_balance_of", (account,)).await
+}
+
+pub async fn fee() -> Result<(candid::Nat,)> {
+    let ck_btc_ledger = Principal::from_text(CK_BTC_LEDGER).unwrap();
+
+    ic_cdk::call(ck_btc_ledger, "icrc1_fee", ()).await
+}
+
+// Transfer from a subaccount of the canister, returns the block index
+pub async fn transfer(
+    args: TransferArg,
+) -> Result<(std::result::Result<candid::Nat, TransferError>,)> {
+    let ck_btc_ledger = Principal::from_text(CK_BTC_LEDGER).unwrap();
+
+    ic_cdk::call(ck_btc_ledger, "icrc1_transfer", (args,)).await
+}
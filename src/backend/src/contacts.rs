@@ -0,0 +1,325 @@
+use crate::business::{self, is_business};
+use crate::error::ContactError;
+use crate::search::{self, SearchDoc};
+use crate::staff::{self, Permission};
+use crate::user::{self, is_user, PayIdOrPrincipal};
+use crate::{PayIds, CONTACTS_MAP};
+use candid::{Decode, Encode, Principal};
+use ic_cdk::api::time;
+use ic_stable_structures::storable::{Bound, Storable};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+const MAX_NICKNAME_LEN: usize = 64;
+const MAX_NOTE_LEN: usize = 500;
+const DEFAULT_IMPORT_LIMIT: usize = 50;
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct Contact {
+    principal: Principal,
+    nickname: Option<String>,
+    note: Option<String>,
+    favorite: bool,
+    added_at: u64,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize, Default)]
+pub struct ContactBook {
+    contacts: BTreeMap<Principal, Contact>,
+}
+
+impl Storable for ContactBook {
+    const BOUND: Bound = Bound::Unbounded;
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl ContactBook {
+    fn get(key: &Principal) -> Option<ContactBook> {
+        CONTACTS_MAP.with(|p| p.borrow().get(key))
+    }
+
+    fn insert(key: Principal, value: ContactBook) -> Option<ContactBook> {
+        CONTACTS_MAP.with(|p| p.borrow_mut().insert(key, value))
+    }
+}
+
+// Contact with the current profile of the account, pay_id is None for principals outside Rapido
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct ContactView {
+    contact: Contact,
+    pay_id: Option<String>,
+    name: String,
+}
+
+impl Contact {
+    fn view(&self) -> ContactView {
+        let (pay_id, name) = match user::get_metadata(&self.principal) {
+            Some(user) => (Some(user.pay_id), user.name),
+            None => match business::get_metadata(&self.principal) {
+                Some(business) => (Some(business.pay_id), business.name),
+                None => (None, "unknown".to_string()),
+            },
+        };
+
+        ContactView {
+            contact: self.clone(),
+            pay_id,
+            name,
+        }
+    }
+
+    fn search_text(&self) -> String {
+        let view = self.view();
+        format!(
+            "{} {} {} {}",
+            view.name,
+            view.pay_id.unwrap_or_else(|| self.principal.to_text()),
+            self.nickname.clone().unwrap_or_default(),
+            self.note.clone().unwrap_or_default()
+        )
+    }
+}
+
+impl ContactView {
+    pub fn added_at(&self) -> u64 {
+        self.contact.added_at
+    }
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct UpdateContactArg {
+    contact: PayIdOrPrincipal,
+    nickname: Option<String>,
+    note: Option<String>,
+    favorite: bool,
+}
+
+fn check_account(caller: &Principal) -> Result<(), ContactError> {
+    if is_user(caller) || is_business(caller) {
+        Ok(())
+    } else {
+        Err(ContactError::AccountNotFound)
+    }
+}
+
+// Pay ids must exist, raw principals can be any account including ones outside Rapido
+fn resolve(caller: &Principal, arg: PayIdOrPrincipal) -> Result<Principal, ContactError> {
+    let principal = match arg {
+        PayIdOrPrincipal::PayId(pay_id) => match PayIds::get(&pay_id) {
+            Some(principal) => principal,
+            None => return Err(ContactError::PayIdNotFound),
+        },
+        PayIdOrPrincipal::Principal(principal) => principal,
+    };
+
+    if &principal == caller {
+        return Err(ContactError::CannotAddSelf);
+    }
+    if principal == Principal::anonymous() {
+        return Err(ContactError::AnonymousPrincipal);
+    }
+    Ok(principal)
+}
+
+fn check_lengths(nickname: &Option<String>, note: &Option<String>) -> Result<(), ContactError> {
+    if nickname
+        .as_ref()
+        .is_some_and(|n| n.chars().count() > MAX_NICKNAME_LEN)
+    {
+        return Err(ContactError::NicknameTooLong {
+            max: MAX_NICKNAME_LEN,
+        });
+    }
+    if note
+        .as_ref()
+        .is_some_and(|n| n.chars().count() > MAX_NOTE_LEN)
+    {
+        return Err(ContactError::NoteTooLong { max: MAX_NOTE_LEN });
+    }
+    Ok(())
+}
+
+pub fn get_contacts(profile: Option<Principal>) -> Result<Vec<ContactView>, ContactError> {
+    let caller = staff::account(profile, Permission::ViewCustomers)?;
+    Ok(ContactBook::get(&caller)
+        .unwrap_or_default()
+        .contacts
+        .values()
+        .map(Contact::view)
+        .collect())
+}
+
+pub fn get_contact(
+    contact: PayIdOrPrincipal,
+    profile: Option<Principal>,
+) -> Result<ContactView, ContactError> {
+    let caller = staff::account(profile, Permission::ViewCustomers)?;
+    check_account(&caller)?;
+    let principal = resolve(&caller, contact)?;
+
+    match find_contact(&caller, &principal) {
+        Some(view) => Ok(view),
+        None => Err(ContactError::ContactNotFound),
+    }
+}
+
+pub fn find_contact(principal: &Principal, contact: &Principal) -> Option<ContactView> {
+    ContactBook::get(principal)?
+        .contacts
+        .get(contact)
+        .map(Contact::view)
+}
+
+// Adds every contact of principal to its search index
+pub fn index_contacts(principal: &Principal) {
+    let book = ContactBook::get(principal).unwrap_or_default();
+
+    for (contact_principal, contact) in book.contacts.iter() {
+        search::add_doc(
+            principal,
+            &contact.search_text(),
+            SearchDoc::Contact(*contact_principal),
+        );
+    }
+}
+
+// Adds the contact, or updates it if it's already in the book
+pub fn update_contact(
+    UpdateContactArg {
+        contact,
+        nickname,
+        note,
+        favorite,
+    }: UpdateContactArg,
+    profile: Option<Principal>,
+) -> Result<ContactView, ContactError> {
+    let caller = staff::account(profile, Permission::ManageCustomers)?;
+    check_account(&caller)?;
+    check_lengths(&nickname, &note)?;
+    let principal = resolve(&caller, contact)?;
+
+    let mut book = ContactBook::get(&caller).unwrap_or_default();
+
+    let contact = match book.contacts.get(&principal) {
+        Some(previous) => {
+            search::remove_doc(
+                &caller,
+                &previous.search_text(),
+                &SearchDoc::Contact(principal),
+            );
+            Contact {
+                nickname,
+                note,
+                favorite,
+                ..previous.clone()
+            }
+        }
+        None => Contact {
+            principal,
+            nickname,
+            note,
+            favorite,
+            added_at: time(),
+        },
+    };
+
+    search::add_doc(
+        &caller,
+        &contact.search_text(),
+        SearchDoc::Contact(principal),
+    );
+    book.contacts.insert(principal, contact.clone());
+    ContactBook::insert(caller, book);
+    Ok(contact.view())
+}
+
+pub fn remove_contact(
+    contact: PayIdOrPrincipal,
+    profile: Option<Principal>,
+) -> Result<(), ContactError> {
+    let caller = staff::account(profile, Permission::ManageCustomers)?;
+    check_account(&caller)?;
+    let principal = resolve(&caller, contact)?;
+
+    let mut book = ContactBook::get(&caller).unwrap_or_default();
+    let removed = match book.contacts.remove(&principal) {
+        Some(contact) => contact,
+        None => return Err(ContactError::ContactNotFound),
+    };
+
+    search::remove_doc(
+        &caller,
+        &removed.search_text(),
+        &SearchDoc::Contact(principal),
+    );
+    ContactBook::insert(caller, book);
+    Ok(())
+}
+
+/*
+ * Adds the latest counterparties of the caller's history that aren't contacts yet.
+ * History keeps the pay_id of Rapido accounts and the principal text of unknown ones.
+ */
+pub fn import_recent_contacts(
+    limit: Option<usize>,
+    profile: Option<Principal>,
+) -> Result<Vec<ContactView>, ContactError> {
+    let caller = staff::account(profile, Permission::ManageCustomers)?;
+    check_account(&caller)?;
+
+    let history = if is_business(&caller) {
+        business::get_transactions(&caller)
+    } else {
+        user::get_history(&caller)
+    };
+
+    let limit = limit.unwrap_or(DEFAULT_IMPORT_LIMIT);
+    let mut book = ContactBook::get(&caller).unwrap_or_default();
+    let mut imported = Vec::new();
+
+    for entry in history.iter().rev() {
+        if imported.len() >= limit {
+            break;
+        }
+        if !entry.kind.is_transfer() {
+            continue;
+        }
+
+        let principal = match PayIds::get(&entry.pay_id) {
+            Some(principal) => principal,
+            None => match Principal::from_text(&entry.pay_id) {
+                Ok(principal) => principal,
+                Err(_) => continue,
+            },
+        };
+
+        if principal == caller || book.contacts.contains_key(&principal) {
+            continue;
+        }
+
+        let contact = Contact {
+            principal,
+            nickname: None,
+            note: None,
+            favorite: false,
+            added_at: time(),
+        };
+        search::add_doc(
+            &caller,
+            &contact.search_text(),
+            SearchDoc::Contact(principal),
+        );
+        imported.push(contact.view());
+        book.contacts.insert(principal, contact);
+    }
+
+    ContactBook::insert(caller, book);
+    Ok(imported)
+}
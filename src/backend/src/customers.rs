@@ -0,0 +1,243 @@
+use crate::business;
+use crate::error::CustomerError;
+use crate::staff::{self, AuditAction, Permission};
+use crate::user::{self, is_user};
+use crate::{PayIds, TxKind, CUSTOMERS_MAP};
+use candid::{Decode, Encode, Nat, Principal};
+use ic_stable_structures::storable::{Bound, Storable};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+const PAGE_SIZE: usize = 20;
+const MAX_TAGS: usize = 10;
+const MAX_TAG_LEN: usize = 32;
+const MAX_NOTE_LEN: usize = 500;
+
+// Key is "{business principal}/{customer pay_id}", so all customers of a business are a range scan
+fn customer_key(business: &Principal, pay_id: &str) -> String {
+    format!("{}/{}", business.to_text(), pay_id)
+}
+
+// A user as seen by one business, tags and note are only visible to the business
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct Customer {
+    principal: Principal,
+    pay_id: String,
+    name: String,
+    total_paid: Nat,     // received by the business from the customer
+    total_paid_out: Nat, // sent by the business to the customer
+    tx_count: u64,
+    first_seen: u64,
+    last_seen: u64,
+    added_business: bool, // the customer added the business with user_add_business
+    tags: Vec<String>,
+    note: Option<String>,
+}
+
+impl Storable for Customer {
+    const BOUND: Bound = Bound::Unbounded;
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl Customer {
+    fn get(key: &String) -> Option<Customer> {
+        CUSTOMERS_MAP.with(|p| p.borrow().get(key))
+    }
+
+    fn insert(key: String, value: Customer) -> Option<Customer> {
+        CUSTOMERS_MAP.with(|p| p.borrow_mut().insert(key, value))
+    }
+
+    // Existing customer or a new one first seen at timestamp, None if principal isn't a user
+    fn get_or_new(business: &Principal, principal: &Principal, timestamp: u64) -> Option<Customer> {
+        let user = user::get_metadata(principal)?;
+
+        let mut customer =
+            Customer::get(&customer_key(business, &user.pay_id)).unwrap_or(Customer {
+                principal: *principal,
+                pay_id: user.pay_id.clone(),
+                name: String::new(),
+                total_paid: Nat::from(0u64),
+                total_paid_out: Nat::from(0u64),
+                tx_count: 0,
+                first_seen: timestamp,
+                last_seen: timestamp,
+                added_business: false,
+                tags: Vec::new(),
+                note: None,
+            });
+        customer.name = user.name;
+        Some(customer)
+    }
+
+    fn add_transaction(&mut self, kind: &TxKind, amount: Nat, timestamp: u64) {
+        if kind.is_outgoing() {
+            self.total_paid_out += amount;
+        } else {
+            self.total_paid += amount;
+        }
+        self.tx_count += 1;
+        self.first_seen = self.first_seen.min(timestamp);
+        self.last_seen = self.last_seen.max(timestamp);
+    }
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct CustomerPage {
+    customers: Vec<Customer>,
+    next_page: Option<u64>,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct UpdateCustomerArg {
+    pay_id: String,
+    tags: Vec<String>,
+    note: Option<String>,
+}
+
+// Called from add_business_transaction, kind is from the side of the business
+pub fn record_transaction(
+    business: &Principal,
+    principal: &Principal,
+    kind: &TxKind,
+    amount: Nat,
+    timestamp: u64,
+) {
+    if let Some(mut customer) = Customer::get_or_new(business, principal, timestamp) {
+        customer.add_transaction(kind, amount, timestamp);
+        Customer::insert(customer_key(business, &customer.pay_id), customer);
+    }
+}
+
+// Called from user_add_business
+pub fn record_added_business(business: &Principal, principal: &Principal, timestamp: u64) {
+    if let Some(mut customer) = Customer::get_or_new(business, principal, timestamp) {
+        customer.added_business = true;
+        customer.last_seen = customer.last_seen.max(timestamp);
+        Customer::insert(customer_key(business, &customer.pay_id), customer);
+    }
+}
+
+// Customers ordered by pay_id, only the ones with the tag if given
+pub fn list_customers(
+    tag: Option<String>,
+    page: u64,
+    profile: Option<Principal>,
+) -> Result<CustomerPage, CustomerError> {
+    let business = staff::actor(profile, Permission::ViewCustomers)?.business;
+
+    let start = customer_key(&business, "");
+    let mut customers = CUSTOMERS_MAP.with(|p| {
+        p.borrow()
+            .range(start.clone()..)
+            .take_while(|(key, _)| key.starts_with(&start))
+            .map(|(_, customer)| customer)
+            .filter(|customer| tag.as_ref().is_none_or(|tag| customer.tags.contains(tag)))
+            .skip(page as usize * PAGE_SIZE)
+            .take(PAGE_SIZE + 1)
+            .collect::<Vec<_>>()
+    });
+
+    let next_page = if customers.len() > PAGE_SIZE {
+        customers.truncate(PAGE_SIZE);
+        Some(page + 1)
+    } else {
+        None
+    };
+
+    Ok(CustomerPage {
+        customers,
+        next_page,
+    })
+}
+
+pub fn get_customer(pay_id: String, profile: Option<Principal>) -> Result<Customer, CustomerError> {
+    let business = staff::actor(profile, Permission::ViewCustomers)?.business;
+
+    match Customer::get(&customer_key(&business, &pay_id)) {
+        Some(customer) => Ok(customer),
+        None => Err(CustomerError::CustomerNotFound),
+    }
+}
+
+// Replaces the tags and note of a customer
+pub fn update_customer(
+    UpdateCustomerArg { pay_id, tags, note }: UpdateCustomerArg,
+    profile: Option<Principal>,
+) -> Result<Customer, CustomerError> {
+    let actor = staff::actor(profile, Permission::ManageCustomers)?;
+
+    if tags.len() > MAX_TAGS {
+        return Err(CustomerError::TooManyTags { max: MAX_TAGS });
+    }
+    if tags.iter().any(|tag| tag.chars().count() > MAX_TAG_LEN) {
+        return Err(CustomerError::TagTooLong { max: MAX_TAG_LEN });
+    }
+    if note
+        .as_ref()
+        .is_some_and(|n| n.chars().count() > MAX_NOTE_LEN)
+    {
+        return Err(CustomerError::NoteTooLong { max: MAX_NOTE_LEN });
+    }
+
+    let key = customer_key(&actor.business, &pay_id);
+    let mut customer = match Customer::get(&key) {
+        Some(customer) => customer,
+        None => return Err(CustomerError::CustomerNotFound),
+    };
+
+    customer.tags = tags;
+    customer.note = note;
+    Customer::insert(key, customer.clone());
+
+    actor.audit(AuditAction::CustomerUpdated { pay_id });
+    Ok(customer)
+}
+
+/*
+ * Recomputes the totals of the business customers from Business.transactions, for transactions
+ * recorded before the index existed. Tags, notes and first and last seen are kept.
+ */
+pub fn rebuild_customers(profile: Option<Principal>) -> Result<(), CustomerError> {
+    let actor = staff::actor(profile, Permission::ManageSettings)?;
+
+    let start = customer_key(&actor.business, "");
+    let existing = CUSTOMERS_MAP.with(|p| {
+        p.borrow()
+            .range(start.clone()..)
+            .take_while(|(key, _)| key.starts_with(&start))
+            .collect::<Vec<_>>()
+    });
+    for (key, mut customer) in existing {
+        customer.total_paid = Nat::from(0u64);
+        customer.total_paid_out = Nat::from(0u64);
+        customer.tx_count = 0;
+        Customer::insert(key, customer);
+    }
+
+    for entry in business::get_transactions(&actor.business) {
+        if !entry.kind.is_transfer() {
+            continue;
+        }
+        let principal = match PayIds::get(&entry.pay_id) {
+            Some(principal) if is_user(&principal) => principal,
+            _ => continue,
+        };
+        record_transaction(
+            &actor.business,
+            &principal,
+            &entry.kind,
+            entry.amount,
+            entry.timestamp,
+        );
+    }
+
+    actor.audit(AuditAction::CustomersRebuilt);
+    Ok(())
+}
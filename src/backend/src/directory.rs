@@ -0,0 +1,204 @@
+use crate::business::{self, is_business, BusinessCategory};
+use crate::error::{DirectoryError, SetVerifiedError};
+use crate::staff::{self, Permission};
+use crate::user::{self, is_user};
+use crate::{PayIds, BUSINESS_DIRECTORY_MAP, DIRECTORY_SETTINGS_MAP};
+use candid::{Decode, Encode, Principal};
+use ic_cdk::{api::is_controller, caller};
+use ic_stable_structures::storable::{Bound, Storable};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+const PAGE_SIZE: usize = 20;
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize, Default)]
+pub struct DirectorySettings {
+    listed: bool,   // opted in to be found without knowing the pay_id
+    verified: bool, // set by a controller
+}
+
+impl Storable for DirectorySettings {
+    const BOUND: Bound = Bound::Unbounded;
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl DirectorySettings {
+    fn get(key: &Principal) -> Option<DirectorySettings> {
+        DIRECTORY_SETTINGS_MAP.with(|p| p.borrow().get(key))
+    }
+
+    fn insert(key: Principal, value: DirectorySettings) -> Option<DirectorySettings> {
+        DIRECTORY_SETTINGS_MAP.with(|p| p.borrow_mut().insert(key, value))
+    }
+}
+
+// Listed businesses keyed by "{category}/{lowercase name}/{pay_id}", so a category and a name prefix are a range scan
+struct BusinessDirectory;
+
+impl BusinessDirectory {
+    fn key(category: &BusinessCategory, name: &str, pay_id: &str) -> String {
+        format!("{:?}/{}/{}", category, name.to_lowercase(), pay_id)
+    }
+
+    fn insert(key: String, value: Principal) -> Option<Principal> {
+        BUSINESS_DIRECTORY_MAP.with(|p| p.borrow_mut().insert(key, value))
+    }
+
+    fn remove(key: &String) -> Option<Principal> {
+        BUSINESS_DIRECTORY_MAP.with(|p| p.borrow_mut().remove(key))
+    }
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize, PartialEq)]
+pub enum AccountKind {
+    User,
+    Business(BusinessCategory),
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct PayIdProfile {
+    pay_id: String,
+    name: String,
+    avatar: String, // profile_pic of users and logo of businesses
+    kind: AccountKind,
+    verified: bool,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct BusinessDirectoryPage {
+    businesses: Vec<PayIdProfile>,
+    next_page: Option<u64>,
+}
+
+fn get_profile(principal: &Principal) -> Option<PayIdProfile> {
+    let verified = DirectorySettings::get(principal)
+        .map(|settings| settings.verified)
+        .unwrap_or_default();
+
+    if let Some(business) = business::get_metadata(principal) {
+        return Some(PayIdProfile {
+            pay_id: business.pay_id,
+            name: business.name,
+            avatar: business.logo,
+            kind: AccountKind::Business(business.category),
+            verified,
+        });
+    }
+
+    user::get_metadata(principal).map(|user| PayIdProfile {
+        pay_id: user.pay_id,
+        name: user.name,
+        avatar: user.profile_pic,
+        kind: AccountKind::User,
+        verified,
+    })
+}
+
+// Public, but accounts that didn't opt in are only shown to signed up callers
+pub fn lookup_pay_id(pay_id: String) -> Option<PayIdProfile> {
+    let principal = PayIds::get(&pay_id)?;
+    let caller = caller();
+
+    let listed = DirectorySettings::get(&principal)
+        .map(|settings| settings.listed)
+        .unwrap_or_default();
+
+    if !listed && !is_user(&caller) && !is_business(&caller) {
+        return None;
+    }
+
+    get_profile(&principal)
+}
+
+pub fn list_businesses(
+    category: Option<BusinessCategory>,
+    prefix: Option<String>,
+    page: u64,
+) -> BusinessDirectoryPage {
+    let prefix = prefix.unwrap_or_default().to_lowercase();
+    let start = match &category {
+        Some(category) => format!("{:?}/{}", category, prefix),
+        None => String::new(),
+    };
+
+    let principals = BUSINESS_DIRECTORY_MAP.with(|p| {
+        p.borrow()
+            .range(start.clone()..)
+            .take_while(|(key, _)| key.starts_with(&start))
+            .filter(|(key, _)| {
+                // without a category the name is matched after the category segment
+                category.is_some()
+                    || key
+                        .split_once('/')
+                        .is_some_and(|(_, name)| name.starts_with(&prefix))
+            })
+            .skip(page as usize * PAGE_SIZE)
+            .take(PAGE_SIZE + 1)
+            .map(|(_, principal)| principal)
+            .collect::<Vec<_>>()
+    });
+
+    let next_page = if principals.len() > PAGE_SIZE {
+        Some(page + 1)
+    } else {
+        None
+    };
+
+    BusinessDirectoryPage {
+        businesses: principals
+            .iter()
+            .take(PAGE_SIZE)
+            .filter_map(get_profile)
+            .collect(),
+        next_page,
+    }
+}
+
+pub fn set_directory_visibility(
+    listed: bool,
+    profile: Option<Principal>,
+) -> Result<(), DirectoryError> {
+    let caller = staff::account(profile, Permission::ManageSettings)?;
+
+    if !is_user(&caller) && !is_business(&caller) {
+        return Err(DirectoryError::AccountNotFound);
+    }
+
+    let mut settings = DirectorySettings::get(&caller).unwrap_or_default();
+    settings.listed = listed;
+    DirectorySettings::insert(caller, settings);
+
+    // Only businesses show up in list_businesses, users can only opt in to public lookup
+    if let Some(business) = business::get_metadata(&caller) {
+        let key = BusinessDirectory::key(&business.category, &business.name, &business.pay_id);
+        if listed {
+            BusinessDirectory::insert(key, caller);
+        } else {
+            BusinessDirectory::remove(&key);
+        }
+    }
+
+    Ok(())
+}
+
+pub fn set_verified(pay_id: String, verified: bool) -> Result<(), SetVerifiedError> {
+    if !is_controller(&caller()) {
+        return Err(SetVerifiedError::NotAController);
+    }
+
+    let principal = match PayIds::get(&pay_id) {
+        Some(principal) => principal,
+        None => return Err(SetVerifiedError::PayIdNotFound),
+    };
+
+    let mut settings = DirectorySettings::get(&principal).unwrap_or_default();
+    settings.verified = verified;
+    DirectorySettings::insert(principal, settings);
+    Ok(())
+}
@@ -1,4 +1,4 @@
-use candid::Principal;
+use candid::{Nat, Principal};
 use serde::{Deserialize, Serialize};
 
 #[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
@@ -6,11 +6,13 @@ pub enum SignUpError {
     AnonymousCaller,
     AccountExist,
     PayIdExist,
+    TooManyProfiles { max: usize },
 }
 
 #[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
 pub enum GetBusinessError {
     AccountNotFound,
+    NotPermitted,
 }
 
 #[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
@@ -18,6 +20,7 @@ pub enum CreateChatErr {
     AccountNotFound,
     CallerAndParticipantSame,
     ParticipantNotFound,
+    Blocked,
 }
 
 #[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
@@ -25,6 +28,23 @@ pub enum AddMessageErr {
     AccountNotFound,
     ChatNotFound,
     NotAParticipant,
+    ReplyToNotFound,
+    Blocked,
+    ChatEncrypted,    // only add_encrypted_message is accepted once encryption is on
+    ChatNotEncrypted, // enable_chat_encryption first
+    InvalidCiphertext,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub enum UpdateMessageErr {
+    AccountNotFound,
+    ChatNotFound,
+    NotAParticipant,
+    MessageNotFound,
+    MessageDeleted,
+    NotMessageSender,
+    InvalidReaction,
+    ChatEncrypted,
 }
 
 #[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
@@ -45,6 +65,7 @@ pub enum RecordTxErr {
     InterCanisterCall(String),
     InvalidTransaction(String),
     BothAccountsNotFound { from: Principal, to: Principal },
+    AccountNotFound(Principal), // the only account of a mint or burn isn't registered
     AlreadyRecorded,
 }
 
@@ -58,6 +79,9 @@ pub enum RequestPaymentError {
     ChatNotFound,
     AccountNotFound,
     NotAParticipant,
+    Blocked,
+    OnlyContactsCanRequest,
+    RateLimited { retry_at: u64 },
 }
 
 #[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
@@ -69,5 +93,410 @@ pub enum RecordRegPayTxErr {
     ChatNotFound,
     AccountNotFound,
     RequestPaymentNotFound,
-    NotAParticipant
+    NotAParticipant,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub enum SyncError {
+    AccountNotFound,
+    NotPermitted,
+}
+
+impl From<StaffError> for SyncError {
+    fn from(err: StaffError) -> Self {
+        match err {
+            StaffError::NotPermitted => SyncError::NotPermitted,
+            _ => SyncError::AccountNotFound,
+        }
+    }
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub enum SearchError {
+    AccountNotFound,
+    NotPermitted,
+    EmptyQuery,
+    InvalidLimit { max: usize },
+}
+
+impl From<StaffError> for SearchError {
+    fn from(err: StaffError) -> Self {
+        match err {
+            StaffError::NotPermitted => SearchError::NotPermitted,
+            _ => SearchError::AccountNotFound,
+        }
+    }
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub enum DirectoryError {
+    AccountNotFound,
+    NotPermitted,
+}
+
+impl From<StaffError> for DirectoryError {
+    fn from(err: StaffError) -> Self {
+        match err {
+            StaffError::NotPermitted => DirectoryError::NotPermitted,
+            _ => DirectoryError::AccountNotFound,
+        }
+    }
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub enum SetVerifiedError {
+    NotAController,
+    PayIdNotFound,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub enum ContactError {
+    AccountNotFound,
+    NotPermitted,
+    PayIdNotFound,
+    ContactNotFound,
+    CannotAddSelf,
+    AnonymousPrincipal,
+    NicknameTooLong { max: usize },
+    NoteTooLong { max: usize },
+}
+
+impl From<StaffError> for ContactError {
+    fn from(err: StaffError) -> Self {
+        match err {
+            StaffError::NotPermitted => ContactError::NotPermitted,
+            _ => ContactError::AccountNotFound,
+        }
+    }
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub enum BlockError {
+    AccountNotFound,
+    NotPermitted,
+    PayIdNotFound,
+    CannotBlockSelf,
+    NotBlocked,
+}
+
+impl From<StaffError> for BlockError {
+    fn from(err: StaffError) -> Self {
+        match err {
+            StaffError::NotPermitted => BlockError::NotPermitted,
+            _ => BlockError::AccountNotFound,
+        }
+    }
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub enum AnalyticsError {
+    AccountNotFound,
+    NotPermitted,
+    InvalidRange,
+    RangeTooLong { max_days: u64 },
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub enum CustomerError {
+    AccountNotFound,
+    NotPermitted,
+    CustomerNotFound,
+    TooManyTags { max: usize },
+    TagTooLong { max: usize },
+    NoteTooLong { max: usize },
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub enum RecordRefundErr {
+    InterCanisterCall(String),
+    InvalidTransaction(String),
+    AlreadyRecorded,
+    OriginalNotRecorded,
+    NotPaidToBusiness,
+    PartiesNotReversed,
+    RefundBeforeOriginal,
+    ExceedsRefundable { refundable: Nat },
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub enum ExportError {
+    AccountNotFound,
+    NotPermitted,
+    InvalidRange,
+    InvalidChunk,
+    ChunkOutOfRange,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub enum FiatError {
+    AccountNotFound,
+    NotPermitted,
+    InvalidCurrency,
+    UnsupportedCurrency, // the XRC has no rate for it
+    NotAController,
+}
+
+impl From<StaffError> for FiatError {
+    fn from(err: StaffError) -> Self {
+        match err {
+            StaffError::NotPermitted => FiatError::NotPermitted,
+            _ => FiatError::AccountNotFound,
+        }
+    }
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub enum StaffError {
+    AccountNotFound,
+    NotPermitted,
+    AnonymousPrincipal,
+    IsABusiness,
+    AlreadyAMember,
+    MemberNotFound,
+    TooManyMembers { max: usize },
+}
+
+// Business endpoints that members can call only fail on staff checks with these two
+impl From<StaffError> for GetBusinessError {
+    fn from(err: StaffError) -> Self {
+        match err {
+            StaffError::NotPermitted => GetBusinessError::NotPermitted,
+            _ => GetBusinessError::AccountNotFound,
+        }
+    }
+}
+
+impl From<StaffError> for AnalyticsError {
+    fn from(err: StaffError) -> Self {
+        match err {
+            StaffError::NotPermitted => AnalyticsError::NotPermitted,
+            _ => AnalyticsError::AccountNotFound,
+        }
+    }
+}
+
+impl From<StaffError> for CustomerError {
+    fn from(err: StaffError) -> Self {
+        match err {
+            StaffError::NotPermitted => CustomerError::NotPermitted,
+            _ => CustomerError::AccountNotFound,
+        }
+    }
+}
+
+impl From<StaffError> for ExportError {
+    fn from(err: StaffError) -> Self {
+        match err {
+            StaffError::NotPermitted => ExportError::NotPermitted,
+            _ => ExportError::AccountNotFound,
+        }
+    }
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub enum CheckoutError {
+    AccountNotFound,
+    NotPermitted,
+    InvalidAmount,
+    ReferenceTooLong { max: usize },
+    InvalidTtl { max: u64 },
+    InterCanisterCall(String),
+    SessionNotFound,
+}
+
+impl From<StaffError> for CheckoutError {
+    fn from(err: StaffError) -> Self {
+        match err {
+            StaffError::NotPermitted => CheckoutError::NotPermitted,
+            _ => CheckoutError::AccountNotFound,
+        }
+    }
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub enum WebhookError {
+    AccountNotFound,
+    NotPermitted,
+    InvalidUrl,
+    NoEvents,
+    TooManyWebhooks { max: usize },
+    WebhookNotFound,
+    InterCanisterCall(String),
+    RateLimited { retry_at: u64 },
+    TooManyPending { max: usize },
+}
+
+impl From<StaffError> for WebhookError {
+    fn from(err: StaffError) -> Self {
+        match err {
+            StaffError::NotPermitted => WebhookError::NotPermitted,
+            _ => WebhookError::AccountNotFound,
+        }
+    }
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub enum AppRequestError {
+    NotAController,
+    NotAllowed, // caller isn't on the allowlist
+    InvalidName { max: usize },
+    AccountNotFound,
+    InvalidAmount,
+    MemoTooLong { max: usize },
+    InvalidCallback,
+    Blocked,
+    TooManyPending { max: usize },
+    RequestNotFound,
+    NotPending,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub enum MandateError {
+    AccountNotFound,
+    NotPermitted,
+    PayeeNotFound,
+    CannotPaySelf,
+    InvalidAmount,
+    InvalidInterval { min: u64, max: u64 },
+    InvalidStartDate,
+    InvalidEndDate,
+    NoteTooLong { max: usize },
+    AllowanceTooLow { allowance: Nat },
+    TooManyMandates { max: usize },
+    InterCanisterCall(String),
+    MandateNotFound,
+    NotActive,
+}
+
+impl From<StaffError> for MandateError {
+    fn from(err: StaffError) -> Self {
+        match err {
+            StaffError::NotPermitted => MandateError::NotPermitted,
+            _ => MandateError::AccountNotFound,
+        }
+    }
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub enum EscrowError {
+    AccountNotFound,
+    ChatNotFound,
+    NotAParticipant,
+    PayeeNotFound,
+    InvalidAmount,
+    NoteTooLong { max: usize },
+    ReasonTooLong { max: usize },
+    InvalidTimeout { min: u64, max: u64 },
+    EscrowNotFound,
+    NotPermitted,
+    NotAController,
+    InvalidState, // the step isn't possible in the current status
+    NotFunded { balance: Nat },
+    PayoutLookup, // an earlier payout is still being looked up on the ledger, tried again later
+    InterCanisterCall(String),
+    TransferFailed(String),
+    Policy(PolicyError),
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub enum PaymentLinkError {
+    AccountNotFound,
+    NotPermitted,
+    InvalidAmount,
+    TitleTooLong { max: usize },
+    InvalidExpiry,
+    TooManyLinks { max: usize },
+    LinkNotFound,
+}
+
+impl From<StaffError> for PaymentLinkError {
+    fn from(err: StaffError) -> Self {
+        match err {
+            StaffError::NotPermitted => PaymentLinkError::NotPermitted,
+            _ => PaymentLinkError::AccountNotFound,
+        }
+    }
+}
+
+// Typed reason a spending policy stopped a send
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub enum PolicyError {
+    PerTransactionLimit { max: Nat },
+    DailyLimit { cap: Nat, spent: Nat },
+    MonthlyLimit { cap: Nat, spent: Nat },
+    ApprovalRequired { approval_id: u64 }, // asked the approver, tried again once approved
+    ApprovalPending { approval_id: u64 },
+    ApprovalRejected { approval_id: u64 },
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub enum SpendingPolicyError {
+    AccountNotFound,
+    NotPermitted,
+    ApproverNotFound,
+    InvalidApprover,
+    ApproverRequired, // an approval threshold needs an approver
+    ApprovalNotFound,
+    AlreadyDecided,
+}
+
+impl From<StaffError> for SpendingPolicyError {
+    fn from(err: StaffError) -> Self {
+        match err {
+            StaffError::NotPermitted => SpendingPolicyError::NotPermitted,
+            _ => SpendingPolicyError::AccountNotFound,
+        }
+    }
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub enum NotificationError {
+    AccountNotFound,
+    NotPermitted,
+}
+
+impl From<StaffError> for NotificationError {
+    fn from(err: StaffError) -> Self {
+        match err {
+            StaffError::NotPermitted => NotificationError::NotPermitted,
+            _ => NotificationError::AccountNotFound,
+        }
+    }
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub enum WebPushError {
+    AccountNotFound,
+    NotPermitted,
+    NotAController,
+    NotConfigured, // setup_web_push hasn't been called yet
+    InvalidSubject,
+    InvalidEndpoint,
+    InvalidKeys,
+    TooManySubscriptions { max: usize },
+    SubscriptionNotFound,
+    KeyGeneration,
+    KeyEncryption, // the VAPID secret couldn't be sealed or opened with the vetKey
+    RateLimited { retry_at: u64 },
+    InterCanisterCall(String),
+}
+
+impl From<StaffError> for WebPushError {
+    fn from(err: StaffError) -> Self {
+        match err {
+            StaffError::NotPermitted => WebPushError::NotPermitted,
+            _ => WebPushError::AccountNotFound,
+        }
+    }
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub enum ChatEncryptionError {
+    AccountNotFound,
+    ChatNotFound,
+    NotAParticipant,
+    ChatNotEncrypted,
+    InvalidTransportKey,
+    RateLimited { retry_at: u64 },
+    InterCanisterCall(String),
 }
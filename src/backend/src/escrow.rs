@@ -0,0 +1,773 @@
+use crate::checkout;
+use crate::ck_btc_ledger::{self, Account, TransferArg, TransferError};
+use crate::error::EscrowError;
+use crate::policies::{self, PolicySend};
+use crate::profiles;
+use crate::user::{self, is_user, user_caller};
+use crate::{
+    timer, PayIds, CANISTER_CONFIG_MAP, ESCROWS_MAP, ESCROW_DEADLINES_MAP, USER_ESCROWS_MAP,
+};
+use candid::{Decode, Encode, Nat, Principal};
+use ic_cdk::{
+    api::{is_controller, time},
+    caller,
+};
+use ic_stable_structures::storable::{Bound, Storable};
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+use std::borrow::Cow;
+
+const ARBITER_CONFIG_KEY: &str = "escrow_arbiter";
+const FUNDING_WINDOW: u64 = 86_400_000_000_000; // 24 hours to fund a new escrow
+const DEFAULT_TIMEOUT: u64 = 7 * 86_400; // seconds
+const MIN_TIMEOUT: u64 = 3_600;
+const MAX_TIMEOUT: u64 = 90 * 86_400;
+const MAX_NOTE_LEN: usize = 100;
+const MAX_REASON_LEN: usize = 500;
+const MEMO_PREFIX: &[u8] = b"ESC"; // memo is the prefix followed by the escrow id in big endian
+const DEADLINES_PER_TICK: usize = 10;
+const RETRY_DELAY: u64 = 300_000_000_000; // deadline payout tried again after a failed transfer
+const NANOS: u64 = 1_000_000_000;
+const PAYOUT_WINDOW: u64 = 86_400 * NANOS; // ledger's transaction window, an older payout can't be sent again as is
+const PERMITTED_DRIFT: u64 = 60 * NANOS; // how far ahead of the ledger's time created_at_time can be
+const LOOKUP_BATCH: u64 = 2_000; // ledger blocks read at once while looking up an earlier payout
+const LOOKUP_BATCHES: usize = 5; // per attempt, the lookup continues on the next one
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize, PartialEq)]
+pub enum EscrowStatus {
+    AwaitingFunds, // payer still has to send deposit_amount to deposit_account
+    Funded,
+    Delivered, // payee says it delivered, released to the payee at the deadline
+    Disputed,  // waits for the arbiter, no deadline
+    Settling,  // payout in flight
+    Released { tx_id: Nat },
+    Refunded { tx_id: Nat },
+    Cancelled, // never funded
+}
+
+#[derive(
+    candid::CandidType, Clone, Serialize, Deserialize, Debug, Eq, PartialEq, Ord, PartialOrd,
+)]
+pub enum EscrowEvent {
+    Created,
+    Funded,
+    Delivered,
+    Disputed { reason: String },
+    Released { tx_id: Nat },
+    Refunded { tx_id: Nat },
+    Cancelled,
+    TimedOut, // deadline passed, followed by the payout or the cancel it caused
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct Dispute {
+    by: Principal,
+    reason: String,
+    opened_at: u64,
+}
+
+// Transfer of a payout, kept until it succeeds so a retry sends the same one and the ledger dedups it
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct PendingPayout {
+    to: Principal,
+    amount: Nat,
+    fee: Nat,
+    created_at_time: u64,
+    lookup_from: u64, // ledger block a lost transfer is looked up from, the log length when it was made
+}
+
+/*
+ * Payment held by the canister on a subaccount of its own until the payer releases it. Without
+ * a release the deadline decides, a delivered escrow goes to the payee and an undelivered one back
+ * to the payer. Disputed escrows are resolved by the arbiter.
+ */
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct Escrow {
+    id: u64,
+    chat_id: String,
+    payer: Principal,
+    payee: Principal,
+    amount: Nat,
+    deposit_amount: Nat, // amount and the ledger fee of the payout
+    deposit_account: Account,
+    payment_uri: String,
+    note: Option<String>,
+    timeout: u64, // seconds from funding to the deadline
+    created_at: u64,
+    funded_at: Option<u64>,
+    delivered_at: Option<u64>,
+    deadline: Option<u64>,
+    dispute: Option<Dispute>,
+    timed_out: bool, // deadline passed, its payout can take more than one try
+    status: EscrowStatus,
+    payout: Option<PendingPayout>,
+}
+
+impl Storable for Escrow {
+    const BOUND: Bound = Bound::Unbounded;
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl Escrow {
+    fn get(key: u64) -> Option<Escrow> {
+        ESCROWS_MAP.with(|p| p.borrow().get(&key))
+    }
+
+    fn insert(key: u64, value: Escrow) -> Option<Escrow> {
+        ESCROWS_MAP.with(|p| p.borrow_mut().insert(key, value))
+    }
+
+    fn next_id() -> u64 {
+        ESCROWS_MAP.with(|p| p.borrow().last_key_value().map_or(0, |(id, _)| id + 1))
+    }
+
+    // Stores the escrow and keeps the deadline index in line
+    fn save(&self) {
+        match self.deadline {
+            Some(deadline)
+                if matches!(
+                    self.status,
+                    EscrowStatus::AwaitingFunds | EscrowStatus::Funded | EscrowStatus::Delivered
+                ) =>
+            {
+                ESCROW_DEADLINES_MAP.with(|p| p.borrow_mut().insert(self.id, deadline));
+                timer::schedule_at(deadline);
+            }
+            _ => {
+                ESCROW_DEADLINES_MAP.with(|p| p.borrow_mut().remove(&self.id));
+            }
+        }
+        Escrow::insert(self.id, self.clone());
+    }
+
+    // Posts event into the chat of the escrow, by is None for the arbiter and timeouts
+    fn post(&self, by: Option<Principal>, event: EscrowEvent) {
+        let sender_id = by
+            .and_then(|principal| user::get_metadata(&principal))
+            .map(|user| user.pay_id)
+            .unwrap_or_default();
+        user::post_escrow_message(
+            &self.chat_id,
+            sender_id,
+            self.id,
+            event,
+            self.amount.clone(),
+        );
+    }
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub enum EscrowResolution {
+    Release, // to the payee
+    Refund,  // to the payer
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct CreateEscrowArg {
+    chat_id: String,
+    amount: Nat,
+    note: Option<String>,
+    timeout: Option<u64>, // seconds, DEFAULT_TIMEOUT if None
+}
+
+// Subaccount of the canister holding escrow id
+fn deposit_subaccount(id: u64) -> Vec<u8> {
+    let mut subaccount = [0u8; 32];
+    subaccount[..6].copy_from_slice(b"escrow");
+    subaccount[24..].copy_from_slice(&id.to_be_bytes());
+    subaccount.to_vec()
+}
+
+fn escrow_memo(id: u64) -> Vec<u8> {
+    [MEMO_PREFIX, &id.to_be_bytes()].concat()
+}
+
+// Key is "{principal}/{id}", id zero padded so escrows of a user are in order
+fn user_key(principal: &Principal, id: u64) -> String {
+    format!("{}/{:020}", principal.to_text(), id)
+}
+
+fn escrow_ids(principal: &Principal) -> Vec<u64> {
+    let prefix = format!("{}/", principal.to_text());
+    USER_ESCROWS_MAP.with(|p| {
+        p.borrow()
+            .range(prefix.clone()..)
+            .take_while(|(key, _)| key.starts_with(&prefix))
+            .map(|(_, id)| id)
+            .collect()
+    })
+}
+
+fn is_arbiter(principal: &Principal) -> bool {
+    is_controller(principal)
+        || CANISTER_CONFIG_MAP.with(|p| p.borrow().get(&ARBITER_CONFIG_KEY.to_string()))
+            == Some(*principal)
+}
+
+// Escrow id of a party, other callers get EscrowNotFound
+fn get_for(id: u64, principal: &Principal) -> Result<Escrow, EscrowError> {
+    match Escrow::get(id) {
+        Some(escrow) if &escrow.payer == principal || &escrow.payee == principal => Ok(escrow),
+        Some(escrow) if is_arbiter(principal) => Ok(escrow),
+        _ => Err(EscrowError::EscrowNotFound),
+    }
+}
+
+async fn deposit_balance(id: u64) -> Result<Nat, EscrowError> {
+    let (balance,) = ck_btc_ledger::balance_of(Account {
+        owner: ic_cdk::api::id(),
+        subaccount: Some(ByteBuf::from(deposit_subaccount(id))),
+    })
+    .await
+    .map_err(|err| EscrowError::InterCanisterCall(format!("icrc1_balance_of failed {:?}", err)))?;
+    Ok(balance)
+}
+
+pub fn set_escrow_arbiter(arbiter: Principal) -> Result<(), EscrowError> {
+    if !is_controller(&caller()) {
+        return Err(EscrowError::NotAController);
+    }
+
+    CANISTER_CONFIG_MAP.with(|p| {
+        p.borrow_mut()
+            .insert(ARBITER_CONFIG_KEY.to_string(), arbiter)
+    });
+    Ok(())
+}
+
+// Opens an escrow paying the other participant of chat_id, funded by sending deposit_amount to deposit_account
+pub async fn create_escrow(
+    CreateEscrowArg {
+        chat_id,
+        amount,
+        note,
+        timeout,
+    }: CreateEscrowArg,
+) -> Result<Escrow, EscrowError> {
+    let payer = user_caller();
+    let payer_pay_id = match user::get_metadata(&payer) {
+        Some(user) => user.pay_id,
+        None => return Err(EscrowError::AccountNotFound),
+    };
+
+    let participants = user::chat_participants(&chat_id).ok_or(EscrowError::ChatNotFound)?;
+    if !participants.contains(&payer_pay_id) {
+        return Err(EscrowError::NotAParticipant);
+    }
+    let payee = match participants
+        .iter()
+        .find(|pay_id| *pay_id != &payer_pay_id)
+        .and_then(PayIds::get)
+    {
+        Some(payee) if is_user(&payee) => payee,
+        _ => return Err(EscrowError::PayeeNotFound),
+    };
+
+    if amount == 0u64 {
+        return Err(EscrowError::InvalidAmount);
+    }
+    if note
+        .as_ref()
+        .is_some_and(|note| note.chars().count() > MAX_NOTE_LEN)
+    {
+        return Err(EscrowError::NoteTooLong { max: MAX_NOTE_LEN });
+    }
+    let timeout = timeout.unwrap_or(DEFAULT_TIMEOUT);
+    if !(MIN_TIMEOUT..=MAX_TIMEOUT).contains(&timeout) {
+        return Err(EscrowError::InvalidTimeout {
+            min: MIN_TIMEOUT,
+            max: MAX_TIMEOUT,
+        });
+    }
+
+    let (fee,) = ck_btc_ledger::fee()
+        .await
+        .map_err(|err| EscrowError::InterCanisterCall(format!("icrc1_fee failed {:?}", err)))?;
+
+    // Taken after the call so escrows created meanwhile don't get the same id
+    let id = Escrow::next_id();
+    let now = time();
+    let deposit_account = Account {
+        owner: ic_cdk::api::id(),
+        subaccount: Some(ByteBuf::from(deposit_subaccount(id))),
+    };
+    let deposit_amount = amount.clone() + fee;
+    let escrow = Escrow {
+        id,
+        chat_id,
+        payer,
+        payee,
+        payment_uri: checkout::payment_uri(
+            &deposit_account,
+            Some(&deposit_amount),
+            &escrow_memo(id),
+        ),
+        amount,
+        deposit_amount,
+        deposit_account,
+        note,
+        timeout,
+        created_at: now,
+        funded_at: None,
+        delivered_at: None,
+        deadline: Some(now + FUNDING_WINDOW),
+        dispute: None,
+        timed_out: false,
+        payout: None,
+        status: EscrowStatus::AwaitingFunds,
+    };
+    USER_ESCROWS_MAP.with(|p| {
+        let mut map = p.borrow_mut();
+        map.insert(user_key(&payer, id), id);
+        map.insert(user_key(&payee, id), id);
+    });
+    escrow.save();
+    escrow.post(Some(payer), EscrowEvent::Created);
+
+    Ok(escrow)
+}
+
+// Checks the deposit on the ledger, called by the payer once the deposit transfer is done
+pub async fn confirm_escrow_funding(id: u64) -> Result<Escrow, EscrowError> {
+    let caller = user_caller();
+    let escrow = get_for(id, &caller)?;
+    if escrow.status != EscrowStatus::AwaitingFunds {
+        return Err(EscrowError::InvalidState);
+    }
+
+    let balance = deposit_balance(id).await?;
+    if balance < escrow.deposit_amount {
+        return Err(EscrowError::NotFunded { balance });
+    }
+
+    // Read again, the deadline or another call could have moved it meanwhile
+    let mut escrow = get_for(id, &caller)?;
+    if escrow.status != EscrowStatus::AwaitingFunds {
+        return Err(EscrowError::InvalidState);
+    }
+    mark_funded(&mut escrow);
+    Ok(escrow)
+}
+
+fn mark_funded(escrow: &mut Escrow) {
+    let now = time();
+    escrow.status = EscrowStatus::Funded;
+    escrow.funded_at = Some(now);
+    escrow.deadline = Some(now + escrow.timeout * NANOS);
+    escrow.save();
+    escrow.post(Some(escrow.payer), EscrowEvent::Funded);
+}
+
+// Payee confirms delivery, the escrow is then released to it at the deadline unless disputed
+pub fn mark_escrow_delivered(id: u64) -> Result<Escrow, EscrowError> {
+    let caller = user_caller();
+    let mut escrow = get_for(id, &caller)?;
+    if escrow.payee != caller {
+        return Err(EscrowError::NotPermitted);
+    }
+    if escrow.status != EscrowStatus::Funded {
+        return Err(EscrowError::InvalidState);
+    }
+
+    escrow.status = EscrowStatus::Delivered;
+    escrow.delivered_at = Some(time());
+    escrow.save();
+    escrow.post(Some(caller), EscrowEvent::Delivered);
+    Ok(escrow)
+}
+
+pub fn dispute_escrow(id: u64, reason: String) -> Result<Escrow, EscrowError> {
+    let caller = user_caller();
+    let mut escrow = get_for(id, &caller)?;
+    if escrow.payer != caller && escrow.payee != caller {
+        return Err(EscrowError::NotPermitted);
+    }
+    if !matches!(
+        escrow.status,
+        EscrowStatus::Funded | EscrowStatus::Delivered
+    ) {
+        return Err(EscrowError::InvalidState);
+    }
+    if reason.chars().count() > MAX_REASON_LEN {
+        return Err(EscrowError::ReasonTooLong {
+            max: MAX_REASON_LEN,
+        });
+    }
+
+    escrow.status = EscrowStatus::Disputed;
+    escrow.deadline = None;
+    escrow.dispute = Some(Dispute {
+        by: caller,
+        reason: reason.clone(),
+        opened_at: time(),
+    });
+    escrow.save();
+    escrow.post(Some(caller), EscrowEvent::Disputed { reason });
+    Ok(escrow)
+}
+
+// Payer releases the funds to the payee
+pub async fn release_escrow(id: u64) -> Result<Escrow, EscrowError> {
+    let caller = user_caller();
+    let escrow = get_for(id, &caller)?;
+    if escrow.payer != caller {
+        return Err(EscrowError::NotPermitted);
+    }
+    if !matches!(
+        escrow.status,
+        EscrowStatus::Funded | EscrowStatus::Delivered
+    ) {
+        return Err(EscrowError::InvalidState);
+    }
+
+    // The payer's spending policy applies, timeout and arbiter releases were agreed to at creation
+    let authorization = policies::authorize(
+        caller,
+        PolicySend::EscrowRelease { escrow_id: id },
+        escrow.payee,
+        &escrow.amount,
+    )
+    .map_err(EscrowError::Policy)?;
+    let result = settle(escrow, EscrowResolution::Release, Some(caller)).await;
+    if result.is_err() {
+        policies::revert(authorization);
+    }
+    result
+}
+
+// Payee gives the funds back, also allowed during a dispute
+pub async fn refund_escrow(id: u64) -> Result<Escrow, EscrowError> {
+    let caller = user_caller();
+    let escrow = get_for(id, &caller)?;
+    if escrow.payee != caller {
+        return Err(EscrowError::NotPermitted);
+    }
+    if !matches!(
+        escrow.status,
+        EscrowStatus::Funded | EscrowStatus::Delivered | EscrowStatus::Disputed
+    ) {
+        return Err(EscrowError::InvalidState);
+    }
+
+    settle(escrow, EscrowResolution::Refund, Some(caller)).await
+}
+
+pub async fn resolve_escrow(id: u64, resolution: EscrowResolution) -> Result<Escrow, EscrowError> {
+    if !is_arbiter(&caller()) {
+        return Err(EscrowError::NotPermitted);
+    }
+    let escrow = Escrow::get(id).ok_or(EscrowError::EscrowNotFound)?;
+    if escrow.status != EscrowStatus::Disputed {
+        return Err(EscrowError::InvalidState);
+    }
+
+    settle(escrow, resolution, None).await
+}
+
+// Payer gives up an escrow it hasn't funded, a partial deposit is sent back
+pub async fn cancel_escrow(id: u64) -> Result<Escrow, EscrowError> {
+    let caller = user_caller();
+    let escrow = get_for(id, &caller)?;
+    if escrow.payer != caller {
+        return Err(EscrowError::NotPermitted);
+    }
+    if escrow.status != EscrowStatus::AwaitingFunds {
+        return Err(EscrowError::InvalidState);
+    }
+
+    cancel(escrow, Some(caller)).await
+}
+
+async fn cancel(mut escrow: Escrow, by: Option<Principal>) -> Result<Escrow, EscrowError> {
+    escrow.status = EscrowStatus::Settling;
+    escrow.save();
+
+    // Funded meanwhile, the whole deposit arrived
+    let balance = match deposit_balance(escrow.id).await {
+        Ok(balance) => balance,
+        Err(err) => {
+            escrow.status = EscrowStatus::AwaitingFunds;
+            escrow.save();
+            return Err(err);
+        }
+    };
+    if balance >= escrow.deposit_amount && by.is_none() {
+        mark_funded(&mut escrow);
+        return Ok(escrow);
+    }
+    // Less than the fee can't be sent back
+    let fee = escrow.deposit_amount.clone() - escrow.amount.clone();
+    if balance > fee {
+        escrow.status = EscrowStatus::AwaitingFunds;
+        escrow.save();
+        return settle(escrow, EscrowResolution::Refund, by).await;
+    }
+
+    escrow.status = EscrowStatus::Cancelled;
+    escrow.deadline = None;
+    escrow.save();
+    escrow.post(by, EscrowEvent::Cancelled);
+    Ok(escrow)
+}
+
+/*
+ * Sends the deposit to the payee or back to the payer. The escrow is Settling during the transfer so
+ * no second payout starts, and goes back to its previous status if the transfer fails.
+ */
+async fn settle(
+    mut escrow: Escrow,
+    resolution: EscrowResolution,
+    by: Option<Principal>,
+) -> Result<Escrow, EscrowError> {
+    let previous = escrow.status.clone();
+    escrow.status = EscrowStatus::Settling;
+    escrow.save();
+
+    let result = payout(&mut escrow, &resolution).await;
+
+    let mut escrow = Escrow::get(escrow.id).unwrap_or(escrow);
+    let tx_id = match result {
+        Ok(tx_id) => tx_id,
+        Err(err) => {
+            escrow.status = previous;
+            escrow.save();
+            return Err(err);
+        }
+    };
+    escrow.payout = None;
+
+    let event = match resolution {
+        EscrowResolution::Release => {
+            escrow.status = EscrowStatus::Released {
+                tx_id: tx_id.clone(),
+            };
+            EscrowEvent::Released {
+                tx_id: tx_id.clone(),
+            }
+        }
+        EscrowResolution::Refund => {
+            escrow.status = EscrowStatus::Refunded {
+                tx_id: tx_id.clone(),
+            };
+            EscrowEvent::Refunded {
+                tx_id: tx_id.clone(),
+            }
+        }
+    };
+    escrow.deadline = None;
+    escrow.save();
+    escrow.post(by, event);
+
+    // Recorded like any other transfer, so it shows in the history of the receiver. It can still be
+    // recorded with record_xfer_transaction if this fails
+    let _ = crate::record_xfer_transaction(tx_id, escrow.note.clone()).await;
+    Ok(escrow)
+}
+
+/*
+ * Whole balance of the deposit subaccount less the fee, so nothing is left on it. The transfer is
+ * saved on the escrow before it's sent, a retry after a lost reply sends the same created_at_time
+ * and memo and the ledger answers Duplicate with the first transfer. After PAYOUT_WINDOW the ledger
+ * doesn't dedup it anymore, the transfer is looked up in its blocks instead.
+ */
+async fn payout(escrow: &mut Escrow, resolution: &EscrowResolution) -> Result<Nat, EscrowError> {
+    let to = match resolution {
+        EscrowResolution::Release => escrow.payee,
+        EscrowResolution::Refund => escrow.payer,
+    };
+    let pending = match escrow.payout.clone() {
+        Some(pending) if pending.to == to && time() < pending.created_at_time + PAYOUT_WINDOW => {
+            pending
+        }
+        // Too old for the ledger to dedup, a transfer that went through is looked up instead
+        Some(pending) if pending.to == to => match find_payout(escrow, pending).await? {
+            Some(tx_id) => return Ok(tx_id),
+            None => new_payout(escrow, to).await?,
+        },
+        _ => new_payout(escrow, to).await?,
+    };
+
+    let (result,) = ck_btc_ledger::transfer(TransferArg {
+        from_subaccount: Some(ByteBuf::from(deposit_subaccount(escrow.id))),
+        to: profiles::ledger_account(&pending.to),
+        amount: pending.amount,
+        fee: Some(pending.fee),
+        memo: Some(ByteBuf::from(escrow_memo(escrow.id))),
+        created_at_time: Some(pending.created_at_time),
+    })
+    .await
+    .map_err(|err| EscrowError::InterCanisterCall(format!("icrc1_transfer failed {:?}", err)))?;
+
+    match result {
+        Ok(tx_id)
+        | Err(TransferError::Duplicate {
+            duplicate_of: tx_id,
+        }) => Ok(tx_id),
+        Err(TransferError::GenericError { message, .. }) => {
+            Err(EscrowError::TransferFailed(message))
+        }
+        Err(other) => Err(EscrowError::TransferFailed(format!("{:?}", other))),
+    }
+}
+
+async fn new_payout(escrow: &mut Escrow, to: Principal) -> Result<PendingPayout, EscrowError> {
+    let (fee,) = ck_btc_ledger::fee()
+        .await
+        .map_err(|err| EscrowError::InterCanisterCall(format!("icrc1_fee failed {:?}", err)))?;
+    let balance = deposit_balance(escrow.id).await?;
+    if balance <= fee {
+        return Err(EscrowError::NotFunded { balance });
+    }
+    let (_, log_length) = ck_btc_ledger::get_blocks(0, 0)
+        .await
+        .map_err(EscrowError::InterCanisterCall)?;
+
+    let pending = PendingPayout {
+        to,
+        amount: balance - fee.clone(),
+        fee,
+        created_at_time: time(),
+        lookup_from: log_length,
+    };
+    escrow.payout = Some(pending.clone());
+    escrow.save();
+    Ok(pending)
+}
+
+/*
+ * Block index of the transfer of pending if the ledger has it, None once the blocks are past the time
+ * it could have been appended at. Reads up to LOOKUP_BATCHES batches, a longer lookup is saved on the
+ * escrow and continues on the next attempt.
+ */
+async fn find_payout(
+    escrow: &mut Escrow,
+    mut pending: PendingPayout,
+) -> Result<Option<Nat>, EscrowError> {
+    let subaccount = deposit_subaccount(escrow.id);
+    let memo = escrow_memo(escrow.id);
+    let appended_by = pending.created_at_time + PAYOUT_WINDOW + PERMITTED_DRIFT;
+
+    for _ in 0..LOOKUP_BATCHES {
+        let (blocks, log_length) = ck_btc_ledger::get_blocks(pending.lookup_from, LOOKUP_BATCH)
+            .await
+            .map_err(EscrowError::InterCanisterCall)?;
+
+        for (index, transaction) in &blocks {
+            let sent = transaction.transfer.as_ref().is_some_and(|transfer| {
+                transfer.from.owner == ic_cdk::api::id()
+                    && transfer.from.subaccount.as_deref() == Some(&subaccount)
+                    && transfer.memo.as_deref() == Some(&memo)
+                    && transfer.created_at_time == Some(pending.created_at_time)
+            });
+            if sent {
+                return Ok(Some(Nat::from(*index)));
+            }
+            if transaction.timestamp > appended_by {
+                return Ok(None);
+            }
+        }
+
+        pending.lookup_from = blocks
+            .last()
+            .map_or(log_length, |(index, _)| index + 1)
+            .max(pending.lookup_from);
+        if pending.lookup_from >= log_length {
+            return Ok(None);
+        }
+    }
+
+    escrow.payout = Some(pending);
+    escrow.save();
+    Err(EscrowError::PayoutLookup)
+}
+
+pub fn get_escrow(id: u64) -> Result<Escrow, EscrowError> {
+    get_for(id, &user_caller())
+}
+
+// Escrows the caller pays or is paid by, latest first
+pub fn get_escrows() -> Result<Vec<Escrow>, EscrowError> {
+    let caller = user_caller();
+    if !is_user(&caller) {
+        return Err(EscrowError::AccountNotFound);
+    }
+
+    Ok(escrow_ids(&caller)
+        .into_iter()
+        .rev()
+        .filter_map(Escrow::get)
+        .collect())
+}
+
+// Open disputes, for the arbiter
+pub fn get_disputed_escrows() -> Result<Vec<Escrow>, EscrowError> {
+    if !is_arbiter(&caller()) {
+        return Err(EscrowError::NotPermitted);
+    }
+
+    Ok(ESCROWS_MAP.with(|p| {
+        p.borrow()
+            .iter()
+            .map(|(_, escrow)| escrow)
+            .filter(|escrow| escrow.status == EscrowStatus::Disputed)
+            .collect()
+    }))
+}
+
+// Earliest deadline of the open escrows
+pub fn next_deadline() -> Option<u64> {
+    ESCROW_DEADLINES_MAP.with(|p| p.borrow().iter().map(|(_, at)| at).min())
+}
+
+// Applies the deadlines that passed, called by the timer
+pub async fn run_due() {
+    let now = time();
+    let due = ESCROW_DEADLINES_MAP.with(|p| {
+        p.borrow()
+            .iter()
+            .filter(|(_, at)| *at <= now)
+            .take(DEADLINES_PER_TICK)
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>()
+    });
+
+    for id in due {
+        let escrow = match Escrow::get(id) {
+            Some(escrow) => escrow,
+            None => {
+                ESCROW_DEADLINES_MAP.with(|p| p.borrow_mut().remove(&id));
+                continue;
+            }
+        };
+        let mut escrow = escrow;
+        if !escrow.timed_out {
+            escrow.timed_out = true;
+            escrow.save();
+            escrow.post(None, EscrowEvent::TimedOut);
+        }
+
+        let result = match escrow.status {
+            EscrowStatus::AwaitingFunds => cancel(escrow, None).await,
+            EscrowStatus::Funded => settle(escrow, EscrowResolution::Refund, None).await,
+            EscrowStatus::Delivered => settle(escrow, EscrowResolution::Release, None).await,
+            _ => {
+                ESCROW_DEADLINES_MAP.with(|p| p.borrow_mut().remove(&id));
+                continue;
+            }
+        };
+
+        // The escrow is back in its previous status, its deadline is tried again later
+        if result.is_err() {
+            if let Some(mut escrow) = Escrow::get(id) {
+                escrow.deadline = Some(time() + RETRY_DELAY);
+                escrow.save();
+            }
+        }
+    }
+}
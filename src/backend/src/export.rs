@@ -0,0 +1,347 @@
+use crate::business;
+use crate::error::ExportError;
+use crate::staff::{self, Permission};
+use crate::user::{self, is_user};
+use crate::{TransactionEntry, TxKind, BI};
+use candid::{Nat, Principal};
+use serde::{Deserialize, Serialize};
+
+const CHUNK_SIZE: usize = 500; // entries per export call
+const DECIMALS: usize = 8; // ckBTC has 8 decimals, amounts are stored in satoshis
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub enum ExportFormat {
+    Csv,
+    Ofx, // also imported as QFX
+    Json,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize, Default)]
+pub struct ExportRange {
+    from: Option<u64>, // timestamps in nanoseconds, both inclusive
+    to: Option<u64>,
+}
+
+// Joining data of every chunk in order gives the whole file
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct ExportChunk {
+    data: String,
+    total_entries: u64,
+    next_chunk: Option<u64>,
+}
+
+// One history entry with everything a bookkeeper needs
+pub struct ExportRow {
+    tx_id: String,
+    date: String, // ISO 8601 in UTC
+    ofx_date: String,
+    kind: &'static str,
+    direction: &'static str,
+    counterparty: String,
+    counterparty_pay_id: String,
+    amount: String, // in ckBTC
+    amount_sats: String,
+    fee_sats: String,
+    note: String,
+    memo: String,
+}
+
+impl ExportRow {
+    pub fn new(entry: &TransactionEntry) -> Self {
+        let (year, month, day, hour, minute, second) = date_time(entry.timestamp);
+
+        let direction = if !entry.kind.is_transfer() {
+            "none"
+        } else if entry.kind.is_outgoing() {
+            "out"
+        } else {
+            "in"
+        };
+
+        let memo = BI::get(entry.tx_id.clone())
+            .and_then(|info| info.memo)
+            .map(|memo| match String::from_utf8(memo.to_vec()) {
+                Ok(text) => text,
+                Err(_) => hex(&memo),
+            })
+            .unwrap_or_default();
+
+        ExportRow {
+            tx_id: entry.tx_id.0.to_string(),
+            date: format!("{year:04}-{month:02}-{day:02}T{hour:02}:{minute:02}:{second:02}Z"),
+            ofx_date: format!("{year:04}{month:02}{day:02}{hour:02}{minute:02}{second:02}"),
+            kind: kind_name(&entry.kind),
+            direction,
+            counterparty: entry.name.clone(),
+            counterparty_pay_id: entry.pay_id.clone(),
+            amount: token_units(&entry.amount),
+            amount_sats: entry.amount.0.to_string(),
+            fee_sats: entry
+                .fee
+                .as_ref()
+                .map(|fee| fee.0.to_string())
+                .unwrap_or_default(),
+            note: entry.note.clone().unwrap_or_default(),
+            memo,
+        }
+    }
+}
+
+fn kind_name(kind: &TxKind) -> &'static str {
+    match kind {
+        TxKind::Receive => "receive",
+        TxKind::Sends => "send",
+        TxKind::SendsRefund { .. } => "refund_sent",
+        TxKind::ReceiveRefund { .. } => "refund_received",
+        TxKind::Mint => "deposit",
+        TxKind::Burn => "withdrawal",
+        TxKind::Approve { .. } => "approve",
+        TxKind::Allowance { .. } => "allowance",
+    }
+}
+
+// 12345 sats -> "0.00012345"
+pub fn token_units(amount: &Nat) -> String {
+    let digits = format!("{:0>width$}", amount.0.to_string(), width = DECIMALS + 1);
+    let (whole, fraction) = digits.split_at(digits.len() - DECIMALS);
+    format!("{whole}.{fraction}")
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+// (year, month, day, hour, minute, second) in UTC, with Howard Hinnant's civil_from_days
+fn date_time(timestamp: u64) -> (u64, u64, u64, u64, u64, u64) {
+    let seconds = timestamp / 1_000_000_000;
+    let days = seconds / 86_400;
+    let seconds_of_day = seconds % 86_400;
+
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+
+    (
+        year,
+        month,
+        day,
+        seconds_of_day / 3_600,
+        seconds_of_day % 3_600 / 60,
+        seconds_of_day % 60,
+    )
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+pub fn json_string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+// OFX 1.x is SGML, only these need escaping
+fn ofx_text(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+const CSV_HEADER: &str =
+    "tx_id,date,kind,direction,counterparty,counterparty_pay_id,amount_ckbtc,amount_sats,fee_sats,note,memo\n";
+
+fn csv_row(row: &ExportRow) -> String {
+    [
+        row.tx_id.as_str(),
+        &row.date,
+        row.kind,
+        row.direction,
+        &row.counterparty,
+        &row.counterparty_pay_id,
+        &row.amount,
+        &row.amount_sats,
+        &row.fee_sats,
+        &row.note,
+        &row.memo,
+    ]
+    .iter()
+    .map(|field| csv_field(field))
+    .collect::<Vec<_>>()
+    .join(",")
+        + "\n"
+}
+
+pub fn json_row(row: &ExportRow) -> String {
+    format!(
+        "{{\"tx_id\":{},\"date\":{},\"kind\":{},\"direction\":{},\"counterparty\":{},\"counterparty_pay_id\":{},\"amount_ckbtc\":{},\"amount_sats\":{},\"fee_sats\":{},\"note\":{},\"memo\":{}}}",
+        json_string(&row.tx_id),
+        json_string(&row.date),
+        json_string(row.kind),
+        json_string(row.direction),
+        json_string(&row.counterparty),
+        json_string(&row.counterparty_pay_id),
+        json_string(&row.amount),
+        json_string(&row.amount_sats),
+        json_string(&row.fee_sats),
+        json_string(&row.note),
+        json_string(&row.memo),
+    )
+}
+
+fn ofx_header(account_id: &str, range: &ExportRange) -> String {
+    let start = date_time(range.from.unwrap_or_default());
+    let end = date_time(range.to.unwrap_or(ic_cdk::api::time()));
+    let ofx = |(y, mo, d, h, mi, s): (u64, u64, u64, u64, u64, u64)| {
+        format!("{y:04}{mo:02}{d:02}{h:02}{mi:02}{s:02}")
+    };
+
+    format!(
+        "OFXHEADER:100\nDATA:OFXSGML\nVERSION:102\nSECURITY:NONE\nENCODING:UTF-8\nCHARSET:NONE\nCOMPRESSION:NONE\nOLDFILEUID:NONE\nNEWFILEUID:NONE\n\n\
+<OFX>\n<BANKMSGSRSV1>\n<STMTTRNRS>\n<TRNUID>0\n<STATUS>\n<CODE>0\n<SEVERITY>INFO\n</STATUS>\n\
+<STMTRS>\n<CURDEF>XBT\n<BANKACCTFROM>\n<BANKID>ckBTC\n<ACCTID>{}\n<ACCTTYPE>CHECKING\n</BANKACCTFROM>\n\
+<BANKTRANLIST>\n<DTSTART>{}\n<DTEND>{}\n",
+        ofx_text(account_id),
+        ofx(start),
+        ofx(end)
+    )
+}
+
+const OFX_FOOTER: &str = "</BANKTRANLIST>\n</STMTRS>\n</STMTTRNRS>\n</BANKMSGSRSV1>\n</OFX>\n";
+
+fn ofx_row(row: &ExportRow) -> String {
+    let (trn_type, sign) = match row.direction {
+        "in" => ("CREDIT", ""),
+        "out" => ("DEBIT", "-"),
+        _ => ("OTHER", ""),
+    };
+    let memo = [row.note.as_str(), row.memo.as_str()]
+        .iter()
+        .filter(|text| !text.is_empty())
+        .copied()
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    format!(
+        "<STMTTRN>\n<TRNTYPE>{}\n<DTPOSTED>{}\n<TRNAMT>{}{}\n<FITID>{}\n<NAME>{}\n<MEMO>{}\n</STMTTRN>\n",
+        trn_type,
+        row.ofx_date,
+        sign,
+        row.amount,
+        row.tx_id,
+        ofx_text(&row.counterparty),
+        ofx_text(&memo)
+    )
+}
+
+/*
+ * History of the caller oldest first, CHUNK_SIZE entries per call. The first chunk starts with the
+ * CSV header, OFX header or `[`, and the last one ends with the OFX footer or `]`.
+ */
+pub fn export_transactions(
+    format: ExportFormat,
+    range: ExportRange,
+    chunk: u64,
+    profile: Option<Principal>,
+) -> Result<ExportChunk, ExportError> {
+    // Without a profile users export their own history, members the business they work for
+    let account = staff::account(profile, Permission::Export)?;
+    let (history, account_id) = if is_user(&account) {
+        let pay_id = user::get_metadata(&account)
+            .map(|user| user.pay_id)
+            .unwrap_or_default();
+        (user::get_history(&account), pay_id)
+    } else {
+        let business = staff::actor(Some(account), Permission::Export)?.business;
+        let pay_id = business::get_metadata(&business)
+            .map(|business| business.pay_id)
+            .unwrap_or_default();
+        (business::get_transactions(&business), pay_id)
+    };
+
+    if let (Some(from), Some(to)) = (range.from, range.to) {
+        if from > to {
+            return Err(ExportError::InvalidRange);
+        }
+    }
+
+    let entries = history
+        .iter()
+        .filter(|entry| {
+            range.from.is_none_or(|from| entry.timestamp >= from)
+                && range.to.is_none_or(|to| entry.timestamp <= to)
+        })
+        .collect::<Vec<_>>();
+
+    let total_entries = entries.len() as u64;
+    // chunk comes from the client, usize is 32 bits on wasm32
+    let start = usize::try_from(chunk)
+        .ok()
+        .and_then(|chunk| chunk.checked_mul(CHUNK_SIZE))
+        .ok_or(ExportError::InvalidChunk)?;
+    if chunk > 0 && start >= entries.len() {
+        return Err(ExportError::ChunkOutOfRange);
+    }
+    let is_first = chunk == 0;
+    let is_last = start + CHUNK_SIZE >= entries.len();
+
+    let mut data = String::new();
+    if is_first {
+        match format {
+            ExportFormat::Csv => data.push_str(CSV_HEADER),
+            ExportFormat::Ofx => data.push_str(&ofx_header(&account_id, &range)),
+            ExportFormat::Json => data.push('['),
+        }
+    }
+
+    for (index, entry) in entries.iter().enumerate().skip(start).take(CHUNK_SIZE) {
+        let row = ExportRow::new(entry);
+        match format {
+            ExportFormat::Csv => data.push_str(&csv_row(&row)),
+            ExportFormat::Ofx => data.push_str(&ofx_row(&row)),
+            ExportFormat::Json => {
+                if index > 0 {
+                    data.push(',');
+                }
+                data.push_str(&json_row(&row));
+            }
+        }
+    }
+
+    if is_last {
+        match format {
+            ExportFormat::Csv => {}
+            ExportFormat::Ofx => data.push_str(OFX_FOOTER),
+            ExportFormat::Json => data.push(']'),
+        }
+    }
+
+    Ok(ExportChunk {
+        data,
+        total_entries,
+        next_chunk: if is_last { None } else { Some(chunk + 1) },
+    })
+}
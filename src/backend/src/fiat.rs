@@ -0,0 +1,299 @@
+use crate::business::is_business;
+use crate::error::FiatError;
+use crate::staff::{self, Permission};
+use crate::user::is_user;
+use crate::xrc::{
+    self, Asset, AssetClass, ExchangeRateError, GetExchangeRateRequest, GetExchangeRateResult,
+};
+use crate::{
+    timer, update_history_entry, CANISTER_CONFIG_MAP, FIAT_RATES_MAP, PENDING_RATES_MAP,
+    PREFERRED_CURRENCY_MAP, UNSUPPORTED_CURRENCIES_MAP,
+};
+use candid::{Decode, Encode, Nat, Principal};
+use ic_cdk::api::{is_controller, time};
+use ic_cdk::caller;
+use ic_stable_structures::storable::{Bound, Storable};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+const DEFAULT_CURRENCY: &str = "USD";
+const XRC_CONFIG_KEY: &str = "xrc";
+const MAINNET_XRC: &str = "uzr34-akd3s-xrdag-3ql62-ocgoh-ld2ao-tamcv-54e7j-krwgb-2gm4z-oqe";
+const LOOKUPS_PER_TICK: usize = 5;
+const MAX_ATTEMPTS: u32 = 3;
+const RETRY_BASE: u64 = 60_000_000_000; // 1 minute, doubled after every failed lookup
+const IN_FLIGHT_TIMEOUT: u64 = 300_000_000_000; // a lookup waiting for the XRC isn't picked again before this
+const CACHE_MINUTES: u64 = 60; // rates are kept this long for entries of the same minute, they're on the entries anyway
+
+// BTC price in the account's preferred currency when the entry was recorded
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct FiatValue {
+    currency: String,
+    rate: u64, // fiat per BTC, scaled by 10^decimals
+    decimals: u32,
+    timestamp: u64, // of the rate in nanoseconds
+}
+
+impl Storable for FiatValue {
+    const BOUND: Bound = Bound::Unbounded;
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+// A rate lookup and the (account, tx_id) entries waiting for it
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct PendingRate {
+    entries: Vec<(Principal, Nat)>,
+    attempts: u32,
+    next_attempt_at: u64,
+}
+
+impl Storable for PendingRate {
+    const BOUND: Bound = Bound::Unbounded;
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl PendingRate {
+    fn get(key: &String) -> Option<PendingRate> {
+        PENDING_RATES_MAP.with(|p| p.borrow().get(key))
+    }
+
+    fn insert(key: String, value: PendingRate) -> Option<PendingRate> {
+        PENDING_RATES_MAP.with(|p| p.borrow_mut().insert(key, value))
+    }
+
+    fn remove(key: &String) -> Option<PendingRate> {
+        PENDING_RATES_MAP.with(|p| p.borrow_mut().remove(key))
+    }
+}
+
+// XRC used for rates, a mock with the same interface can be set for local and test deployments
+fn xrc_canister() -> Principal {
+    CANISTER_CONFIG_MAP
+        .with(|p| p.borrow().get(&XRC_CONFIG_KEY.to_string()))
+        .unwrap_or_else(|| Principal::from_text(MAINNET_XRC).unwrap())
+}
+
+pub fn preferred_currency(principal: &Principal) -> String {
+    PREFERRED_CURRENCY_MAP
+        .with(|p| p.borrow().get(principal))
+        .unwrap_or_else(|| DEFAULT_CURRENCY.to_string())
+}
+
+enum RateLookup {
+    Found(FiatValue),
+    Unsupported, // the XRC doesn't know the currency
+    Failed,
+}
+
+fn is_unsupported(currency: &str) -> bool {
+    UNSUPPORTED_CURRENCIES_MAP.with(|p| p.borrow().contains_key(&currency.to_string()))
+}
+
+async fn get_rate(currency: &str, minute: u64) -> RateLookup {
+    let args = GetExchangeRateRequest {
+        base_asset: Asset {
+            symbol: "BTC".to_string(),
+            class: AssetClass::Cryptocurrency,
+        },
+        quote_asset: Asset {
+            symbol: currency.to_string(),
+            class: AssetClass::FiatCurrency,
+        },
+        timestamp: Some(minute * 60),
+    };
+
+    match xrc::get_exchange_rate(xrc_canister(), args).await {
+        Ok((GetExchangeRateResult::Ok(rate),)) => RateLookup::Found(FiatValue {
+            currency: currency.to_string(),
+            rate: rate.rate,
+            decimals: rate.metadata.decimals,
+            timestamp: rate.timestamp * 1_000_000_000,
+        }),
+        Ok((GetExchangeRateResult::Err(
+            ExchangeRateError::ForexQuoteAssetNotFound
+            | ExchangeRateError::CryptoQuoteAssetNotFound,
+        ),)) => RateLookup::Unsupported,
+        _ => RateLookup::Failed,
+    }
+}
+
+// XRC rates are per minute, entries recorded in the same minute share one lookup
+fn rate_key(currency: &str, timestamp: u64) -> String {
+    format!("{}/{:012}", currency, timestamp / 1_000_000_000 / 60)
+}
+
+fn split_rate_key(key: &str) -> (String, u64) {
+    let (currency, minute) = key.split_once('/').unwrap();
+    (currency.to_string(), minute.parse().unwrap())
+}
+
+// Drops the cached rates of currency older than CACHE_MINUTES
+fn prune_rates(currency: &str) {
+    let minute = time() / 1_000_000_000 / 60;
+    let end = format!("{}/{:012}", currency, minute.saturating_sub(CACHE_MINUTES));
+    FIAT_RATES_MAP.with(|p| {
+        let mut map = p.borrow_mut();
+        let keys = map
+            .range(format!("{}/", currency)..end)
+            .map(|(key, _)| key)
+            .collect::<Vec<_>>();
+        for key in keys {
+            map.remove(&key);
+        }
+    });
+}
+
+// The currency won't get a rate, entries waiting for one keep none
+fn mark_unsupported(currency: &str) {
+    UNSUPPORTED_CURRENCIES_MAP.with(|p| p.borrow_mut().insert(currency.to_string(), time()));
+    PENDING_RATES_MAP.with(|p| {
+        let mut map = p.borrow_mut();
+        let keys = map
+            .range(format!("{}/", currency)..format!("{}0", currency))
+            .map(|(key, _)| key)
+            .collect::<Vec<_>>();
+        for key in keys {
+            map.remove(&key);
+        }
+    });
+}
+
+/*
+ * Saves the rate at timestamp, in the preferred currency of each account, on the entries of tx_id.
+ * Called right after the entries are recorded. Cached rates are set at once, the others are looked up
+ * from the timer so recording never waits on the XRC. Entries whose rate can't be fetched keep none.
+ */
+pub fn queue_rates(tx_id: &Nat, principals: &[Principal], timestamp: u64) {
+    let now = time();
+    for principal in principals {
+        if !is_user(principal) && !is_business(principal) {
+            continue;
+        }
+        let currency = preferred_currency(principal);
+        if is_unsupported(&currency) {
+            continue;
+        }
+        let key = rate_key(&currency, timestamp);
+
+        if let Some(rate) = FIAT_RATES_MAP.with(|p| p.borrow().get(&key)) {
+            update_history_entry(principal, tx_id, |entry| entry.fiat = Some(rate));
+            continue;
+        }
+
+        let mut pending = PendingRate::get(&key).unwrap_or(PendingRate {
+            entries: Vec::new(),
+            attempts: 0,
+            next_attempt_at: now,
+        });
+        pending.entries.push((*principal, tx_id.clone()));
+        PendingRate::insert(key, pending);
+        timer::schedule();
+    }
+}
+
+pub fn next_lookup_at() -> Option<u64> {
+    PENDING_RATES_MAP.with(|p| {
+        p.borrow()
+            .iter()
+            .map(|(_, pending)| pending.next_attempt_at)
+            .min()
+    })
+}
+
+pub async fn lookup_due() {
+    let now = time();
+    let due = PENDING_RATES_MAP.with(|p| {
+        p.borrow()
+            .iter()
+            .filter(|(_, pending)| pending.next_attempt_at <= now)
+            .take(LOOKUPS_PER_TICK)
+            .map(|(key, _)| key)
+            .collect::<Vec<_>>()
+    });
+
+    for key in due {
+        let Some(mut pending) = PendingRate::get(&key) else {
+            continue;
+        };
+        // Not picked again by the next tick while the call is in flight
+        pending.next_attempt_at = now + IN_FLIGHT_TIMEOUT;
+        PendingRate::insert(key.clone(), pending);
+
+        let (currency, minute) = split_rate_key(&key);
+        let rate = get_rate(&currency, minute).await;
+
+        // Read again, entries of the same minute may have been queued during the call
+        let Some(mut pending) = PendingRate::get(&key) else {
+            continue;
+        };
+        match rate {
+            RateLookup::Found(rate) => {
+                for (principal, tx_id) in pending.entries {
+                    update_history_entry(&principal, &tx_id, |entry| {
+                        entry.fiat = Some(rate.clone())
+                    });
+                }
+                FIAT_RATES_MAP.with(|p| p.borrow_mut().insert(key.clone(), rate));
+                PendingRate::remove(&key);
+                prune_rates(&currency);
+            }
+            RateLookup::Unsupported => mark_unsupported(&currency),
+            RateLookup::Failed => {
+                pending.attempts += 1;
+                if pending.attempts >= MAX_ATTEMPTS {
+                    PendingRate::remove(&key);
+                } else {
+                    pending.next_attempt_at = time() + RETRY_BASE * (1 << (pending.attempts - 1));
+                    PendingRate::insert(key, pending);
+                }
+            }
+        }
+    }
+}
+
+pub fn get_preferred_currency(profile: Option<Principal>) -> Result<String, FiatError> {
+    let caller = staff::account(profile, Permission::ViewBusiness)?;
+    Ok(preferred_currency(&caller))
+}
+
+// ISO 4217 code supported by the XRC, like USD or EUR
+pub fn set_preferred_currency(
+    currency: String,
+    profile: Option<Principal>,
+) -> Result<(), FiatError> {
+    let caller = staff::account(profile, Permission::ManageSettings)?;
+
+    if !is_user(&caller) && !is_business(&caller) {
+        return Err(FiatError::AccountNotFound);
+    }
+    if currency.len() != 3 || !currency.chars().all(|c| c.is_ascii_uppercase()) {
+        return Err(FiatError::InvalidCurrency);
+    }
+    if is_unsupported(&currency) {
+        return Err(FiatError::UnsupportedCurrency);
+    }
+
+    PREFERRED_CURRENCY_MAP.with(|p| p.borrow_mut().insert(caller, currency));
+    Ok(())
+}
+
+pub fn set_exchange_rate_canister(canister: Principal) -> Result<(), FiatError> {
+    if !is_controller(&caller()) {
+        return Err(FiatError::NotAController);
+    }
+
+    CANISTER_CONFIG_MAP.with(|p| p.borrow_mut().insert(XRC_CONFIG_KEY.to_string(), canister));
+    Ok(())
+}
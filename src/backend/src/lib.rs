@@ -1,33 +1,121 @@
 use candid::{Nat, Principal};
+use ic_cdk::api::management_canister::http_request::{HttpResponse, TransformArgs};
 use ic_cdk::caller;
-use ic_cdk_macros::{query, update};
+use ic_cdk_macros::{post_upgrade, query, update};
 use ic_stable_structures::{
     memory_manager::{MemoryId, MemoryManager, VirtualMemory},
     DefaultMemoryImpl, StableBTreeMap,
 };
 use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
 use std::cell::RefCell;
 
 mod error;
 use error::{
-    AddBusinessError, AddMessageErr, CreateChatErr, FetchInitDataError, GetBusinessError,
-    MarkMessageReadErr, RecordRegPayTxErr, RecordTxErr, RequestPaymentError, SignUpError,
+    AddBusinessError, AddMessageErr, AnalyticsError, AppRequestError, BlockError,
+    ChatEncryptionError, CheckoutError, ContactError, CreateChatErr, CustomerError, DirectoryError,
+    EscrowError, ExportError, FetchInitDataError, FiatError, GetBusinessError, MandateError,
+    MarkMessageReadErr, NotificationError, PaymentLinkError, RecordRefundErr, RecordRegPayTxErr,
+    RecordTxErr, RequestPaymentError, SearchError, SetVerifiedError, SignUpError,
+    SpendingPolicyError, StaffError, SyncError, UpdateMessageErr, WebPushError, WebhookError,
 };
 
 mod business;
 use business::{
-    is_business, BtoBTxArg, Business, BusinessSignUpArgs, BusinessTxArg, BusinessUnknownTxArg,
+    is_business, BtoBTxArg, Business, BusinessCategory, BusinessSignUpArgs, BusinessTxArg,
+    BusinessUnknownTxArg,
 };
 
 mod user;
 use user::{
-    is_user, BusinessInUser, Chat, ChatId, Message, PayIdOrPrincipal, RecordReqPayTxArg, ReqPayArg,
-    RequestPayment, User, UserBusinessTxArg, UserData, UserSignUpArgs, UserToUserTxArg,
-    UserUnknownTxArg,
+    is_user, BusinessInUser, Chat, ChatId, Message, MessageId, MyChat, PayIdOrPrincipal,
+    RecordReqPayTxArg, ReqPayArg, RequestPayment, User, UserBusinessTxArg, UserData,
+    UserSignUpArgs, UserToUserTxArg, UserUnknownTxArg,
 };
 
+mod sync;
+use sync::{ChangeLog, SyncResponse};
+
+mod search;
+use search::{Postings, SearchArgs, SearchResponse};
+
+mod directory;
+use directory::{BusinessDirectoryPage, DirectorySettings, PayIdProfile};
+
+mod contacts;
+use contacts::{ContactBook, ContactView, UpdateContactArg};
+
+mod analytics;
+use analytics::{AnalyticsBucket, AnalyticsRange, BusinessAnalytics, DayStats};
+
+mod customers;
+use customers::{Customer, CustomerPage, UpdateCustomerArg};
+
+mod export;
+use export::{ExportChunk, ExportFormat, ExportRange};
+
+mod xrc;
+
+mod fiat;
+use fiat::{FiatValue, PendingRate};
+
+mod refund;
+use refund::RefundState;
+
+mod checkout;
+use checkout::CheckoutSession;
+
+mod timer;
+
+mod webhooks;
+use webhooks::{Delivery, DeliveryPage, WebhookCreated, WebhookEvent, WebhookView, Webhooks};
+
+mod app_requests;
+use app_requests::{AllowedApp, AppPaymentRequest, AppRequestPage};
+
+mod mandates;
+use mandates::{CreateMandateArg, Mandate};
+
+mod escrow;
+use escrow::{CreateEscrowArg, Escrow, EscrowResolution};
+
+mod payment_links;
+use payment_links::{
+    CreatePaymentLinkArg, LinkPayment, LinkPaymentPage, PaymentLink, PaymentLinkDetails,
+    PaymentLinkView,
+};
+
+mod policies;
+use policies::{
+    PolicyDecision, PolicyLogPage, SendApproval, SpendingPolicy, SpendingPolicyArg,
+    SpendingPolicyView,
+};
+
+mod notifications;
+use notifications::{
+    Notification, NotificationKind, NotificationPage, NotificationState, ScheduledNotice,
+};
+
+mod profiles;
+use profiles::{OwnedProfiles, ProfileAccount, ProfilesData};
+
+mod staff;
+use staff::{AuditEntry, AuditPage, Member, Membership, Permission, Role, Staff};
+
+mod safety;
+use safety::{AccountSafety, SafetySettings};
+
+mod web_push;
+use web_push::{
+    AddPushSubscriptionArg, PendingPush, PushSubscriptionView, PushSubscriptions, VapidKey,
+};
+
+mod vetkd;
+
+mod chat_keys;
+
 mod ck_btc_ledger;
-use ck_btc_ledger::GetTransactionsResponse;
+use ck_btc_ledger::{Account, GetTransactionsResponse};
 
 pub type Memory = VirtualMemory<DefaultMemoryImpl>;
 
@@ -39,6 +127,55 @@ const BUSINESS_IN_USER_MAP_MEMORY_ID: MemoryId = MemoryId::new(4);
 const TRANSACTION_HISTORY_MAP_MEMORY_ID: MemoryId = MemoryId::new(5);
 
 const BI_LOOKUP_MAP_MEMORY_ID: MemoryId = MemoryId::new(6);
+const CHANGE_LOG_MAP_MEMORY_ID: MemoryId = MemoryId::new(7);
+const SEARCH_INDEX_MAP_MEMORY_ID: MemoryId = MemoryId::new(8);
+const DIRECTORY_SETTINGS_MAP_MEMORY_ID: MemoryId = MemoryId::new(9);
+const BUSINESS_DIRECTORY_MAP_MEMORY_ID: MemoryId = MemoryId::new(10);
+const CONTACTS_MAP_MEMORY_ID: MemoryId = MemoryId::new(11);
+const SAFETY_MAP_MEMORY_ID: MemoryId = MemoryId::new(12);
+const ANALYTICS_MAP_MEMORY_ID: MemoryId = MemoryId::new(13);
+const CUSTOMERS_MAP_MEMORY_ID: MemoryId = MemoryId::new(14);
+const REFUNDS_MAP_MEMORY_ID: MemoryId = MemoryId::new(15);
+const PREFERRED_CURRENCY_MAP_MEMORY_ID: MemoryId = MemoryId::new(16);
+const CANISTER_CONFIG_MAP_MEMORY_ID: MemoryId = MemoryId::new(17);
+const STAFF_MAP_MEMORY_ID: MemoryId = MemoryId::new(18);
+const MEMBERSHIP_MAP_MEMORY_ID: MemoryId = MemoryId::new(19);
+const AUDIT_MAP_MEMORY_ID: MemoryId = MemoryId::new(20);
+const OWNED_PROFILES_MAP_MEMORY_ID: MemoryId = MemoryId::new(21);
+const PROFILE_ACCOUNTS_MAP_MEMORY_ID: MemoryId = MemoryId::new(22);
+const CHECKOUTS_MAP_MEMORY_ID: MemoryId = MemoryId::new(23);
+const OPEN_CHECKOUTS_MAP_MEMORY_ID: MemoryId = MemoryId::new(24);
+const WEBHOOKS_MAP_MEMORY_ID: MemoryId = MemoryId::new(25);
+const DELIVERIES_MAP_MEMORY_ID: MemoryId = MemoryId::new(26);
+const PENDING_DELIVERIES_MAP_MEMORY_ID: MemoryId = MemoryId::new(27);
+const ALLOWED_APPS_MAP_MEMORY_ID: MemoryId = MemoryId::new(28);
+const APP_REQUESTS_MAP_MEMORY_ID: MemoryId = MemoryId::new(29);
+const USER_APP_REQUESTS_MAP_MEMORY_ID: MemoryId = MemoryId::new(30);
+const MANDATES_MAP_MEMORY_ID: MemoryId = MemoryId::new(31);
+const ACTIVE_MANDATES_MAP_MEMORY_ID: MemoryId = MemoryId::new(32);
+const USER_MANDATES_MAP_MEMORY_ID: MemoryId = MemoryId::new(33);
+const ESCROWS_MAP_MEMORY_ID: MemoryId = MemoryId::new(34);
+const ESCROW_DEADLINES_MAP_MEMORY_ID: MemoryId = MemoryId::new(35);
+const USER_ESCROWS_MAP_MEMORY_ID: MemoryId = MemoryId::new(36);
+const PAYMENT_LINKS_MAP_MEMORY_ID: MemoryId = MemoryId::new(37);
+const LINK_PAYMENTS_MAP_MEMORY_ID: MemoryId = MemoryId::new(38);
+const OWNER_LINKS_MAP_MEMORY_ID: MemoryId = MemoryId::new(39);
+const SPENDING_POLICIES_MAP_MEMORY_ID: MemoryId = MemoryId::new(40);
+const SEND_APPROVALS_MAP_MEMORY_ID: MemoryId = MemoryId::new(41);
+const USER_APPROVALS_MAP_MEMORY_ID: MemoryId = MemoryId::new(42);
+const POLICY_LOG_MAP_MEMORY_ID: MemoryId = MemoryId::new(43);
+const NOTIFICATIONS_MAP_MEMORY_ID: MemoryId = MemoryId::new(44);
+const NOTIFICATION_STATE_MAP_MEMORY_ID: MemoryId = MemoryId::new(45);
+const NOTIFICATION_SCHEDULE_MAP_MEMORY_ID: MemoryId = MemoryId::new(46);
+const VAPID_KEY_MAP_MEMORY_ID: MemoryId = MemoryId::new(47);
+const PUSH_SUBSCRIPTIONS_MAP_MEMORY_ID: MemoryId = MemoryId::new(48);
+const PENDING_PUSHES_MAP_MEMORY_ID: MemoryId = MemoryId::new(49);
+const FIAT_RATES_MAP_MEMORY_ID: MemoryId = MemoryId::new(50);
+const PENDING_RATES_MAP_MEMORY_ID: MemoryId = MemoryId::new(51);
+const PUSH_SCHEDULE_MAP_MEMORY_ID: MemoryId = MemoryId::new(52);
+const FEED_PUSHES_MAP_MEMORY_ID: MemoryId = MemoryId::new(53);
+const UNSUPPORTED_CURRENCIES_MAP_MEMORY_ID: MemoryId = MemoryId::new(54);
+const DELIVERY_SCHEDULE_MAP_MEMORY_ID: MemoryId = MemoryId::new(55);
 
 thread_local! {
     static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> =
@@ -85,6 +222,326 @@ thread_local! {
             MEMORY_MANAGER.with(|m| m.borrow().get(BI_LOOKUP_MAP_MEMORY_ID)),
         )
     );
+
+    static CHANGE_LOG_MAP: RefCell<StableBTreeMap<Principal, ChangeLog, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(CHANGE_LOG_MAP_MEMORY_ID)),
+        )
+    );
+
+    static SEARCH_INDEX_MAP: RefCell<StableBTreeMap<String, Postings, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(SEARCH_INDEX_MAP_MEMORY_ID)),
+        )
+    );
+
+    static DIRECTORY_SETTINGS_MAP: RefCell<StableBTreeMap<Principal, DirectorySettings, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(DIRECTORY_SETTINGS_MAP_MEMORY_ID)),
+        )
+    );
+
+    static BUSINESS_DIRECTORY_MAP: RefCell<StableBTreeMap<String, Principal, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(BUSINESS_DIRECTORY_MAP_MEMORY_ID)),
+        )
+    );
+
+    static CONTACTS_MAP: RefCell<StableBTreeMap<Principal, ContactBook, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(CONTACTS_MAP_MEMORY_ID)),
+        )
+    );
+
+    static SAFETY_MAP: RefCell<StableBTreeMap<Principal, AccountSafety, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(SAFETY_MAP_MEMORY_ID)),
+        )
+    );
+
+    static ANALYTICS_MAP: RefCell<StableBTreeMap<String, DayStats, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(ANALYTICS_MAP_MEMORY_ID)),
+        )
+    );
+
+    static CUSTOMERS_MAP: RefCell<StableBTreeMap<String, Customer, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(CUSTOMERS_MAP_MEMORY_ID)),
+        )
+    );
+
+    static REFUNDS_MAP: RefCell<StableBTreeMap<BI, RefundState, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(REFUNDS_MAP_MEMORY_ID)),
+        )
+    );
+
+    static PREFERRED_CURRENCY_MAP: RefCell<StableBTreeMap<Principal, String, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(PREFERRED_CURRENCY_MAP_MEMORY_ID)),
+        )
+    );
+
+    // Canisters the backend calls that can be changed by a controller, keyed by name
+    static CANISTER_CONFIG_MAP: RefCell<StableBTreeMap<String, Principal, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(CANISTER_CONFIG_MAP_MEMORY_ID)),
+        )
+    );
+
+    static STAFF_MAP: RefCell<StableBTreeMap<Principal, Staff, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(STAFF_MAP_MEMORY_ID)),
+        )
+    );
+
+    // Member principal to the business it works for
+    static MEMBERSHIP_MAP: RefCell<StableBTreeMap<Principal, Principal, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MEMBERSHIP_MAP_MEMORY_ID)),
+        )
+    );
+
+    static AUDIT_MAP: RefCell<StableBTreeMap<String, AuditEntry, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(AUDIT_MAP_MEMORY_ID)),
+        )
+    );
+
+    // Identity to the business profiles it owns
+    static OWNED_PROFILES_MAP: RefCell<StableBTreeMap<Principal, OwnedProfiles, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(OWNED_PROFILES_MAP_MEMORY_ID)),
+        )
+    );
+
+    // Business profile principal to the owner subaccount it's paid on
+    static PROFILE_ACCOUNTS_MAP: RefCell<StableBTreeMap<Principal, ProfileAccount, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(PROFILE_ACCOUNTS_MAP_MEMORY_ID)),
+        )
+    );
+
+    static CHECKOUTS_MAP: RefCell<StableBTreeMap<u64, CheckoutSession, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(CHECKOUTS_MAP_MEMORY_ID)),
+        )
+    );
+
+    // Session id to expires_at of the sessions not paid yet, scanned by the timer
+    static OPEN_CHECKOUTS_MAP: RefCell<StableBTreeMap<u64, u64, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(OPEN_CHECKOUTS_MAP_MEMORY_ID)),
+        )
+    );
+
+    static WEBHOOKS_MAP: RefCell<StableBTreeMap<Principal, Webhooks, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(WEBHOOKS_MAP_MEMORY_ID)),
+        )
+    );
+
+    // Keyed "{business}/{index}" like the audit trail
+    static DELIVERIES_MAP: RefCell<StableBTreeMap<String, Delivery, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(DELIVERIES_MAP_MEMORY_ID)),
+        )
+    );
+
+    // Delivery key to its next attempt, only deliveries still to be sent
+    static PENDING_DELIVERIES_MAP: RefCell<StableBTreeMap<String, u64, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(PENDING_DELIVERIES_MAP_MEMORY_ID)),
+        )
+    );
+
+    // Keyed "{next attempt}/{delivery key}" to the delivery key, pending deliveries in the order they're due
+    static DELIVERY_SCHEDULE_MAP: RefCell<StableBTreeMap<String, String, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(DELIVERY_SCHEDULE_MAP_MEMORY_ID)),
+        )
+    );
+
+    // Canisters allowed to call request_payment_from
+    static ALLOWED_APPS_MAP: RefCell<StableBTreeMap<Principal, AllowedApp, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(ALLOWED_APPS_MAP_MEMORY_ID)),
+        )
+    );
+
+    static APP_REQUESTS_MAP: RefCell<StableBTreeMap<u64, AppPaymentRequest, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(APP_REQUESTS_MAP_MEMORY_ID)),
+        )
+    );
+
+    // "{user}/{id}" to the id of an app payment request posted to the user
+    static USER_APP_REQUESTS_MAP: RefCell<StableBTreeMap<String, u64, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(USER_APP_REQUESTS_MAP_MEMORY_ID)),
+        )
+    );
+
+    static MANDATES_MAP: RefCell<StableBTreeMap<u64, Mandate, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MANDATES_MAP_MEMORY_ID)),
+        )
+    );
+
+    // Active mandate id to its next run
+    static ACTIVE_MANDATES_MAP: RefCell<StableBTreeMap<u64, u64, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(ACTIVE_MANDATES_MAP_MEMORY_ID)),
+        )
+    );
+
+    // "{payer or payee}/{id}" to the mandate id
+    static USER_MANDATES_MAP: RefCell<StableBTreeMap<String, u64, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(USER_MANDATES_MAP_MEMORY_ID)),
+        )
+    );
+
+    static ESCROWS_MAP: RefCell<StableBTreeMap<u64, Escrow, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(ESCROWS_MAP_MEMORY_ID)),
+        )
+    );
+
+    // Escrow id to its deadline, only escrows that are waiting for funds or a release
+    static ESCROW_DEADLINES_MAP: RefCell<StableBTreeMap<u64, u64, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(ESCROW_DEADLINES_MAP_MEMORY_ID)),
+        )
+    );
+
+    // "{payer or payee}/{id}" to the escrow id
+    static USER_ESCROWS_MAP: RefCell<StableBTreeMap<String, u64, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(USER_ESCROWS_MAP_MEMORY_ID)),
+        )
+    );
+
+    static PAYMENT_LINKS_MAP: RefCell<StableBTreeMap<u64, PaymentLink, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(PAYMENT_LINKS_MAP_MEMORY_ID)),
+        )
+    );
+
+    // Keyed "{link id}/{index}", the payment log of every link
+    static LINK_PAYMENTS_MAP: RefCell<StableBTreeMap<String, LinkPayment, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(LINK_PAYMENTS_MAP_MEMORY_ID)),
+        )
+    );
+
+    // "{owner}/{id}" to the link id
+    static OWNER_LINKS_MAP: RefCell<StableBTreeMap<String, u64, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(OWNER_LINKS_MAP_MEMORY_ID)),
+        )
+    );
+
+    static SPENDING_POLICIES_MAP: RefCell<StableBTreeMap<Principal, SpendingPolicy, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(SPENDING_POLICIES_MAP_MEMORY_ID)),
+        )
+    );
+
+    static SEND_APPROVALS_MAP: RefCell<StableBTreeMap<u64, SendApproval, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(SEND_APPROVALS_MAP_MEMORY_ID)),
+        )
+    );
+
+    // "{principal}/{id}" to the approval id, for both the account and the approver
+    static USER_APPROVALS_MAP: RefCell<StableBTreeMap<String, u64, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(USER_APPROVALS_MAP_MEMORY_ID)),
+        )
+    );
+
+    // Keyed "{principal}/{index}", every decision taken by the policy of an account
+    static POLICY_LOG_MAP: RefCell<StableBTreeMap<String, PolicyDecision, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(POLICY_LOG_MAP_MEMORY_ID)),
+        )
+    );
+
+    // Keyed "{principal}/{id}", the notification feed of every account
+    static NOTIFICATIONS_MAP: RefCell<StableBTreeMap<String, Notification, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(NOTIFICATIONS_MAP_MEMORY_ID)),
+        )
+    );
+
+    static NOTIFICATION_STATE_MAP: RefCell<StableBTreeMap<Principal, NotificationState, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(NOTIFICATION_STATE_MAP_MEMORY_ID)),
+        )
+    );
+
+    // Keyed "{at}/...", notices the timer sends once their time comes
+    static NOTIFICATION_SCHEDULE_MAP: RefCell<StableBTreeMap<String, ScheduledNotice, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(NOTIFICATION_SCHEDULE_MAP_MEMORY_ID)),
+        )
+    );
+
+    static VAPID_KEY_MAP: RefCell<StableBTreeMap<String, VapidKey, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(VAPID_KEY_MAP_MEMORY_ID)),
+        )
+    );
+
+    static PUSH_SUBSCRIPTIONS_MAP: RefCell<StableBTreeMap<Principal, PushSubscriptions, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(PUSH_SUBSCRIPTIONS_MAP_MEMORY_ID)),
+        )
+    );
+
+    // Pushes waiting for their outcall, removed once delivered or given up
+    static PENDING_PUSHES_MAP: RefCell<StableBTreeMap<u64, PendingPush, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(PENDING_PUSHES_MAP_MEMORY_ID)),
+        )
+    );
+
+    // Keyed "{next attempt}/{id}" to the id, the pending pushes in the order they're due
+    static PUSH_SCHEDULE_MAP: RefCell<StableBTreeMap<String, u64, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(PUSH_SCHEDULE_MAP_MEMORY_ID)),
+        )
+    );
+
+    // Keyed "{principal}/{id}" to the id, the pending pushes of each feed
+    static FEED_PUSHES_MAP: RefCell<StableBTreeMap<String, u64, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(FEED_PUSHES_MAP_MEMORY_ID)),
+        )
+    );
+
+    // "{currency}/{minute:012}" to the XRC rate of that minute, kept for fiat::CACHE_MINUTES
+    static FIAT_RATES_MAP: RefCell<StableBTreeMap<String, FiatValue, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(FIAT_RATES_MAP_MEMORY_ID)),
+        )
+    );
+
+    // Rates not cached yet with the entries waiting for them, same keys as FIAT_RATES_MAP
+    static PENDING_RATES_MAP: RefCell<StableBTreeMap<String, PendingRate, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(PENDING_RATES_MAP_MEMORY_ID)),
+        )
+    );
+
+    // Currencies the XRC answered it has no rate for, to when it did, they are never looked up again
+    static UNSUPPORTED_CURRENCIES_MAP: RefCell<StableBTreeMap<String, u64, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(UNSUPPORTED_CURRENCIES_MAP_MEMORY_ID)),
+        )
+    );
 }
 
 pub struct PayIds;
@@ -123,6 +580,55 @@ use std::borrow::Cow;
 pub enum TxKind {
     Receive,
     Sends,
+    SendsRefund { original_tx_id: Nat }, // refund of a tx received earlier
+    ReceiveRefund { original_tx_id: Nat }, // refund of a tx sent earlier
+    Mint,                                // BTC deposited, ckBTC minted to the account
+    Burn,                                // ckBTC burned to withdraw BTC
+    Approve { expires_at: Option<u64> }, // allowance given to the other account
+    Allowance { expires_at: Option<u64> }, // allowance received from the other account
+}
+
+impl TxKind {
+    // Kind of the same tx in the history of the other party
+    pub fn counterpart(&self) -> TxKind {
+        match self {
+            TxKind::Receive => TxKind::Sends,
+            TxKind::Sends => TxKind::Receive,
+            TxKind::SendsRefund { original_tx_id } => TxKind::ReceiveRefund {
+                original_tx_id: original_tx_id.clone(),
+            },
+            TxKind::ReceiveRefund { original_tx_id } => TxKind::SendsRefund {
+                original_tx_id: original_tx_id.clone(),
+            },
+            TxKind::Approve { expires_at } => TxKind::Allowance {
+                expires_at: *expires_at,
+            },
+            TxKind::Allowance { expires_at } => TxKind::Approve {
+                expires_at: *expires_at,
+            },
+            // only the account itself is involved
+            TxKind::Mint => TxKind::Mint,
+            TxKind::Burn => TxKind::Burn,
+        }
+    }
+
+    pub fn is_outgoing(&self) -> bool {
+        matches!(
+            self,
+            TxKind::Sends | TxKind::SendsRefund { .. } | TxKind::Burn
+        )
+    }
+
+    // Funds moved between two accounts, mints, burns and approvals aren't
+    pub fn is_transfer(&self) -> bool {
+        matches!(
+            self,
+            TxKind::Receive
+                | TxKind::Sends
+                | TxKind::SendsRefund { .. }
+                | TxKind::ReceiveRefund { .. }
+        )
+    }
 }
 
 // Used for storing Tx History both in user and Business
@@ -135,6 +641,9 @@ pub struct TransactionEntry {
     timestamp: u64,
     amount: candid::Nat,
     note: Option<String>,
+    refund: Option<RefundState>, // set on the original entry once it has been refunded
+    fee: Option<candid::Nat>,    // ledger fee paid by the sender
+    fiat: Option<FiatValue>,     // BTC rate in the account's preferred currency at payment time
 }
 
 pub type TxHistory = Vec<TransactionEntry>;
@@ -146,7 +655,7 @@ pub struct StoreHistory {
 
 impl Storable for StoreHistory {
     const BOUND: Bound = Bound::Unbounded;
-    fn to_bytes(&self) -> Cow<[u8]> {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
         Cow::Owned(Encode!(self).unwrap())
     }
 
@@ -172,7 +681,7 @@ pub struct BI(Nat);
 impl Storable for BI {
     const BOUND: Bound = Bound::Unbounded;
 
-    fn to_bytes(&self) -> Cow<[u8]> {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
         Cow::Owned(Encode!(self).unwrap())
     }
 
@@ -184,12 +693,13 @@ impl Storable for BI {
 pub struct TxInfo {
     from: Option<Principal>,
     to: Option<Principal>,
+    memo: Option<ByteBuf>, // ledger memo, not set for transactions recorded before it was kept
 }
 
 impl Storable for TxInfo {
     const BOUND: Bound = Bound::Unbounded;
 
-    fn to_bytes(&self) -> Cow<[u8]> {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
         Cow::Owned(Encode!(self).unwrap())
     }
 
@@ -230,15 +740,21 @@ pub fn sign_up(args: SignUpArg) -> Result<(), SignUpError> {
     }
 }
 
+// Business endpoints take the business profile to act for, see staff::actor_for
 #[query]
-pub fn get_business() -> Result<Business, GetBusinessError> {
-    business::ic_get_business()
+pub fn get_business(profile: Option<Principal>) -> Result<Business, GetBusinessError> {
+    business::ic_get_business(profile)
 }
 
 #[query]
-pub fn get_my_chats() -> Vec<Chat> {
+pub fn get_my_chats() -> Vec<MyChat> {
     user::get_my_chats()
 }
+
+#[query]
+pub fn get_unread_count() -> u64 {
+    user::get_unread_count()
+}
 #[query]
 pub fn get_user() -> Option<User> {
     user::get_user()
@@ -251,34 +767,125 @@ pub fn create_chat(participant_id: user::PayIdOrPrincipal) -> Result<Chat, Creat
 
 #[update]
 pub fn add_message(chat_id: ChatId, content: String) -> Result<Message, AddMessageErr> {
-    user::add_message(chat_id, content)
+    user::add_message(chat_id, content, None)
 }
 
 #[update]
-pub fn mark_message_read(chat_id: ChatId) -> Result<(), MarkMessageReadErr> {
-    user::mark_message_read(chat_id)
+pub fn reply_to_message(
+    chat_id: ChatId,
+    reply_to: MessageId,
+    content: String,
+) -> Result<Message, AddMessageErr> {
+    user::add_message(chat_id, content, Some(reply_to))
 }
 
-#[query]
-pub fn get_chat(chat_id: ChatId) -> Option<Chat> {
-    user::get_chat(chat_id)
+// Ciphertext encrypted with the chat key, reply_to is None for a plain message
+#[update]
+pub fn add_encrypted_message(
+    chat_id: ChatId,
+    ciphertext: ByteBuf,
+    reply_to: Option<MessageId>,
+) -> Result<Message, AddMessageErr> {
+    user::add_encrypted_message(chat_id, ciphertext, reply_to)
 }
 
-#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
-pub enum DataResponse {
-    NotSignUp,
-    User(UserData),
-    Business(Business),
+// Turns on end-to-end encryption for a chat, new messages have to be sent with add_encrypted_message
+#[update]
+pub fn enable_chat_encryption(chat_id: ChatId) -> Result<Chat, ChatEncryptionError> {
+    user::enable_chat_encryption(chat_id)
 }
 
-#[query]
-pub fn fetch_initial_data() -> Result<DataResponse, FetchInitDataError> {
-    let caller = caller();
-
-    if is_business(&caller) {
-        Ok(DataResponse::Business(business::fetch_init_business_data()))
+// vetKD public key to verify chat keys with, see chat_keys
+#[update]
+pub async fn get_chat_key_public_key() -> Result<ByteBuf, ChatEncryptionError> {
+    chat_keys::get_chat_key_public_key().await
+}
+
+// Chat key encrypted under the caller's transport public key
+#[update]
+pub async fn get_encrypted_chat_key(
+    chat_id: ChatId,
+    transport_public_key: ByteBuf,
+) -> Result<ByteBuf, ChatEncryptionError> {
+    chat_keys::get_encrypted_chat_key(chat_id, transport_public_key).await
+}
+
+#[update]
+pub fn edit_message(
+    chat_id: ChatId,
+    message_id: MessageId,
+    content: String,
+) -> Result<Message, UpdateMessageErr> {
+    user::edit_message(chat_id, message_id, content)
+}
+
+#[update]
+pub fn delete_message(chat_id: ChatId, message_id: MessageId) -> Result<(), UpdateMessageErr> {
+    user::delete_message(chat_id, message_id)
+}
+
+#[update]
+pub fn react_to_message(
+    chat_id: ChatId,
+    message_id: MessageId,
+    emoji: Option<String>,
+) -> Result<Message, UpdateMessageErr> {
+    user::react_to_message(chat_id, message_id, emoji)
+}
+
+#[update]
+pub fn mark_message_read(chat_id: ChatId) -> Result<(), MarkMessageReadErr> {
+    user::mark_message_read(chat_id)
+}
+
+#[query]
+pub fn get_chat(chat_id: ChatId) -> Option<Chat> {
+    user::get_chat(chat_id)
+}
+
+// Changes since the given version, see sync::sync
+#[query]
+pub fn sync(since: u64, profile: Option<Principal>) -> Result<SyncResponse, SyncError> {
+    sync::sync(since, profile)
+}
+
+#[query]
+pub fn search(args: SearchArgs, profile: Option<Principal>) -> Result<SearchResponse, SearchError> {
+    search::search(args, profile)
+}
+
+// Index data recorded before search existed, only needed once per account
+#[update]
+pub fn rebuild_search_index(profile: Option<Principal>) -> Result<(), SearchError> {
+    search::rebuild_search_index(profile)
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub enum DataResponse {
+    NotSignUp,
+    User(UserData),
+    Business(Business),
+    Profiles(ProfilesData), // identities that own profiles besides their own account
+}
+
+#[query]
+pub fn fetch_initial_data() -> Result<DataResponse, FetchInitDataError> {
+    let caller = caller();
+
+    if profiles::has_profiles(&caller) {
+        Ok(DataResponse::Profiles(profiles::fetch_profiles(
+            &caller, true,
+        )))
+    } else if is_business(&caller) {
+        Ok(DataResponse::Business(business::fetch_init_business_data(
+            &caller,
+        )))
     } else if is_user(&caller) {
         Ok(DataResponse::User(user::fetch_init_user_data()))
+    } else if let Ok(actor) = staff::actor(None, Permission::ViewBusiness) {
+        Ok(DataResponse::Business(business::fetch_init_business_data(
+            &actor.business,
+        )))
     } else {
         if caller == Principal::anonymous() {
             Err(FetchInitDataError::AnonymousCaller)
@@ -292,10 +899,20 @@ pub fn fetch_initial_data() -> Result<DataResponse, FetchInitDataError> {
 pub fn fetch_data() -> Result<DataResponse, FetchInitDataError> {
     let caller = caller();
 
-    if is_business(&caller) {
-        Ok(DataResponse::Business(business::fetch_business_data()))
+    if profiles::has_profiles(&caller) {
+        Ok(DataResponse::Profiles(profiles::fetch_profiles(
+            &caller, false,
+        )))
+    } else if is_business(&caller) {
+        Ok(DataResponse::Business(business::fetch_business_data(
+            &caller,
+        )))
     } else if is_user(&caller) {
         Ok(DataResponse::User(user::fetch_user_data()))
+    } else if let Ok(actor) = staff::actor(None, Permission::ViewBusiness) {
+        Ok(DataResponse::Business(business::fetch_business_data(
+            &actor.business,
+        )))
     } else {
         if caller == Principal::anonymous() {
             Err(FetchInitDataError::AnonymousCaller)
@@ -310,6 +927,16 @@ pub fn user_add_business(arg: PayIdOrPrincipal) -> Result<BusinessInUser, AddBus
     user::user_add_business(arg)
 }
 
+// Records a transfer from a business back to a customer as a refund of original_tx_id
+#[update]
+pub async fn record_refund(
+    tx_id: candid::Nat,
+    original_tx_id: candid::Nat,
+    note: Option<String>,
+) -> Result<RefundState, RecordRefundErr> {
+    refund::record_refund(tx_id, original_tx_id, note).await
+}
+
 #[update]
 pub async fn record_xfer_transaction(
     tx_id: candid::Nat,
@@ -325,14 +952,29 @@ pub async fn record_xfer_transaction(
             RecordTxErr::InterCanisterCall(format!("get_transactions failed {:?}", err))
         })?;
 
+    let ledger_tx = inspect_transaction(tx_id.clone(), get_tx_response)?;
+
+    // Checked again, the same tx could have been recorded during the call
+    if BI::contains_key(tx_id.clone()) {
+        return Err(RecordTxErr::AlreadyRecorded);
+    }
+    let parties = ledger_tx.parties();
+    let recorded_at = ledger_tx.timestamp();
+
     let TransferTx {
         from,
         to,
         timestamp,
         amount,
-    } = match inspect_xfer_transaction(tx_id.clone(), get_tx_response) {
-        Ok(xfer_tx) => xfer_tx,
-        Err(err) => return Err(err),
+        fee,
+        memo,
+    } = match ledger_tx {
+        LedgerTx::Transfer(xfer_tx) => xfer_tx,
+        LedgerTx::Other(other) => {
+            record_ledger_transaction(tx_id.clone(), other, note)?;
+            fiat::queue_rates(&tx_id, &parties, recorded_at);
+            return Ok(());
+        }
     };
 
     let is_from_is_user = is_user(&from);
@@ -340,6 +982,13 @@ pub async fn record_xfer_transaction(
     let is_from_is_busi = is_business(&from);
     let is_to_is_busi = is_business(&to);
 
+    checkout::record_payment(&tx_id, from, to, &amount, memo.as_ref(), timestamp);
+    app_requests::record_payment(&tx_id, from, to, &amount, memo.as_ref(), timestamp);
+    payment_links::record_payment(&tx_id, from, to, &amount, memo.as_ref(), timestamp);
+    if is_to_is_user || is_to_is_busi {
+        notifications::payment_received(to, from, &tx_id, &amount);
+    }
+
     if is_from_is_user && is_to_is_user {
         // record both
         user::add_user_to_user_transaction(UserToUserTxArg {
@@ -349,6 +998,7 @@ pub async fn record_xfer_transaction(
             note,
             amount,
             tx_id: tx_id.clone(),
+            fee: fee.clone(),
         });
     } else if is_from_is_user && is_to_is_busi {
         // record both
@@ -359,10 +1009,12 @@ pub async fn record_xfer_transaction(
             amount: amount.clone(),
             timestamp,
             tx_id: tx_id.clone(),
+            fee: fee.clone(),
         });
 
         user::add_user_business_transaction(UserBusinessTxArg {
             tx_id: tx_id.clone(),
+            fee: fee.clone(),
             amount,
             timestamp,
             b_principal: to,
@@ -380,10 +1032,12 @@ pub async fn record_xfer_transaction(
             amount: amount.clone(),
             timestamp,
             tx_id: tx_id.clone(),
+            fee: fee.clone(),
         });
 
         user::add_user_business_transaction(UserBusinessTxArg {
             tx_id: tx_id.clone(),
+            fee: fee.clone(),
             amount,
             timestamp,
             b_principal: from,
@@ -400,12 +1054,15 @@ pub async fn record_xfer_transaction(
             note,
             amount,
             tx_id: tx_id.clone(),
+            fee: fee.clone(),
+            tx_kind: TxKind::Sends,
         })
     } else if is_from_is_user || is_from_is_busi {
         // if from account exist
         if is_from_is_user {
             user::add_unknown_transaction(UserUnknownTxArg {
                 tx_id: tx_id.clone(),
+                fee: fee.clone(),
                 u_principal: from,
                 unknown: to,
                 amount,
@@ -416,6 +1073,7 @@ pub async fn record_xfer_transaction(
         } else {
             business::add_unknown_transaction(BusinessUnknownTxArg {
                 tx_id: tx_id.clone(),
+                fee: fee.clone(),
                 b_principal: from,
                 unknown: to,
                 amount,
@@ -429,6 +1087,7 @@ pub async fn record_xfer_transaction(
         if is_to_is_user {
             user::add_unknown_transaction(UserUnknownTxArg {
                 tx_id: tx_id.clone(),
+                fee: fee.clone(),
                 u_principal: to,
                 unknown: from,
                 amount,
@@ -439,6 +1098,7 @@ pub async fn record_xfer_transaction(
         } else {
             business::add_unknown_transaction(BusinessUnknownTxArg {
                 tx_id: tx_id.clone(),
+                fee: fee.clone(),
                 b_principal: to,
                 unknown: from,
                 amount,
@@ -452,12 +1112,14 @@ pub async fn record_xfer_transaction(
     }
 
     BI::insert(
-        tx_id,
+        tx_id.clone(),
         TxInfo {
             from: Some(from),
             to: Some(to),
+            memo,
         },
     );
+    fiat::queue_rates(&tx_id, &parties, recorded_at);
 
     Ok(())
 }
@@ -497,28 +1159,39 @@ pub async fn record_request_payment(
         to,
         timestamp,
         amount,
+        fee,
+        memo,
     } = match inspect_xfer_transaction_for_req_payment(tx_id.clone(), get_tx_response) {
         Ok(xfer_tx) => xfer_tx,
         Err(err) => return Err(err),
     };
 
+    // Checked again, the same tx could have been recorded during the call
+    if BI::contains_key(tx_id.clone()) {
+        return Err(RecordRegPayTxErr::AlreadyRecorded);
+    }
+
     match user::record_request_payment(RecordReqPayTxArg {
         from,
         to,
         timestamp,
-        amount,
+        amount: amount.clone(),
         tx_id: tx_id.clone(),
-        chat_id,
+        fee,
+        chat_id: chat_id.clone(),
         message_index,
     }) {
         Ok(()) => {
+            notifications::request_paid(to, from, &chat_id, message_index, &amount, &tx_id);
             BI::insert(
-                tx_id,
+                tx_id.clone(),
                 TxInfo {
                     from: Some(from),
                     to: Some(to),
+                    memo,
                 },
             );
+            fiat::queue_rates(&tx_id, &[from, to], timestamp);
 
             Ok(())
         }
@@ -531,39 +1204,809 @@ pub fn is_pay_id_available(pay_id: String) -> bool {
     if pay_id.len() < 3 {
         return false;
     }
-    match PayIds::get(&pay_id) {
-        Some(_) => false,
-        None => true,
-    }
+    PayIds::get(&pay_id).is_none()
 }
 
 #[query]
 pub fn get_account_from_pay_id(pay_id: String) -> Option<Principal> {
     let caller = caller();
 
+    // Business profiles are paid on a subaccount of their owner, their principal can't receive funds
     if is_user(&caller) || is_business(&caller) {
-        return PayIds::get(&pay_id);
+        return PayIds::get(&pay_id).filter(|principal| !profiles::is_profile(principal));
     }
 
     None
 }
 
+// Ledger account to send payments for pay_id to, the owner's subaccount for business profiles
+#[query]
+pub fn get_ledger_account(pay_id: String) -> Option<Account> {
+    PayIds::get(&pay_id).map(|principal| profiles::ledger_account(&principal))
+}
+
+// Display profile of a pay_id, callable without an account
+#[query]
+pub fn lookup_pay_id(pay_id: String) -> Option<PayIdProfile> {
+    directory::lookup_pay_id(pay_id)
+}
+
+#[query]
+pub fn list_businesses(
+    category: Option<BusinessCategory>,
+    prefix: Option<String>,
+    page: u64,
+) -> BusinessDirectoryPage {
+    directory::list_businesses(category, prefix, page)
+}
+
+#[update]
+pub fn set_directory_visibility(
+    listed: bool,
+    profile: Option<Principal>,
+) -> Result<(), DirectoryError> {
+    directory::set_directory_visibility(listed, profile)
+}
+
+#[update]
+pub fn set_verified(pay_id: String, verified: bool) -> Result<(), SetVerifiedError> {
+    directory::set_verified(pay_id, verified)
+}
+
+#[query]
+pub fn get_contacts(profile: Option<Principal>) -> Result<Vec<ContactView>, ContactError> {
+    contacts::get_contacts(profile)
+}
+
+#[query]
+pub fn get_contact(
+    contact: PayIdOrPrincipal,
+    profile: Option<Principal>,
+) -> Result<ContactView, ContactError> {
+    contacts::get_contact(contact, profile)
+}
+
+// Adds or updates a contact
+#[update]
+pub fn update_contact(
+    arg: UpdateContactArg,
+    profile: Option<Principal>,
+) -> Result<ContactView, ContactError> {
+    contacts::update_contact(arg, profile)
+}
+
+#[update]
+pub fn remove_contact(
+    contact: PayIdOrPrincipal,
+    profile: Option<Principal>,
+) -> Result<(), ContactError> {
+    contacts::remove_contact(contact, profile)
+}
+
+// Adds recent counterparties from the transaction history, returns only the new contacts
+#[update]
+pub fn import_recent_contacts(
+    limit: Option<usize>,
+    profile: Option<Principal>,
+) -> Result<Vec<ContactView>, ContactError> {
+    contacts::import_recent_contacts(limit, profile)
+}
+
+#[query]
+pub fn get_safety_settings(profile: Option<Principal>) -> Result<SafetySettings, BlockError> {
+    safety::get_safety_settings(profile)
+}
+
+// Blocked accounts can't create chats, send messages or request payments with the caller
+#[update]
+pub fn block_account(
+    account: PayIdOrPrincipal,
+    profile: Option<Principal>,
+) -> Result<(), BlockError> {
+    safety::block_account(account, profile)
+}
+
+#[update]
+pub fn unblock_account(
+    account: PayIdOrPrincipal,
+    profile: Option<Principal>,
+) -> Result<(), BlockError> {
+    safety::unblock_account(account, profile)
+}
+
+// When enabled only accounts in the caller's contacts can send it payment requests
+#[update]
+pub fn set_only_contacts_can_request(
+    enabled: bool,
+    profile: Option<Principal>,
+) -> Result<(), BlockError> {
+    safety::set_only_contacts_can_request(enabled, profile)
+}
+
+// Totals of the caller business per day, week or month
+#[query]
+pub fn get_business_analytics(
+    range: AnalyticsRange,
+    bucket: AnalyticsBucket,
+    profile: Option<Principal>,
+) -> Result<BusinessAnalytics, AnalyticsError> {
+    analytics::get_business_analytics(range, bucket, profile)
+}
+
+// Aggregate transactions recorded before analytics existed, only needed once per business
+#[update]
+pub fn rebuild_business_analytics(profile: Option<Principal>) -> Result<(), AnalyticsError> {
+    analytics::rebuild_business_analytics(profile)
+}
+
+// Customers of the caller business, filtered by one of its tags if given
+#[query]
+pub fn list_customers(
+    tag: Option<String>,
+    page: u64,
+    profile: Option<Principal>,
+) -> Result<CustomerPage, CustomerError> {
+    customers::list_customers(tag, page, profile)
+}
+
+#[query]
+pub fn get_customer(pay_id: String, profile: Option<Principal>) -> Result<Customer, CustomerError> {
+    customers::get_customer(pay_id, profile)
+}
+
+// Sets the business private tags and note of a customer
+#[update]
+pub fn update_customer(
+    arg: UpdateCustomerArg,
+    profile: Option<Principal>,
+) -> Result<Customer, CustomerError> {
+    customers::update_customer(arg, profile)
+}
+
+// Customer totals from transactions recorded before the customer index existed, only needed once per business
+#[update]
+pub fn rebuild_customers(profile: Option<Principal>) -> Result<(), CustomerError> {
+    customers::rebuild_customers(profile)
+}
+
+// Business the caller works for as a member, None for owners and accounts that aren't members
+#[query]
+pub fn get_membership() -> Option<Membership> {
+    staff::get_membership()
+}
+
+#[query]
+pub fn list_members(profile: Option<Principal>) -> Result<Vec<Member>, StaffError> {
+    staff::list_members(profile)
+}
+
+// Lets principal act for the caller's business with role, admins can only add cashiers and viewers
+#[update]
+pub fn add_member(
+    principal: Principal,
+    role: Role,
+    profile: Option<Principal>,
+) -> Result<Member, StaffError> {
+    staff::add_member(principal, role, profile)
+}
+
+#[update]
+pub fn update_member_role(
+    principal: Principal,
+    role: Role,
+    profile: Option<Principal>,
+) -> Result<Member, StaffError> {
+    staff::update_member_role(principal, role, profile)
+}
+
+// Members can also remove themselves to leave the business
+#[update]
+pub fn remove_member(principal: Principal, profile: Option<Principal>) -> Result<(), StaffError> {
+    staff::remove_member(principal, profile)
+}
+
+// Updates made by the business and its members, latest first
+#[query]
+pub fn get_audit_log(page: u64, profile: Option<Principal>) -> Result<AuditPage, StaffError> {
+    staff::get_audit_log(page, profile)
+}
+
+// Currency of the fiat value saved on new history entries of the caller, USD by default
+#[update]
+pub fn set_preferred_currency(
+    currency: String,
+    profile: Option<Principal>,
+) -> Result<(), FiatError> {
+    fiat::set_preferred_currency(currency, profile)
+}
+
+#[query]
+pub fn get_preferred_currency(profile: Option<Principal>) -> Result<String, FiatError> {
+    fiat::get_preferred_currency(profile)
+}
+
+// Exchange rate canister used for fiat values, a mock with the XRC interface can be set locally
+#[update]
+pub fn set_exchange_rate_canister(canister: Principal) -> Result<(), FiatError> {
+    fiat::set_exchange_rate_canister(canister)
+}
+
+// Point of sale charge of amount, show payment_uri as QR code and poll get_checkout_status, ttl in seconds
+#[update]
+pub async fn create_checkout_session(
+    amount: Nat,
+    reference: String,
+    ttl: Option<u64>,
+    profile: Option<Principal>,
+) -> Result<CheckoutSession, CheckoutError> {
+    checkout::create_checkout_session(amount, reference, ttl, profile).await
+}
+
+#[query]
+pub fn get_checkout_status(
+    id: u64,
+    profile: Option<Principal>,
+) -> Result<CheckoutSession, CheckoutError> {
+    checkout::get_checkout_status(id, profile)
+}
+
+// Registers a URL notified of events, the signing secret is only returned here
+#[update]
+pub async fn add_webhook(
+    url: String,
+    events: Vec<WebhookEvent>,
+    profile: Option<Principal>,
+) -> Result<WebhookCreated, WebhookError> {
+    webhooks::add_webhook(url, events, profile).await
+}
+
+#[query]
+pub fn list_webhooks(profile: Option<Principal>) -> Result<Vec<WebhookView>, WebhookError> {
+    webhooks::list_webhooks(profile)
+}
+
+#[update]
+pub fn remove_webhook(id: u64, profile: Option<Principal>) -> Result<(), WebhookError> {
+    webhooks::remove_webhook(id, profile)
+}
+
+// Queues a Ping to the webhook, its result shows up in get_webhook_deliveries
+#[update]
+pub fn send_test_webhook(id: u64, profile: Option<Principal>) -> Result<Delivery, WebhookError> {
+    webhooks::send_test_webhook(id, profile)
+}
+
+#[query]
+pub fn get_webhook_deliveries(
+    page: u64,
+    profile: Option<Principal>,
+) -> Result<DeliveryPage, WebhookError> {
+    webhooks::get_webhook_deliveries(page, profile)
+}
+
+// Posts a payment request from the calling canister to a user, callback is called once it's paid or declined
+#[update]
+pub fn request_payment_from(
+    pay_id: String,
+    amount: Nat,
+    memo: Option<String>,
+    callback: String,
+) -> Result<AppPaymentRequest, AppRequestError> {
+    app_requests::request_payment_from(pay_id, amount, memo, callback)
+}
+
+#[query]
+pub fn get_app_payment_request(id: u64) -> Result<AppPaymentRequest, AppRequestError> {
+    app_requests::get_app_payment_request(id)
+}
+
+// Requests from other canisters to the caller, paid by recording the transfer with record_xfer_transaction
+#[query]
+pub fn get_app_payment_requests(page: u64) -> Result<AppRequestPage, AppRequestError> {
+    app_requests::get_app_payment_requests(page)
+}
+
+#[update]
+pub fn decline_app_payment_request(id: u64) -> Result<AppPaymentRequest, AppRequestError> {
+    app_requests::decline_app_payment_request(id)
+}
+
+// Recurring payment to a pay_id, approve the canister as ICRC-2 spender first
+#[update]
+pub async fn create_mandate(arg: CreateMandateArg) -> Result<Mandate, MandateError> {
+    mandates::create_mandate(arg).await
+}
+
+#[query]
+pub fn get_mandates(profile: Option<Principal>) -> Result<Vec<Mandate>, MandateError> {
+    mandates::get_mandates(profile)
+}
+
+#[update]
+pub fn cancel_mandate(id: u64, profile: Option<Principal>) -> Result<Mandate, MandateError> {
+    mandates::cancel_mandate(id, profile)
+}
+
+// Shareable link paid by anyone, with or without an account, through record_xfer_transaction
+#[update]
+pub fn create_payment_link(
+    arg: CreatePaymentLinkArg,
+    profile: Option<Principal>,
+) -> Result<PaymentLinkDetails, PaymentLinkError> {
+    payment_links::create_payment_link(arg, profile)
+}
+
+// Public, what the payer sees when opening the link
+#[query]
+pub fn get_payment_link(id: u64) -> Option<PaymentLinkView> {
+    payment_links::get_payment_link(id)
+}
+
+#[query]
+pub fn list_payment_links(
+    profile: Option<Principal>,
+) -> Result<Vec<PaymentLinkDetails>, PaymentLinkError> {
+    payment_links::list_payment_links(profile)
+}
+
+#[update]
+pub fn disable_payment_link(
+    id: u64,
+    profile: Option<Principal>,
+) -> Result<PaymentLinkDetails, PaymentLinkError> {
+    payment_links::disable_payment_link(id, profile)
+}
+
+#[query]
+pub fn get_link_payments(
+    id: u64,
+    page: u64,
+    profile: Option<Principal>,
+) -> Result<LinkPaymentPage, PaymentLinkError> {
+    payment_links::get_link_payments(id, page, profile)
+}
+
+// Feed of the caller or of the business profile, latest first. cursor is the next_cursor of the previous page
+#[query]
+pub fn get_notifications(
+    cursor: Option<u64>,
+    profile: Option<Principal>,
+) -> Result<NotificationPage, NotificationError> {
+    notifications::get_notifications(cursor, profile)
+}
+
+// Marks notifications before up_to read, all of them without it, returns the unread count
+#[update]
+pub fn mark_notifications_read(
+    up_to: Option<u64>,
+    profile: Option<Principal>,
+) -> Result<u64, NotificationError> {
+    notifications::mark_notifications_read(up_to, profile)
+}
+
+#[query]
+pub fn get_muted_notifications(
+    profile: Option<Principal>,
+) -> Result<Vec<NotificationKind>, NotificationError> {
+    notifications::get_muted_notifications(profile)
+}
+
+#[update]
+pub fn set_muted_notifications(
+    muted: Vec<NotificationKind>,
+    profile: Option<Principal>,
+) -> Result<Vec<NotificationKind>, NotificationError> {
+    notifications::set_muted_notifications(muted, profile)
+}
+
+// Creates the VAPID key on first call, controllers only. Returns the public key
+#[update]
+pub async fn setup_web_push(subject: String) -> Result<String, WebPushError> {
+    web_push::setup_web_push(subject).await
+}
+
+// applicationServerKey for PushManager.subscribe, base64url
+#[query]
+pub fn get_vapid_public_key() -> Option<String> {
+    web_push::get_vapid_public_key()
+}
+
+// Devices alerted by web push when a payment arrives, with a profile for the business feed
+#[update]
+pub fn add_push_subscription(
+    arg: AddPushSubscriptionArg,
+    profile: Option<Principal>,
+) -> Result<PushSubscriptionView, WebPushError> {
+    web_push::add_push_subscription(arg, profile)
+}
+
+#[query]
+pub fn get_push_subscriptions(
+    profile: Option<Principal>,
+) -> Result<Vec<PushSubscriptionView>, WebPushError> {
+    web_push::get_push_subscriptions(profile)
+}
+
+#[update]
+pub fn remove_push_subscription(id: u64, profile: Option<Principal>) -> Result<(), WebPushError> {
+    web_push::remove_push_subscription(id, profile)
+}
+
+#[update]
+pub fn send_test_push(profile: Option<Principal>) -> Result<(), WebPushError> {
+    web_push::send_test_push(profile)
+}
+
+// Caps and approvals applied to mandate payments and escrow releases of an account
+#[update]
+pub fn set_spending_policy(
+    arg: SpendingPolicyArg,
+    profile: Option<Principal>,
+) -> Result<SpendingPolicyView, SpendingPolicyError> {
+    policies::set_spending_policy(arg, profile)
+}
+
+#[query]
+pub fn get_spending_policy(
+    profile: Option<Principal>,
+) -> Result<Option<SpendingPolicyView>, SpendingPolicyError> {
+    policies::get_spending_policy(profile)
+}
+
+#[query]
+pub fn get_policy_log(
+    page: u64,
+    profile: Option<Principal>,
+) -> Result<PolicyLogPage, SpendingPolicyError> {
+    policies::get_policy_log(page, profile)
+}
+
+#[query]
+pub fn get_send_approvals(
+    profile: Option<Principal>,
+) -> Result<Vec<SendApproval>, SpendingPolicyError> {
+    policies::get_send_approvals(profile)
+}
+
+// Approver's answer, an approved send goes through at its next attempt
+#[update]
+pub fn decide_send_approval(id: u64, approve: bool) -> Result<SendApproval, SpendingPolicyError> {
+    policies::decide_send_approval(id, approve)
+}
+
+// Escrow paying the other participant of a chat, send deposit_amount to deposit_account to fund it
+#[update]
+pub async fn create_escrow(arg: CreateEscrowArg) -> Result<Escrow, EscrowError> {
+    escrow::create_escrow(arg).await
+}
+
+#[update]
+pub async fn confirm_escrow_funding(id: u64) -> Result<Escrow, EscrowError> {
+    escrow::confirm_escrow_funding(id).await
+}
+
+#[update]
+pub fn mark_escrow_delivered(id: u64) -> Result<Escrow, EscrowError> {
+    escrow::mark_escrow_delivered(id)
+}
+
+#[update]
+pub async fn release_escrow(id: u64) -> Result<Escrow, EscrowError> {
+    escrow::release_escrow(id).await
+}
+
+#[update]
+pub async fn refund_escrow(id: u64) -> Result<Escrow, EscrowError> {
+    escrow::refund_escrow(id).await
+}
+
+#[update]
+pub async fn cancel_escrow(id: u64) -> Result<Escrow, EscrowError> {
+    escrow::cancel_escrow(id).await
+}
+
+#[update]
+pub fn dispute_escrow(id: u64, reason: String) -> Result<Escrow, EscrowError> {
+    escrow::dispute_escrow(id, reason)
+}
+
+// Arbiter only, pays out a disputed escrow
+#[update]
+pub async fn resolve_escrow(id: u64, resolution: EscrowResolution) -> Result<Escrow, EscrowError> {
+    escrow::resolve_escrow(id, resolution).await
+}
+
+#[query]
+pub fn get_escrow(id: u64) -> Result<Escrow, EscrowError> {
+    escrow::get_escrow(id)
+}
+
+#[query]
+pub fn get_escrows() -> Result<Vec<Escrow>, EscrowError> {
+    escrow::get_escrows()
+}
+
+#[query]
+pub fn get_disputed_escrows() -> Result<Vec<Escrow>, EscrowError> {
+    escrow::get_disputed_escrows()
+}
+
+#[update]
+pub fn set_escrow_arbiter(arbiter: Principal) -> Result<(), EscrowError> {
+    escrow::set_escrow_arbiter(arbiter)
+}
+
+#[update]
+pub fn allow_payment_requester(canister: Principal, name: String) -> Result<(), AppRequestError> {
+    app_requests::allow_payment_requester(canister, name)
+}
+
+#[update]
+pub fn remove_payment_requester(canister: Principal) -> Result<(), AppRequestError> {
+    app_requests::remove_payment_requester(canister)
+}
+
+#[query]
+pub fn list_payment_requesters() -> Vec<AllowedApp> {
+    app_requests::list_payment_requesters()
+}
+
+// Used by the webhook and web push outcalls
+#[query]
+fn transform_webhook_response(args: TransformArgs) -> HttpResponse {
+    webhooks::transform_response(args)
+}
+
+// Caller's history as CSV, OFX or JSON, call again with next_chunk until it's None
+#[query]
+pub fn export_transactions(
+    format: ExportFormat,
+    range: ExportRange,
+    chunk: u64,
+    profile: Option<Principal>,
+) -> Result<ExportChunk, ExportError> {
+    export::export_transactions(format, range, chunk, profile)
+}
+
 struct TransferTx {
     from: Principal,
     to: Principal,
     timestamp: u64,
     amount: Nat,
+    fee: Option<Nat>,
+    memo: Option<ByteBuf>,
+}
+
+// ckBTC minter, shown as the other account of mints and burns
+const CK_BTC_MINTER: &str = "mqygn-kiaaa-aaaar-qaadq-cai";
+
+// ckBTC ledger transactions that record_xfer_transaction can add to the history
+enum LedgerTx {
+    Transfer(TransferTx),
+    Other(OtherLedgerTx),
+}
+
+// Ledger transactions other than transfers, recorded by record_ledger_transaction
+enum OtherLedgerTx {
+    Mint {
+        to: Principal,
+        timestamp: u64,
+        amount: Nat,
+        memo: Option<ByteBuf>,
+    },
+    Burn {
+        from: Principal,
+        timestamp: u64,
+        amount: Nat,
+        memo: Option<ByteBuf>,
+    },
+    Approve {
+        from: Principal,
+        spender: Principal,
+        timestamp: u64,
+        amount: Nat,
+        fee: Option<Nat>,
+        expires_at: Option<u64>,
+        memo: Option<ByteBuf>,
+    },
+}
+
+impl LedgerTx {
+    fn parties(&self) -> Vec<Principal> {
+        match self {
+            LedgerTx::Transfer(xfer_tx) => vec![xfer_tx.from, xfer_tx.to],
+            LedgerTx::Other(OtherLedgerTx::Mint { to, .. }) => vec![*to],
+            LedgerTx::Other(OtherLedgerTx::Burn { from, .. }) => vec![*from],
+            LedgerTx::Other(OtherLedgerTx::Approve { from, spender, .. }) => vec![*from, *spender],
+        }
+    }
+
+    fn timestamp(&self) -> u64 {
+        match self {
+            LedgerTx::Transfer(xfer_tx) => xfer_tx.timestamp,
+            LedgerTx::Other(
+                OtherLedgerTx::Mint { timestamp, .. }
+                | OtherLedgerTx::Burn { timestamp, .. }
+                | OtherLedgerTx::Approve { timestamp, .. },
+            ) => *timestamp,
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            LedgerTx::Transfer(_) => "transfer",
+            LedgerTx::Other(OtherLedgerTx::Mint { .. }) => "mint",
+            LedgerTx::Other(OtherLedgerTx::Burn { .. }) => "burn",
+            LedgerTx::Other(OtherLedgerTx::Approve { .. }) => "approve",
+        }
+    }
+}
+
+// (name, pay_id) of principal as shown in the history of the other account
+fn history_name(principal: &Principal) -> (String, String) {
+    if let Some(user) = user::get_metadata(principal) {
+        return (user.name, user.pay_id);
+    }
+    if let Some(business) = business::get_metadata(principal) {
+        return (business.name, business.pay_id);
+    }
+    (principal.to_text(), principal.to_text())
+}
+
+// Adds the entry to the user history or business transactions, false if principal has no account
+fn push_history_entry(principal: Principal, entry: TransactionEntry) -> bool {
+    if is_user(&principal) {
+        StoreHistory::push_entry(principal, entry);
+        true
+    } else if is_business(&principal) {
+        business::push_entry(principal, entry);
+        true
+    } else {
+        false
+    }
+}
+
+// Updates the entry of tx_id in the user history or business transactions of principal
+fn update_history_entry(
+    principal: &Principal,
+    tx_id: &Nat,
+    update: impl FnOnce(&mut TransactionEntry),
+) {
+    if is_user(principal) {
+        user::update_entry(principal, tx_id, update);
+    } else if is_business(principal) {
+        business::update_entry(principal, tx_id, update);
+    }
+}
+
+// Kind tx_id was recorded with in the history of principal
+fn history_kind(principal: &Principal, tx_id: &Nat) -> Option<TxKind> {
+    let history = if is_user(principal) {
+        user::get_history(principal)
+    } else {
+        business::get_transactions(principal)
+    };
+    history
+        .into_iter()
+        .rfind(|entry| &entry.tx_id == tx_id)
+        .map(|entry| entry.kind)
+}
+
+// Records mints and burns for their account, and approvals for both the owner and the spender
+fn record_ledger_transaction(
+    tx_id: Nat,
+    ledger_tx: OtherLedgerTx,
+    note: Option<String>,
+) -> Result<(), RecordTxErr> {
+    let (from, to, memo) = match ledger_tx {
+        OtherLedgerTx::Mint {
+            to,
+            timestamp,
+            amount,
+            memo,
+        } => {
+            let entry = TransactionEntry {
+                kind: TxKind::Mint,
+                name: "ckBTC minter".to_string(),
+                pay_id: CK_BTC_MINTER.to_string(),
+                tx_id: tx_id.clone(),
+                timestamp,
+                amount,
+                note,
+                refund: None,
+                fiat: None,
+                fee: None,
+            };
+            if !push_history_entry(to, entry) {
+                return Err(RecordTxErr::AccountNotFound(to));
+            }
+            (None, Some(to), memo)
+        }
+        OtherLedgerTx::Burn {
+            from,
+            timestamp,
+            amount,
+            memo,
+        } => {
+            let entry = TransactionEntry {
+                kind: TxKind::Burn,
+                name: "ckBTC minter".to_string(),
+                pay_id: CK_BTC_MINTER.to_string(),
+                tx_id: tx_id.clone(),
+                timestamp,
+                amount,
+                note,
+                refund: None,
+                fiat: None,
+                fee: None,
+            };
+            if !push_history_entry(from, entry) {
+                return Err(RecordTxErr::AccountNotFound(from));
+            }
+            (Some(from), None, memo)
+        }
+        OtherLedgerTx::Approve {
+            from,
+            spender,
+            timestamp,
+            amount,
+            fee,
+            expires_at,
+            memo,
+        } => {
+            let (spender_name, spender_pay_id) = history_name(&spender);
+            let (owner_name, owner_pay_id) = history_name(&from);
+
+            let owner_entry = TransactionEntry {
+                kind: TxKind::Approve { expires_at },
+                name: spender_name,
+                pay_id: spender_pay_id,
+                tx_id: tx_id.clone(),
+                timestamp,
+                amount: amount.clone(),
+                note: note.clone(),
+                refund: None,
+                fiat: None,
+                fee: fee.clone(),
+            };
+            let spender_entry = TransactionEntry {
+                kind: TxKind::Allowance { expires_at },
+                name: owner_name,
+                pay_id: owner_pay_id,
+                tx_id: tx_id.clone(),
+                timestamp,
+                amount,
+                note,
+                refund: None,
+                fiat: None,
+                fee,
+            };
+
+            let owner_recorded = push_history_entry(from, owner_entry);
+            let spender_recorded = push_history_entry(spender, spender_entry);
+            if !owner_recorded && !spender_recorded {
+                return Err(RecordTxErr::BothAccountsNotFound { from, to: spender });
+            }
+            (Some(from), Some(spender), memo)
+        }
+    };
+
+    BI::insert(tx_id, TxInfo { from, to, memo });
+    Ok(())
 }
 
 #[query]
-pub fn get_new_business_transactions(length: usize) -> Vec<TransactionEntry> {
-    business::get_new_business_transactions(length)
+pub fn get_new_business_transactions(
+    length: usize,
+    profile: Option<Principal>,
+) -> Vec<TransactionEntry> {
+    business::get_new_business_transactions(length, profile)
 }
 
-fn inspect_xfer_transaction(
+fn inspect_transaction(
     tx_id: candid::Nat,
     mut arg: GetTransactionsResponse,
-) -> Result<TransferTx, RecordTxErr> {
+) -> Result<LedgerTx, RecordTxErr> {
     if tx_id >= arg.log_length {
         return Err(RecordTxErr::InvalidTransaction(format!(
             "Invalid ckBTC transaction ID: {tx_id}, Log lenght is: {}",
@@ -574,22 +2017,61 @@ fn inspect_xfer_transaction(
     // Panics if index is out of bounds. cases: transaction archived, invalid block index, length arg is 0
     let transaction = arg.transactions.swap_remove(0);
 
-    let transfer = match transaction.transfer {
-        Some(xfer) => xfer,
-        None => {
-            return Err(RecordTxErr::InvalidTransaction(format!(
-                "Expected Transfer, but Transaction kind is {}",
-                transaction.kind
-            )))
-        }
-    };
+    if let Some(transfer) = transaction.transfer {
+        return Ok(LedgerTx::Transfer(TransferTx {
+            from: profiles::account_principal(&transfer.from),
+            to: profiles::account_principal(&transfer.to),
+            timestamp: transaction.timestamp,
+            amount: transfer.amount,
+            fee: transfer.fee,
+            memo: transfer.memo,
+        }));
+    }
+    if let Some(mint) = transaction.mint {
+        return Ok(LedgerTx::Other(OtherLedgerTx::Mint {
+            to: profiles::account_principal(&mint.to),
+            timestamp: transaction.timestamp,
+            amount: mint.amount,
+            memo: mint.memo,
+        }));
+    }
+    if let Some(burn) = transaction.burn {
+        return Ok(LedgerTx::Other(OtherLedgerTx::Burn {
+            from: profiles::account_principal(&burn.from),
+            timestamp: transaction.timestamp,
+            amount: burn.amount,
+            memo: burn.memo,
+        }));
+    }
+    if let Some(approve) = transaction.approve {
+        return Ok(LedgerTx::Other(OtherLedgerTx::Approve {
+            from: profiles::account_principal(&approve.from),
+            spender: profiles::account_principal(&approve.spender),
+            timestamp: transaction.timestamp,
+            amount: approve.amount,
+            fee: approve.fee,
+            expires_at: approve.expires_at,
+            memo: approve.memo,
+        }));
+    }
 
-    Ok(TransferTx {
-        from: transfer.from.owner,
-        to: transfer.to.owner,
-        timestamp: transaction.timestamp,
-        amount: transfer.amount,
-    })
+    Err(RecordTxErr::InvalidTransaction(format!(
+        "Unknown transaction kind {}",
+        transaction.kind
+    )))
+}
+
+fn inspect_xfer_transaction(
+    tx_id: candid::Nat,
+    arg: GetTransactionsResponse,
+) -> Result<TransferTx, RecordTxErr> {
+    match inspect_transaction(tx_id, arg)? {
+        LedgerTx::Transfer(xfer_tx) => Ok(xfer_tx),
+        other => Err(RecordTxErr::InvalidTransaction(format!(
+            "Expected Transfer, but Transaction kind is {}",
+            other.kind()
+        ))),
+    }
 }
 
 fn inspect_xfer_transaction_for_req_payment(
@@ -617,13 +2099,24 @@ fn inspect_xfer_transaction_for_req_payment(
     };
 
     Ok(TransferTx {
-        from: transfer.from.owner,
-        to: transfer.to.owner,
+        from: profiles::account_principal(&transfer.from),
+        to: profiles::account_principal(&transfer.to),
         timestamp: transaction.timestamp,
         amount: transfer.amount,
+        fee: transfer.fee,
+        memo: transfer.memo,
     })
 }
 
+#[post_upgrade]
+fn post_upgrade() {
+    web_push::index_pending_pushes();
+    webhooks::index_pending_deliveries();
+    if let Some(next) = timer::next_work() {
+        timer::schedule_at(next);
+    }
+}
+
 #[test]
 fn generate_candid() {
     candid::export_service!();
@@ -0,0 +1,531 @@
+use crate::business::is_business;
+use crate::ck_btc_ledger::{self, Account, AllowanceArgs, TransferFromArgs, TransferFromError};
+use crate::error::{MandateError, PolicyError, RecordTxErr};
+use crate::notifications;
+use crate::policies::{self, PolicySend};
+use crate::profiles;
+use crate::staff::{self, Permission};
+use crate::sync::{self, Change};
+use crate::user::{is_user, user_caller};
+use crate::{timer, PayIds, ACTIVE_MANDATES_MAP, MANDATES_MAP, USER_MANDATES_MAP};
+use candid::{Decode, Encode, Nat, Principal};
+use ic_cdk::api::{id, time};
+use ic_stable_structures::storable::{Bound, Storable};
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+use std::borrow::Cow;
+
+const MIN_INTERVAL: u64 = 3_600; // seconds
+const MAX_INTERVAL: u64 = 366 * 86_400;
+const MAX_NOTE_LEN: usize = 100;
+const MAX_MANDATES: usize = 20; // active mandates of one payer
+const MAX_EVENTS: usize = 20; // latest events kept per mandate
+const MAX_ATTEMPTS: u32 = 3; // attempts of one run before it's skipped
+const RETRY_DELAY: u64 = 3_600_000_000_000; // 1 hour
+const IN_FLIGHT_TIMEOUT: u64 = 300_000_000_000; // a run waiting for the ledger isn't picked again before this
+const RUNS_PER_TICK: usize = 10;
+const MEMO_PREFIX: &[u8] = b"MND"; // memo is the prefix followed by the mandate id in big endian
+const NANOS: u64 = 1_000_000_000;
+const RUN_WINDOW: u64 = 86_400 * NANOS; // ledger's transaction window, older runs fail as TooOld
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize, PartialEq)]
+pub enum MandateStatus {
+    Active,
+    Cancelled { by: Principal, at: u64 },
+    Completed, // ends_at reached
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub enum MandateEventKind {
+    Paid { tx_id: Nat },
+    Failed { reason: String, attempt: u32 },
+    Skipped, // the run failed MAX_ATTEMPTS times, the next one is at the next interval
+    Blocked { error: PolicyError }, // the payer's spending policy held the run back
+    Cancelled,
+    Completed,
+    NotRecorded { tx_id: Nat, error: RecordTxErr }, // paid, but not added to the histories
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct MandateEvent {
+    kind: MandateEventKind,
+    run_at: u64, // scheduled time of the run
+    timestamp: u64,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct Mandate {
+    id: u64,
+    payer: Principal,
+    payee: Principal,
+    payee_pay_id: String,
+    amount: Nat,
+    interval: u64, // seconds
+    note: Option<String>,
+    created_at: u64,
+    next_run_at: u64,
+    ends_at: u64,
+    attempts: u32,         // failed attempts of the next run
+    retry_at: Option<u64>, // next attempt of a failed run
+    payments: u64,
+    status: MandateStatus,
+    events: Vec<MandateEvent>, // latest last
+}
+
+impl Storable for Mandate {
+    const BOUND: Bound = Bound::Unbounded;
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl Mandate {
+    fn get(key: u64) -> Option<Mandate> {
+        MANDATES_MAP.with(|p| p.borrow().get(&key))
+    }
+
+    fn insert(key: u64, value: Mandate) -> Option<Mandate> {
+        MANDATES_MAP.with(|p| p.borrow_mut().insert(key, value))
+    }
+
+    fn next_id() -> u64 {
+        MANDATES_MAP.with(|p| p.borrow().last_key_value().map_or(0, |(id, _)| id + 1))
+    }
+
+    // Failures are also sent to the payer's notifications, events are only seen in the mandate
+    fn push_event(&mut self, kind: MandateEventKind, run_at: u64) {
+        if matches!(
+            kind,
+            MandateEventKind::Failed { .. }
+                | MandateEventKind::Skipped
+                | MandateEventKind::Blocked { .. }
+        ) {
+            notifications::mandate_problem(
+                self.payer,
+                self.id,
+                &self.payee_pay_id,
+                &self.amount,
+                kind.clone(),
+            );
+        }
+        self.events.push(MandateEvent {
+            kind,
+            run_at,
+            timestamp: time(),
+        });
+        if self.events.len() > MAX_EVENTS {
+            self.events.remove(0);
+        }
+    }
+
+    // Moves to the next run, or completes the mandate after the last one
+    fn advance(&mut self) {
+        let run_at = self.next_run_at;
+        let next = next_run(run_at, self.interval);
+        self.next_run_at = next.unwrap_or(u64::MAX);
+        self.attempts = 0;
+        self.retry_at = None;
+        if next.is_none_or(|next| next > self.ends_at) {
+            self.status = MandateStatus::Completed;
+            self.push_event(MandateEventKind::Completed, run_at);
+        }
+    }
+
+    // Stores the mandate, keeps the schedule index in line and tells both parties
+    fn save(self) {
+        let id = self.id;
+        if self.status == MandateStatus::Active {
+            let run_at = self.retry_at.unwrap_or(self.next_run_at);
+            ACTIVE_MANDATES_MAP.with(|p| p.borrow_mut().insert(id, run_at));
+        } else {
+            ACTIVE_MANDATES_MAP.with(|p| p.borrow_mut().remove(&id));
+        }
+        sync::record_change(self.payer, Change::Mandate(id));
+        sync::record_change(self.payee, Change::Mandate(id));
+        Mandate::insert(id, self);
+    }
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct CreateMandateArg {
+    payee: String, // pay_id
+    amount: Nat,
+    interval: u64,          // seconds between payments
+    starts_at: Option<u64>, // first payment, now by default
+    ends_at: u64,           // no payment after this
+    note: Option<String>,
+}
+
+// None past the end of time, create_mandate rejects mandates whose second run would get there
+fn next_run(run_at: u64, interval: u64) -> Option<u64> {
+    interval
+        .checked_mul(NANOS)
+        .and_then(|interval| run_at.checked_add(interval))
+}
+
+// Key is "{principal}/{id}", id zero padded so mandates of an account are in order
+fn user_key(principal: &Principal, id: u64) -> String {
+    format!("{}/{:020}", principal.to_text(), id)
+}
+
+// Mandates principal pays or is paid by, oldest first
+fn mandate_ids(principal: &Principal) -> Vec<u64> {
+    let prefix = format!("{}/", principal.to_text());
+    USER_MANDATES_MAP.with(|p| {
+        p.borrow()
+            .range(prefix.clone()..)
+            .take_while(|(key, _)| key.starts_with(&prefix))
+            .map(|(_, id)| id)
+            .collect()
+    })
+}
+
+/*
+ * Creates a mandate paying amount to payee every interval until ends_at. The caller has to approve
+ * the canister as ICRC-2 spender first, with an allowance covering at least one payment. Every payment
+ * also takes the ledger fee from the payer.
+ */
+pub async fn create_mandate(
+    CreateMandateArg {
+        payee,
+        amount,
+        interval,
+        starts_at,
+        ends_at,
+        note,
+    }: CreateMandateArg,
+) -> Result<Mandate, MandateError> {
+    let payer = user_caller();
+    if !is_user(&payer) {
+        return Err(MandateError::AccountNotFound);
+    }
+
+    let payee_pay_id = payee;
+    let payee = match PayIds::get(&payee_pay_id) {
+        Some(payee) if is_user(&payee) || is_business(&payee) => payee,
+        _ => return Err(MandateError::PayeeNotFound),
+    };
+    if payee == payer {
+        return Err(MandateError::CannotPaySelf);
+    }
+    if amount == 0u64 {
+        return Err(MandateError::InvalidAmount);
+    }
+    if !(MIN_INTERVAL..=MAX_INTERVAL).contains(&interval) {
+        return Err(MandateError::InvalidInterval {
+            min: MIN_INTERVAL,
+            max: MAX_INTERVAL,
+        });
+    }
+    let now = time();
+    let starts_at = starts_at.unwrap_or(now).max(now);
+    if next_run(starts_at, interval).is_none() {
+        return Err(MandateError::InvalidStartDate);
+    }
+    if ends_at < starts_at {
+        return Err(MandateError::InvalidEndDate);
+    }
+    if note
+        .as_ref()
+        .is_some_and(|note| note.chars().count() > MAX_NOTE_LEN)
+    {
+        return Err(MandateError::NoteTooLong { max: MAX_NOTE_LEN });
+    }
+
+    let (allowance,) = ck_btc_ledger::allowance(AllowanceArgs {
+        account: profiles::ledger_account(&payer),
+        spender: Account {
+            owner: id(),
+            subaccount: None,
+        },
+    })
+    .await
+    .map_err(|err| MandateError::InterCanisterCall(format!("icrc2_allowance failed {:?}", err)))?;
+    if allowance.allowance < amount {
+        return Err(MandateError::AllowanceTooLow {
+            allowance: allowance.allowance,
+        });
+    }
+
+    // Counted after the call, another mandate could have been created meanwhile
+    let active = mandate_ids(&payer)
+        .into_iter()
+        .filter_map(Mandate::get)
+        .filter(|mandate| mandate.payer == payer && mandate.status == MandateStatus::Active)
+        .count();
+    if active >= MAX_MANDATES {
+        return Err(MandateError::TooManyMandates { max: MAX_MANDATES });
+    }
+
+    let id = Mandate::next_id();
+    let mandate = Mandate {
+        id,
+        payer,
+        payee,
+        payee_pay_id,
+        amount,
+        interval,
+        note,
+        created_at: now,
+        next_run_at: starts_at,
+        ends_at,
+        attempts: 0,
+        retry_at: None,
+        payments: 0,
+        status: MandateStatus::Active,
+        events: Vec::new(),
+    };
+    USER_MANDATES_MAP.with(|p| {
+        let mut map = p.borrow_mut();
+        map.insert(user_key(&payer, id), id);
+        map.insert(user_key(&payee, id), id);
+    });
+    mandate.clone().save();
+
+    timer::schedule_at(starts_at);
+    Ok(mandate)
+}
+
+// Mandates the caller pays or is paid by, latest first
+pub fn get_mandates(profile: Option<Principal>) -> Result<Vec<Mandate>, MandateError> {
+    let caller = staff::account(profile, Permission::ViewBusiness)?;
+    if !is_user(&caller) && !is_business(&caller) {
+        return Err(MandateError::AccountNotFound);
+    }
+
+    Ok(mandate_ids(&caller)
+        .into_iter()
+        .rev()
+        .filter_map(Mandate::get)
+        .collect())
+}
+
+pub fn get_mandates_by_id(principal: &Principal, ids: &[u64]) -> Vec<Mandate> {
+    ids.iter()
+        .filter_map(|id| Mandate::get(*id))
+        .filter(|mandate| &mandate.payer == principal || &mandate.payee == principal)
+        .collect()
+}
+
+// Either party can cancel, the allowance itself has to be revoked on the ledger by the payer
+pub fn cancel_mandate(id: u64, profile: Option<Principal>) -> Result<Mandate, MandateError> {
+    let caller = staff::account(profile, Permission::ManageSettings)?;
+    let mut mandate = match Mandate::get(id) {
+        Some(mandate) if mandate.payer == caller || mandate.payee == caller => mandate,
+        _ => return Err(MandateError::MandateNotFound),
+    };
+    if mandate.status != MandateStatus::Active {
+        return Err(MandateError::NotActive);
+    }
+
+    mandate.status = MandateStatus::Cancelled {
+        by: caller,
+        at: time(),
+    };
+    let run_at = mandate.next_run_at;
+    mandate.push_event(MandateEventKind::Cancelled, run_at);
+    mandate.clone().save();
+    Ok(mandate)
+}
+
+// Earliest next run of the active mandates
+pub fn next_run_at() -> Option<u64> {
+    ACTIVE_MANDATES_MAP.with(|p| p.borrow().iter().map(|(_, at)| at).min())
+}
+
+fn failure_reason(err: &TransferFromError) -> String {
+    match err {
+        TransferFromError::InsufficientFunds { balance } => {
+            format!("Insufficient funds, balance is {}", balance)
+        }
+        TransferFromError::InsufficientAllowance { allowance } => {
+            format!("Insufficient allowance, allowance is {}", allowance)
+        }
+        TransferFromError::TooOld => "Run missed for over a day".to_string(),
+        TransferFromError::GenericError { message, .. } => message.clone(),
+        other => format!("{:?}", other),
+    }
+}
+
+/*
+ * A run the payer's policy didn't allow. A run waiting for approval is tried again without counting
+ * as a failed attempt, a rejected one is skipped.
+ */
+fn block(mut mandate: Mandate, error: PolicyError) {
+    let run_at = mandate.next_run_at;
+    match error {
+        // The ledger takes a run for a day after its created_at_time, an approval after that is too late
+        PolicyError::ApprovalPending { .. } if time() > run_at + RUN_WINDOW => {
+            mandate.push_event(MandateEventKind::Blocked { error }, run_at);
+            mandate.attempts = MAX_ATTEMPTS;
+        }
+        PolicyError::ApprovalPending { .. } => {}
+        PolicyError::ApprovalRequired { .. } => mandate.push_event(
+            MandateEventKind::Blocked {
+                error: error.clone(),
+            },
+            run_at,
+        ),
+        PolicyError::ApprovalRejected { .. } => {
+            mandate.push_event(MandateEventKind::Blocked { error }, run_at);
+            mandate.attempts = MAX_ATTEMPTS;
+        }
+        _ => {
+            mandate.push_event(MandateEventKind::Blocked { error }, run_at);
+            mandate.attempts += 1;
+        }
+    }
+
+    if mandate.attempts >= MAX_ATTEMPTS {
+        mandate.push_event(MandateEventKind::Skipped, run_at);
+        mandate.advance();
+    } else {
+        mandate.retry_at = Some(time() + RETRY_DELAY);
+    }
+    mandate.save();
+}
+
+// Runs an approved run held back by the payer's policy at the next tick
+pub fn retry_now(id: u64) {
+    match Mandate::get(id) {
+        Some(mut mandate)
+            if mandate.status == MandateStatus::Active && mandate.retry_at.is_some() =>
+        {
+            let now = time();
+            mandate.retry_at = Some(now);
+            mandate.save();
+            timer::schedule_at(now);
+        }
+        _ => {}
+    }
+}
+
+async fn run(mandate: Mandate) {
+    let run_at = mandate.next_run_at;
+    let authorization = match policies::authorize(
+        mandate.payer,
+        PolicySend::MandatePayment {
+            mandate_id: mandate.id,
+            run_at,
+        },
+        mandate.payee,
+        &mandate.amount,
+    ) {
+        Ok(authorization) => authorization,
+        Err(error) => return block(mandate, error),
+    };
+
+    let result = ck_btc_ledger::transfer_from(TransferFromArgs {
+        spender_subaccount: None,
+        from: profiles::ledger_account(&mandate.payer),
+        to: profiles::ledger_account(&mandate.payee),
+        amount: mandate.amount.clone(),
+        fee: None,
+        memo: Some(ByteBuf::from(
+            [MEMO_PREFIX, &mandate.id.to_be_bytes()].concat(),
+        )),
+        // Same for every attempt of a run, so the ledger rejects a second payment as Duplicate
+        created_at_time: Some(run_at),
+    })
+    .await;
+
+    let tx_id = match result {
+        Ok((Ok(tx_id),)) => Ok(tx_id),
+        Ok((Err(TransferFromError::Duplicate { duplicate_of }),)) => Ok(duplicate_of),
+        Ok((Err(err),)) => Err(err),
+        Err((code, message)) => Err(TransferFromError::GenericError {
+            error_code: Nat::from(code as u64),
+            message,
+        }),
+    };
+
+    // Read again, the mandate could have been cancelled meanwhile, a payment made still counts
+    let mandate_id = mandate.id;
+    let mut mandate = Mandate::get(mandate_id).unwrap_or(mandate);
+    mandate.retry_at = None;
+    match &tx_id {
+        Ok(tx_id) => {
+            mandate.payments += 1;
+            mandate.push_event(
+                MandateEventKind::Paid {
+                    tx_id: tx_id.clone(),
+                },
+                run_at,
+            );
+            if mandate.status == MandateStatus::Active {
+                mandate.advance();
+            }
+        }
+        Err(err) => {
+            policies::revert(authorization);
+            // Runs missed for over a day can't be sent with their created_at_time anymore
+            mandate.attempts = match err {
+                TransferFromError::TooOld => MAX_ATTEMPTS,
+                _ => mandate.attempts + 1,
+            };
+            mandate.push_event(
+                MandateEventKind::Failed {
+                    reason: failure_reason(err),
+                    attempt: mandate.attempts,
+                },
+                run_at,
+            );
+            if mandate.status == MandateStatus::Active {
+                if mandate.attempts >= MAX_ATTEMPTS {
+                    mandate.push_event(MandateEventKind::Skipped, run_at);
+                    mandate.advance();
+                } else {
+                    mandate.retry_at = Some(time() + RETRY_DELAY);
+                }
+            }
+        }
+    }
+    let note = mandate.note.clone();
+    mandate.save();
+
+    // Recorded like any other transfer, so it shows in both histories. It can still be recorded with
+    // record_xfer_transaction if this fails
+    if let Ok(tx_id) = tx_id {
+        match crate::record_xfer_transaction(tx_id.clone(), note).await {
+            Ok(_) | Err(RecordTxErr::AlreadyRecorded) => {}
+            Err(error) => {
+                if let Some(mut mandate) = Mandate::get(mandate_id) {
+                    mandate.push_event(MandateEventKind::NotRecorded { tx_id, error }, run_at);
+                    mandate.save();
+                }
+            }
+        }
+    }
+}
+
+// Runs the mandates that are due, called by the timer
+pub async fn run_due() {
+    let now = time();
+    let due = ACTIVE_MANDATES_MAP.with(|p| {
+        p.borrow()
+            .iter()
+            .filter(|(_, at)| *at <= now)
+            .take(RUNS_PER_TICK)
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>()
+    });
+
+    // One after the other, runs of the same payer are checked against its policy with the earlier
+    // ones already recorded
+    for id in due {
+        match Mandate::get(id) {
+            Some(mandate) if mandate.status == MandateStatus::Active => {
+                // Not picked again by the next tick while its transfer is in flight
+                ACTIVE_MANDATES_MAP.with(|p| p.borrow_mut().insert(id, now + IN_FLIGHT_TIMEOUT));
+                run(mandate).await;
+            }
+            _ => {
+                ACTIVE_MANDATES_MAP.with(|p| p.borrow_mut().remove(&id));
+            }
+        }
+    }
+}
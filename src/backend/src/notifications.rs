@@ -0,0 +1,650 @@
+use crate::app_requests::{self, AppRequestStatus};
+use crate::business::{self, is_business};
+use crate::error::{NotificationError, StaffError};
+use crate::mandates::MandateEventKind;
+use crate::staff::{self, Permission, Role};
+use crate::sync::{self, Change};
+use crate::user::{self, is_user, user_caller, ChatId, MessageId};
+use crate::web_push;
+use crate::{timer, PayIds, NOTIFICATIONS_MAP, NOTIFICATION_SCHEDULE_MAP, NOTIFICATION_STATE_MAP};
+use candid::{Decode, Encode, Nat, Principal};
+use ic_cdk::api::time;
+use ic_stable_structures::storable::{Bound, Storable};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+const MAX_NOTIFICATIONS: u64 = 500; // latest kept per account
+const PAGE_SIZE: u64 = 20;
+const DUE_NOTICE: u64 = 3_600_000_000_000; // payers are reminded 1 hour before a request expires
+const EXPIRY_BUFFER: u64 = 1_800_000_000_000; // requests can still be paid 30 minutes after expires_at
+const NOTICES_PER_TICK: usize = 50;
+
+#[derive(
+    candid::CandidType, Clone, Copy, Serialize, Debug, Deserialize, PartialEq, Eq, PartialOrd, Ord,
+)]
+pub enum NotificationKind {
+    PaymentReceived,
+    RequestReceived,
+    RequestPaid,
+    RequestExpired,
+    BusinessAdded,
+    Mention,
+    InvoiceDue,
+    MandateProblem,
+    AppRequestReceived,
+    AppRequestClosed,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub enum NotificationEvent {
+    PaymentReceived {
+        tx_id: Nat,
+        from: String, // pay_id, or the principal of a payer without an account
+        amount: Nat,
+    },
+    RequestReceived {
+        chat_id: ChatId,
+        message_id: MessageId,
+        from: String,
+        amount: Nat,
+    },
+    RequestPaid {
+        chat_id: ChatId,
+        message_id: MessageId,
+        by: String,
+        amount: Nat,
+        tx_id: Nat,
+    },
+    RequestExpired {
+        chat_id: ChatId,
+        message_id: MessageId,
+        amount: Nat,
+    },
+    BusinessAdded {
+        business: Principal, // business the account was added to as a member
+        name: String,
+        role: Role,
+    },
+    Mention {
+        chat_id: ChatId,
+        message_id: MessageId,
+        by: String,
+    },
+    InvoiceDue {
+        chat_id: ChatId,
+        message_id: MessageId,
+        from: String,
+        amount: Nat,
+        expires_at: u64,
+    },
+    MandateProblem {
+        mandate_id: u64,
+        payee: String, // pay_id
+        amount: Nat,
+        event: MandateEventKind, // Failed, Skipped or Blocked
+    },
+    AppRequestReceived {
+        request_id: u64,
+        app: String, // name of the app
+        amount: Nat,
+        memo: Option<String>,
+    },
+    AppRequestClosed {
+        request_id: u64,
+        app: String,
+        amount: Nat,
+        status: AppRequestStatus, // Paid, Declined or Expired
+    },
+}
+
+impl NotificationEvent {
+    fn kind(&self) -> NotificationKind {
+        match self {
+            NotificationEvent::PaymentReceived { .. } => NotificationKind::PaymentReceived,
+            NotificationEvent::RequestReceived { .. } => NotificationKind::RequestReceived,
+            NotificationEvent::RequestPaid { .. } => NotificationKind::RequestPaid,
+            NotificationEvent::RequestExpired { .. } => NotificationKind::RequestExpired,
+            NotificationEvent::BusinessAdded { .. } => NotificationKind::BusinessAdded,
+            NotificationEvent::Mention { .. } => NotificationKind::Mention,
+            NotificationEvent::InvoiceDue { .. } => NotificationKind::InvoiceDue,
+            NotificationEvent::MandateProblem { .. } => NotificationKind::MandateProblem,
+            NotificationEvent::AppRequestReceived { .. } => NotificationKind::AppRequestReceived,
+            NotificationEvent::AppRequestClosed { .. } => NotificationKind::AppRequestClosed,
+        }
+    }
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct Notification {
+    id: u64, // index in the feed of the account
+    event: NotificationEvent,
+    created_at: u64,
+    read: bool, // set when returned, from NotificationState.read_up_to
+}
+
+impl Storable for Notification {
+    const BOUND: Bound = Bound::Unbounded;
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize, Default)]
+pub struct NotificationState {
+    len: u64,
+    read_up_to: u64, // notifications with a lower id are read
+    muted: Vec<NotificationKind>,
+}
+
+impl Storable for NotificationState {
+    const BOUND: Bound = Bound::Unbounded;
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl NotificationState {
+    fn get(key: &Principal) -> Option<NotificationState> {
+        NOTIFICATION_STATE_MAP.with(|p| p.borrow().get(key))
+    }
+
+    fn insert(key: Principal, value: NotificationState) -> Option<NotificationState> {
+        NOTIFICATION_STATE_MAP.with(|p| p.borrow_mut().insert(key, value))
+    }
+
+    fn unread(&self) -> u64 {
+        self.len
+            - self
+                .read_up_to
+                .max(self.len.saturating_sub(MAX_NOTIFICATIONS))
+    }
+}
+
+// Notification sent later by the timer, if the payment request is still unpaid by then
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub enum ScheduledNotice {
+    RequestDue {
+        chat_id: ChatId,
+        message_id: MessageId,
+    },
+    RequestExpired {
+        chat_id: ChatId,
+        message_id: MessageId,
+    },
+    AppRequestExpired {
+        request_id: u64,
+    },
+}
+
+impl Storable for ScheduledNotice {
+    const BOUND: Bound = Bound::Unbounded;
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct NotificationPage {
+    notifications: Vec<Notification>, // latest first
+    next_cursor: Option<u64>,         // pass to get_notifications for older ones
+    unread: u64,
+}
+
+// Key is "{principal}/{id}", id zero padded so the feed of an account is in order
+fn feed_key(principal: &Principal, id: u64) -> String {
+    format!("{}/{:012}", principal.to_text(), id)
+}
+
+// Key is "{at}/{chat id}/{message id}/{notice}", at zero padded so notices are in time order
+fn schedule_key(at: u64, chat_id: &ChatId, message_id: MessageId, notice: &str) -> String {
+    format!("{:020}/{}/{}/{}", at, chat_id, message_id, notice)
+}
+
+// Key is "{at}/app/{request id}", in the same time order as the chat notices
+fn app_schedule_key(at: u64, request_id: u64) -> String {
+    format!("{:020}/app/{}", at, request_id)
+}
+
+fn has_feed(principal: &Principal) -> bool {
+    is_user(principal) || is_business(principal) || staff::member_of(principal).is_some()
+}
+
+/*
+ * Feed the caller works on. Without a profile it's the caller's own, members get theirs for being
+ * added to a business. A business profile is the business feed, shared by everyone allowed to see it.
+ */
+pub fn feed_of(
+    profile: Option<Principal>,
+    permission: Permission,
+) -> Result<Principal, StaffError> {
+    let caller = user_caller();
+    let feed = match profile {
+        Some(profile) if profile != caller => staff::actor(Some(profile), permission)?.business,
+        _ => caller,
+    };
+    if !has_feed(&feed) {
+        return Err(StaffError::AccountNotFound);
+    }
+    Ok(feed)
+}
+
+// pay_id of an account, the principal for payers without one
+fn display_id(principal: &Principal) -> String {
+    if let Some(user) = user::get_metadata(principal) {
+        user.pay_id
+    } else if let Some(business) = business::get_metadata(principal) {
+        business.pay_id
+    } else {
+        principal.to_text()
+    }
+}
+
+// Adds event to the feed of principal unless its kind is muted
+pub fn notify(principal: Principal, event: NotificationEvent) {
+    let mut state = NotificationState::get(&principal).unwrap_or_default();
+    if state.muted.contains(&event.kind()) {
+        return;
+    }
+
+    let id = state.len;
+    push(&principal, id, &event);
+    NOTIFICATIONS_MAP.with(|p| {
+        let mut map = p.borrow_mut();
+        map.insert(
+            feed_key(&principal, id),
+            Notification {
+                id,
+                event,
+                created_at: time(),
+                read: false,
+            },
+        );
+        if id >= MAX_NOTIFICATIONS {
+            map.remove(&feed_key(&principal, id - MAX_NOTIFICATIONS));
+        }
+    });
+    state.len += 1;
+    NotificationState::insert(principal, state);
+    sync::record_change(principal, Change::Notification(id));
+}
+
+// Payments arriving also go to the devices of principal as web push
+fn push(principal: &Principal, id: u64, event: &NotificationEvent) {
+    let (title, body) = match event {
+        NotificationEvent::PaymentReceived { from, amount, .. } => (
+            "Payment received",
+            format!("{} sent you {} sats", from, amount),
+        ),
+        NotificationEvent::RequestPaid { by, amount, .. } => (
+            "Payment request paid",
+            format!("{} paid your request of {} sats", by, amount),
+        ),
+        NotificationEvent::AppRequestReceived { app, amount, .. } => (
+            "Payment request",
+            format!("{} requests {} sats", app, amount),
+        ),
+        NotificationEvent::MandateProblem { payee, amount, .. } => (
+            "Recurring payment not made",
+            format!(
+                "Your payment of {} sats to {} didn't go through",
+                amount, payee
+            ),
+        ),
+        _ => return,
+    };
+    web_push::push(principal, title, &body, &format!("notification-{}", id));
+}
+
+pub fn payment_received(to: Principal, from: Principal, tx_id: &Nat, amount: &Nat) {
+    notify(
+        to,
+        NotificationEvent::PaymentReceived {
+            tx_id: tx_id.clone(),
+            from: display_id(&from),
+            amount: amount.clone(),
+        },
+    );
+}
+
+// Tells the other participants of the chat and schedules the due and expired notices
+pub fn request_received(
+    participants: &[String],
+    requester: &str,
+    chat_id: &ChatId,
+    message_id: MessageId,
+    amount: &Nat,
+    expires_at: u64,
+) {
+    for pay_id in participants.iter().filter(|pay_id| *pay_id != requester) {
+        if let Some(principal) = PayIds::get(pay_id) {
+            notify(
+                principal,
+                NotificationEvent::RequestReceived {
+                    chat_id: chat_id.clone(),
+                    message_id,
+                    from: requester.to_string(),
+                    amount: amount.clone(),
+                },
+            );
+        }
+    }
+
+    let due_at = expires_at.saturating_sub(DUE_NOTICE);
+    let expired_at = expires_at + EXPIRY_BUFFER;
+    NOTIFICATION_SCHEDULE_MAP.with(|p| {
+        let mut map = p.borrow_mut();
+        map.insert(
+            schedule_key(due_at, chat_id, message_id, "due"),
+            ScheduledNotice::RequestDue {
+                chat_id: chat_id.clone(),
+                message_id,
+            },
+        );
+        map.insert(
+            schedule_key(expired_at, chat_id, message_id, "expired"),
+            ScheduledNotice::RequestExpired {
+                chat_id: chat_id.clone(),
+                message_id,
+            },
+        );
+    });
+    timer::schedule_at(due_at);
+}
+
+pub fn request_paid(
+    requester: Principal,
+    payer: Principal,
+    chat_id: &ChatId,
+    message_id: MessageId,
+    amount: &Nat,
+    tx_id: &Nat,
+) {
+    notify(
+        requester,
+        NotificationEvent::RequestPaid {
+            chat_id: chat_id.clone(),
+            message_id,
+            by: display_id(&payer),
+            amount: amount.clone(),
+            tx_id: tx_id.clone(),
+        },
+    );
+}
+
+// Participants whose pay_id follows an @ in the message, except the sender
+pub fn mentions(
+    participants: &[String],
+    sender: &str,
+    chat_id: &ChatId,
+    message_id: MessageId,
+    content: &str,
+) {
+    let mentioned = content
+        .split(|c: char| !(c.is_alphanumeric() || c == '@' || c == '_' || c == '-' || c == '.'))
+        .filter_map(|word| word.strip_prefix('@'))
+        .map(|pay_id| pay_id.trim_end_matches('.'))
+        .collect::<Vec<_>>();
+
+    for pay_id in participants
+        .iter()
+        .filter(|pay_id| *pay_id != sender && mentioned.contains(&pay_id.as_str()))
+    {
+        if let Some(principal) = PayIds::get(pay_id) {
+            notify(
+                principal,
+                NotificationEvent::Mention {
+                    chat_id: chat_id.clone(),
+                    message_id,
+                    by: sender.to_string(),
+                },
+            );
+        }
+    }
+}
+
+// Payer of a mandate whose run failed, was skipped or was held back by its spending policy
+pub fn mandate_problem(
+    payer: Principal,
+    mandate_id: u64,
+    payee: &str,
+    amount: &Nat,
+    event: MandateEventKind,
+) {
+    notify(
+        payer,
+        NotificationEvent::MandateProblem {
+            mandate_id,
+            payee: payee.to_string(),
+            amount: amount.clone(),
+            event,
+        },
+    );
+}
+
+// Payment requests of apps aren't in a chat, so the user learns of them here
+pub fn app_request_received(
+    user: Principal,
+    request_id: u64,
+    app: &str,
+    amount: &Nat,
+    memo: Option<String>,
+    expires_at: u64,
+) {
+    notify(
+        user,
+        NotificationEvent::AppRequestReceived {
+            request_id,
+            app: app.to_string(),
+            amount: amount.clone(),
+            memo,
+        },
+    );
+
+    let expired_at = expires_at + EXPIRY_BUFFER;
+    NOTIFICATION_SCHEDULE_MAP.with(|p| {
+        p.borrow_mut().insert(
+            app_schedule_key(expired_at, request_id),
+            ScheduledNotice::AppRequestExpired { request_id },
+        )
+    });
+    timer::schedule_at(expired_at);
+}
+
+pub fn app_request_closed(
+    user: Principal,
+    request_id: u64,
+    app: &str,
+    amount: &Nat,
+    status: AppRequestStatus,
+) {
+    notify(
+        user,
+        NotificationEvent::AppRequestClosed {
+            request_id,
+            app: app.to_string(),
+            amount: amount.clone(),
+            status,
+        },
+    );
+}
+
+pub fn business_added(member: Principal, business: Principal, role: Role) {
+    let name = business::get_metadata(&business).map_or_else(|| business.to_text(), |b| b.name);
+    notify(
+        member,
+        NotificationEvent::BusinessAdded {
+            business,
+            name,
+            role,
+        },
+    );
+}
+
+fn check_caller(
+    profile: Option<Principal>,
+    permission: Permission,
+) -> Result<(Principal, NotificationState), NotificationError> {
+    let feed = feed_of(profile, permission)?;
+    Ok((feed, NotificationState::get(&feed).unwrap_or_default()))
+}
+
+// Latest notifications first, cursor is the next_cursor of the previous page
+pub fn get_notifications(
+    cursor: Option<u64>,
+    profile: Option<Principal>,
+) -> Result<NotificationPage, NotificationError> {
+    let (caller, state) = check_caller(profile, Permission::ViewBusiness)?;
+
+    let oldest = state.len.saturating_sub(MAX_NOTIFICATIONS);
+    let end = cursor.unwrap_or(state.len).min(state.len).max(oldest);
+    let start = end.saturating_sub(PAGE_SIZE).max(oldest);
+    let notifications = get_notifications_at(&caller, &state, (start..end).rev());
+
+    Ok(NotificationPage {
+        notifications,
+        next_cursor: if start > oldest { Some(start) } else { None },
+        unread: state.unread(),
+    })
+}
+
+fn get_notifications_at(
+    principal: &Principal,
+    state: &NotificationState,
+    ids: impl Iterator<Item = u64>,
+) -> Vec<Notification> {
+    NOTIFICATIONS_MAP.with(|p| {
+        let map = p.borrow();
+        ids.filter_map(|id| map.get(&feed_key(principal, id)))
+            .map(|mut notification| {
+                notification.read = notification.id < state.read_up_to;
+                notification
+            })
+            .collect()
+    })
+}
+
+pub fn get_notifications_by_id(principal: &Principal, ids: &[u64]) -> Vec<Notification> {
+    let state = NotificationState::get(principal).unwrap_or_default();
+    get_notifications_at(principal, &state, ids.iter().copied())
+}
+
+// Marks the notifications before up_to read, all of them without it. Returns the unread count left
+pub fn mark_notifications_read(
+    up_to: Option<u64>,
+    profile: Option<Principal>,
+) -> Result<u64, NotificationError> {
+    let (caller, mut state) = check_caller(profile, Permission::ViewBusiness)?;
+
+    let up_to = up_to.unwrap_or(state.len).min(state.len);
+    if up_to > state.read_up_to {
+        state.read_up_to = up_to;
+        NotificationState::insert(caller, state.clone());
+    }
+    Ok(state.unread())
+}
+
+pub fn get_muted_notifications(
+    profile: Option<Principal>,
+) -> Result<Vec<NotificationKind>, NotificationError> {
+    let (_, state) = check_caller(profile, Permission::ViewBusiness)?;
+    Ok(state.muted)
+}
+
+// Muted kinds aren't added to the feed at all, unmuting doesn't bring back what was missed
+pub fn set_muted_notifications(
+    mut muted: Vec<NotificationKind>,
+    profile: Option<Principal>,
+) -> Result<Vec<NotificationKind>, NotificationError> {
+    let (caller, mut state) = check_caller(profile, Permission::ManageSettings)?;
+
+    muted.sort();
+    muted.dedup();
+    state.muted = muted.clone();
+    NotificationState::insert(caller, state);
+    Ok(muted)
+}
+
+// Earliest scheduled notice
+pub fn next_notice_at() -> Option<u64> {
+    NOTIFICATION_SCHEDULE_MAP.with(|p| {
+        p.borrow()
+            .first_key_value()
+            .and_then(|(key, _)| key.split('/').next()?.parse().ok())
+    })
+}
+
+// Sends the scheduled notices that are due, called by the timer
+pub fn run_due() {
+    let now = time();
+    let due = NOTIFICATION_SCHEDULE_MAP.with(|p| {
+        p.borrow()
+            .range(..format!("{:020}0", now))
+            .take(NOTICES_PER_TICK)
+            .collect::<Vec<_>>()
+    });
+
+    for (key, notice) in due {
+        NOTIFICATION_SCHEDULE_MAP.with(|p| p.borrow_mut().remove(&key));
+
+        let (chat_id, message_id) = match &notice {
+            ScheduledNotice::RequestDue {
+                chat_id,
+                message_id,
+            }
+            | ScheduledNotice::RequestExpired {
+                chat_id,
+                message_id,
+            } => (chat_id.clone(), *message_id),
+            ScheduledNotice::AppRequestExpired { request_id } => {
+                app_requests::expired(*request_id);
+                continue;
+            }
+        };
+        let request = match user::unpaid_request(&chat_id, message_id) {
+            Some(request) => request,
+            None => continue, // paid meanwhile
+        };
+
+        match notice {
+            ScheduledNotice::RequestDue { .. } => {
+                for principal in request.payers.iter().filter_map(PayIds::get) {
+                    notify(
+                        principal,
+                        NotificationEvent::InvoiceDue {
+                            chat_id: chat_id.clone(),
+                            message_id,
+                            from: request.requester.clone(),
+                            amount: request.amount.clone(),
+                            expires_at: request.expires_at,
+                        },
+                    );
+                }
+            }
+            ScheduledNotice::RequestExpired { .. } => {
+                if let Some(principal) = PayIds::get(&request.requester) {
+                    notify(
+                        principal,
+                        NotificationEvent::RequestExpired {
+                            chat_id,
+                            message_id,
+                            amount: request.amount,
+                        },
+                    );
+                }
+            }
+            ScheduledNotice::AppRequestExpired { .. } => {}
+        }
+    }
+}
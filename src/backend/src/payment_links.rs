@@ -0,0 +1,394 @@
+use crate::business::{self, is_business};
+use crate::checkout;
+use crate::error::PaymentLinkError;
+use crate::profiles;
+use crate::staff::{self, Actor, AuditAction, Permission};
+use crate::user::{self, is_user};
+use crate::{LINK_PAYMENTS_MAP, OWNER_LINKS_MAP, PAYMENT_LINKS_MAP};
+use candid::{Decode, Encode, Nat, Principal};
+use ic_cdk::api::time;
+use ic_stable_structures::storable::{Bound, Storable};
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+use std::borrow::Cow;
+
+const MAX_TITLE_LEN: usize = 100;
+const MAX_ACTIVE_LINKS: usize = 100;
+const MEMO_PREFIX: &[u8] = b"LNK"; // memo is the prefix followed by the link id in big endian
+const PAYMENTS_PAGE_SIZE: u64 = 50;
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct PaymentLink {
+    id: u64,
+    owner: Principal, // user or business paid by the link
+    title: String,
+    amount: Option<Nat>, // None lets the payer pick the amount
+    single_use: bool,
+    expires_at: Option<u64>,
+    created_by: Principal,
+    created_at: u64,
+    disabled_at: Option<u64>,
+    payment_count: u64,
+    total_received: Nat,
+    last_paid_at: Option<u64>,
+}
+
+impl Storable for PaymentLink {
+    const BOUND: Bound = Bound::Unbounded;
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl PaymentLink {
+    fn get(key: u64) -> Option<PaymentLink> {
+        PAYMENT_LINKS_MAP.with(|p| p.borrow().get(&key))
+    }
+
+    fn insert(key: u64, value: PaymentLink) -> Option<PaymentLink> {
+        PAYMENT_LINKS_MAP.with(|p| p.borrow_mut().insert(key, value))
+    }
+
+    fn next_id() -> u64 {
+        PAYMENT_LINKS_MAP.with(|p| p.borrow().last_key_value().map_or(0, |(id, _)| id + 1))
+    }
+
+    fn status(&self, now: u64) -> LinkStatus {
+        if self.disabled_at.is_some() {
+            LinkStatus::Disabled
+        } else if self.single_use && self.payment_count > 0 {
+            LinkStatus::Paid
+        } else if self.expires_at.is_some_and(|expires_at| now > expires_at) {
+            LinkStatus::Expired
+        } else if self.payment_count > 0 {
+            LinkStatus::PaidOpen
+        } else {
+            LinkStatus::Unpaid
+        }
+    }
+
+    // Can still take a payment made at timestamp
+    fn accepts(&self, timestamp: u64) -> bool {
+        matches!(
+            self.status(timestamp),
+            LinkStatus::Unpaid | LinkStatus::PaidOpen
+        )
+    }
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize, PartialEq)]
+pub enum LinkStatus {
+    Unpaid,
+    Paid,     // single use link paid
+    PaidOpen, // multi use link paid at least once and still taking payments
+    Expired,
+    Disabled,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct LinkPayment {
+    tx_id: Nat,
+    from: Principal, // any principal, payers don't need an account
+    amount: Nat,
+    timestamp: u64,
+}
+
+impl Storable for LinkPayment {
+    const BOUND: Bound = Bound::Unbounded;
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+// Link as shown to payers, public
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct PaymentLinkView {
+    id: u64,
+    owner_name: String,
+    owner_pay_id: String,
+    title: String,
+    amount: Option<Nat>,
+    single_use: bool,
+    expires_at: Option<u64>,
+    status: LinkStatus,
+    payment_uri: String, // ICRC-22 with the link memo, amount left out for open amounts
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct PaymentLinkDetails {
+    link: PaymentLink,
+    view: PaymentLinkView,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct LinkPaymentPage {
+    payments: Vec<LinkPayment>, // latest first
+    next_page: Option<u64>,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct CreatePaymentLinkArg {
+    title: String,
+    amount: Option<Nat>,
+    single_use: bool,
+    expires_at: Option<u64>,
+}
+
+fn link_memo(id: u64) -> Vec<u8> {
+    [MEMO_PREFIX, &id.to_be_bytes()].concat()
+}
+
+fn link_id(memo: &[u8]) -> Option<u64> {
+    let id = memo.strip_prefix(MEMO_PREFIX)?;
+    Some(u64::from_be_bytes(id.try_into().ok()?))
+}
+
+// Key is "{owner}/{id}", id zero padded so links of an owner are in order
+fn owner_key(owner: &Principal, id: u64) -> String {
+    format!("{}/{:020}", owner.to_text(), id)
+}
+
+fn payment_key(id: u64, index: u64) -> String {
+    format!("{:020}/{:012}", id, index)
+}
+
+fn link_ids(owner: &Principal) -> Vec<u64> {
+    let prefix = format!("{}/", owner.to_text());
+    OWNER_LINKS_MAP.with(|p| {
+        p.borrow()
+            .range(prefix.clone()..)
+            .take_while(|(key, _)| key.starts_with(&prefix))
+            .map(|(_, id)| id)
+            .collect()
+    })
+}
+
+fn view(link: &PaymentLink) -> PaymentLinkView {
+    let (owner_name, owner_pay_id) = if let Some(user) = user::get_metadata(&link.owner) {
+        (user.name, user.pay_id)
+    } else if let Some(business) = business::get_metadata(&link.owner) {
+        (business.name, business.pay_id)
+    } else {
+        (link.owner.to_text(), link.owner.to_text())
+    };
+
+    PaymentLinkView {
+        id: link.id,
+        owner_name,
+        owner_pay_id,
+        title: link.title.clone(),
+        amount: link.amount.clone(),
+        single_use: link.single_use,
+        expires_at: link.expires_at,
+        status: link.status(time()),
+        payment_uri: checkout::payment_uri(
+            &profiles::ledger_account(&link.owner),
+            link.amount.as_ref(),
+            &link_memo(link.id),
+        ),
+    }
+}
+
+/*
+ * Account the caller manages links for, a business through its staff or the caller's own user
+ * account. Business staff need the CreateInvoice permission, their actor is returned for the audit log.
+ */
+fn owner(profile: Option<Principal>) -> Result<(Principal, Option<Actor>), PaymentLinkError> {
+    let account = staff::account(profile, Permission::CreateInvoice)?;
+    if is_user(&account) {
+        return Ok((account, None));
+    }
+    let actor = staff::actor(Some(account), Permission::CreateInvoice)?;
+    Ok((actor.business, Some(actor)))
+}
+
+fn owned_link(id: u64, owner: &Principal) -> Result<PaymentLink, PaymentLinkError> {
+    match PaymentLink::get(id) {
+        Some(link) if &link.owner == owner => Ok(link),
+        _ => Err(PaymentLinkError::LinkNotFound),
+    }
+}
+
+pub fn create_payment_link(
+    CreatePaymentLinkArg {
+        title,
+        amount,
+        single_use,
+        expires_at,
+    }: CreatePaymentLinkArg,
+    profile: Option<Principal>,
+) -> Result<PaymentLinkDetails, PaymentLinkError> {
+    let (owner, actor) = owner(profile)?;
+
+    if title.chars().count() > MAX_TITLE_LEN {
+        return Err(PaymentLinkError::TitleTooLong { max: MAX_TITLE_LEN });
+    }
+    if amount.as_ref().is_some_and(|amount| *amount == 0u64) {
+        return Err(PaymentLinkError::InvalidAmount);
+    }
+    let now = time();
+    if expires_at.is_some_and(|expires_at| expires_at <= now) {
+        return Err(PaymentLinkError::InvalidExpiry);
+    }
+
+    let active = link_ids(&owner)
+        .into_iter()
+        .filter_map(PaymentLink::get)
+        .filter(|link| link.accepts(now))
+        .count();
+    if active >= MAX_ACTIVE_LINKS {
+        return Err(PaymentLinkError::TooManyLinks {
+            max: MAX_ACTIVE_LINKS,
+        });
+    }
+
+    let id = PaymentLink::next_id();
+    let link = PaymentLink {
+        id,
+        owner,
+        title,
+        amount,
+        single_use,
+        expires_at,
+        created_by: actor.as_ref().map_or(owner, |actor| actor.member),
+        created_at: now,
+        disabled_at: None,
+        payment_count: 0,
+        total_received: Nat::from(0u64),
+        last_paid_at: None,
+    };
+    PaymentLink::insert(id, link.clone());
+    OWNER_LINKS_MAP.with(|p| p.borrow_mut().insert(owner_key(&owner, id), id));
+    if let Some(actor) = actor {
+        actor.audit(AuditAction::PaymentLinkCreated { id });
+    }
+
+    Ok(PaymentLinkDetails {
+        view: view(&link),
+        link,
+    })
+}
+
+// What a payer opening the link sees, callable by anyone
+pub fn get_payment_link(id: u64) -> Option<PaymentLinkView> {
+    PaymentLink::get(id).map(|link| view(&link))
+}
+
+// Links of the caller or its business, latest first
+pub fn list_payment_links(
+    profile: Option<Principal>,
+) -> Result<Vec<PaymentLinkDetails>, PaymentLinkError> {
+    let (owner, _) = owner(profile)?;
+
+    Ok(link_ids(&owner)
+        .into_iter()
+        .rev()
+        .filter_map(PaymentLink::get)
+        .map(|link| PaymentLinkDetails {
+            view: view(&link),
+            link,
+        })
+        .collect())
+}
+
+pub fn disable_payment_link(
+    id: u64,
+    profile: Option<Principal>,
+) -> Result<PaymentLinkDetails, PaymentLinkError> {
+    let (owner, actor) = owner(profile)?;
+    let mut link = owned_link(id, &owner)?;
+
+    if link.disabled_at.is_none() {
+        link.disabled_at = Some(time());
+        PaymentLink::insert(id, link.clone());
+        if let Some(actor) = actor {
+            actor.audit(AuditAction::PaymentLinkDisabled { id });
+        }
+    }
+    Ok(PaymentLinkDetails {
+        view: view(&link),
+        link,
+    })
+}
+
+pub fn get_link_payments(
+    id: u64,
+    page: u64,
+    profile: Option<Principal>,
+) -> Result<LinkPaymentPage, PaymentLinkError> {
+    let (owner, _) = owner(profile)?;
+    let link = owned_link(id, &owner)?;
+
+    let end = link.payment_count.saturating_sub(page * PAYMENTS_PAGE_SIZE);
+    let start = end.saturating_sub(PAYMENTS_PAGE_SIZE);
+    let payments = LINK_PAYMENTS_MAP.with(|p| {
+        let map = p.borrow();
+        (start..end)
+            .rev()
+            .filter_map(|index| map.get(&payment_key(id, index)))
+            .collect::<Vec<_>>()
+    });
+
+    Ok(LinkPaymentPage {
+        payments,
+        next_page: if start > 0 { Some(page + 1) } else { None },
+    })
+}
+
+/*
+ * Adds the transfer to the log of the link in memo if it pays its owner and the link still takes
+ * payments. Called by record_xfer_transaction, which anyone can call for a payer without an account.
+ */
+pub fn record_payment(
+    tx_id: &Nat,
+    from: Principal,
+    to: Principal,
+    amount: &Nat,
+    memo: Option<&ByteBuf>,
+    timestamp: u64,
+) -> bool {
+    let id = match memo.and_then(|memo| link_id(memo)) {
+        Some(id) => id,
+        None => return false,
+    };
+    let mut link = match PaymentLink::get(id) {
+        Some(link) => link,
+        None => return false,
+    };
+
+    let covers = match &link.amount {
+        Some(price) => amount >= price,
+        None => *amount > 0u64,
+    };
+    if link.owner != to || !covers || !link.accepts(timestamp) {
+        return false;
+    }
+    if !is_user(&to) && !is_business(&to) {
+        return false;
+    }
+
+    LINK_PAYMENTS_MAP.with(|p| {
+        p.borrow_mut().insert(
+            payment_key(id, link.payment_count),
+            LinkPayment {
+                tx_id: tx_id.clone(),
+                from,
+                amount: amount.clone(),
+                timestamp,
+            },
+        )
+    });
+    link.payment_count += 1;
+    link.total_received += amount.clone();
+    link.last_paid_at = Some(timestamp);
+    PaymentLink::insert(id, link);
+    true
+}
@@ -0,0 +1,553 @@
+use crate::analytics;
+use crate::business::is_business;
+use crate::error::{PolicyError, SpendingPolicyError};
+use crate::mandates;
+use crate::staff::{self, Permission};
+use crate::user::{is_user, user_caller};
+use crate::{POLICY_LOG_MAP, SEND_APPROVALS_MAP, SPENDING_POLICIES_MAP, USER_APPROVALS_MAP};
+use candid::{Decode, Encode, Nat, Principal};
+use ic_cdk::api::time;
+use ic_stable_structures::storable::{Bound, Storable};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+const DAY: u64 = 86_400_000_000_000; // 1 day in nanoseconds
+const APPROVAL_TTL: u64 = 7 * DAY; // pending or unused approvals lapse after this
+const LOG_PAGE_SIZE: u64 = 50;
+
+// Amount sent by canister-initiated sends on one UTC day
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct DaySpend {
+    day: u64, // since epoch
+    amount: Nat,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize, Default)]
+pub struct SpendingPolicy {
+    daily_cap: Option<Nat>,
+    monthly_cap: Option<Nat>, // calendar month in UTC
+    max_per_tx: Option<Nat>,
+    approval_threshold: Option<Nat>, // sends above it need the approver's approval
+    approver: Option<Principal>,     // once set, the only one able to change the policy
+    updated_at: u64,
+    spending: Vec<DaySpend>, // days of the current month
+    log_len: u64,
+}
+
+impl Storable for SpendingPolicy {
+    const BOUND: Bound = Bound::Unbounded;
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl SpendingPolicy {
+    fn get(key: &Principal) -> Option<SpendingPolicy> {
+        SPENDING_POLICIES_MAP.with(|p| p.borrow().get(key))
+    }
+
+    fn insert(key: Principal, value: SpendingPolicy) -> Option<SpendingPolicy> {
+        SPENDING_POLICIES_MAP.with(|p| p.borrow_mut().insert(key, value))
+    }
+
+    fn is_set(&self) -> bool {
+        self.daily_cap.is_some()
+            || self.monthly_cap.is_some()
+            || self.max_per_tx.is_some()
+            || self.approval_threshold.is_some()
+    }
+
+    fn spent_since(&self, day: u64) -> Nat {
+        self.spending
+            .iter()
+            .filter(|spend| spend.day >= day)
+            .fold(Nat::from(0u64), |sum, spend| sum + spend.amount.clone())
+    }
+
+    fn add_spent(&mut self, day: u64, amount: &Nat) {
+        match self.spending.iter_mut().find(|spend| spend.day == day) {
+            Some(spend) => spend.amount += amount.clone(),
+            None => self.spending.push(DaySpend {
+                day,
+                amount: amount.clone(),
+            }),
+        }
+    }
+
+    fn remove_spent(&mut self, day: u64, amount: &Nat) {
+        if let Some(spend) = self.spending.iter_mut().find(|spend| spend.day == day) {
+            spend.amount = if spend.amount > *amount {
+                spend.amount.clone() - amount.clone()
+            } else {
+                Nat::from(0u64)
+            };
+        }
+    }
+
+    // Days before the current month don't count for any cap anymore
+    fn prune(&mut self, today: u64) {
+        let month_start = analytics::month_start(today);
+        self.spending.retain(|spend| spend.day >= month_start);
+    }
+
+    fn log(&mut self, account: &Principal, decision: PolicyDecision) {
+        POLICY_LOG_MAP.with(|p| {
+            p.borrow_mut()
+                .insert(log_key(account, self.log_len), decision)
+        });
+        self.log_len += 1;
+    }
+}
+
+// Send made by the canister on behalf of an account
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize, PartialEq)]
+pub enum PolicySend {
+    MandatePayment { mandate_id: u64, run_at: u64 },
+    EscrowRelease { escrow_id: u64 },
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize, PartialEq)]
+pub enum ApprovalStatus {
+    Pending,
+    Approved { at: u64 },
+    Rejected { at: u64 },
+    Used { at: u64 }, // the approved send went through
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct SendApproval {
+    id: u64,
+    account: Principal,
+    approver: Principal,
+    send: PolicySend,
+    to: Principal,
+    amount: Nat,
+    status: ApprovalStatus,
+    created_at: u64,
+    expires_at: u64,
+}
+
+impl Storable for SendApproval {
+    const BOUND: Bound = Bound::Unbounded;
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl SendApproval {
+    fn get(key: u64) -> Option<SendApproval> {
+        SEND_APPROVALS_MAP.with(|p| p.borrow().get(&key))
+    }
+
+    fn insert(key: u64, value: SendApproval) -> Option<SendApproval> {
+        SEND_APPROVALS_MAP.with(|p| p.borrow_mut().insert(key, value))
+    }
+
+    fn next_id() -> u64 {
+        SEND_APPROVALS_MAP.with(|p| p.borrow().last_key_value().map_or(0, |(id, _)| id + 1))
+    }
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub enum PolicyOutcome {
+    Allowed { approval_id: Option<u64> },
+    Denied { error: PolicyError },
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct PolicyDecision {
+    send: PolicySend,
+    to: Principal,
+    amount: Nat,
+    outcome: PolicyOutcome,
+    timestamp: u64,
+}
+
+impl Storable for PolicyDecision {
+    const BOUND: Bound = Bound::Unbounded;
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct SpendingPolicyArg {
+    daily_cap: Option<Nat>,
+    monthly_cap: Option<Nat>,
+    max_per_tx: Option<Nat>,
+    approval_threshold: Option<Nat>,
+    approver: Option<Principal>,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct SpendingPolicyView {
+    account: Principal,
+    daily_cap: Option<Nat>,
+    monthly_cap: Option<Nat>,
+    max_per_tx: Option<Nat>,
+    approval_threshold: Option<Nat>,
+    approver: Option<Principal>,
+    updated_at: u64,
+    spent_today: Nat,
+    spent_this_month: Nat,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct PolicyLogPage {
+    decisions: Vec<PolicyDecision>, // latest first
+    next_page: Option<u64>,
+}
+
+// Spend counted against the caps, given back with revert when the send fails
+pub struct Authorization {
+    account: Principal,
+    day: u64,
+    amount: Nat,
+    approval_id: Option<u64>,
+}
+
+// Key is "{principal}/{index}", index zero padded so decisions of an account are in order
+fn log_key(account: &Principal, index: u64) -> String {
+    format!("{}/{:012}", account.to_text(), index)
+}
+
+// Key is "{principal}/{id}", for the account and for the approver
+fn user_key(principal: &Principal, id: u64) -> String {
+    format!("{}/{:020}", principal.to_text(), id)
+}
+
+fn approval_ids(principal: &Principal) -> Vec<u64> {
+    let prefix = format!("{}/", principal.to_text());
+    USER_APPROVALS_MAP.with(|p| {
+        p.borrow()
+            .range(prefix.clone()..)
+            .take_while(|(key, _)| key.starts_with(&prefix))
+            .map(|(_, id)| id)
+            .collect()
+    })
+}
+
+fn view(account: Principal, policy: &SpendingPolicy) -> SpendingPolicyView {
+    let today = time() / DAY;
+    SpendingPolicyView {
+        account,
+        daily_cap: policy.daily_cap.clone(),
+        monthly_cap: policy.monthly_cap.clone(),
+        max_per_tx: policy.max_per_tx.clone(),
+        approval_threshold: policy.approval_threshold.clone(),
+        approver: policy.approver,
+        updated_at: policy.updated_at,
+        spent_today: policy.spent_since(today),
+        spent_this_month: policy.spent_since(analytics::month_start(today)),
+    }
+}
+
+/*
+ * Account whose policy the caller can see. The approver of an account reaches it through profile,
+ * otherwise it's the caller's own user account or a business through its staff.
+ */
+fn account_of(
+    profile: Option<Principal>,
+    permission: Permission,
+) -> Result<Principal, SpendingPolicyError> {
+    let caller = user_caller();
+    if let Some(profile) = profile {
+        let approver = SpendingPolicy::get(&profile).and_then(|policy| policy.approver);
+        if approver == Some(caller) {
+            return Ok(profile);
+        }
+    }
+    Ok(staff::account(profile, permission)?)
+}
+
+/*
+ * Sets the policy of an account, all limits left out removes it. Once the policy has an approver,
+ * like a parent or a finance lead, only the approver can change it, the account itself can't lift
+ * its own limits.
+ */
+pub fn set_spending_policy(
+    SpendingPolicyArg {
+        daily_cap,
+        monthly_cap,
+        max_per_tx,
+        approval_threshold,
+        approver,
+    }: SpendingPolicyArg,
+    profile: Option<Principal>,
+) -> Result<SpendingPolicyView, SpendingPolicyError> {
+    let account = account_of(profile, Permission::ManageSettings)?;
+    let mut policy = SpendingPolicy::get(&account).unwrap_or_default();
+    if policy
+        .approver
+        .is_some_and(|current| current != user_caller())
+    {
+        return Err(SpendingPolicyError::NotPermitted);
+    }
+
+    if let Some(approver) = approver {
+        if approver == account {
+            return Err(SpendingPolicyError::InvalidApprover);
+        }
+        if !is_user(&approver) {
+            return Err(SpendingPolicyError::ApproverNotFound);
+        }
+    }
+    if approval_threshold.is_some() && approver.is_none() {
+        return Err(SpendingPolicyError::ApproverRequired);
+    }
+
+    policy.daily_cap = daily_cap;
+    policy.monthly_cap = monthly_cap;
+    policy.max_per_tx = max_per_tx;
+    policy.approval_threshold = approval_threshold;
+    policy.approver = approver;
+    policy.updated_at = time();
+    if !policy.is_set() {
+        policy.approver = None;
+    }
+
+    let view = view(account, &policy);
+    SpendingPolicy::insert(account, policy);
+    Ok(view)
+}
+
+pub fn get_spending_policy(
+    profile: Option<Principal>,
+) -> Result<Option<SpendingPolicyView>, SpendingPolicyError> {
+    let account = account_of(profile, Permission::ViewBusiness)?;
+    Ok(SpendingPolicy::get(&account)
+        .filter(|policy| policy.is_set())
+        .map(|policy| view(account, &policy)))
+}
+
+pub fn get_policy_log(
+    page: u64,
+    profile: Option<Principal>,
+) -> Result<PolicyLogPage, SpendingPolicyError> {
+    let account = account_of(profile, Permission::ViewAuditLog)?;
+    let log_len = SpendingPolicy::get(&account).map_or(0, |policy| policy.log_len);
+
+    let end = log_len.saturating_sub(page * LOG_PAGE_SIZE);
+    let start = end.saturating_sub(LOG_PAGE_SIZE);
+    let decisions = POLICY_LOG_MAP.with(|p| {
+        let map = p.borrow();
+        (start..end)
+            .rev()
+            .filter_map(|index| map.get(&log_key(&account, index)))
+            .collect::<Vec<_>>()
+    });
+
+    Ok(PolicyLogPage {
+        decisions,
+        next_page: if start > 0 { Some(page + 1) } else { None },
+    })
+}
+
+// Approvals the caller asked for or has to give, latest first
+pub fn get_send_approvals(
+    profile: Option<Principal>,
+) -> Result<Vec<SendApproval>, SpendingPolicyError> {
+    let caller = staff::account(profile, Permission::ViewBusiness)?;
+    if !is_user(&caller) && !is_business(&caller) {
+        return Err(SpendingPolicyError::AccountNotFound);
+    }
+
+    Ok(approval_ids(&caller)
+        .into_iter()
+        .rev()
+        .filter_map(SendApproval::get)
+        .collect())
+}
+
+// Approver's answer to a pending approval, the send goes through at its next attempt
+pub fn decide_send_approval(id: u64, approve: bool) -> Result<SendApproval, SpendingPolicyError> {
+    let mut approval = match SendApproval::get(id) {
+        Some(approval) if approval.approver == user_caller() => approval,
+        _ => return Err(SpendingPolicyError::ApprovalNotFound),
+    };
+    let now = time();
+    if approval.status != ApprovalStatus::Pending || now > approval.expires_at {
+        return Err(SpendingPolicyError::AlreadyDecided);
+    }
+
+    approval.status = if approve {
+        ApprovalStatus::Approved { at: now }
+    } else {
+        ApprovalStatus::Rejected { at: now }
+    };
+    SendApproval::insert(id, approval.clone());
+
+    if let (true, PolicySend::MandatePayment { mandate_id, .. }) = (approve, &approval.send) {
+        mandates::retry_now(*mandate_id);
+    }
+    Ok(approval)
+}
+
+// Latest approval asked for this send, a new one once it lapsed or was used
+fn find_approval(account: &Principal, send: &PolicySend, now: u64) -> Option<SendApproval> {
+    approval_ids(account)
+        .into_iter()
+        .rev()
+        .filter_map(SendApproval::get)
+        .find(|approval| &approval.account == account && &approval.send == send)
+        .filter(|approval| {
+            now <= approval.expires_at && !matches!(approval.status, ApprovalStatus::Used { .. })
+        })
+}
+
+fn check(
+    policy: &SpendingPolicy,
+    account: Principal,
+    send: &PolicySend,
+    to: Principal,
+    amount: &Nat,
+    now: u64,
+) -> Result<Option<u64>, PolicyError> {
+    let today = now / DAY;
+
+    if let Some(max) = &policy.max_per_tx {
+        if amount > max {
+            return Err(PolicyError::PerTransactionLimit { max: max.clone() });
+        }
+    }
+    if let Some(cap) = &policy.daily_cap {
+        let spent = policy.spent_since(today);
+        if spent.clone() + amount.clone() > *cap {
+            return Err(PolicyError::DailyLimit {
+                cap: cap.clone(),
+                spent,
+            });
+        }
+    }
+    if let Some(cap) = &policy.monthly_cap {
+        let spent = policy.spent_since(analytics::month_start(today));
+        if spent.clone() + amount.clone() > *cap {
+            return Err(PolicyError::MonthlyLimit {
+                cap: cap.clone(),
+                spent,
+            });
+        }
+    }
+
+    let approver = match (&policy.approval_threshold, policy.approver) {
+        (Some(threshold), Some(approver)) if amount > threshold => approver,
+        _ => return Ok(None),
+    };
+    if let Some(mut approval) = find_approval(&account, send, now) {
+        let approval_id = approval.id;
+        match approval.status {
+            ApprovalStatus::Approved { .. } if approval.amount >= *amount => {
+                approval.status = ApprovalStatus::Used { at: now };
+                SendApproval::insert(approval_id, approval);
+                return Ok(Some(approval_id));
+            }
+            ApprovalStatus::Pending => return Err(PolicyError::ApprovalPending { approval_id }),
+            ApprovalStatus::Rejected { .. } => {
+                return Err(PolicyError::ApprovalRejected { approval_id })
+            }
+            _ => {}
+        }
+    }
+
+    let id = SendApproval::next_id();
+    SendApproval::insert(
+        id,
+        SendApproval {
+            id,
+            account,
+            approver,
+            send: send.clone(),
+            to,
+            amount: amount.clone(),
+            status: ApprovalStatus::Pending,
+            created_at: now,
+            expires_at: now + APPROVAL_TTL,
+        },
+    );
+    USER_APPROVALS_MAP.with(|p| {
+        let mut map = p.borrow_mut();
+        map.insert(user_key(&account, id), id);
+        map.insert(user_key(&approver, id), id);
+    });
+    Err(PolicyError::ApprovalRequired { approval_id: id })
+}
+
+/*
+ * Checks a canister-initiated send of account against its policy and logs the decision. An allowed
+ * send counts against the caps right away, so concurrent sends can't both fit under them, and has to
+ * be reverted if it fails.
+ */
+pub fn authorize(
+    account: Principal,
+    send: PolicySend,
+    to: Principal,
+    amount: &Nat,
+) -> Result<Authorization, PolicyError> {
+    let now = time();
+    let today = now / DAY;
+    let mut authorization = Authorization {
+        account,
+        day: today,
+        amount: Nat::from(0u64),
+        approval_id: None,
+    };
+    let mut policy = match SpendingPolicy::get(&account) {
+        Some(policy) if policy.is_set() => policy,
+        _ => return Ok(authorization),
+    };
+    policy.prune(today);
+
+    let result = check(&policy, account, &send, to, amount, now);
+    let outcome = match &result {
+        Ok(approval_id) => {
+            policy.add_spent(today, amount);
+            authorization.amount = amount.clone();
+            authorization.approval_id = *approval_id;
+            PolicyOutcome::Allowed {
+                approval_id: *approval_id,
+            }
+        }
+        Err(error) => PolicyOutcome::Denied {
+            error: error.clone(),
+        },
+    };
+    policy.log(
+        &account,
+        PolicyDecision {
+            send,
+            to,
+            amount: amount.clone(),
+            outcome,
+            timestamp: now,
+        },
+    );
+    SpendingPolicy::insert(account, policy);
+
+    result.map(|_| authorization)
+}
+
+// Gives back the spend and the approval of a send that didn't go through
+pub fn revert(authorization: Authorization) {
+    if let Some(mut policy) = SpendingPolicy::get(&authorization.account) {
+        policy.remove_spent(authorization.day, &authorization.amount);
+        SpendingPolicy::insert(authorization.account, policy);
+    }
+    if let Some(mut approval) = authorization.approval_id.and_then(SendApproval::get) {
+        if let ApprovalStatus::Used { at } = approval.status {
+            approval.status = ApprovalStatus::Approved { at };
+            SendApproval::insert(approval.id, approval);
+        }
+    }
+}
@@ -0,0 +1,251 @@
+use crate::business::{self, Business};
+use crate::ck_btc_ledger::Account;
+use crate::user::{self, is_user, UserData};
+use crate::{OWNED_PROFILES_MAP, PROFILE_ACCOUNTS_MAP};
+use candid::{Decode, Encode, Principal};
+use ic_stable_structures::storable::{Bound, Storable};
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+use sha2::{Digest, Sha224};
+use std::borrow::Cow;
+
+pub const MAX_BUSINESS_PROFILES: usize = 10;
+const USER_PROFILE_INDEX: u64 = u64::MAX; // business profiles count up from 1
+const DERIVED_ID_CLASS: u8 = 0x03; // class byte of derived principals in the IC spec
+const DOMAIN: &[u8] = b"\x10business-profile";
+
+// Profiles an identity runs besides its own account
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize, Default)]
+pub struct OwnedProfiles {
+    businesses: Vec<Principal>,
+    user: Option<Principal>, // user account of an identity whose own principal is a business
+}
+
+impl Storable for OwnedProfiles {
+    const BOUND: Bound = Bound::Unbounded;
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl OwnedProfiles {
+    fn get(key: &Principal) -> Option<OwnedProfiles> {
+        OWNED_PROFILES_MAP.with(|p| p.borrow().get(key))
+    }
+
+    fn insert(key: Principal, value: OwnedProfiles) -> Option<OwnedProfiles> {
+        OWNED_PROFILES_MAP.with(|p| p.borrow_mut().insert(key, value))
+    }
+}
+
+/*
+ * Ledger account of a profile. The profile principal is derived from it and nobody holds its key,
+ * funds of the profile are on a subaccount of the owner identity.
+ */
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct ProfileAccount {
+    owner: Principal,
+    subaccount: ByteBuf,
+}
+
+impl Storable for ProfileAccount {
+    const BOUND: Bound = Bound::Unbounded;
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl ProfileAccount {
+    fn get(key: &Principal) -> Option<ProfileAccount> {
+        PROFILE_ACCOUNTS_MAP.with(|p| p.borrow().get(key))
+    }
+
+    fn insert(key: Principal, value: ProfileAccount) -> Option<ProfileAccount> {
+        PROFILE_ACCOUNTS_MAP.with(|p| p.borrow_mut().insert(key, value))
+    }
+
+    fn contains_key(key: &Principal) -> bool {
+        PROFILE_ACCOUNTS_MAP.with(|p| p.borrow().contains_key(key))
+    }
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct BusinessProfile {
+    principal: Principal,
+    subaccount: Option<ByteBuf>, // None for a business on the identity's own account
+    business: Business,
+}
+
+// Every profile of an identity, returned by fetch_initial_data once it owns a business profile
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct ProfilesData {
+    user: Option<UserData>,
+    user_subaccount: Option<ByteBuf>, // set when the user account is a profile on a subaccount
+    businesses: Vec<BusinessProfile>,
+}
+
+// Subaccount number index, the index is big endian in the last bytes and 0 is the default account
+fn subaccount(index: u64) -> [u8; 32] {
+    let mut subaccount = [0u8; 32];
+    subaccount[24..].copy_from_slice(&index.to_be_bytes());
+    subaccount
+}
+
+fn profile_principal(owner: &Principal, subaccount: &[u8]) -> Principal {
+    let mut hasher = Sha224::new();
+    hasher.update(DOMAIN);
+    hasher.update(owner.as_slice());
+    hasher.update(subaccount);
+
+    let mut bytes = hasher.finalize().to_vec();
+    bytes.push(DERIVED_ID_CLASS);
+    Principal::from_slice(&bytes)
+}
+
+pub fn is_profile(principal: &Principal) -> bool {
+    ProfileAccount::contains_key(principal)
+}
+
+pub fn owner_of(profile: &Principal) -> Option<Principal> {
+    ProfileAccount::get(profile).map(|account| account.owner)
+}
+
+pub fn owned_businesses(owner: &Principal) -> Vec<Principal> {
+    OwnedProfiles::get(owner).unwrap_or_default().businesses
+}
+
+// True if the identity runs any profile besides its own account
+pub fn has_profiles(owner: &Principal) -> bool {
+    let owned = OwnedProfiles::get(owner).unwrap_or_default();
+    !owned.businesses.is_empty() || owned.user.is_some()
+}
+
+// Principal of the identity's user account, its user profile if its own principal is a business
+pub fn user_principal(identity: &Principal) -> Principal {
+    OwnedProfiles::get(identity)
+        .and_then(|owned| owned.user)
+        .unwrap_or(*identity)
+}
+
+// Principal a ledger account is recorded under, the profile if it's the subaccount of one
+pub fn account_principal(account: &Account) -> Principal {
+    match &account.subaccount {
+        Some(subaccount) if subaccount.iter().any(|byte| *byte != 0) => {
+            let profile = profile_principal(&account.owner, subaccount);
+            if is_profile(&profile) {
+                profile
+            } else {
+                account.owner
+            }
+        }
+        _ => account.owner,
+    }
+}
+
+// Ledger account payments to principal have to be sent to
+pub fn ledger_account(principal: &Principal) -> Account {
+    match ProfileAccount::get(principal) {
+        Some(ProfileAccount { owner, subaccount }) => Account {
+            owner,
+            subaccount: Some(subaccount),
+        },
+        None => Account {
+            owner: *principal,
+            subaccount: None,
+        },
+    }
+}
+
+// Reserves the next business profile of owner, None if it has MAX_BUSINESS_PROFILES already
+pub fn new_business_profile(owner: &Principal) -> Option<Principal> {
+    let mut owned = OwnedProfiles::get(owner).unwrap_or_default();
+    if owned.businesses.len() >= MAX_BUSINESS_PROFILES {
+        return None;
+    }
+
+    let subaccount = subaccount(owned.businesses.len() as u64 + 1);
+    let profile = profile_principal(owner, &subaccount);
+
+    ProfileAccount::insert(
+        profile,
+        ProfileAccount {
+            owner: *owner,
+            subaccount: ByteBuf::from(subaccount.to_vec()),
+        },
+    );
+    owned.businesses.push(profile);
+    OwnedProfiles::insert(*owner, owned);
+    Some(profile)
+}
+
+// Creates the user profile of a business identity, signing up as a user on its own principal isn't possible
+pub fn new_user_profile(owner: &Principal) -> Principal {
+    let mut owned = OwnedProfiles::get(owner).unwrap_or_default();
+    let subaccount = subaccount(USER_PROFILE_INDEX);
+    let profile = profile_principal(owner, &subaccount);
+
+    ProfileAccount::insert(
+        profile,
+        ProfileAccount {
+            owner: *owner,
+            subaccount: ByteBuf::from(subaccount.to_vec()),
+        },
+    );
+    owned.user = Some(profile);
+    OwnedProfiles::insert(*owner, owned);
+    profile
+}
+
+// Data of the user profile and every business of owner, init only has the latest transactions
+pub fn fetch_profiles(owner: &Principal, init: bool) -> ProfilesData {
+    let fetch_business = |principal: &Principal| {
+        if init {
+            business::fetch_init_business_data(principal)
+        } else {
+            business::fetch_business_data(principal)
+        }
+    };
+
+    let user_account = user_principal(owner);
+    let user = if is_user(&user_account) {
+        Some(if init {
+            user::fetch_init_user_data()
+        } else {
+            user::fetch_user_data()
+        })
+    } else {
+        None
+    };
+
+    let mut businesses = Vec::new();
+    if business::is_business(owner) {
+        businesses.push(BusinessProfile {
+            principal: *owner,
+            subaccount: None,
+            business: fetch_business(owner),
+        });
+    }
+    for principal in owned_businesses(owner) {
+        if business::is_business(&principal) {
+            businesses.push(BusinessProfile {
+                principal,
+                subaccount: ProfileAccount::get(&principal).map(|account| account.subaccount),
+                business: fetch_business(&principal),
+            });
+        }
+    }
+
+    ProfilesData {
+        user,
+        user_subaccount: ProfileAccount::get(&user_account).map(|account| account.subaccount),
+        businesses,
+    }
+}
@@ -0,0 +1,214 @@
+use crate::business::{self, is_business, BtoBTxArg, BusinessTxArg, BusinessUnknownTxArg};
+use crate::ck_btc_ledger;
+use crate::error::{RecordRefundErr, RecordTxErr};
+use crate::fiat;
+use crate::user::{self, is_user, UserBusinessTxArg};
+use crate::{
+    history_kind, inspect_xfer_transaction, update_history_entry, TransferTx, TxInfo, TxKind, BI,
+    REFUNDS_MAP,
+};
+use candid::{Decode, Encode, Nat};
+use ic_stable_structures::storable::{Bound, Storable};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+// Refunds of one original transaction, kept on the original entry of both histories
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct RefundState {
+    refunded: Nat,
+    refundable: Nat, // original amount minus refunded
+    refund_tx_ids: Vec<Nat>,
+}
+
+impl Storable for RefundState {
+    const BOUND: Bound = Bound::Unbounded;
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+// Keyed by the tx_id of the original transaction
+impl RefundState {
+    fn get(key: &Nat) -> Option<RefundState> {
+        REFUNDS_MAP.with(|p| p.borrow().get(&BI(key.clone())))
+    }
+
+    fn insert(key: Nat, value: RefundState) -> Option<RefundState> {
+        REFUNDS_MAP.with(|p| p.borrow_mut().insert(BI(key), value))
+    }
+}
+
+async fn get_transfer(tx_id: &Nat) -> Result<TransferTx, RecordRefundErr> {
+    let (get_tx_response,) = ck_btc_ledger::get_transactions(tx_id.clone())
+        .await
+        .map_err(|err| {
+            RecordRefundErr::InterCanisterCall(format!("get_transactions failed {:?}", err))
+        })?;
+
+    inspect_xfer_transaction(tx_id.clone(), get_tx_response).map_err(|err| match err {
+        RecordTxErr::InvalidTransaction(reason) => RecordRefundErr::InvalidTransaction(reason),
+        other => RecordRefundErr::InvalidTransaction(format!("{:?}", other)),
+    })
+}
+
+// True once tx_id is linked to an original, recorded as a plain transfer it can still be linked
+fn is_recorded_refund(tx_id: &Nat) -> bool {
+    let info = BI::get(tx_id.clone()).unwrap_or_default();
+    [info.from, info.to]
+        .into_iter()
+        .flatten()
+        .filter_map(|principal| history_kind(&principal, tx_id))
+        .any(|kind| {
+            matches!(
+                kind,
+                TxKind::SendsRefund { .. } | TxKind::ReceiveRefund { .. }
+            )
+        })
+}
+
+/*
+ * Records tx_id as a refund of original_tx_id. The original must already be recorded and paid to a
+ * business, the refund must go from that business back to the original sender after the original, and
+ * its amount can't be more than what is left of the original after earlier refunds. A refund already
+ * recorded as a plain transfer, as frontends do after every transfer, has its entries turned into a
+ * refund.
+ */
+pub async fn record_refund(
+    tx_id: Nat,
+    original_tx_id: Nat,
+    note: Option<String>,
+) -> Result<RefundState, RecordRefundErr> {
+    if is_recorded_refund(&tx_id) {
+        return Err(RecordRefundErr::AlreadyRecorded);
+    }
+    if !BI::contains_key(original_tx_id.clone()) {
+        return Err(RecordRefundErr::OriginalNotRecorded);
+    }
+
+    let original = get_transfer(&original_tx_id).await?;
+    let refund = get_transfer(&tx_id).await?;
+
+    // Checked again after the calls, another refund could have been recorded meanwhile
+    if is_recorded_refund(&tx_id) {
+        return Err(RecordRefundErr::AlreadyRecorded);
+    }
+    if !is_business(&original.to) {
+        return Err(RecordRefundErr::NotPaidToBusiness);
+    }
+    if refund.from != original.to || refund.to != original.from {
+        return Err(RecordRefundErr::PartiesNotReversed);
+    }
+    // An earlier transfer between the same parties can't be a refund of this one
+    if refund.timestamp <= original.timestamp {
+        return Err(RecordRefundErr::RefundBeforeOriginal);
+    }
+
+    let mut state = RefundState::get(&original_tx_id).unwrap_or(RefundState {
+        refunded: Nat::from(0u64),
+        refundable: original.amount.clone(),
+        refund_tx_ids: Vec::new(),
+    });
+
+    if refund.amount > state.refundable {
+        return Err(RecordRefundErr::ExceedsRefundable {
+            refundable: state.refundable,
+        });
+    }
+
+    state.refunded += refund.amount.clone();
+    state.refundable -= refund.amount.clone();
+    state.refund_tx_ids.push(tx_id.clone());
+
+    let TransferTx {
+        from,
+        to,
+        timestamp,
+        amount,
+        fee,
+        memo,
+    } = refund;
+
+    let kind = TxKind::SendsRefund {
+        original_tx_id: original_tx_id.clone(),
+    };
+
+    // Recorded as a plain transfer before, its entries in both histories become the refund
+    let recorded = BI::contains_key(tx_id.clone());
+    if recorded {
+        for (principal, kind) in [(from, kind.clone()), (to, kind.counterpart())] {
+            update_history_entry(&principal, &tx_id, |entry| {
+                entry.kind = kind;
+                if entry.note.is_none() {
+                    entry.note = note.clone();
+                }
+            });
+        }
+    } else if is_user(&to) {
+        business::add_business_transaction(BusinessTxArg {
+            b_principal: from,
+            u_principal: to,
+            tx_kind: kind.clone(),
+            amount: amount.clone(),
+            timestamp,
+            tx_id: tx_id.clone(),
+            fee: fee.clone(),
+        });
+
+        user::add_user_business_transaction(UserBusinessTxArg {
+            tx_id: tx_id.clone(),
+            fee,
+            amount,
+            timestamp,
+            b_principal: from,
+            u_principal: to,
+            note,
+            tx_kind: kind.counterpart(),
+        });
+    } else if is_business(&to) {
+        business::add_business_to_business_transaction(BtoBTxArg {
+            from,
+            to,
+            timestamp,
+            note,
+            amount,
+            tx_id: tx_id.clone(),
+            fee,
+            tx_kind: kind,
+        });
+    } else {
+        business::add_unknown_transaction(BusinessUnknownTxArg {
+            tx_id: tx_id.clone(),
+            fee,
+            b_principal: from,
+            unknown: to,
+            amount,
+            timestamp,
+            note,
+            tx_kind: kind,
+        });
+    }
+    for principal in [from, to] {
+        update_history_entry(&principal, &original_tx_id, |entry| {
+            entry.refund = Some(state.clone())
+        });
+    }
+
+    RefundState::insert(original_tx_id, state.clone());
+    if !recorded {
+        fiat::queue_rates(&tx_id, &[from, to], timestamp);
+        BI::insert(
+            tx_id,
+            TxInfo {
+                from: Some(from),
+                to: Some(to),
+                memo,
+            },
+        );
+    }
+
+    Ok(state)
+}
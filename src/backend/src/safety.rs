@@ -0,0 +1,205 @@
+use crate::business::is_business;
+use crate::contacts;
+use crate::error::{BlockError, RequestPaymentError};
+use crate::staff::{self, Permission};
+use crate::user::{is_user, ChatId, PayIdOrPrincipal};
+use crate::{PayIds, SAFETY_MAP};
+use candid::{Decode, Encode, Principal};
+use ic_cdk::api::time;
+use ic_stable_structures::storable::{Bound, Storable};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+
+const REQUEST_WINDOW: u64 = 3_600_000_000_000; // 1 hour in nanoseconds
+const MAX_REQUESTS_PER_CHAT: usize = 5; // payment requests one sender can post in a chat per window
+const MAX_REQUESTS_PER_SENDER: usize = 20; // across all chats, so opening a chat per target doesn't raise the limit
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize, Default)]
+pub struct AccountSafety {
+    blocked: BTreeSet<Principal>,
+    only_contacts_can_request: bool,
+    recent_requests: Vec<(u64, ChatId)>, // payment requests sent within REQUEST_WINDOW
+}
+
+impl Storable for AccountSafety {
+    const BOUND: Bound = Bound::Unbounded;
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl AccountSafety {
+    fn get(key: &Principal) -> Option<AccountSafety> {
+        SAFETY_MAP.with(|p| p.borrow().get(key))
+    }
+
+    fn insert(key: Principal, value: AccountSafety) -> Option<AccountSafety> {
+        SAFETY_MAP.with(|p| p.borrow_mut().insert(key, value))
+    }
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct SafetySettings {
+    blocked: Vec<Principal>,
+    only_contacts_can_request: bool,
+}
+
+fn resolve(caller: &Principal, arg: PayIdOrPrincipal) -> Result<Principal, BlockError> {
+    if !is_user(caller) && !is_business(caller) {
+        return Err(BlockError::AccountNotFound);
+    }
+
+    let principal = match arg {
+        PayIdOrPrincipal::PayId(pay_id) => match PayIds::get(&pay_id) {
+            Some(principal) => principal,
+            None => return Err(BlockError::PayIdNotFound),
+        },
+        PayIdOrPrincipal::Principal(principal) => principal,
+    };
+
+    if &principal == caller {
+        return Err(BlockError::CannotBlockSelf);
+    }
+    Ok(principal)
+}
+
+// True if either account blocked the other
+pub fn is_blocked(a: &Principal, b: &Principal) -> bool {
+    let blocks = |owner: &Principal, other: &Principal| {
+        AccountSafety::get(owner).is_some_and(|safety| safety.blocked.contains(other))
+    };
+    blocks(a, b) || blocks(b, a)
+}
+
+// True if principal and any other participant of the chat blocked each other
+pub fn is_blocked_in_chat(principal: &Principal, participants: &[String]) -> bool {
+    participants
+        .iter()
+        .filter_map(PayIds::get)
+        .any(|participant| &participant != principal && is_blocked(principal, &participant))
+}
+
+/*
+ * Checks a new payment request from requester in chat_id against blocks, the rate limits
+ * (per chat and across all chats) and the only_contacts_can_request setting of the other participants.
+ */
+pub fn check_payment_request(
+    requester: &Principal,
+    chat_id: &ChatId,
+    participants: &[String],
+) -> Result<(), RequestPaymentError> {
+    let now = time();
+
+    // oldest first, as record_payment_request appends
+    let sent = AccountSafety::get(requester)
+        .unwrap_or_default()
+        .recent_requests
+        .into_iter()
+        .filter(|(requested_at, _)| requested_at + REQUEST_WINDOW > now)
+        .collect::<Vec<_>>();
+
+    if sent.len() >= MAX_REQUESTS_PER_SENDER {
+        return Err(RequestPaymentError::RateLimited {
+            retry_at: sent[sent.len() - MAX_REQUESTS_PER_SENDER].0 + REQUEST_WINDOW,
+        });
+    }
+
+    let sent_in_chat = sent
+        .iter()
+        .filter(|(_, id)| id == chat_id)
+        .map(|(requested_at, _)| *requested_at)
+        .collect::<Vec<_>>();
+
+    if sent_in_chat.len() >= MAX_REQUESTS_PER_CHAT {
+        return Err(RequestPaymentError::RateLimited {
+            retry_at: sent_in_chat[sent_in_chat.len() - MAX_REQUESTS_PER_CHAT] + REQUEST_WINDOW,
+        });
+    }
+
+    for participant in participants.iter().filter_map(PayIds::get) {
+        if &participant == requester {
+            continue;
+        }
+        if is_blocked(requester, &participant) {
+            return Err(RequestPaymentError::Blocked);
+        }
+        let only_contacts =
+            AccountSafety::get(&participant).is_some_and(|safety| safety.only_contacts_can_request);
+        if only_contacts && contacts::find_contact(&participant, requester).is_none() {
+            return Err(RequestPaymentError::OnlyContactsCanRequest);
+        }
+    }
+
+    Ok(())
+}
+
+pub fn record_payment_request(requester: Principal, chat_id: ChatId) {
+    let now = time();
+    let mut safety = AccountSafety::get(&requester).unwrap_or_default();
+
+    safety
+        .recent_requests
+        .retain(|(requested_at, _)| requested_at + REQUEST_WINDOW > now);
+    safety.recent_requests.push((now, chat_id));
+
+    AccountSafety::insert(requester, safety);
+}
+
+pub fn block_account(
+    account: PayIdOrPrincipal,
+    profile: Option<Principal>,
+) -> Result<(), BlockError> {
+    let caller = staff::account(profile, Permission::ManageSettings)?;
+    let principal = resolve(&caller, account)?;
+
+    let mut safety = AccountSafety::get(&caller).unwrap_or_default();
+    safety.blocked.insert(principal);
+    AccountSafety::insert(caller, safety);
+    Ok(())
+}
+
+pub fn unblock_account(
+    account: PayIdOrPrincipal,
+    profile: Option<Principal>,
+) -> Result<(), BlockError> {
+    let caller = staff::account(profile, Permission::ManageSettings)?;
+    let principal = resolve(&caller, account)?;
+
+    let mut safety = AccountSafety::get(&caller).unwrap_or_default();
+    if !safety.blocked.remove(&principal) {
+        return Err(BlockError::NotBlocked);
+    }
+    AccountSafety::insert(caller, safety);
+    Ok(())
+}
+
+pub fn set_only_contacts_can_request(
+    enabled: bool,
+    profile: Option<Principal>,
+) -> Result<(), BlockError> {
+    let caller = staff::account(profile, Permission::ManageSettings)?;
+
+    if !is_user(&caller) && !is_business(&caller) {
+        return Err(BlockError::AccountNotFound);
+    }
+
+    let mut safety = AccountSafety::get(&caller).unwrap_or_default();
+    safety.only_contacts_can_request = enabled;
+    AccountSafety::insert(caller, safety);
+    Ok(())
+}
+
+pub fn get_safety_settings(profile: Option<Principal>) -> Result<SafetySettings, BlockError> {
+    let caller = staff::account(profile, Permission::ViewBusiness)?;
+    let safety = AccountSafety::get(&caller).unwrap_or_default();
+
+    Ok(SafetySettings {
+        blocked: safety.blocked.into_iter().collect(),
+        only_contacts_can_request: safety.only_contacts_can_request,
+    })
+}
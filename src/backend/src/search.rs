@@ -0,0 +1,355 @@
+use crate::business::{self, is_business};
+use crate::contacts::{self, ContactView};
+use crate::error::SearchError;
+use crate::staff::{self, Permission};
+use crate::user::{self, is_user, BusinessInUser, ChatId, MessageId, MessageOrTransaction};
+use crate::{PayIds, TransactionEntry, SEARCH_INDEX_MAP};
+use candid::{Decode, Encode, Nat, Principal};
+use ic_stable_structures::storable::{Bound, Storable};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+
+const MIN_TERM_LEN: usize = 2;
+const MAX_TERM_LEN: usize = 32;
+const MAX_PREFIX_EXPANSION: usize = 50; // index terms looked at for one query term
+const MAX_PAGE_SIZE: usize = 50;
+
+// Index key is "{principal}/{term}", so every term of an account can be found with a range scan
+fn index_key(principal: &Principal, term: &str) -> String {
+    format!("{}/{}", principal.to_text(), term)
+}
+
+#[derive(
+    candid::CandidType, Clone, Serialize, Deserialize, Debug, Eq, PartialEq, Ord, PartialOrd,
+)]
+pub enum SearchDoc {
+    Message(ChatId, MessageId),
+    History(usize),   // index in user history or Business.transactions
+    Business(String), // BusinessInUser id
+    Contact(Principal),
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize, Default)]
+pub struct Postings {
+    docs: BTreeSet<SearchDoc>,
+}
+
+impl Storable for Postings {
+    const BOUND: Bound = Bound::Unbounded;
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl Postings {
+    fn get(key: &String) -> Option<Postings> {
+        SEARCH_INDEX_MAP.with(|p| p.borrow().get(key))
+    }
+
+    fn insert(key: String, value: Postings) -> Option<Postings> {
+        SEARCH_INDEX_MAP.with(|p| p.borrow_mut().insert(key, value))
+    }
+
+    fn remove(key: &String) -> Option<Postings> {
+        SEARCH_INDEX_MAP.with(|p| p.borrow_mut().remove(key))
+    }
+
+    // (term, postings) of every indexed term of principal starting with prefix
+    fn with_prefix(principal: &Principal, prefix: &str, limit: usize) -> Vec<(String, Postings)> {
+        let start = index_key(principal, prefix);
+        let term_start = start.len() - prefix.len();
+
+        SEARCH_INDEX_MAP.with(|p| {
+            p.borrow()
+                .range(start.clone()..)
+                .take_while(|(key, _)| key.starts_with(&start))
+                .take(limit)
+                .map(|(key, postings)| (key[term_start..].to_string(), postings))
+                .collect()
+        })
+    }
+}
+
+// Lowercased words, `_` is kept so pay ids stay a single term
+pub fn tokenize(text: &str) -> BTreeSet<String> {
+    text.to_lowercase()
+        .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .filter(|term| term.chars().count() >= MIN_TERM_LEN)
+        .map(|term| term.chars().take(MAX_TERM_LEN).collect())
+        .collect()
+}
+
+pub fn add_doc(principal: &Principal, text: &str, doc: SearchDoc) {
+    for term in tokenize(text) {
+        let key = index_key(principal, &term);
+        let mut postings = Postings::get(&key).unwrap_or_default();
+        if postings.docs.insert(doc.clone()) {
+            Postings::insert(key, postings);
+        }
+    }
+}
+
+pub fn remove_doc(principal: &Principal, text: &str, doc: &SearchDoc) {
+    for term in tokenize(text) {
+        let key = index_key(principal, &term);
+        let mut postings = match Postings::get(&key) {
+            Some(postings) => postings,
+            None => continue,
+        };
+        postings.docs.remove(doc);
+        if postings.docs.is_empty() {
+            Postings::remove(&key);
+        } else {
+            Postings::insert(key, postings);
+        }
+    }
+}
+
+// Message content is indexed for every participant of the chat
+pub fn index_message(participants: &[String], chat_id: &ChatId, message_id: MessageId, text: &str) {
+    for pay_id in participants {
+        if let Some(principal) = PayIds::get(pay_id) {
+            add_doc(
+                &principal,
+                text,
+                SearchDoc::Message(chat_id.clone(), message_id),
+            );
+        }
+    }
+}
+
+pub fn unindex_message(
+    participants: &[String],
+    chat_id: &ChatId,
+    message_id: MessageId,
+    text: &str,
+) {
+    let doc = SearchDoc::Message(chat_id.clone(), message_id);
+    for pay_id in participants {
+        if let Some(principal) = PayIds::get(pay_id) {
+            remove_doc(&principal, text, &doc);
+        }
+    }
+}
+
+pub fn index_history(principal: &Principal, index: usize, entry: &TransactionEntry) {
+    let text = format!(
+        "{} {} {}",
+        entry.name,
+        entry.pay_id,
+        entry.note.clone().unwrap_or_default()
+    );
+    add_doc(principal, &text, SearchDoc::History(index));
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct SearchArgs {
+    query: String,
+    min_amount: Option<Nat>, // amount filters only match history entries
+    max_amount: Option<Nat>,
+    from: Option<u64>, // timestamp range in nanoseconds
+    to: Option<u64>,
+    offset: usize,
+    limit: usize,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub enum SearchItem {
+    Message {
+        chat_id: ChatId,
+        message_id: MessageId,
+        message: MessageOrTransaction,
+    },
+    History(TransactionEntry),
+    Business(BusinessInUser),
+    Contact(ContactView),
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct SearchResult {
+    score: u32,
+    item: SearchItem,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize, Default)]
+pub struct SearchResponse {
+    total: u64, // matches before pagination
+    results: Vec<SearchResult>,
+}
+
+/*
+ * Every query term is matched as a prefix of the indexed terms, an exact match scores 2 and a
+ * prefix match 1. Results are sorted by score and then by most recent.
+ */
+pub fn search(args: SearchArgs, profile: Option<Principal>) -> Result<SearchResponse, SearchError> {
+    let caller = staff::account(profile, Permission::ViewBusiness)?;
+    let caller_is_business = is_business(&caller);
+
+    if !caller_is_business && !is_user(&caller) {
+        return Err(SearchError::AccountNotFound);
+    }
+    if args.limit == 0 || args.limit > MAX_PAGE_SIZE {
+        return Err(SearchError::InvalidLimit { max: MAX_PAGE_SIZE });
+    }
+
+    let terms = tokenize(&args.query);
+    let has_filters = args.min_amount.is_some()
+        || args.max_amount.is_some()
+        || args.from.is_some()
+        || args.to.is_some();
+
+    if terms.is_empty() && !has_filters {
+        return Err(SearchError::EmptyQuery);
+    }
+
+    let history = if caller_is_business {
+        business::get_transactions(&caller)
+    } else {
+        user::get_history(&caller)
+    };
+
+    let mut scores: BTreeMap<SearchDoc, u32> = BTreeMap::new();
+
+    if terms.is_empty() {
+        // Only filters given, every history entry is a candidate
+        for index in 0..history.len() {
+            scores.insert(SearchDoc::History(index), 0);
+        }
+    } else {
+        for term in terms.iter() {
+            // best score of this term per doc, so a term can't count twice through prefixes
+            let mut term_scores: BTreeMap<SearchDoc, u32> = BTreeMap::new();
+            for (indexed_term, postings) in
+                Postings::with_prefix(&caller, term, MAX_PREFIX_EXPANSION)
+            {
+                let score = if &indexed_term == term { 2 } else { 1 };
+                for doc in postings.docs {
+                    let best = term_scores.entry(doc).or_insert(0);
+                    *best = (*best).max(score);
+                }
+            }
+            for (doc, score) in term_scores {
+                *scores.entry(doc).or_insert(0) += score;
+            }
+        }
+    }
+
+    let mut results: Vec<(u64, SearchResult)> = Vec::new();
+
+    for (doc, score) in scores {
+        let (timestamp, item) = match doc {
+            SearchDoc::Message(chat_id, message_id) => {
+                if args.min_amount.is_some() || args.max_amount.is_some() {
+                    continue;
+                }
+                match user::get_chat_entry(&caller, &chat_id, message_id) {
+                    Some(message) => (
+                        message.timestamp(),
+                        SearchItem::Message {
+                            chat_id,
+                            message_id,
+                            message,
+                        },
+                    ),
+                    None => continue,
+                }
+            }
+            SearchDoc::History(index) => match history.get(index) {
+                Some(entry) => {
+                    if !amount_in_range(&entry.amount, &args) {
+                        continue;
+                    }
+                    (entry.timestamp, SearchItem::History(entry.clone()))
+                }
+                None => continue,
+            },
+            SearchDoc::Business(id) => {
+                if args.min_amount.is_some() || args.max_amount.is_some() {
+                    continue;
+                }
+                match user::get_business_in_user(&caller, &id) {
+                    Some(business_in_user) => (
+                        business_in_user.last_activity(),
+                        SearchItem::Business(business_in_user),
+                    ),
+                    None => continue,
+                }
+            }
+            SearchDoc::Contact(principal) => {
+                if args.min_amount.is_some() || args.max_amount.is_some() {
+                    continue;
+                }
+                match contacts::find_contact(&caller, &principal) {
+                    Some(contact) => (contact.added_at(), SearchItem::Contact(contact)),
+                    None => continue,
+                }
+            }
+        };
+
+        if !time_in_range(timestamp, &args) {
+            continue;
+        }
+
+        results.push((timestamp, SearchResult { score, item }));
+    }
+
+    // Highest score first, then latest first
+    results.sort_by(|(a_time, a), (b_time, b)| b.score.cmp(&a.score).then(b_time.cmp(a_time)));
+
+    Ok(SearchResponse {
+        total: results.len() as u64,
+        results: results
+            .into_iter()
+            .skip(args.offset)
+            .take(args.limit)
+            .map(|(_, result)| result)
+            .collect(),
+    })
+}
+
+fn amount_in_range(amount: &Nat, args: &SearchArgs) -> bool {
+    if let Some(min) = &args.min_amount {
+        if amount < min {
+            return false;
+        }
+    }
+    if let Some(max) = &args.max_amount {
+        if amount > max {
+            return false;
+        }
+    }
+    true
+}
+
+fn time_in_range(timestamp: u64, args: &SearchArgs) -> bool {
+    args.from.is_none_or(|from| timestamp >= from) && args.to.is_none_or(|to| timestamp <= to)
+}
+
+// Indexes everything the caller can already see, for data written before the index existed
+pub fn rebuild_search_index(profile: Option<Principal>) -> Result<(), SearchError> {
+    let caller = staff::account(profile, Permission::ViewBusiness)?;
+
+    if is_business(&caller) {
+        for (index, entry) in business::get_transactions(&caller).iter().enumerate() {
+            index_history(&caller, index, entry);
+        }
+        contacts::index_contacts(&caller);
+        return Ok(());
+    }
+
+    if !is_user(&caller) {
+        return Err(SearchError::AccountNotFound);
+    }
+
+    for (index, entry) in user::get_history(&caller).iter().enumerate() {
+        index_history(&caller, index, entry);
+    }
+    user::index_chats_and_businesses(&caller);
+    contacts::index_contacts(&caller);
+    Ok(())
+}
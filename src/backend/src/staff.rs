@@ -0,0 +1,392 @@
+use crate::business::{self, is_business};
+use crate::error::StaffError;
+use crate::notifications;
+use crate::profiles;
+use crate::user::is_user;
+use crate::{AUDIT_MAP, MEMBERSHIP_MAP, STAFF_MAP};
+use candid::{Decode, Encode, Nat, Principal};
+use ic_cdk::{api::time, caller};
+use ic_stable_structures::storable::{Bound, Storable};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+const MAX_MEMBERS: usize = 50;
+const AUDIT_PAGE_SIZE: u64 = 50;
+
+#[derive(candid::CandidType, Clone, Copy, Serialize, Debug, Deserialize, PartialEq)]
+pub enum Role {
+    Owner,   // everything, including adding and removing other owners and admins
+    Admin,   // everything except managing owners and admins
+    Cashier, // takes payments, sees the business and its customers
+    Viewer,  // read only
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Permission {
+    ViewBusiness,
+    ViewCustomers,
+    ManageCustomers,
+    ViewAnalytics,
+    CreateInvoice,
+    Export,
+    ManageMembers,
+    ViewAuditLog,
+    ManageSettings,
+}
+
+impl Role {
+    fn can(&self, permission: Permission) -> bool {
+        use Permission::*;
+
+        match self {
+            Role::Owner | Role::Admin => true,
+            Role::Cashier => matches!(permission, ViewBusiness | ViewCustomers | CreateInvoice),
+            Role::Viewer => matches!(
+                permission,
+                ViewBusiness | ViewCustomers | ViewAnalytics | Export
+            ),
+        }
+    }
+
+    // Admins manage cashiers and viewers, only owners manage owners and admins
+    fn can_manage(&self, role: Role) -> bool {
+        match self {
+            Role::Owner => true,
+            Role::Admin => matches!(role, Role::Cashier | Role::Viewer),
+            Role::Cashier | Role::Viewer => false,
+        }
+    }
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct Member {
+    principal: Principal,
+    role: Role,
+    added_by: Principal,
+    added_at: u64,
+}
+
+// Members of one business, the business principal itself is always an owner and isn't listed
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize, Default)]
+pub struct Staff {
+    members: Vec<Member>,
+    audit_len: u64,
+}
+
+impl Storable for Staff {
+    const BOUND: Bound = Bound::Unbounded;
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl Staff {
+    fn get(key: &Principal) -> Option<Staff> {
+        STAFF_MAP.with(|p| p.borrow().get(key))
+    }
+
+    fn insert(key: Principal, value: Staff) -> Option<Staff> {
+        STAFF_MAP.with(|p| p.borrow_mut().insert(key, value))
+    }
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub enum AuditAction {
+    MemberAdded { principal: Principal, role: Role },
+    MemberRoleChanged { principal: Principal, role: Role },
+    MemberRemoved { principal: Principal },
+    CustomerUpdated { pay_id: String },
+    CustomersRebuilt,
+    AnalyticsRebuilt,
+    WebhookAdded { id: u64, url: String },
+    WebhookRemoved { id: u64 },
+    CheckoutSessionCreated { id: u64, amount: Nat },
+    PaymentLinkCreated { id: u64 },
+    PaymentLinkDisabled { id: u64 },
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct AuditEntry {
+    member: Principal,
+    role: Role,
+    action: AuditAction,
+    timestamp: u64,
+}
+
+impl Storable for AuditEntry {
+    const BOUND: Bound = Bound::Unbounded;
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+// Key is "{business principal}/{index}", index zero padded so entries of a business are in order
+fn audit_key(business: &Principal, index: u64) -> String {
+    format!("{}/{:012}", business.to_text(), index)
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct AuditPage {
+    entries: Vec<AuditEntry>, // latest first
+    next_page: Option<u64>,
+}
+
+// Business a member works for, for frontends signed in with a staff identity
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct Membership {
+    business: Principal,
+    name: String,
+    pay_id: String,
+    role: Role,
+}
+
+// Caller acting for a business, either the business principal itself or one of its members
+pub struct Actor {
+    pub business: Principal,
+    pub member: Principal,
+    pub role: Role,
+}
+
+impl Actor {
+    pub fn audit(&self, action: AuditAction) {
+        let mut staff = Staff::get(&self.business).unwrap_or_default();
+
+        AUDIT_MAP.with(|p| {
+            p.borrow_mut().insert(
+                audit_key(&self.business, staff.audit_len),
+                AuditEntry {
+                    member: self.member,
+                    role: self.role,
+                    action,
+                    timestamp: time(),
+                },
+            )
+        });
+        staff.audit_len += 1;
+        Staff::insert(self.business, staff);
+    }
+}
+
+// Business principal the member belongs to
+pub fn member_of(principal: &Principal) -> Option<Principal> {
+    MEMBERSHIP_MAP.with(|p| p.borrow().get(principal))
+}
+
+fn role_of(business: &Principal, principal: &Principal) -> Option<Role> {
+    if business == principal || profiles::owner_of(business).as_ref() == Some(principal) {
+        return Some(Role::Owner);
+    }
+    Staff::get(business)?
+        .members
+        .iter()
+        .find(|member| &member.principal == principal)
+        .map(|member| member.role)
+}
+
+/*
+ * Resolves who principal acts for and checks its role allows permission. The business profile is
+ * picked per call, without one a business acts for itself and a member for the business it belongs to.
+ * Owners of business profiles act for them as owner.
+ */
+pub fn actor_for(
+    principal: Principal,
+    profile: Option<Principal>,
+    permission: Permission,
+) -> Result<Actor, StaffError> {
+    let business = match profile {
+        Some(profile) if is_business(&profile) => profile,
+        Some(_) => return Err(StaffError::AccountNotFound),
+        None if is_business(&principal) => principal,
+        None => member_of(&principal).ok_or(StaffError::AccountNotFound)?,
+    };
+    let role = role_of(&business, &principal).ok_or(StaffError::AccountNotFound)?;
+
+    if !role.can(permission) {
+        return Err(StaffError::NotPermitted);
+    }
+
+    Ok(Actor {
+        business,
+        member: principal,
+        role,
+    })
+}
+
+pub fn actor(profile: Option<Principal>, permission: Permission) -> Result<Actor, StaffError> {
+    actor_for(caller(), profile, permission)
+}
+
+/*
+ * Account an endpoint open to users and businesses works on. A business profile goes through actor
+ * and needs permission. Without a profile it's the caller's user account if it has one, otherwise the
+ * business it acts for. Unregistered callers get their own principal back, callers check it exists.
+ */
+pub fn account(
+    profile: Option<Principal>,
+    permission: Permission,
+) -> Result<Principal, StaffError> {
+    let identity = caller();
+    let user = profiles::user_principal(&identity);
+    match profile {
+        Some(profile) if profile == user && is_user(&user) => Ok(user),
+        Some(_) => Ok(actor(profile, permission)?.business),
+        None if is_user(&user) => Ok(user),
+        None => match actor(None, permission) {
+            Ok(actor) => Ok(actor.business),
+            Err(StaffError::AccountNotFound) => Ok(identity),
+            Err(err) => Err(err),
+        },
+    }
+}
+
+pub fn get_membership() -> Option<Membership> {
+    let caller = caller();
+    let business = member_of(&caller)?;
+    let metadata = business::get_metadata(&business)?;
+
+    Some(Membership {
+        business,
+        name: metadata.name,
+        pay_id: metadata.pay_id,
+        role: role_of(&business, &caller)?,
+    })
+}
+
+pub fn list_members(profile: Option<Principal>) -> Result<Vec<Member>, StaffError> {
+    let actor = actor(profile, Permission::ViewBusiness)?;
+    Ok(Staff::get(&actor.business).unwrap_or_default().members)
+}
+
+/*
+ * Adds principal as a member of the caller's business. A principal can be a member of one business
+ * only and can't be a business itself, it can still have its own user account.
+ */
+pub fn add_member(
+    principal: Principal,
+    role: Role,
+    profile: Option<Principal>,
+) -> Result<Member, StaffError> {
+    let actor = actor(profile, Permission::ManageMembers)?;
+
+    if !actor.role.can_manage(role) {
+        return Err(StaffError::NotPermitted);
+    }
+    if principal == Principal::anonymous() {
+        return Err(StaffError::AnonymousPrincipal);
+    }
+    if is_business(&principal) {
+        return Err(StaffError::IsABusiness);
+    }
+    if member_of(&principal).is_some() {
+        return Err(StaffError::AlreadyAMember);
+    }
+
+    let mut staff = Staff::get(&actor.business).unwrap_or_default();
+    if staff.members.len() >= MAX_MEMBERS {
+        return Err(StaffError::TooManyMembers { max: MAX_MEMBERS });
+    }
+
+    let member = Member {
+        principal,
+        role,
+        added_by: actor.member,
+        added_at: time(),
+    };
+    staff.members.push(member.clone());
+    Staff::insert(actor.business, staff);
+    MEMBERSHIP_MAP.with(|p| p.borrow_mut().insert(principal, actor.business));
+
+    actor.audit(AuditAction::MemberAdded { principal, role });
+    notifications::business_added(principal, actor.business, role);
+    Ok(member)
+}
+
+pub fn update_member_role(
+    principal: Principal,
+    role: Role,
+    profile: Option<Principal>,
+) -> Result<Member, StaffError> {
+    let actor = actor(profile, Permission::ManageMembers)?;
+
+    let mut staff = Staff::get(&actor.business).unwrap_or_default();
+    let member = match staff
+        .members
+        .iter_mut()
+        .find(|member| member.principal == principal)
+    {
+        Some(member) => member,
+        None => return Err(StaffError::MemberNotFound),
+    };
+
+    // Both the current and the new role have to be manageable, so admins can't promote to admin
+    if !actor.role.can_manage(member.role) || !actor.role.can_manage(role) {
+        return Err(StaffError::NotPermitted);
+    }
+
+    member.role = role;
+    let member = member.clone();
+    Staff::insert(actor.business, staff);
+
+    actor.audit(AuditAction::MemberRoleChanged { principal, role });
+    Ok(member)
+}
+
+// Removes a member, every member can also remove itself to leave the business
+pub fn remove_member(principal: Principal, profile: Option<Principal>) -> Result<(), StaffError> {
+    let caller = caller();
+    let actor = if member_of(&caller).is_some_and(|_| caller == principal) {
+        actor_for(caller, None, Permission::ViewBusiness)?
+    } else {
+        actor_for(caller, profile, Permission::ManageMembers)?
+    };
+
+    let mut staff = Staff::get(&actor.business).unwrap_or_default();
+    let index = match staff
+        .members
+        .iter()
+        .position(|member| member.principal == principal)
+    {
+        Some(index) => index,
+        None => return Err(StaffError::MemberNotFound),
+    };
+
+    if caller != principal && !actor.role.can_manage(staff.members[index].role) {
+        return Err(StaffError::NotPermitted);
+    }
+
+    staff.members.remove(index);
+    Staff::insert(actor.business, staff);
+    MEMBERSHIP_MAP.with(|p| p.borrow_mut().remove(&principal));
+
+    actor.audit(AuditAction::MemberRemoved { principal });
+    Ok(())
+}
+
+// Audit trail of the caller's business, latest first
+pub fn get_audit_log(page: u64, profile: Option<Principal>) -> Result<AuditPage, StaffError> {
+    let actor = actor(profile, Permission::ViewAuditLog)?;
+    let audit_len = Staff::get(&actor.business).unwrap_or_default().audit_len;
+
+    let end = audit_len.saturating_sub(page * AUDIT_PAGE_SIZE);
+    let start = end.saturating_sub(AUDIT_PAGE_SIZE);
+    let entries = AUDIT_MAP.with(|p| {
+        let map = p.borrow();
+        (start..end)
+            .rev()
+            .filter_map(|index| map.get(&audit_key(&actor.business, index)))
+            .collect::<Vec<_>>()
+    });
+
+    Ok(AuditPage {
+        entries,
+        next_page: if start > 0 { Some(page + 1) } else { None },
+    })
+}
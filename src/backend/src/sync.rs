@@ -0,0 +1,185 @@
+use crate::app_requests::{self, AppPaymentRequest};
+use crate::business::{self, is_business};
+use crate::error::SyncError;
+use crate::mandates::{self, Mandate};
+use crate::notifications::{self, Notification};
+use crate::staff::{self, Permission};
+use crate::user::{self, is_user, BusinessInUser, ChatId, ChatUpdate, MessageId};
+use crate::{PayIds, TransactionEntry, CHANGE_LOG_MAP};
+use candid::{Decode, Encode, Principal};
+use ic_stable_structures::storable::{Bound, Storable};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+
+// Only the latest changes are kept per account, older clients have to fetch_data again
+const MAX_CHANGES: usize = 1_000;
+
+// Something that changed for an account, the data itself is read from its own map when syncing
+#[derive(
+    candid::CandidType, Clone, Serialize, Deserialize, Debug, Eq, PartialEq, Ord, PartialOrd,
+)]
+pub enum Change {
+    Chat(ChatId), // chat created, its read watermarks moved or encryption turned on
+    Message(ChatId, MessageId), // entry added or updated in a chat
+    History(usize), // index in user history or Business.transactions
+    Business(String), // BusinessInUser id
+    Mandate(u64), // recurring payment created, paid, failed or ended
+    Notification(u64), // added to the notification feed
+    AppRequest(u64), // payment request of an app posted, paid, declined or expired
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize, Default)]
+pub struct ChangeLog {
+    version: u64, // version of the latest change
+    changes: Vec<(u64, Change)>,
+}
+
+impl Storable for ChangeLog {
+    const BOUND: Bound = Bound::Unbounded;
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl ChangeLog {
+    fn get(key: &Principal) -> Option<ChangeLog> {
+        CHANGE_LOG_MAP.with(|p| p.borrow().get(key))
+    }
+
+    fn insert(key: Principal, value: ChangeLog) -> Option<ChangeLog> {
+        CHANGE_LOG_MAP.with(|p| p.borrow_mut().insert(key, value))
+    }
+
+    // version of the oldest change still in the log
+    fn first_version(&self) -> u64 {
+        match self.changes.first() {
+            Some((version, _)) => *version,
+            None => self.version + 1,
+        }
+    }
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize, Default)]
+pub struct SyncResponse {
+    version: u64,      // pass as `since` in the next sync call
+    full_resync: bool, // `since` is older than the log, call fetch_data and continue from version
+    chats: Vec<ChatUpdate>,
+    history: Vec<TransactionEntry>,
+    business: Vec<BusinessInUser>,
+    mandates: Vec<Mandate>,
+    notifications: Vec<Notification>,
+    app_requests: Vec<AppPaymentRequest>,
+}
+
+pub fn record_change(principal: Principal, change: Change) {
+    let mut log = ChangeLog::get(&principal).unwrap_or_default();
+
+    log.version += 1;
+    log.changes.push((log.version, change));
+
+    if log.changes.len() > MAX_CHANGES {
+        let excess = log.changes.len() - MAX_CHANGES;
+        log.changes.drain(..excess);
+    }
+
+    ChangeLog::insert(principal, log);
+}
+
+// Records the change for every participant of a chat
+pub fn record_chat_change(participants: &[String], change: Change) {
+    for pay_id in participants {
+        if let Some(principal) = PayIds::get(pay_id) {
+            record_change(principal, change.clone());
+        }
+    }
+}
+
+/*
+ * Clients start with sync(0). If full_resync is returned the log has already been trimmed,
+ * so they load everything with fetch_data and keep polling with the returned version.
+ */
+pub fn sync(since: u64, profile: Option<Principal>) -> Result<SyncResponse, SyncError> {
+    let caller = staff::account(profile, Permission::ViewBusiness)?;
+
+    if !is_user(&caller) && !is_business(&caller) {
+        return Err(SyncError::AccountNotFound);
+    }
+
+    let log = ChangeLog::get(&caller).unwrap_or_default();
+
+    if since >= log.version {
+        return Ok(SyncResponse {
+            version: log.version,
+            ..Default::default()
+        });
+    }
+
+    if since + 1 < log.first_version() {
+        return Ok(SyncResponse {
+            version: log.version,
+            full_resync: true,
+            ..Default::default()
+        });
+    }
+
+    // Only the latest state is returned, so repeated changes of the same item are merged
+    let changes = log
+        .changes
+        .into_iter()
+        .filter(|(version, _)| *version > since)
+        .map(|(_, change)| change)
+        .collect::<BTreeSet<_>>();
+
+    let mut chats: BTreeMap<ChatId, Vec<MessageId>> = BTreeMap::new();
+    let mut history_indexes = Vec::new();
+    let mut business_ids = Vec::new();
+    let mut mandate_ids = Vec::new();
+    let mut notification_ids = Vec::new();
+    let mut app_request_ids = Vec::new();
+
+    for change in changes {
+        match change {
+            Change::Chat(chat_id) => {
+                chats.entry(chat_id).or_default();
+            }
+            Change::Message(chat_id, message_id) => {
+                chats.entry(chat_id).or_default().push(message_id);
+            }
+            Change::History(index) => history_indexes.push(index),
+            Change::Business(id) => business_ids.push(id),
+            Change::Mandate(id) => mandate_ids.push(id),
+            Change::Notification(id) => notification_ids.push(id),
+            Change::AppRequest(id) => app_request_ids.push(id),
+        }
+    }
+
+    let history = if is_business(&caller) {
+        business::get_transactions_at(&caller, &history_indexes)
+    } else {
+        user::get_history_at(&caller, &history_indexes)
+    };
+
+    Ok(SyncResponse {
+        version: log.version,
+        full_resync: false,
+        chats: chats
+            .into_iter()
+            .filter_map(|(chat_id, message_ids)| {
+                user::get_chat_update(&caller, &chat_id, &message_ids)
+            })
+            .collect(),
+        history,
+        business: business_ids
+            .iter()
+            .filter_map(|id| user::get_business_in_user(&caller, id))
+            .collect(),
+        mandates: mandates::get_mandates_by_id(&caller, &mandate_ids),
+        notifications: notifications::get_notifications_by_id(&caller, &notification_ids),
+        app_requests: app_requests::get_app_requests_by_id(&caller, &app_request_ids),
+    })
+}
@@ -0,0 +1,91 @@
+use crate::{checkout, escrow, fiat, mandates, notifications, web_push, webhooks};
+use ic_cdk::api::time;
+use ic_cdk_timers::{clear_timer, set_timer, TimerId};
+use std::cell::Cell;
+use std::time::Duration;
+
+const TICK: u64 = 5_000_000_000; // 5 seconds in nanoseconds
+
+thread_local! {
+    static NEXT_TICK: Cell<Option<(u64, TimerId)>> = const { Cell::new(None) };
+    static RUNNING: Cell<bool> = const { Cell::new(false) };
+}
+
+/*
+ * Background work runs on one timer, armed only while there is work to do. An earlier timestamp
+ * replaces the armed timer. Timers aren't kept over upgrades, post_upgrade arms it again if needed.
+ */
+pub fn schedule_at(timestamp: u64) {
+    let next = NEXT_TICK.get();
+    if next.is_none_or(|(at, _)| timestamp < at) {
+        if let Some((_, id)) = next {
+            clear_timer(id);
+        }
+        let delay = Duration::from_nanos(timestamp.saturating_sub(time()));
+        NEXT_TICK.set(Some((timestamp, set_timer(delay, tick))));
+    }
+}
+
+pub fn schedule() {
+    schedule_at(time() + TICK);
+}
+
+// When the timer should fire next, None if there is nothing to do
+pub fn next_work() -> Option<u64> {
+    let scan = if checkout::has_open_sessions() {
+        Some(time() + TICK)
+    } else {
+        None
+    };
+
+    [
+        scan,
+        webhooks::next_attempt_at(),
+        mandates::next_run_at(),
+        escrow::next_deadline(),
+        notifications::next_notice_at(),
+        web_push::next_push_at(),
+        fiat::next_lookup_at(),
+    ]
+    .into_iter()
+    .flatten()
+    .min()
+}
+
+// Cleared when the tick ends, also when it traps in a callback and its future is dropped
+struct RunningGuard;
+
+impl Drop for RunningGuard {
+    fn drop(&mut self) {
+        RUNNING.set(false);
+    }
+}
+
+fn tick() {
+    NEXT_TICK.set(None);
+
+    // The tick before is still awaiting its calls, check again later instead of running twice
+    if RUNNING.get() {
+        schedule();
+        return;
+    }
+    RUNNING.set(true);
+
+    ic_cdk::spawn(async {
+        let _guard = RunningGuard;
+        if checkout::has_open_sessions() {
+            checkout::scan_ledger().await;
+        }
+        mandates::run_due().await;
+        escrow::run_due().await;
+        notifications::run_due();
+        webhooks::deliver_due().await;
+        web_push::deliver_due().await;
+        fiat::lookup_due().await;
+
+        // Deliveries and sessions added meanwhile have armed the timer already if they are earlier
+        if let Some(next) = next_work() {
+            schedule_at(next.max(time() + TICK));
+        }
+    });
+}
@@ -1,21 +1,69 @@
 use crate::business::{self, is_business, BusinessCategory};
+use crate::customers;
 use crate::error::{
-    AddBusinessError, AddMessageErr, CreateChatErr, MarkMessageReadErr, RecordRegPayTxErr,
-    RecordTxErr, RequestPaymentError, SignUpError,
+    AddBusinessError, AddMessageErr, ChatEncryptionError, CreateChatErr, MarkMessageReadErr,
+    RecordRegPayTxErr, RequestPaymentError, SignUpError, UpdateMessageErr,
 };
+use crate::escrow::EscrowEvent;
+use crate::notifications;
+use crate::profiles;
+use crate::safety;
+use crate::search::{self, SearchDoc};
+use crate::sync::{self, Change};
 use crate::{
-    is_payid_exist, PayIds, StoreHistory, TransactionEntry, TxHistory, TxInfo, TxKind, BI,
+    is_payid_exist, PayIds, StoreHistory, TransactionEntry, TxHistory, TxKind,
     BUSINESS_IN_USER_MAP, CHATS_MAP, TRANSACTION_HISTORY_MAP, USERS_MAP,
 };
 use candid::{Decode, Encode, Nat, Principal};
 use ic_cdk::{api::time, caller};
 use ic_stable_structures::storable::{Bound, Storable};
 use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
 use std::borrow::Cow;
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 
 type PayId = String;
 
+// Position of the entry in `Chat.messages`, entries are never removed so it stays stable
+pub type MessageId = usize;
+
+const MAX_CIPHERTEXT_LEN: usize = 16_384;
+
+#[derive(
+    candid::CandidType,
+    Clone,
+    Serialize,
+    Deserialize,
+    Default,
+    Debug,
+    Eq,
+    PartialEq,
+    Ord,
+    PartialOrd,
+)]
+pub struct MessageEdit {
+    content: String, // content before the edit
+    edited_at: u64,
+}
+
+#[derive(
+    candid::CandidType,
+    Clone,
+    Serialize,
+    Deserialize,
+    Default,
+    Debug,
+    Eq,
+    PartialEq,
+    Ord,
+    PartialOrd,
+)]
+pub struct Reaction {
+    pay_id: PayId,
+    emoji: String,
+}
+
+// Fields added after the first release are Optional, so chats already stored in stable memory still decode
 #[derive(
     candid::CandidType,
     Clone,
@@ -33,7 +81,12 @@ pub struct Message {
     sender_id: PayId,
     content: String,
     timestamp: u64,
-    read_by: Vec<PayId>, // Tracked read status
+    read_by: Vec<PayId>, // Legacy read status, replaced by Chat.last_read
+    reply_to: Option<MessageId>,
+    edit_history: Option<Vec<MessageEdit>>, // previous versions, oldest first
+    deleted_at: Option<u64>,                // tombstone, content and history are cleared on delete
+    reactions: Option<Vec<Reaction>>,       // one reaction per participant
+    ciphertext: Option<ByteBuf>,            // end-to-end encrypted content, content is empty then
 }
 
 #[derive(
@@ -54,7 +107,7 @@ pub struct Transaction {
     note: Option<String>,
     amount: candid::Nat,
     tx_id: candid::Nat,
-    read_by: Vec<PayId>, // Tracked read status
+    read_by: Vec<PayId>, // Legacy read status, replaced by Chat.last_read
 }
 
 #[derive(
@@ -77,7 +130,20 @@ pub struct RequestPayment {
     tx_id: Option<candid::Nat>,
     expires_at: u64,
     note: Option<String>,
-    read_by: Vec<PayId>, // Tracked read status
+    read_by: Vec<PayId>, // Legacy read status, replaced by Chat.last_read
+}
+
+// Posted by the canister on every step of an escrow between the chat participants
+#[derive(
+    candid::CandidType, Clone, Serialize, Deserialize, Debug, Eq, PartialEq, Ord, PartialOrd,
+)]
+pub struct EscrowMessage {
+    escrow_id: u64,
+    sender_id: PayId, // participant who acted, empty for the arbiter and timeouts
+    event: EscrowEvent,
+    amount: Nat,
+    timestamp: u64,
+    read_by: Vec<PayId>, // always empty, chats use last_read
 }
 
 #[derive(
@@ -87,6 +153,36 @@ pub enum MessageOrTransaction {
     Message(Message),
     Transaction(Transaction),
     RequestPayment(RequestPayment),
+    Escrow(EscrowMessage),
+}
+
+impl MessageOrTransaction {
+    pub fn timestamp(&self) -> u64 {
+        match self {
+            MessageOrTransaction::Message(msg) => msg.timestamp,
+            MessageOrTransaction::Transaction(tx) => tx.timestamp,
+            MessageOrTransaction::RequestPayment(req) => req.requested_at,
+            MessageOrTransaction::Escrow(escrow) => escrow.timestamp,
+        }
+    }
+
+    fn sender_id(&self) -> &PayId {
+        match self {
+            MessageOrTransaction::Message(msg) => &msg.sender_id,
+            MessageOrTransaction::Transaction(tx) => &tx.sender_id,
+            MessageOrTransaction::RequestPayment(req) => &req.sender_id,
+            MessageOrTransaction::Escrow(escrow) => &escrow.sender_id,
+        }
+    }
+
+    fn read_by(&self) -> &Vec<PayId> {
+        match self {
+            MessageOrTransaction::Message(msg) => &msg.read_by,
+            MessageOrTransaction::Transaction(tx) => &tx.read_by,
+            MessageOrTransaction::RequestPayment(req) => &req.read_by,
+            MessageOrTransaction::Escrow(escrow) => &escrow.read_by,
+        }
+    }
 }
 
 pub type ChatId = String;
@@ -109,10 +205,59 @@ pub struct Chat {
     messages: Vec<MessageOrTransaction>,
     last_activity: u64,
     // metadata: ChatMetadata, // New chat metadata
+    last_read: Option<BTreeMap<PayId, MessageId>>, // last MessageId read by each participant
+    encrypted: Option<bool>, // new messages only carry ciphertext, set by enable_chat_encryption
+}
+
+impl Chat {
+    pub fn is_encrypted(&self) -> bool {
+        self.encrypted.unwrap_or(false)
+    }
+
+    // Chats stored before last_read existed fall back to the legacy read_by of each entry
+    fn last_read_by(&self, pay_id: &PayId) -> Option<MessageId> {
+        if let Some(message_id) = self.last_read.as_ref().and_then(|l| l.get(pay_id)) {
+            return Some(*message_id);
+        }
+        self.messages
+            .iter()
+            .rposition(|msg_or_tx| msg_or_tx.read_by().contains(pay_id))
+    }
+
+    // Entries after the watermark, own messages are never unread
+    fn unread_count(&self, pay_id: &PayId) -> u64 {
+        let first_unread = match self.last_read_by(pay_id) {
+            Some(message_id) => message_id + 1,
+            None => 0,
+        };
+        self.messages
+            .iter()
+            .skip(first_unread)
+            .filter(|msg_or_tx| msg_or_tx.sender_id() != pay_id)
+            .count() as u64
+    }
+}
+
+// Chat with only the entries that changed since the last sync
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct ChatUpdate {
+    id: ChatId,
+    participants: Vec<PayId>,
+    last_activity: u64,
+    last_read: Option<BTreeMap<PayId, MessageId>>,
+    encrypted: Option<bool>,
+    unread_count: u64,
+    messages: Vec<(MessageId, MessageOrTransaction)>,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct MyChat {
+    chat: Chat,
+    unread_count: u64,
 }
 impl Storable for Chat {
     const BOUND: Bound = Bound::Unbounded;
-    fn to_bytes(&self) -> Cow<[u8]> {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
         Cow::Owned(Encode!(self).unwrap())
     }
 
@@ -127,25 +272,9 @@ impl Chats {
         CHATS_MAP.with(|p| p.borrow().get(key))
     }
 
-    fn get_mut(key: &ChatId) -> Option<Chat> {
-        CHATS_MAP.with(|p| p.borrow_mut().get(key))
-    }
-
     fn insert(key: ChatId, value: Chat) -> Option<Chat> {
         CHATS_MAP.with(|p| p.borrow_mut().insert(key, value))
     }
-
-    fn remove(key: &ChatId) -> Option<Chat> {
-        CHATS_MAP.with(|p| p.borrow_mut().remove(key))
-    }
-
-    fn contains_key(key: &ChatId) -> bool {
-        CHATS_MAP.with(|p| p.borrow().contains_key(key))
-    }
-
-    fn len() -> u64 {
-        CHATS_MAP.with(|p| p.borrow().len())
-    }
 }
 
 // #[derive(
@@ -179,7 +308,7 @@ pub struct User {
 
 impl Storable for User {
     const BOUND: Bound = Bound::Unbounded;
-    fn to_bytes(&self) -> Cow<[u8]> {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
         Cow::Owned(Encode!(self).unwrap())
     }
 
@@ -191,21 +320,13 @@ impl Storable for User {
 struct Users;
 impl Users {
     fn get(key: &Principal) -> Option<User> {
-        USERS_MAP.with(|p| p.borrow().get(&key))
-    }
-
-    fn get_mut(key: &Principal) -> Option<User> {
-        USERS_MAP.with(|p| p.borrow_mut().get(&key))
+        USERS_MAP.with(|p| p.borrow().get(key))
     }
 
     fn insert(key: Principal, value: User) -> Option<User> {
         USERS_MAP.with(|p| p.borrow_mut().insert(key, value))
     }
 
-    fn remove(key: Principal) -> Option<User> {
-        USERS_MAP.with(|p| p.borrow_mut().remove(&key))
-    }
-
     fn contains_key(key: &Principal) -> bool {
         USERS_MAP.with(|p| p.borrow().contains_key(key))
     }
@@ -236,7 +357,7 @@ pub struct BusinessInUser {
 }
 impl Storable for BusinessInUser {
     const BOUND: Bound = Bound::Unbounded;
-    fn to_bytes(&self) -> Cow<[u8]> {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
         Cow::Owned(Encode!(self).unwrap())
     }
 
@@ -250,63 +371,48 @@ impl BusinessInUser {
         BUSINESS_IN_USER_MAP.with(|p| p.borrow().get(key))
     }
 
-    fn get_mut(key: &String) -> Option<BusinessInUser> {
-        BUSINESS_IN_USER_MAP.with(|p| p.borrow_mut().get(key))
-    }
-
     fn insert(key: String, value: BusinessInUser) -> Option<BusinessInUser> {
         BUSINESS_IN_USER_MAP.with(|p| p.borrow_mut().insert(key, value))
     }
 
-    fn remove(key: &String) -> Option<BusinessInUser> {
-        BUSINESS_IN_USER_MAP.with(|p| p.borrow_mut().remove(key))
-    }
-
-    fn contains_key(key: &String) -> bool {
-        BUSINESS_IN_USER_MAP.with(|p| p.borrow().contains_key(key))
+    pub fn last_activity(&self) -> u64 {
+        self.last_activity
     }
 
-    fn len() -> u64 {
-        BUSINESS_IN_USER_MAP.with(|p| p.borrow().len())
+    fn index_for(&self, principal: &Principal) {
+        let text = format!("{} {} {:?}", self.b_name, self.b_pay_id, self.b_category);
+        search::add_doc(principal, &text, SearchDoc::Business(self.id.clone()));
     }
 }
 
 impl StoreHistory {
     fn get(key: &Principal) -> Option<StoreHistory> {
-        TRANSACTION_HISTORY_MAP.with(|p| p.borrow().get(&key))
-    }
-
-    fn get_mut(key: &Principal) -> Option<StoreHistory> {
-        TRANSACTION_HISTORY_MAP.with(|p| p.borrow_mut().get(&key))
+        TRANSACTION_HISTORY_MAP.with(|p| p.borrow().get(key))
     }
 
     fn insert(key: Principal, value: StoreHistory) -> Option<StoreHistory> {
         TRANSACTION_HISTORY_MAP.with(|p| p.borrow_mut().insert(key, value))
     }
 
-    fn remove(key: Principal) -> Option<StoreHistory> {
-        TRANSACTION_HISTORY_MAP.with(|p| p.borrow_mut().remove(&key))
-    }
-
-    fn contains_key(key: &Principal) -> bool {
-        TRANSACTION_HISTORY_MAP.with(|p| p.borrow().contains_key(key))
-    }
-
     fn new(value: TxHistory) -> Self {
         Self { history: value }
     }
     pub fn get_history(key: &Principal) -> Option<TxHistory> {
-        match StoreHistory::get(key) {
-            Some(store) => Some(store.history),
-            None => None,
-        }
+        StoreHistory::get(key).map(|store| store.history)
     }
 
     pub fn insert_history(key: Principal, value: TxHistory) -> Option<TxHistory> {
-        match StoreHistory::insert(key, StoreHistory::new(value)) {
-            Some(store) => Some(store.history),
-            None => None,
-        }
+        StoreHistory::insert(key, StoreHistory::new(value)).map(|store| store.history)
+    }
+
+    // Appends an entry to the history of key and records the change for sync
+    pub fn push_entry(key: Principal, entry: TransactionEntry) {
+        let mut history = StoreHistory::get_history(&key).unwrap_or_default();
+        history.push(entry);
+        let index = history.len() - 1;
+        search::index_history(&key, index, &history[index]);
+        StoreHistory::insert_history(key, history);
+        sync::record_change(key, Change::History(index));
     }
 }
 
@@ -314,7 +420,6 @@ pub struct UserMetadata {
     pub name: String,
     pub pay_id: String,
     pub profile_pic: String,
-    pub created_at: u64,
 }
 
 #[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
@@ -339,6 +444,7 @@ pub struct UserToUserTxArg {
     pub amount: Nat,
     pub tx_id: Nat,
     // is_read_by_sender: bool,
+    pub fee: Option<Nat>,
 }
 
 pub struct UserBusinessTxArg {
@@ -349,6 +455,7 @@ pub struct UserBusinessTxArg {
     pub u_principal: Principal, // user
     pub note: Option<String>,
     pub tx_kind: TxKind, // Based on User (from or to)
+    pub fee: Option<Nat>,
 }
 
 pub struct UserUnknownTxArg {
@@ -359,6 +466,7 @@ pub struct UserUnknownTxArg {
     pub timestamp: u64,
     pub note: Option<String>,
     pub tx_kind: TxKind, // Based on User (from or to)
+    pub fee: Option<Nat>,
 }
 
 #[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
@@ -370,9 +478,9 @@ pub struct UserSignUpArgs {
 
 fn create_key(pay_id1: &PayId, pay_id2: &PayId) -> String {
     if pay_id1 > pay_id2 {
-        return format!("{pay_id1}/{pay_id2}");
+        format!("{pay_id1}/{pay_id2}")
     } else if pay_id2 > pay_id1 {
-        return format!("{pay_id2}/{pay_id1}");
+        format!("{pay_id2}/{pay_id1}")
     } else {
         ic_cdk::trap("Both PayIds are same")
     }
@@ -388,25 +496,39 @@ pub fn get_metadata(principal: &Principal) -> Option<UserMetadata> {
             name: u.name,
             pay_id: u.pay_id,
             profile_pic: u.profile_pic,
-            created_at: u.created_at,
         }),
         None => None,
     }
 }
 
+// The caller's user account, its user profile if the identity's own principal is a business
+pub fn user_caller() -> Principal {
+    profiles::user_principal(&caller())
+}
+
+/*
+ * The user account is on the caller's own principal. A business identity gets it as a profile on a
+ * subaccount instead, see profiles.rs, so one identity can both pay personally and run the business.
+ */
 pub fn sign_up_user(args: UserSignUpArgs) -> Result<(), SignUpError> {
-    let caller: Principal = caller();
+    let identity: Principal = caller();
 
-    if caller == Principal::anonymous() {
+    if identity == Principal::anonymous() {
         return Err(SignUpError::AnonymousCaller);
     }
-    if is_user(&caller) || is_business(&caller) {
+    if is_user(&profiles::user_principal(&identity)) {
         return Err(SignUpError::AccountExist);
     }
     if is_payid_exist(&args.pay_id) {
         return Err(SignUpError::PayIdExist);
     }
 
+    let caller = if is_business(&identity) {
+        profiles::new_user_profile(&identity)
+    } else {
+        identity
+    };
+
     let pay_id = args.pay_id.clone();
     let user = User {
         name: args.name,
@@ -422,7 +544,7 @@ pub fn sign_up_user(args: UserSignUpArgs) -> Result<(), SignUpError> {
 }
 
 pub fn create_chat(participant_id: PayIdOrPrincipal) -> Result<Chat, CreateChatErr> {
-    let caller = caller();
+    let caller = user_caller();
 
     let mut user = match Users::get(&caller) {
         Some(user) => user,
@@ -440,7 +562,7 @@ pub fn create_chat(participant_id: PayIdOrPrincipal) -> Result<Chat, CreateChatE
 
         PayIdOrPrincipal::PayId(pay_id) => {
             // Check caller and participant same or not
-            if &pay_id == &user.pay_id {
+            if pay_id == user.pay_id {
                 return Err(CreateChatErr::CallerAndParticipantSame);
             }
             match PayIds::get(&pay_id) {
@@ -455,6 +577,10 @@ pub fn create_chat(participant_id: PayIdOrPrincipal) -> Result<Chat, CreateChatE
         None => return Err(CreateChatErr::ParticipantNotFound),
     };
 
+    if safety::is_blocked(&caller, &participant_p) {
+        return Err(CreateChatErr::Blocked);
+    }
+
     let chat_id = create_key(&user.pay_id, &participant.pay_id);
 
     if let Some(chat) = Chats::get(&chat_id) {
@@ -466,9 +592,12 @@ pub fn create_chat(participant_id: PayIdOrPrincipal) -> Result<Chat, CreateChatE
         participants: vec![user.pay_id.clone(), participant.pay_id.clone()],
         messages: Vec::new(),
         last_activity: time(),
+        last_read: None,
+        encrypted: None,
     };
 
     Chats::insert(chat_id.clone(), chat.clone());
+    sync::record_chat_change(&chat.participants, Change::Chat(chat_id.clone()));
 
     user.my_chats.insert((chat.last_activity, chat_id.clone()));
     participant.my_chats.insert((chat.last_activity, chat_id));
@@ -478,8 +607,33 @@ pub fn create_chat(participant_id: PayIdOrPrincipal) -> Result<Chat, CreateChatE
     Ok(chat)
 }
 
-pub fn add_message(chat_id: ChatId, content: String) -> Result<Message, AddMessageErr> {
-    let caller = caller();
+pub fn add_message(
+    chat_id: ChatId,
+    content: String,
+    reply_to: Option<MessageId>,
+) -> Result<Message, AddMessageErr> {
+    push_message(chat_id, content, None, reply_to)
+}
+
+// Content is encrypted by the sender with the chat key from chat_keys, only the ciphertext is stored
+pub fn add_encrypted_message(
+    chat_id: ChatId,
+    ciphertext: ByteBuf,
+    reply_to: Option<MessageId>,
+) -> Result<Message, AddMessageErr> {
+    if ciphertext.is_empty() || ciphertext.len() > MAX_CIPHERTEXT_LEN {
+        return Err(AddMessageErr::InvalidCiphertext);
+    }
+    push_message(chat_id, String::new(), Some(ciphertext), reply_to)
+}
+
+fn push_message(
+    chat_id: ChatId,
+    content: String,
+    ciphertext: Option<ByteBuf>,
+    reply_to: Option<MessageId>,
+) -> Result<Message, AddMessageErr> {
+    let caller = user_caller();
 
     let mut user = match Users::get(&caller) {
         Some(user) => user,
@@ -495,17 +649,40 @@ pub fn add_message(chat_id: ChatId, content: String) -> Result<Message, AddMessa
     if !chat.participants.contains(&user.pay_id) {
         return Err(AddMessageErr::NotAParticipant);
     }
+
+    if safety::is_blocked_in_chat(&caller, &chat.participants) {
+        return Err(AddMessageErr::Blocked);
+    }
+
+    match (chat.is_encrypted(), ciphertext.is_some()) {
+        (true, false) => return Err(AddMessageErr::ChatEncrypted),
+        (false, true) => return Err(AddMessageErr::ChatNotEncrypted),
+        _ => {}
+    }
+
+    if let Some(message_id) = reply_to {
+        if message_id >= chat.messages.len() {
+            return Err(AddMessageErr::ReplyToNotFound);
+        }
+    }
+
     let pre_last_activity = chat.last_activity;
     let message = Message {
         sender_id: user.pay_id.clone(),
         content,
         timestamp: time(),
-        read_by: vec![user.pay_id.clone()],
+        read_by: Vec::new(),
+        reply_to,
+        edit_history: None,
+        deleted_at: None,
+        reactions: None,
+        ciphertext,
     };
 
     chat.messages
         .push(MessageOrTransaction::Message(message.clone()));
     chat.last_activity = message.timestamp;
+    let message_id = chat.messages.len() - 1;
 
     // Update the ordering of the chat in the user's BTreeSet
     if !user.my_chats.remove(&(pre_last_activity, chat_id.clone())) {
@@ -536,60 +713,222 @@ pub fn add_message(chat_id: ChatId, content: String) -> Result<Message, AddMessa
         }
     }
 
+    // The canister can't read encrypted messages, they are neither searchable nor scanned for mentions
+    if message.ciphertext.is_none() {
+        search::index_message(&chat.participants, &chat_id, message_id, &message.content);
+        notifications::mentions(
+            &chat.participants,
+            &user.pay_id,
+            &chat_id,
+            message_id,
+            &message.content,
+        );
+    }
+    sync::record_chat_change(
+        &chat.participants,
+        Change::Message(chat_id.clone(), message_id),
+    );
     Chats::insert(chat_id, chat);
     Users::insert(caller, user);
 
     Ok(message)
 }
 
-pub fn mark_message_read(chat_id: ChatId) -> Result<(), MarkMessageReadErr> {
-    match Chats::get(&chat_id) {
-        Some(mut chat) => {
-            match Users::get(&caller()) {
-                Some(user) => {
-                    // Alternatively, we can check chat_id is contains in my_chats (faster search) if there are many participants in chat
-                    if !chat.participants.contains(&user.pay_id) {
-                        return Err(MarkMessageReadErr::NotAParticipant);
-                    }
-
-                    for msg_or_tx in chat.messages.iter_mut().rev() {
-                        match msg_or_tx {
-                            MessageOrTransaction::Message(msg) => {
-                                if msg.read_by.contains(&user.pay_id) {
-                                    break;
-                                } else {
-                                    msg.read_by.push(user.pay_id.clone());
-                                }
-                            }
-                            MessageOrTransaction::Transaction(tx) => {
-                                if tx.read_by.contains(&user.pay_id) {
-                                    break;
-                                } else {
-                                    tx.read_by.push(user.pay_id.clone());
-                                }
-                            }
-                            MessageOrTransaction::RequestPayment(req) => {
-                                if req.read_by.contains(&user.pay_id) {
-                                    break;
-                                } else {
-                                    req.read_by.push(user.pay_id.clone());
-                                }
-                            }
-                        }
-                    }
-
-                    Chats::insert(chat_id, chat);
-                    return Ok(());
-                }
-                None => return Err(MarkMessageReadErr::AccountNotFound),
-            };
+// Any participant can turn on end-to-end encryption, it can't be turned off. Earlier entries stay as they are
+pub fn enable_chat_encryption(chat_id: ChatId) -> Result<Chat, ChatEncryptionError> {
+    let mut chat = get_chat_for_encryption(&chat_id)?;
+    if !chat.is_encrypted() {
+        chat.encrypted = Some(true);
+        Chats::insert(chat_id.clone(), chat.clone());
+        sync::record_chat_change(&chat.participants, Change::Chat(chat_id));
+    }
+    Ok(chat)
+}
+
+// The chat if the caller takes part in it, for enabling encryption and requesting the chat key
+pub fn get_chat_for_encryption(chat_id: &ChatId) -> Result<Chat, ChatEncryptionError> {
+    let user = match Users::get(&user_caller()) {
+        Some(user) => user,
+        None => return Err(ChatEncryptionError::AccountNotFound),
+    };
+
+    let chat = match Chats::get(chat_id) {
+        Some(chat) => chat,
+        None => return Err(ChatEncryptionError::ChatNotFound),
+    };
+
+    if !chat.participants.contains(&user.pay_id) {
+        return Err(ChatEncryptionError::NotAParticipant);
+    }
+
+    Ok(chat)
+}
+
+// Returns the caller's pay_id and the chat, checking that the caller takes part in it
+fn get_chat_for_update(chat_id: &ChatId) -> Result<(PayId, Chat), UpdateMessageErr> {
+    let user = match Users::get(&user_caller()) {
+        Some(user) => user,
+        None => return Err(UpdateMessageErr::AccountNotFound),
+    };
+
+    let chat = match Chats::get(chat_id) {
+        Some(chat) => chat,
+        None => return Err(UpdateMessageErr::ChatNotFound),
+    };
+
+    if !chat.participants.contains(&user.pay_id) {
+        return Err(UpdateMessageErr::NotAParticipant);
+    }
+
+    Ok((user.pay_id, chat))
+}
+
+fn get_message_mut(
+    chat: &mut Chat,
+    message_id: MessageId,
+) -> Result<&mut Message, UpdateMessageErr> {
+    match chat.messages.get_mut(message_id) {
+        Some(MessageOrTransaction::Message(msg)) => {
+            if msg.deleted_at.is_some() {
+                return Err(UpdateMessageErr::MessageDeleted);
+            }
+            Ok(msg)
+        }
+        _ => Err(UpdateMessageErr::MessageNotFound),
+    }
+}
+
+// Only the sender can edit, previous content is kept in edit_history
+pub fn edit_message(
+    chat_id: ChatId,
+    message_id: MessageId,
+    content: String,
+) -> Result<Message, UpdateMessageErr> {
+    let (pay_id, mut chat) = get_chat_for_update(&chat_id)?;
+    // A plaintext edit would leak into an encrypted chat, encrypted messages are deleted and sent again
+    if chat.is_encrypted() {
+        return Err(UpdateMessageErr::ChatEncrypted);
+    }
+
+    let msg = get_message_mut(&mut chat, message_id)?;
+    if msg.sender_id != pay_id {
+        return Err(UpdateMessageErr::NotMessageSender);
+    }
+
+    let previous = std::mem::replace(&mut msg.content, content);
+    msg.edit_history
+        .get_or_insert_with(Vec::new)
+        .push(MessageEdit {
+            content: previous.clone(),
+            edited_at: time(),
+        });
+
+    let message = msg.clone();
+    search::unindex_message(&chat.participants, &chat_id, message_id, &previous);
+    search::index_message(&chat.participants, &chat_id, message_id, &message.content);
+    sync::record_chat_change(
+        &chat.participants,
+        Change::Message(chat_id.clone(), message_id),
+    );
+    Chats::insert(chat_id, chat);
+    Ok(message)
+}
+
+// Only the sender can delete. Message stays in chat as a tombstone so MessageIds (and replies) keep pointing to right entry
+pub fn delete_message(chat_id: ChatId, message_id: MessageId) -> Result<(), UpdateMessageErr> {
+    let (pay_id, mut chat) = get_chat_for_update(&chat_id)?;
+
+    let msg = get_message_mut(&mut chat, message_id)?;
+    if msg.sender_id != pay_id {
+        return Err(UpdateMessageErr::NotMessageSender);
+    }
+
+    let content = std::mem::take(&mut msg.content);
+    msg.ciphertext = None;
+    msg.edit_history = None;
+    msg.reactions = None;
+    msg.deleted_at = Some(time());
+
+    search::unindex_message(&chat.participants, &chat_id, message_id, &content);
+    sync::record_chat_change(
+        &chat.participants,
+        Change::Message(chat_id.clone(), message_id),
+    );
+    Chats::insert(chat_id, chat);
+    Ok(())
+}
+
+// Set the caller's reaction on a message, None removes it
+pub fn react_to_message(
+    chat_id: ChatId,
+    message_id: MessageId,
+    emoji: Option<String>,
+) -> Result<Message, UpdateMessageErr> {
+    let (pay_id, mut chat) = get_chat_for_update(&chat_id)?;
+
+    if let Some(emoji) = &emoji {
+        // an emoji with modifiers can be several chars, but not a sentence
+        if emoji.is_empty() || emoji.chars().count() > 8 {
+            return Err(UpdateMessageErr::InvalidReaction);
         }
-        None => Err(MarkMessageReadErr::ChatNotFound),
     }
+
+    let msg = get_message_mut(&mut chat, message_id)?;
+    let reactions = msg.reactions.get_or_insert_with(Vec::new);
+    reactions.retain(|reaction| reaction.pay_id != pay_id);
+    if let Some(emoji) = emoji {
+        reactions.push(Reaction { pay_id, emoji });
+    }
+    if reactions.is_empty() {
+        msg.reactions = None;
+    }
+
+    let message = msg.clone();
+    sync::record_chat_change(
+        &chat.participants,
+        Change::Message(chat_id.clone(), message_id),
+    );
+    Chats::insert(chat_id, chat);
+    Ok(message)
+}
+
+// Moves the caller's watermark to the latest entry, every earlier entry is read as well
+pub fn mark_message_read(chat_id: ChatId) -> Result<(), MarkMessageReadErr> {
+    let mut chat = match Chats::get(&chat_id) {
+        Some(chat) => chat,
+        None => return Err(MarkMessageReadErr::ChatNotFound),
+    };
+
+    let user = match Users::get(&user_caller()) {
+        Some(user) => user,
+        None => return Err(MarkMessageReadErr::AccountNotFound),
+    };
+
+    // Alternatively, we can check chat_id is contains in my_chats (faster search) if there are many participants in chat
+    if !chat.participants.contains(&user.pay_id) {
+        return Err(MarkMessageReadErr::NotAParticipant);
+    }
+
+    let latest = match chat.messages.len().checked_sub(1) {
+        Some(message_id) => message_id,
+        None => return Ok(()), // nothing to read
+    };
+
+    if chat.last_read_by(&user.pay_id) == Some(latest) {
+        return Ok(());
+    }
+
+    chat.last_read
+        .get_or_insert_with(BTreeMap::new)
+        .insert(user.pay_id, latest);
+
+    sync::record_chat_change(&chat.participants, Change::Chat(chat_id.clone()));
+    Chats::insert(chat_id, chat);
+    Ok(())
 }
 
 pub fn user_add_business(arg: PayIdOrPrincipal) -> Result<BusinessInUser, AddBusinessError> {
-    let caller = caller();
+    let caller = user_caller();
 
     let mut user = match Users::get(&caller) {
         Some(user) => user,
@@ -628,6 +967,9 @@ pub fn user_add_business(arg: PayIdOrPrincipal) -> Result<BusinessInUser, AddBus
     };
 
     BusinessInUser::insert(id.clone(), business_in_user.clone());
+    customers::record_added_business(&b_principal, &caller, business_in_user.last_activity);
+    business_in_user.index_for(&caller);
+    sync::record_change(caller, Change::Business(id.clone()));
     user.with_businesses
         .insert((business_in_user.last_activity, id));
     Users::insert(caller, user);
@@ -635,37 +977,169 @@ pub fn user_add_business(arg: PayIdOrPrincipal) -> Result<BusinessInUser, AddBus
 }
 
 // get all the chat associated with the caller. return empty if user not found or no chats
-pub fn get_my_chats() -> Vec<Chat> {
+pub fn get_my_chats() -> Vec<MyChat> {
     let mut chats = Vec::new();
-    let user = match Users::get(&caller()) {
+    let user = match Users::get(&user_caller()) {
         Some(user) => user,
         None => return chats,
     };
 
     for (_, chat_id) in user.my_chats.iter() {
         match Chats::get(chat_id) {
-            Some(chat) => chats.push(chat),
+            Some(chat) => chats.push(MyChat {
+                unread_count: chat.unread_count(&user.pay_id),
+                chat,
+            }),
             None => continue,
         }
     }
     chats
 }
+
+// Total of unread entries across all chats of the caller, for the badge
+pub fn get_unread_count() -> u64 {
+    let user = match Users::get(&user_caller()) {
+        Some(user) => user,
+        None => return 0,
+    };
+
+    user.my_chats
+        .iter()
+        .filter_map(|(_, chat_id)| Chats::get(chat_id))
+        .map(|chat| chat.unread_count(&user.pay_id))
+        .sum()
+}
 // Sort by last_activity
 // chats.sort_by(|a, b| b.last_activity.cmp(&a.last_activity));
 
-// get a specific chat with id. Chat returned if caller is participant and chat is found otherwise return NONE
-pub fn get_chat(chat_id: ChatId) -> Option<Chat> {
-    let caller = caller();
+// Chat with the given entries, None if principal is not a participant
+pub fn get_chat_update(
+    principal: &Principal,
+    chat_id: &ChatId,
+    message_ids: &[MessageId],
+) -> Option<ChatUpdate> {
+    let pay_id = Users::get(principal)?.pay_id;
+    let chat = Chats::get(chat_id)?;
+
+    if !chat.participants.contains(&pay_id) {
+        return None;
+    }
 
-    let pay_id = match Users::get(&caller) {
-        Some(user) => user.pay_id,
-        None => return None, // User not Found
+    let messages = message_ids
+        .iter()
+        .filter_map(|message_id| {
+            chat.messages
+                .get(*message_id)
+                .map(|msg_or_tx| (*message_id, msg_or_tx.clone()))
+        })
+        .collect();
+
+    Some(ChatUpdate {
+        unread_count: chat.unread_count(&pay_id),
+        id: chat.id,
+        participants: chat.participants,
+        last_activity: chat.last_activity,
+        last_read: chat.last_read,
+        encrypted: chat.encrypted,
+        messages,
+    })
+}
+
+pub fn get_history(principal: &Principal) -> TxHistory {
+    StoreHistory::get_history(principal).unwrap_or_default()
+}
+
+// Entry of a chat, None if principal is not a participant
+pub fn get_chat_entry(
+    principal: &Principal,
+    chat_id: &ChatId,
+    message_id: MessageId,
+) -> Option<MessageOrTransaction> {
+    let pay_id = Users::get(principal)?.pay_id;
+    let chat = Chats::get(chat_id)?;
+
+    if !chat.participants.contains(&pay_id) {
+        return None;
+    }
+    chat.messages.get(message_id).cloned()
+}
+
+// Adds the chats and businesses of principal to its search index
+pub fn index_chats_and_businesses(principal: &Principal) {
+    let user = match Users::get(principal) {
+        Some(user) => user,
+        None => return,
     };
-    let chat = match Chats::get(&chat_id) {
-        Some(chat) => chat,
-        None => return None, // Chat not found
+
+    for (_, chat_id) in user.my_chats.iter() {
+        let chat = match Chats::get(chat_id) {
+            Some(chat) => chat,
+            None => continue,
+        };
+        for (message_id, msg_or_tx) in chat.messages.iter().enumerate() {
+            if let MessageOrTransaction::Message(msg) = msg_or_tx {
+                let doc = SearchDoc::Message(chat_id.clone(), message_id);
+                search::add_doc(principal, &msg.content, doc);
+            }
+        }
+    }
+
+    for (_, usr_bus_id) in user.with_businesses.iter() {
+        if let Some(business_in_user) = BusinessInUser::get(usr_bus_id) {
+            business_in_user.index_for(principal);
+        }
+    }
+}
+
+// Updates the entry of tx_id in the history of principal and records the change for sync
+pub fn update_entry(
+    principal: &Principal,
+    tx_id: &Nat,
+    update: impl FnOnce(&mut TransactionEntry),
+) {
+    let mut history = StoreHistory::get_history(principal).unwrap_or_default();
+
+    let index = match history.iter().rposition(|entry| &entry.tx_id == tx_id) {
+        Some(index) => index,
+        None => return,
     };
-    if !chat.participants.contains(&pay_id) {
+
+    update(&mut history[index]);
+    StoreHistory::insert_history(*principal, history);
+    sync::record_change(*principal, Change::History(index));
+}
+
+pub fn get_history_at(principal: &Principal, indexes: &[usize]) -> Vec<TransactionEntry> {
+    let history = StoreHistory::get_history(principal).unwrap_or_default();
+
+    indexes
+        .iter()
+        .filter_map(|index| history.get(*index).cloned())
+        .collect()
+}
+
+// BusinessInUser with the id, None if it doesn't belong to principal
+pub fn get_business_in_user(principal: &Principal, id: &String) -> Option<BusinessInUser> {
+    let user = Users::get(principal)?;
+
+    if !user
+        .with_businesses
+        .iter()
+        .any(|(_, usr_bus_id)| usr_bus_id == id)
+    {
+        return None;
+    }
+    BusinessInUser::get(id)
+}
+
+// get a specific chat with id. Chat returned if caller is participant and chat is found otherwise return NONE
+pub fn get_chat(chat_id: ChatId) -> Option<Chat> {
+    let caller = user_caller();
+
+    let pay_id = Users::get(&caller)?.pay_id; // None if User not Found
+    let chat = Chats::get(&chat_id)?; // None if Chat not found
+
+    if chat.participants.contains(&pay_id) {
         Some(chat)
     } else {
         None // if caller is not participant
@@ -680,6 +1154,7 @@ pub fn add_user_to_user_transaction(
         note,
         amount,
         tx_id,
+        fee,
     }: UserToUserTxArg,
 ) {
     /*
@@ -701,30 +1176,26 @@ pub fn add_user_to_user_transaction(
         participants: vec![from_user.pay_id.clone(), to_user.pay_id.clone()],
         messages: Vec::new(),
         last_activity: timestamp,
+        last_read: None,
+        encrypted: None,
     });
     let pre_last_activity = chat.last_activity;
 
-    let caller = caller();
-
-    let read_by: Vec<PayId> = if caller == from {
-        vec![from_user.pay_id.clone()]
-    } else if caller == to {
-        vec![from_user.pay_id.clone()]
-    } else {
-        Vec::new()
-    };
-
     let transaction = Transaction {
         sender_id: from_user.pay_id.clone(),
         timestamp,
         note: note.clone(),
         amount: amount.clone(),
         tx_id: tx_id.clone(),
-        read_by,
+        read_by: Vec::new(),
     };
     chat.last_activity = transaction.timestamp;
     chat.messages
         .push(MessageOrTransaction::Transaction(transaction));
+    sync::record_chat_change(
+        &chat.participants,
+        Change::Message(chat_id.clone(), chat.messages.len() - 1),
+    );
 
     // Update the ordering of the chat in the user's BTreeSet
     // from_user.my_chats.remove(&chat_id);
@@ -755,6 +1226,9 @@ pub fn add_user_to_user_transaction(
         timestamp,
         amount: amount.clone(),
         note: note.clone(),
+        fee: fee.clone(),
+        refund: None,
+        fiat: None,
     };
 
     let tx_entry_to_user = TransactionEntry {
@@ -765,29 +1239,13 @@ pub fn add_user_to_user_transaction(
         timestamp,
         amount,
         note,
+        fee,
+        refund: None,
+        fiat: None,
     };
 
-    match StoreHistory::get_history(&from) {
-        Some(mut tx_history) => {
-            tx_history.push(tx_entry_from_user);
-            StoreHistory::insert_history(from, tx_history);
-        }
-        None => {
-            let tx_history = vec![tx_entry_from_user];
-            StoreHistory::insert_history(from, tx_history);
-        }
-    }
-
-    match StoreHistory::get_history(&to) {
-        Some(mut tx_history) => {
-            tx_history.push(tx_entry_to_user);
-            StoreHistory::insert_history(from, tx_history);
-        }
-        None => {
-            let tx_history = vec![tx_entry_to_user];
-            StoreHistory::insert_history(from, tx_history);
-        }
-    }
+    StoreHistory::push_entry(from, tx_entry_from_user);
+    StoreHistory::push_entry(to, tx_entry_to_user);
 
     Chats::insert(chat_id, chat);
     Users::insert(from, from_user);
@@ -803,6 +1261,7 @@ pub fn add_user_business_transaction(
         u_principal,
         note,
         tx_kind,
+        fee,
     }: UserBusinessTxArg,
 ) {
     // No need for second checking of Accounts, already checked in record_xfer_transaction function
@@ -824,9 +1283,10 @@ pub fn add_user_business_transaction(
 
     let pre_last_activity = business_in_user.last_activity; //previous activity time
 
-    let sender_id = match tx_kind {
-        TxKind::Sends => user.pay_id.clone(),
-        TxKind::Receive => business.pay_id.clone(),
+    let sender_id = if tx_kind.is_outgoing() {
+        user.pay_id.clone()
+    } else {
+        business.pay_id.clone()
     };
     let business_user_tx = BusinessUserTx {
         sender_id,
@@ -853,19 +1313,15 @@ pub fn add_user_business_transaction(
         timestamp,
         amount,
         note,
+        fee,
+        refund: None,
+        fiat: None,
     };
 
-    match StoreHistory::get_history(&u_principal) {
-        Some(mut tx_history) => {
-            tx_history.push(tx_entry);
-            StoreHistory::insert_history(u_principal, tx_history);
-        }
-        None => {
-            let tx_history = vec![tx_entry];
-            StoreHistory::insert_history(u_principal, tx_history);
-        }
-    }
+    StoreHistory::push_entry(u_principal, tx_entry);
 
+    business_in_user.index_for(&u_principal);
+    sync::record_change(u_principal, Change::Business(user_busi_id.clone()));
     BusinessInUser::insert(user_busi_id, business_in_user);
     Users::insert(u_principal, user);
 }
@@ -879,6 +1335,7 @@ pub fn add_unknown_transaction(
         timestamp,
         note,
         tx_kind,
+        fee,
     }: UserUnknownTxArg,
 ) {
     let tx_entry = TransactionEntry {
@@ -889,23 +1346,17 @@ pub fn add_unknown_transaction(
         timestamp,
         amount,
         note,
+        fee,
+        refund: None,
+        fiat: None,
     };
 
-    match StoreHistory::get_history(&u_principal) {
-        Some(mut tx_history) => {
-            tx_history.push(tx_entry);
-            StoreHistory::insert_history(u_principal, tx_history);
-        }
-        None => {
-            let tx_history = vec![tx_entry];
-            StoreHistory::insert_history(u_principal, tx_history);
-        }
-    }
+    StoreHistory::push_entry(u_principal, tx_entry);
 }
 
 // Fetch User metadata, latest transaction history 50, 8 chats and 4 businessInUser
 pub fn fetch_init_user_data() -> UserData {
-    let caller = caller();
+    let caller = user_caller();
 
     // cheked in parent fn
     let user = Users::get(&caller).unwrap();
@@ -922,11 +1373,7 @@ pub fn fetch_init_user_data() -> UserData {
     for (_, chat_id) in user
         .my_chats
         .iter()
-        .skip(if user.my_chats.len() > 8 {
-            user.my_chats.len() - 8
-        } else {
-            0
-        })
+        .skip(user.my_chats.len().saturating_sub(8))
         .rev()
     {
         match Chats::get(chat_id) {
@@ -941,11 +1388,7 @@ pub fn fetch_init_user_data() -> UserData {
     for (_, usr_bus_id) in user
         .with_businesses
         .iter()
-        .skip(if user.with_businesses.len() > 4 {
-            user.with_businesses.len() - 4
-        } else {
-            0
-        })
+        .skip(user.with_businesses.len().saturating_sub(4))
         .rev()
     {
         match BusinessInUser::get(usr_bus_id) {
@@ -963,14 +1406,10 @@ pub fn fetch_init_user_data() -> UserData {
     //     .collect::<Vec<_>>();
     let history = StoreHistory::get_history(&caller).unwrap_or_default();
     let history_len = history.len();
-     // latest first order
+    // latest first order
     let history = history
         .into_iter()
-        .skip(if history_len > 50 {
-            history_len - 50
-        } else {
-            0
-        })
+        .skip(history_len.saturating_sub(50))
         .rev()
         .collect::<Vec<_>>();
 
@@ -984,7 +1423,7 @@ pub fn fetch_init_user_data() -> UserData {
 
 // fetch entire user data(metadata, tx history, chats, businessInChat)
 pub fn fetch_user_data() -> UserData {
-    let caller = caller();
+    let caller = user_caller();
 
     // cheked in parent fn
     let user = Users::get(&caller).unwrap();
@@ -1009,7 +1448,7 @@ pub fn fetch_user_data() -> UserData {
         }
     }
 
-     // latest first order
+    // latest first order
     // Getting history
     let history = StoreHistory::get_history(&caller)
         .unwrap_or_default()
@@ -1026,7 +1465,7 @@ pub fn fetch_user_data() -> UserData {
 }
 
 pub fn get_user() -> Option<User> {
-    Users::get(&caller())
+    Users::get(&user_caller())
 }
 
 #[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
@@ -1043,7 +1482,7 @@ pub fn request_payment(
         note,
     }: ReqPayArg,
 ) -> Result<RequestPayment, RequestPaymentError> {
-    let caller = caller();
+    let caller = user_caller();
 
     //requester
     let mut user = match Users::get(&caller) {
@@ -1060,6 +1499,8 @@ pub fn request_payment(
         return Err(RequestPaymentError::NotAParticipant);
     }
 
+    safety::check_payment_request(&caller, &chat_id, &chat.participants)?;
+
     let pre_last_activity = chat.last_activity;
     let timestamp = time();
     let request_payment = RequestPayment {
@@ -1070,12 +1511,16 @@ pub fn request_payment(
         payment_at: None,
         tx_id: None,
         expires_at: get_24h_expiry(timestamp),
-        read_by: vec![user.pay_id.clone()],
+        read_by: Vec::new(),
     };
 
     chat.messages.push(MessageOrTransaction::RequestPayment(
         request_payment.clone(),
     ));
+    sync::record_chat_change(
+        &chat.participants,
+        Change::Message(chat_id.clone(), chat.messages.len() - 1),
+    );
 
     chat.last_activity = timestamp;
 
@@ -1106,6 +1551,15 @@ pub fn request_payment(
         }
     }
 
+    notifications::request_received(
+        &chat.participants,
+        &user.pay_id,
+        &chat_id,
+        chat.messages.len() - 1,
+        &request_payment.amount,
+        request_payment.expires_at,
+    );
+    safety::record_payment_request(caller, chat_id.clone());
     Chats::insert(chat_id, chat);
     Users::insert(caller, user);
 
@@ -1121,6 +1575,7 @@ pub struct RecordReqPayTxArg {
     pub chat_id: ChatId,
     pub message_index: usize,
     // is_read_by_sender: bool,
+    pub fee: Option<Nat>,
 }
 
 pub fn record_request_payment(
@@ -1132,10 +1587,9 @@ pub fn record_request_payment(
         tx_id,
         chat_id,
         message_index,
+        fee,
     }: RecordReqPayTxArg,
 ) -> Result<(), RecordRegPayTxErr> {
-
-
     let mut from_user = match Users::get(&from) {
         Some(user) => user,
         None => return Err(RecordRegPayTxErr::AccountNotFound),
@@ -1164,25 +1618,32 @@ pub fn record_request_payment(
 
     let pre_last_activity = chat.last_activity;
 
-    let req_pay    = match chat.messages.get_mut(message_index){
+    let req_pay = match chat.messages.get_mut(message_index) {
         Some(MessageOrTransaction::RequestPayment(req_pay)) => req_pay,
-       _ => return Err(RecordRegPayTxErr::RequestPaymentNotFound),
+        _ => return Err(RecordRegPayTxErr::RequestPaymentNotFound),
     };
 
     /* We will add a 30 miniutes (1,800,000,000,000 nanoseconds) buffer to expiry time,
-     Incase if user send payment in a time closes  to expiry time this will condition will return Expired error
-     */
-    if (req_pay.expires_at + 1_800_000_000_000) < timestamp{
-        return Err(RecordRegPayTxErr::InvalidTransaction("Expired payment request".to_string()))
+    Incase if user send payment in a time closes  to expiry time this will condition will return Expired error
+    */
+    if (req_pay.expires_at + 1_800_000_000_000) < timestamp {
+        return Err(RecordRegPayTxErr::InvalidTransaction(
+            "Expired payment request".to_string(),
+        ));
     }
 
-    if req_pay.amount != amount{
-        return Err(RecordRegPayTxErr::InvalidTransaction("Requested Amount is not equal to actual amount".to_string()));
+    if req_pay.amount != amount {
+        return Err(RecordRegPayTxErr::InvalidTransaction(
+            "Requested Amount is not equal to actual amount".to_string(),
+        ));
     }
 
     // Payment requester is sender_id, 'to' is the payment receiver who requested payment
-    if &req_pay.sender_id != &to_user.pay_id{
-        return  Err(RecordRegPayTxErr::InvalidTransaction(format!("Requester {} is not the payment receiver {}", req_pay.sender_id, to_user.pay_id)));
+    if req_pay.sender_id != to_user.pay_id {
+        return Err(RecordRegPayTxErr::InvalidTransaction(format!(
+            "Requester {} is not the payment receiver {}",
+            req_pay.sender_id, to_user.pay_id
+        )));
     }
 
     req_pay.payment_at = Some(timestamp);
@@ -1204,59 +1665,130 @@ pub fn record_request_payment(
         .my_chats
         .insert((chat.last_activity, chat_id.clone()));
 
-        let tx_entry_from_user = TransactionEntry {
-            kind: TxKind::Sends,            // for from its sending
-            name: to_user.name.clone(),     // received by to
-            pay_id: to_user.pay_id.clone(), // received by to
-            tx_id: tx_id.clone(),
-            timestamp,
-            amount: amount.clone(),
-            note: req_pay.note.clone(),
-        };
-    
-        let tx_entry_to_user = TransactionEntry {
-            kind: TxKind::Receive,            // for to its receiveing
-            name: from_user.name.clone(),     // send by from
-            pay_id: from_user.pay_id.clone(), // send by from
-            tx_id: tx_id.clone(),
-            timestamp,
-            amount,
-            note: req_pay.note.clone(),
-        };
+    let tx_entry_from_user = TransactionEntry {
+        kind: TxKind::Sends,            // for from its sending
+        name: to_user.name.clone(),     // received by to
+        pay_id: to_user.pay_id.clone(), // received by to
+        tx_id: tx_id.clone(),
+        timestamp,
+        amount: amount.clone(),
+        note: req_pay.note.clone(),
+        fee: fee.clone(),
+        refund: None,
+        fiat: None,
+    };
 
+    let tx_entry_to_user = TransactionEntry {
+        kind: TxKind::Receive,            // for to its receiveing
+        name: from_user.name.clone(),     // send by from
+        pay_id: from_user.pay_id.clone(), // send by from
+        tx_id: tx_id.clone(),
+        timestamp,
+        amount,
+        note: req_pay.note.clone(),
+        fee,
+        refund: None,
+        fiat: None,
+    };
 
-        match StoreHistory::get_history(&from) {
-            Some(mut tx_history) => {
-                tx_history.push(tx_entry_from_user);
-                StoreHistory::insert_history(from, tx_history);
-            }
-            None => {
-                let tx_history = vec![tx_entry_from_user];
-                StoreHistory::insert_history(from, tx_history);
-            }
-        }
-    
-        match StoreHistory::get_history(&to) {
-            Some(mut tx_history) => {
-                tx_history.push(tx_entry_to_user);
-                StoreHistory::insert_history(from, tx_history);
-            }
-            None => {
-                let tx_history = vec![tx_entry_to_user];
-                StoreHistory::insert_history(from, tx_history);
-            }
+    StoreHistory::push_entry(from, tx_entry_from_user);
+    StoreHistory::push_entry(to, tx_entry_to_user);
+
+    sync::record_chat_change(
+        &chat.participants,
+        Change::Message(chat_id.clone(), message_index),
+    );
+    Chats::insert(chat_id, chat);
+    Users::insert(from, from_user);
+    Users::insert(to, to_user);
+
+    Ok(())
+}
+
+// Payment request not paid yet, for the due and expired notifications
+pub struct UnpaidRequest {
+    pub requester: PayId,
+    pub payers: Vec<PayId>, // the other participants of the chat
+    pub amount: Nat,
+    pub expires_at: u64,
+}
+
+pub fn unpaid_request(chat_id: &ChatId, message_id: MessageId) -> Option<UnpaidRequest> {
+    let chat = Chats::get(chat_id)?;
+    match chat.messages.get(message_id) {
+        Some(MessageOrTransaction::RequestPayment(request)) if request.tx_id.is_none() => {
+            Some(UnpaidRequest {
+                requester: request.sender_id.clone(),
+                payers: chat
+                    .participants
+                    .iter()
+                    .filter(|pay_id| **pay_id != request.sender_id)
+                    .cloned()
+                    .collect(),
+                amount: request.amount.clone(),
+                expires_at: request.expires_at,
+            })
         }
+        _ => None,
+    }
+}
+
+// Pay ids of the two participants of a one to one chat
+pub fn chat_participants(chat_id: &ChatId) -> Option<Vec<PayId>> {
+    Chats::get(chat_id).map(|chat| chat.participants)
+}
 
+// Adds an escrow step to the chat, sender_id is empty if the arbiter or a timeout acted
+pub fn post_escrow_message(
+    chat_id: &ChatId,
+    sender_id: PayId,
+    escrow_id: u64,
+    event: EscrowEvent,
+    amount: Nat,
+) {
+    let mut chat = match Chats::get(chat_id) {
+        Some(chat) => chat,
+        None => return,
+    };
 
-        Chats::insert(chat_id, chat);
-        Users::insert(from, from_user);
-        Users::insert(to, to_user);
+    let pre_last_activity = chat.last_activity;
+    let timestamp = time();
+    chat.messages
+        .push(MessageOrTransaction::Escrow(EscrowMessage {
+            escrow_id,
+            sender_id,
+            event,
+            amount,
+            timestamp,
+            read_by: Vec::new(),
+        }));
+    chat.last_activity = timestamp;
 
-    Ok(())
+    // Update the ordering of the chat in the participants' BTreeSet
+    for pay_id in &chat.participants {
+        let principal = match PayIds::get(pay_id) {
+            Some(principal) => principal,
+            None => continue,
+        };
+        if let Some(mut participant) = Users::get(&principal) {
+            participant
+                .my_chats
+                .remove(&(pre_last_activity, chat_id.clone()));
+            participant
+                .my_chats
+                .insert((chat.last_activity, chat_id.clone()));
+            Users::insert(principal, participant);
+        }
+    }
+
+    sync::record_chat_change(
+        &chat.participants,
+        Change::Message(chat_id.clone(), chat.messages.len() - 1),
+    );
+    Chats::insert(chat_id.clone(), chat);
 }
 
 fn get_24h_expiry(nanosec_time: u64) -> u64 {
-    const A_DAY: u64 = 86_400;
     const NANO_SEC: u64 = 1_000_000_000;
 
     let in_sec = nanosec_time / NANO_SEC;
@@ -0,0 +1,70 @@
+use candid::{self, CandidType, Deserialize, Principal};
+use ic_cdk::api::call::CallResult as Result;
+use serde_bytes::ByteBuf;
+
+const KEY_NAME: &str = "key_1"; // the local replica provides a key with the same name
+                                // Cycles attached to every vetkd_derive_key call with key_1 (34 node subnet), unused cycles are refunded
+const DERIVE_KEY_CYCLES: u128 = 26_153_846_153;
+
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub enum VetKdCurve {
+    #[serde(rename = "bls12_381_g2")]
+    Bls12381G2,
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct VetKdKeyId {
+    pub curve: VetKdCurve,
+    pub name: String,
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct VetKdPublicKeyArgs {
+    pub canister_id: Option<Principal>, // the calling canister if None
+    pub context: ByteBuf,
+    pub key_id: VetKdKeyId,
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct VetKdPublicKeyResult {
+    pub public_key: ByteBuf,
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct VetKdDeriveKeyArgs {
+    pub input: ByteBuf,
+    pub context: ByteBuf,
+    pub transport_public_key: ByteBuf,
+    pub key_id: VetKdKeyId,
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct VetKdDeriveKeyResult {
+    pub encrypted_key: ByteBuf,
+}
+
+pub fn key_id() -> VetKdKeyId {
+    VetKdKeyId {
+        curve: VetKdCurve::Bls12381G2,
+        name: KEY_NAME.to_string(),
+    }
+}
+
+pub async fn vetkd_public_key(args: VetKdPublicKeyArgs) -> Result<(VetKdPublicKeyResult,)> {
+    ic_cdk::call(
+        Principal::management_canister(),
+        "vetkd_public_key",
+        (args,),
+    )
+    .await
+}
+
+pub async fn vetkd_derive_key(args: VetKdDeriveKeyArgs) -> Result<(VetKdDeriveKeyResult,)> {
+    ic_cdk::api::call::call_with_payment128(
+        Principal::management_canister(),
+        "vetkd_derive_key",
+        (args,),
+        DERIVE_KEY_CYCLES,
+    )
+    .await
+}
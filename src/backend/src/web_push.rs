@@ -0,0 +1,777 @@
+use crate::business::is_business;
+use crate::error::WebPushError;
+use crate::export::json_string;
+use crate::notifications;
+use crate::staff::{self, Permission};
+use crate::vetkd::{self, VetKdDeriveKeyArgs};
+use crate::webhooks::{is_valid_url, outcall_cycles};
+use crate::{
+    timer, FEED_PUSHES_MAP, PENDING_PUSHES_MAP, PUSH_SCHEDULE_MAP, PUSH_SUBSCRIPTIONS_MAP,
+    VAPID_KEY_MAP,
+};
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes128Gcm, Aes256Gcm, Nonce};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use candid::{Decode, Encode, Principal};
+use futures::future::join_all;
+use hkdf::Hkdf;
+use ic_bls12_381::{G1Affine, G1Projective, Scalar};
+use ic_cdk::api::management_canister::http_request::{
+    http_request, CanisterHttpRequestArgument, HttpHeader, HttpMethod, TransformContext,
+};
+use ic_cdk::api::{is_controller, management_canister::main::raw_rand, time};
+use ic_cdk::caller;
+use ic_stable_structures::storable::{Bound, Storable};
+use p256::ecdsa::{signature::Signer, Signature, SigningKey};
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use p256::{PublicKey, SecretKey};
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+use sha2::Sha256;
+use std::borrow::Cow;
+use std::cell::Cell;
+
+const VAPID_KEY: &str = "vapid";
+const MAX_SUBSCRIPTIONS: usize = 5; // devices of one identity on a feed
+const MAX_SUBJECT_LEN: usize = 200;
+const MAX_PAYLOAD_LEN: usize = 3_000; // push services take 4 KB with the encryption overhead
+const MAX_ATTEMPTS: u32 = 3;
+const RETRY_BASE: u64 = 60_000_000_000; // 1 minute, doubled after every failed attempt
+const IN_FLIGHT_TIMEOUT: u64 = 300_000_000_000; // a push waiting for its outcall isn't picked again before this
+const PUSHES_PER_TICK: usize = 10;
+const MAX_PENDING: usize = 50; // pushes of one feed waiting to be sent, later ones are dropped
+const TEST_INTERVAL: u64 = 60_000_000_000; // 1 minute between test pushes of an identity
+const JWT_TTL: u64 = 12 * 3_600; // seconds, push services refuse VAPID tokens valid for over 24 hours
+const MESSAGE_TTL: u64 = 86_400; // seconds the push service keeps a message for an offline device
+const RECORD_SIZE: u32 = 4_096;
+const VAPID_KEY_INPUT: &[u8] = b"vapid"; // vetKey the VAPID secret is sealed with
+const VAPID_KEY_CONTEXT: &[u8] = b"rapido_web_push";
+
+thread_local! {
+    static WRAPPING_KEY: Cell<Option<[u8; 32]>> = const { Cell::new(None) };
+}
+
+/*
+ * Application server key of the canister. Threshold ECDSA only signs with secp256k1 while VAPID needs
+ * P-256, so the key is generated from raw_rand once. Its secret is kept in stable memory encrypted
+ * under a vetKey of the canister, see wrapping_key, only its public half is ever returned.
+ */
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct VapidKey {
+    secret: ByteBuf,     // P-256 scalar, sealed unless 32 bytes, see vapid_secret
+    public_key: ByteBuf, // uncompressed point, the applicationServerKey of PushManager.subscribe
+    subject: String,     // mailto: or https: contact sent to push services
+}
+
+impl Storable for VapidKey {
+    const BOUND: Bound = Bound::Unbounded;
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct PushSubscription {
+    id: u64,
+    endpoint: String,
+    p256dh: ByteBuf, // public key of the browser, payloads are encrypted to it
+    auth: ByteBuf,   // 16 byte secret shared with the browser
+    created_at: u64,
+    added_by: Option<Principal>, // identity that subscribed, None for subscriptions before shared feeds
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize, Default)]
+pub struct PushSubscriptions {
+    subscriptions: Vec<PushSubscription>,
+    next_id: u64,
+    last_tests: Option<Vec<(Principal, u64)>>, // identities that sent a test push within TEST_INTERVAL
+}
+
+impl Storable for PushSubscriptions {
+    const BOUND: Bound = Bound::Unbounded;
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl PushSubscriptions {
+    fn get(key: &Principal) -> Option<PushSubscriptions> {
+        PUSH_SUBSCRIPTIONS_MAP.with(|p| p.borrow().get(key))
+    }
+
+    fn insert(key: Principal, value: PushSubscriptions) -> Option<PushSubscriptions> {
+        PUSH_SUBSCRIPTIONS_MAP.with(|p| p.borrow_mut().insert(key, value))
+    }
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct PendingPush {
+    principal: Principal,
+    subscription_id: u64,
+    payload: String, // JSON shown by the service worker
+    topic: String,   // lets the push service replace an undelivered copy of the same push
+    attempts: u32,
+    next_attempt_at: u64,
+}
+
+impl Storable for PendingPush {
+    const BOUND: Bound = Bound::Unbounded;
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+fn schedule_key(at: u64, id: u64) -> String {
+    format!("{:020}/{:020}", at, id)
+}
+
+fn feed_key(principal: &Principal, id: u64) -> String {
+    format!("{}/{:020}", principal.to_text(), id)
+}
+
+// Keeps PUSH_SCHEDULE_MAP and FEED_PUSHES_MAP in step with the pending pushes
+impl PendingPush {
+    fn index(&self, id: u64) {
+        PUSH_SCHEDULE_MAP.with(|p| {
+            p.borrow_mut()
+                .insert(schedule_key(self.next_attempt_at, id), id)
+        });
+        FEED_PUSHES_MAP.with(|p| p.borrow_mut().insert(feed_key(&self.principal, id), id));
+    }
+
+    fn unindex(&self, id: u64) {
+        PUSH_SCHEDULE_MAP.with(|p| {
+            p.borrow_mut()
+                .remove(&schedule_key(self.next_attempt_at, id))
+        });
+        FEED_PUSHES_MAP.with(|p| p.borrow_mut().remove(&feed_key(&self.principal, id)));
+    }
+
+    fn insert(key: u64, value: PendingPush) -> Option<PendingPush> {
+        let old = PendingPush::remove(key);
+        value.index(key);
+        PENDING_PUSHES_MAP.with(|p| p.borrow_mut().insert(key, value));
+        old
+    }
+
+    fn remove(key: u64) -> Option<PendingPush> {
+        let old = PENDING_PUSHES_MAP.with(|p| p.borrow_mut().remove(&key));
+        if let Some(old) = &old {
+            old.unindex(key);
+        }
+        old
+    }
+
+    fn next_id() -> u64 {
+        PENDING_PUSHES_MAP.with(|p| p.borrow().last_key_value().map_or(0, |(id, _)| id + 1))
+    }
+}
+
+// Subscription as returned to its owner, without the keys
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct PushSubscriptionView {
+    id: u64,
+    endpoint: String,
+    created_at: u64,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct AddPushSubscriptionArg {
+    endpoint: String,
+    p256dh: String, // base64url, as in PushSubscription.toJSON()
+    auth: String,
+}
+
+fn vapid_key() -> Option<VapidKey> {
+    VAPID_KEY_MAP.with(|p| p.borrow().get(&VAPID_KEY.to_string()))
+}
+
+fn expand<const N: usize>(ikm: &[u8], info: &[u8]) -> [u8; N] {
+    let mut okm = [0u8; N];
+    Hkdf::<Sha256>::new(None, ikm)
+        .expand(info, &mut okm)
+        .unwrap();
+    okm
+}
+
+/*
+ * AES-256 key the VAPID secret is sealed with, derived from the vetKey of VAPID_KEY_INPUT. The vetKey
+ * comes encrypted under a transport key made from random, it's decrypted here with the transport
+ * secret. Only kept on the heap, an upgrade derives it again.
+ */
+async fn wrapping_key(random: &[u8]) -> Result<[u8; 32], WebPushError> {
+    if let Some(key) = WRAPPING_KEY.get() {
+        return Ok(key);
+    }
+
+    let transport_secret = Scalar::from_bytes_wide(&expand(random, b"vapid transport key"));
+    let transport_public = G1Affine::from(G1Affine::generator() * transport_secret);
+    let args = VetKdDeriveKeyArgs {
+        input: ByteBuf::from(VAPID_KEY_INPUT),
+        context: ByteBuf::from(VAPID_KEY_CONTEXT),
+        transport_public_key: ByteBuf::from(transport_public.to_compressed().to_vec()),
+        key_id: vetkd::key_id(),
+    };
+    let (result,) = vetkd::vetkd_derive_key(args).await.map_err(|err| {
+        WebPushError::InterCanisterCall(format!("vetkd_derive_key failed {:?}", err))
+    })?;
+
+    // c1 (G1), c2 (G2) and c3 (G1), the vetKey is c3 - c1 * transport secret
+    let point = |bytes: &[u8]| {
+        <&[u8; 48]>::try_from(bytes)
+            .ok()
+            .and_then(|bytes| G1Affine::from_compressed(bytes).into_option())
+    };
+    let encrypted = &result.encrypted_key;
+    let (c1, c3) = match (encrypted.len(), encrypted.get(..48), encrypted.get(144..)) {
+        (192, Some(c1), Some(c3)) => (point(c1), point(c3)),
+        _ => (None, None),
+    };
+    let (c1, c3) = c1.zip(c3).ok_or(WebPushError::KeyEncryption)?;
+    let vetkey = G1Affine::from(G1Projective::from(c3) - c1 * transport_secret);
+
+    let key = expand(&vetkey.to_compressed(), b"vapid wrapping key");
+    WRAPPING_KEY.set(Some(key));
+    Ok(key)
+}
+
+// Nonce followed by the AES-GCM ciphertext of secret
+fn seal(wrapping_key: &[u8; 32], secret: &[u8], random: &[u8]) -> Result<Vec<u8>, WebPushError> {
+    let nonce: [u8; 12] = expand(random, b"vapid nonce");
+    let ciphertext = Aes256Gcm::new_from_slice(wrapping_key)
+        .ok()
+        .and_then(|cipher| cipher.encrypt(&Nonce::from(nonce), secret).ok())
+        .ok_or(WebPushError::KeyEncryption)?;
+    Ok([nonce.as_slice(), &ciphertext].concat())
+}
+
+/*
+ * The VAPID secret in the clear, for signing. A key stored before sealing existed is sealed now and
+ * written back, so no secret stays in stable memory in the clear.
+ */
+async fn vapid_secret(key: &VapidKey, random: &[u8]) -> Result<Vec<u8>, WebPushError> {
+    let wrapping_key = wrapping_key(random).await?;
+    if key.secret.len() == 32 {
+        let sealed = seal(&wrapping_key, &key.secret, random)?;
+        if let Some(mut stored) = vapid_key().filter(|stored| stored.secret == key.secret) {
+            stored.secret = ByteBuf::from(sealed);
+            VAPID_KEY_MAP.with(|p| p.borrow_mut().insert(VAPID_KEY.to_string(), stored));
+        }
+        return Ok(key.secret.to_vec());
+    }
+
+    let (nonce, ciphertext) = key.secret.split_at(key.secret.len().min(12));
+    let nonce = <[u8; 12]>::try_from(nonce).map_err(|_| WebPushError::KeyEncryption)?;
+    Aes256Gcm::new_from_slice(&wrapping_key)
+        .ok()
+        .and_then(|cipher| cipher.decrypt(&Nonce::from(nonce), ciphertext).ok())
+        .ok_or(WebPushError::KeyEncryption)
+}
+
+// Subscriptions belong to a notification feed, see notifications::feed_of
+fn check_caller(profile: Option<Principal>) -> Result<Principal, WebPushError> {
+    Ok(notifications::feed_of(profile, Permission::ViewBusiness)?)
+}
+
+// Creates the VAPID key on first call, later calls only change the subject
+pub async fn setup_web_push(subject: String) -> Result<String, WebPushError> {
+    if !is_controller(&caller()) {
+        return Err(WebPushError::NotAController);
+    }
+    if subject.len() > MAX_SUBJECT_LEN
+        || !(subject.starts_with("mailto:") || subject.starts_with("https://"))
+    {
+        return Err(WebPushError::InvalidSubject);
+    }
+
+    if let Some(mut key) = vapid_key() {
+        key.subject = subject;
+        let public_key = URL_SAFE_NO_PAD.encode(&key.public_key);
+        VAPID_KEY_MAP.with(|p| p.borrow_mut().insert(VAPID_KEY.to_string(), key));
+        return Ok(public_key);
+    }
+
+    let (random,) = raw_rand()
+        .await
+        .map_err(|err| WebPushError::InterCanisterCall(format!("raw_rand failed {:?}", err)))?;
+    // A random 32 bytes is a valid scalar except with negligible probability
+    let secret = SecretKey::from_slice(&random).map_err(|_| WebPushError::KeyGeneration)?;
+    let public_key = secret.public_key().to_encoded_point(false);
+    let sealed = seal(&wrapping_key(&random).await?, &secret.to_bytes(), &random)?;
+
+    // Checked again after the calls, keeping a key subscriptions may already use
+    let key = vapid_key().unwrap_or(VapidKey {
+        secret: ByteBuf::from(sealed),
+        public_key: ByteBuf::from(public_key.as_bytes().to_vec()),
+        subject,
+    });
+    let public_key = URL_SAFE_NO_PAD.encode(&key.public_key);
+    VAPID_KEY_MAP.with(|p| p.borrow_mut().insert(VAPID_KEY.to_string(), key));
+    Ok(public_key)
+}
+
+// base64url key for PushManager.subscribe, None until setup_web_push was called
+pub fn get_vapid_public_key() -> Option<String> {
+    vapid_key().map(|key| URL_SAFE_NO_PAD.encode(&key.public_key))
+}
+
+// Same endpoint again replaces the keys, browsers renew subscriptions from time to time
+pub fn add_push_subscription(
+    AddPushSubscriptionArg {
+        endpoint,
+        p256dh,
+        auth,
+    }: AddPushSubscriptionArg,
+    profile: Option<Principal>,
+) -> Result<PushSubscriptionView, WebPushError> {
+    let identity = caller();
+    let feed = check_caller(profile)?;
+    if vapid_key().is_none() {
+        return Err(WebPushError::NotConfigured);
+    }
+    if !is_valid_url(&endpoint) {
+        return Err(WebPushError::InvalidEndpoint);
+    }
+    let p256dh = URL_SAFE_NO_PAD
+        .decode(p256dh.trim_end_matches('='))
+        .ok()
+        .filter(|key| key.len() == 65 && PublicKey::from_sec1_bytes(key).is_ok())
+        .ok_or(WebPushError::InvalidKeys)?;
+    let auth = URL_SAFE_NO_PAD
+        .decode(auth.trim_end_matches('='))
+        .ok()
+        .filter(|auth| auth.len() == 16)
+        .ok_or(WebPushError::InvalidKeys)?;
+
+    // A business feed is shared by its staff, each member subscribes its own devices
+    let mut subscriptions = PushSubscriptions::get(&feed).unwrap_or_default();
+    subscriptions
+        .subscriptions
+        .retain(|subscription| subscription.endpoint != endpoint);
+    let devices = subscriptions
+        .subscriptions
+        .iter()
+        .filter(|subscription| subscription.added_by.is_none_or(|by| by == identity))
+        .count();
+    if devices >= MAX_SUBSCRIPTIONS {
+        return Err(WebPushError::TooManySubscriptions {
+            max: MAX_SUBSCRIPTIONS,
+        });
+    }
+
+    let subscription = PushSubscription {
+        id: subscriptions.next_id,
+        endpoint,
+        p256dh: ByteBuf::from(p256dh),
+        auth: ByteBuf::from(auth),
+        created_at: time(),
+        added_by: Some(identity),
+    };
+    let view = view(&subscription);
+    subscriptions.next_id += 1;
+    subscriptions.subscriptions.push(subscription);
+    PushSubscriptions::insert(feed, subscriptions);
+    Ok(view)
+}
+
+fn view(subscription: &PushSubscription) -> PushSubscriptionView {
+    PushSubscriptionView {
+        id: subscription.id,
+        endpoint: subscription.endpoint.clone(),
+        created_at: subscription.created_at,
+    }
+}
+
+pub fn get_push_subscriptions(
+    profile: Option<Principal>,
+) -> Result<Vec<PushSubscriptionView>, WebPushError> {
+    let caller = check_caller(profile)?;
+    Ok(PushSubscriptions::get(&caller)
+        .unwrap_or_default()
+        .subscriptions
+        .iter()
+        .map(view)
+        .collect())
+}
+
+pub fn remove_push_subscription(id: u64, profile: Option<Principal>) -> Result<(), WebPushError> {
+    let caller = check_caller(profile)?;
+    remove_subscription(&caller, id)
+}
+
+fn remove_subscription(principal: &Principal, id: u64) -> Result<(), WebPushError> {
+    let mut subscriptions = PushSubscriptions::get(principal).unwrap_or_default();
+    let len = subscriptions.subscriptions.len();
+    subscriptions
+        .subscriptions
+        .retain(|subscription| subscription.id != id);
+    if subscriptions.subscriptions.len() == len {
+        return Err(WebPushError::SubscriptionNotFound);
+    }
+    PushSubscriptions::insert(*principal, subscriptions);
+    Ok(())
+}
+
+// Queues a push to every device of principal, delivered by the timer
+pub fn push(principal: &Principal, title: &str, body: &str, tag: &str) {
+    let subscriptions = match PushSubscriptions::get(principal) {
+        Some(subscriptions) if vapid_key().is_some() => subscriptions.subscriptions,
+        _ => return,
+    };
+
+    let mut payload = format!(
+        "{{\"title\":{},\"body\":{},\"tag\":{}}}",
+        json_string(title),
+        json_string(body),
+        json_string(tag)
+    );
+    if payload.len() > MAX_PAYLOAD_LEN {
+        payload = format!(
+            "{{\"title\":{},\"tag\":{}}}",
+            json_string(title),
+            json_string(tag)
+        );
+    }
+
+    let now = time();
+    let mut pending = pending_count(principal);
+    for subscription in subscriptions {
+        if !can_receive(principal, &subscription) {
+            continue;
+        }
+        if pending >= MAX_PENDING {
+            break;
+        }
+        pending += 1;
+        let id = PendingPush::next_id();
+        PendingPush::insert(
+            id,
+            PendingPush {
+                principal: *principal,
+                subscription_id: subscription.id,
+                payload: payload.clone(),
+                topic: topic(tag),
+                attempts: 0,
+                next_attempt_at: now,
+            },
+        );
+    }
+    timer::schedule();
+}
+
+// Devices of members removed from a business, or left without ViewBusiness, stop getting its pushes
+fn can_receive(principal: &Principal, subscription: &PushSubscription) -> bool {
+    match subscription.added_by {
+        Some(by) if by != *principal && is_business(principal) => {
+            staff::actor_for(by, Some(*principal), Permission::ViewBusiness).is_ok()
+        }
+        _ => true,
+    }
+}
+
+// Topic header, at most 32 URL safe characters
+fn topic(tag: &str) -> String {
+    tag.chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+        .take(32)
+        .collect()
+}
+
+fn pending_count(principal: &Principal) -> usize {
+    let prefix = format!("{}/", principal.to_text());
+    FEED_PUSHES_MAP.with(|p| {
+        p.borrow()
+            .range(prefix.clone()..)
+            .take_while(|(key, _)| key.starts_with(&prefix))
+            .count()
+    })
+}
+
+// Every test costs an outcall per device, so an identity can send one per TEST_INTERVAL
+pub fn send_test_push(profile: Option<Principal>) -> Result<(), WebPushError> {
+    let identity = caller();
+    let feed = check_caller(profile)?;
+    if vapid_key().is_none() {
+        return Err(WebPushError::NotConfigured);
+    }
+    let mut subscriptions = match PushSubscriptions::get(&feed) {
+        Some(subscriptions) if !subscriptions.subscriptions.is_empty() => subscriptions,
+        _ => return Err(WebPushError::SubscriptionNotFound),
+    };
+
+    let now = time();
+    let mut last_tests = subscriptions.last_tests.take().unwrap_or_default();
+    last_tests.retain(|(_, at)| at + TEST_INTERVAL > now);
+    if let Some((_, at)) = last_tests.iter().find(|(by, _)| *by == identity) {
+        return Err(WebPushError::RateLimited {
+            retry_at: at + TEST_INTERVAL,
+        });
+    }
+    last_tests.push((identity, now));
+    subscriptions.last_tests = Some(last_tests);
+    PushSubscriptions::insert(feed, subscriptions);
+
+    push(&feed, "Rapido", "Push notifications are on", "test");
+    Ok(())
+}
+
+// Earliest attempt of the queued pushes
+pub fn next_push_at() -> Option<u64> {
+    PUSH_SCHEDULE_MAP.with(|p| {
+        p.borrow()
+            .first_key_value()
+            .and_then(|(key, _)| key.split('/').next()?.parse().ok())
+    })
+}
+
+// Indexes pushes queued before PUSH_SCHEDULE_MAP and FEED_PUSHES_MAP existed, called after upgrades
+pub fn index_pending_pushes() {
+    if PUSH_SCHEDULE_MAP.with(|p| !p.borrow().is_empty()) {
+        return;
+    }
+    PENDING_PUSHES_MAP.with(|p| {
+        for (id, push) in p.borrow().iter() {
+            push.index(id);
+        }
+    });
+}
+
+/*
+ * VAPID Authorization header (RFC 8292), an ES256 JWT for the origin of the push service. Signing
+ * with RFC 6979 nonces is deterministic, so every replica sends the same request.
+ */
+fn vapid_authorization(key: &VapidKey, secret: &[u8], endpoint: &str, now: u64) -> Option<String> {
+    let origin = endpoint
+        .splitn(4, '/')
+        .take(3)
+        .collect::<Vec<_>>()
+        .join("/");
+    let header = URL_SAFE_NO_PAD.encode(r#"{"typ":"JWT","alg":"ES256"}"#);
+    let claims = URL_SAFE_NO_PAD.encode(format!(
+        "{{\"aud\":{},\"exp\":{},\"sub\":{}}}",
+        json_string(&origin),
+        now / 1_000_000_000 + JWT_TTL,
+        json_string(&key.subject)
+    ));
+    let signing_input = format!("{}.{}", header, claims);
+
+    let signing_key = SigningKey::from_slice(secret).ok()?;
+    let signature: Signature = signing_key.sign(signing_input.as_bytes());
+    Some(format!(
+        "vapid t={}.{}, k={}",
+        signing_input,
+        URL_SAFE_NO_PAD.encode(signature.to_bytes()),
+        URL_SAFE_NO_PAD.encode(&key.public_key)
+    ))
+}
+
+/*
+ * Encrypts payload for the subscription with aes128gcm (RFC 8291), in a single record. seed gives the
+ * ephemeral key and the salt, it comes from raw_rand so all replicas encrypt the same way.
+ */
+fn encrypt(subscription: &PushSubscription, payload: &str, seed: &[u8; 48]) -> Option<Vec<u8>> {
+    let ephemeral = SecretKey::from_slice(&seed[..32]).ok()?;
+    let salt = &seed[32..];
+    let server_point = ephemeral.public_key().to_encoded_point(false);
+    let server_public = server_point.as_bytes();
+    let browser_public = PublicKey::from_sec1_bytes(&subscription.p256dh).ok()?;
+    let shared =
+        p256::ecdh::diffie_hellman(ephemeral.to_nonzero_scalar(), browser_public.as_affine());
+
+    let mut key_info = b"WebPush: info\0".to_vec();
+    key_info.extend_from_slice(&subscription.p256dh);
+    key_info.extend_from_slice(server_public);
+    let mut ikm = [0u8; 32];
+    Hkdf::<Sha256>::new(Some(&subscription.auth), shared.raw_secret_bytes())
+        .expand(&key_info, &mut ikm)
+        .ok()?;
+
+    let prk = Hkdf::<Sha256>::new(Some(salt), &ikm);
+    let mut cek = [0u8; 16];
+    let mut nonce = [0u8; 12];
+    prk.expand(b"Content-Encoding: aes128gcm\0", &mut cek)
+        .ok()?;
+    prk.expand(b"Content-Encoding: nonce\0", &mut nonce).ok()?;
+
+    let mut record = payload.as_bytes().to_vec();
+    record.push(2); // padding delimiter of the last record
+    let ciphertext = Aes128Gcm::new_from_slice(&cek)
+        .ok()?
+        .encrypt(&Nonce::from(nonce), record.as_slice())
+        .ok()?;
+
+    let mut body = salt.to_vec();
+    body.extend_from_slice(&RECORD_SIZE.to_be_bytes());
+    body.push(server_public.len() as u8);
+    body.extend_from_slice(server_public);
+    body.extend_from_slice(&ciphertext);
+    Some(body)
+}
+
+fn seed(random: &[u8], id: u64) -> [u8; 48] {
+    let mut seed = [0u8; 48];
+    Hkdf::<Sha256>::new(None, random)
+        .expand(&id.to_be_bytes(), &mut seed)
+        .unwrap();
+    seed
+}
+
+enum PushOutcome {
+    Delivered,
+    Gone, // the browser unsubscribed, push services answer 404 or 410
+    Failed,
+}
+
+async fn attempt(id: u64, push: PendingPush, key: &VapidKey, secret: &[u8], random: &[u8]) {
+    let subscription = PushSubscriptions::get(&push.principal).and_then(|subscriptions| {
+        subscriptions
+            .subscriptions
+            .into_iter()
+            .find(|subscription| subscription.id == push.subscription_id)
+    });
+
+    let now = time();
+    let request = subscription.as_ref().and_then(|subscription| {
+        Some(CanisterHttpRequestArgument {
+            url: subscription.endpoint.clone(),
+            max_response_bytes: Some(1_024),
+            method: HttpMethod::POST,
+            headers: vec![
+                HttpHeader {
+                    name: "Authorization".to_string(),
+                    value: vapid_authorization(key, secret, &subscription.endpoint, now)?,
+                },
+                HttpHeader {
+                    name: "Content-Encoding".to_string(),
+                    value: "aes128gcm".to_string(),
+                },
+                HttpHeader {
+                    name: "Content-Type".to_string(),
+                    value: "application/octet-stream".to_string(),
+                },
+                HttpHeader {
+                    name: "TTL".to_string(),
+                    value: MESSAGE_TTL.to_string(),
+                },
+                HttpHeader {
+                    name: "Urgency".to_string(),
+                    value: "high".to_string(),
+                },
+                HttpHeader {
+                    name: "Topic".to_string(),
+                    value: push.topic.clone(),
+                },
+            ],
+            body: Some(encrypt(subscription, &push.payload, &seed(random, id))?),
+            transform: Some(TransformContext::from_name(
+                "transform_webhook_response".to_string(),
+                vec![],
+            )),
+        })
+    });
+    let request = match request {
+        Some(request) => request,
+        None => {
+            // Removed subscription or keys the payload can't be encrypted to
+            PendingPush::remove(id);
+            return;
+        }
+    };
+
+    let cycles = outcall_cycles(push.payload.len() + request.url.len() + 1_024);
+    let outcome = match http_request(request, cycles).await {
+        Ok((response,)) => match u16::try_from(response.status.0).unwrap_or(0) {
+            200..=299 => PushOutcome::Delivered,
+            404 | 410 => PushOutcome::Gone,
+            _ => PushOutcome::Failed,
+        },
+        Err(_) => PushOutcome::Failed,
+    };
+
+    match outcome {
+        PushOutcome::Delivered => {
+            PendingPush::remove(id);
+        }
+        PushOutcome::Gone => {
+            PendingPush::remove(id);
+            let _ = remove_subscription(&push.principal, push.subscription_id);
+        }
+        PushOutcome::Failed if push.attempts + 1 >= MAX_ATTEMPTS => {
+            PendingPush::remove(id);
+        }
+        PushOutcome::Failed => {
+            let attempts = push.attempts + 1;
+            PendingPush::insert(
+                id,
+                PendingPush {
+                    attempts,
+                    next_attempt_at: time() + RETRY_BASE * 2u64.pow(attempts - 1),
+                    ..push
+                },
+            );
+        }
+    }
+}
+
+// Sends the queued pushes that are due, called by the timer
+pub async fn deliver_due() {
+    let now = time();
+    let due = PUSH_SCHEDULE_MAP.with(|p| {
+        p.borrow()
+            .range(..format!("{:020}0", now))
+            .take(PUSHES_PER_TICK)
+            .map(|(_, id)| id)
+            .collect::<Vec<_>>()
+    });
+    let due = PENDING_PUSHES_MAP.with(|p| {
+        let map = p.borrow();
+        due.into_iter()
+            .filter_map(|id| Some((id, map.get(&id)?)))
+            .collect::<Vec<_>>()
+    });
+    let key = match vapid_key() {
+        Some(key) if !due.is_empty() => key,
+        _ => return,
+    };
+
+    // Not picked again by the next tick while the randomness and the outcalls are awaited
+    for (id, push) in &due {
+        PendingPush::insert(
+            *id,
+            PendingPush {
+                next_attempt_at: now + IN_FLIGHT_TIMEOUT,
+                ..push.clone()
+            },
+        );
+    }
+
+    // Without randomness or the secret the pushes are picked again after IN_FLIGHT_TIMEOUT
+    let random = match raw_rand().await {
+        Ok((random,)) => random,
+        Err(_) => return,
+    };
+    let secret = match vapid_secret(&key, &random).await {
+        Ok(secret) => secret,
+        Err(_) => return,
+    };
+
+    // The outcalls are made at the same time, a slow push service doesn't hold up the others
+    join_all(
+        due.into_iter()
+            .map(|(id, push)| attempt(id, push, &key, &secret, &random)),
+    )
+    .await;
+}
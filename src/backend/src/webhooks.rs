@@ -0,0 +1,617 @@
+use crate::error::WebhookError;
+use crate::export::{hex, json_row, json_string, ExportRow};
+use crate::staff::{self, AuditAction, Permission};
+use crate::{
+    timer, TransactionEntry, TxKind, DELIVERIES_MAP, DELIVERY_SCHEDULE_MAP, PENDING_DELIVERIES_MAP,
+    WEBHOOKS_MAP,
+};
+use candid::{Decode, Encode, Principal};
+use futures::future::join_all;
+use hmac::{Hmac, Mac};
+use ic_cdk::api::management_canister::http_request::{
+    http_request, CanisterHttpRequestArgument, HttpHeader, HttpMethod, HttpResponse, TransformArgs,
+    TransformContext,
+};
+use ic_cdk::api::{management_canister::main::raw_rand, time};
+use ic_stable_structures::storable::{Bound, Storable};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::borrow::Cow;
+
+const MAX_WEBHOOKS: usize = 5;
+const MAX_URL_LEN: usize = 512;
+const MAX_ATTEMPTS: u32 = 8;
+const RETRY_BASE: u64 = 30_000_000_000; // 30 seconds, doubled after every failed attempt
+const MAX_RETRY_DELAY: u64 = 21_600_000_000_000; // 6 hours
+const IN_FLIGHT_TIMEOUT: u64 = 300_000_000_000; // a delivery waiting for its outcall isn't picked again before this
+const DELIVERIES_PER_TICK: usize = 10;
+const DELIVERY_PAGE_SIZE: u64 = 20;
+const MAX_DELIVERY_LOG: u64 = 500; // latest deliveries kept per business, older ones are pruned
+const MAX_PENDING: usize = 100; // deliveries of one business waiting to be sent
+const TEST_INTERVAL: u64 = 60_000_000_000; // 1 minute between test deliveries of a business
+const MAX_RESPONSE_BYTES: u64 = 1_024; // only the status is kept
+const MAX_ERROR_LEN: usize = 200;
+
+#[derive(candid::CandidType, Clone, Copy, Serialize, Debug, Deserialize, PartialEq)]
+pub enum WebhookEvent {
+    PaymentRecorded, // any transfer recorded in the business history
+    CheckoutPaid,
+    Refund, // refund sent or received by the business
+    Ping,   // sent with send_test_webhook only
+}
+
+impl WebhookEvent {
+    fn name(&self) -> &'static str {
+        match self {
+            WebhookEvent::PaymentRecorded => "payment.recorded",
+            WebhookEvent::CheckoutPaid => "checkout.paid",
+            WebhookEvent::Refund => "refund",
+            WebhookEvent::Ping => "ping",
+        }
+    }
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct WebhookEndpoint {
+    id: u64,
+    url: String,
+    events: Vec<WebhookEvent>,
+    secret: String, // hex, HMAC-SHA256 key of the signature header
+    created_at: u64,
+}
+
+// Webhooks of one business
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize, Default)]
+pub struct Webhooks {
+    endpoints: Vec<WebhookEndpoint>,
+    next_id: u64,
+    delivery_len: u64,
+    last_test_at: Option<u64>,
+}
+
+impl Storable for Webhooks {
+    const BOUND: Bound = Bound::Unbounded;
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl Webhooks {
+    fn get(key: &Principal) -> Option<Webhooks> {
+        WEBHOOKS_MAP.with(|p| p.borrow().get(key))
+    }
+
+    fn insert(key: Principal, value: Webhooks) -> Option<Webhooks> {
+        WEBHOOKS_MAP.with(|p| p.borrow_mut().insert(key, value))
+    }
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize, PartialEq)]
+pub enum DeliveryStatus {
+    Pending,
+    Delivered,
+    Failed, // gave up after MAX_ATTEMPTS
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct Delivery {
+    id: String, // "{business}/{index}", sent as X-Rapido-Delivery so receivers can drop duplicates
+    business: Principal,
+    webhook_id: u64,
+    url: String,
+    event: WebhookEvent,
+    payload: String,
+    status: DeliveryStatus,
+    attempts: u32,
+    last_status_code: Option<u16>,
+    last_error: Option<String>,
+    next_attempt_at: Option<u64>,
+    created_at: u64,
+    delivered_at: Option<u64>,
+}
+
+impl Storable for Delivery {
+    const BOUND: Bound = Bound::Unbounded;
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl Delivery {
+    fn get(key: &String) -> Option<Delivery> {
+        DELIVERIES_MAP.with(|p| p.borrow().get(key))
+    }
+
+    fn insert(key: String, value: Delivery) -> Option<Delivery> {
+        DELIVERIES_MAP.with(|p| p.borrow_mut().insert(key, value))
+    }
+}
+
+fn delivery_key(business: &Principal, index: u64) -> String {
+    format!("{}/{:012}", business.to_text(), index)
+}
+
+fn schedule_key(at: u64, key: &str) -> String {
+    format!("{:020}/{}", at, key)
+}
+
+// Queues the delivery of key for at, PENDING_DELIVERIES_MAP and DELIVERY_SCHEDULE_MAP change together
+fn set_pending(key: &String, at: u64) {
+    clear_pending(key);
+    PENDING_DELIVERIES_MAP.with(|p| p.borrow_mut().insert(key.clone(), at));
+    DELIVERY_SCHEDULE_MAP.with(|p| p.borrow_mut().insert(schedule_key(at, key), key.clone()));
+}
+
+fn clear_pending(key: &String) {
+    if let Some(at) = PENDING_DELIVERIES_MAP.with(|p| p.borrow_mut().remove(key)) {
+        DELIVERY_SCHEDULE_MAP.with(|p| p.borrow_mut().remove(&schedule_key(at, key)));
+    }
+}
+
+// Indexes deliveries queued before DELIVERY_SCHEDULE_MAP existed, called after upgrades
+pub fn index_pending_deliveries() {
+    if DELIVERY_SCHEDULE_MAP.with(|p| !p.borrow().is_empty()) {
+        return;
+    }
+    let pending = PENDING_DELIVERIES_MAP.with(|p| p.borrow().iter().collect::<Vec<_>>());
+    DELIVERY_SCHEDULE_MAP.with(|p| {
+        let mut map = p.borrow_mut();
+        for (key, at) in pending {
+            map.insert(schedule_key(at, &key), key);
+        }
+    });
+}
+
+fn pending_count(business: &Principal) -> usize {
+    let prefix = format!("{}/", business.to_text());
+    PENDING_DELIVERIES_MAP.with(|p| {
+        p.borrow()
+            .range(prefix.clone()..)
+            .take_while(|(key, _)| key.starts_with(&prefix))
+            .count()
+    })
+}
+
+// Webhook without its secret, the secret is only returned by add_webhook
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct WebhookView {
+    id: u64,
+    url: String,
+    events: Vec<WebhookEvent>,
+    created_at: u64,
+}
+
+impl From<&WebhookEndpoint> for WebhookView {
+    fn from(endpoint: &WebhookEndpoint) -> Self {
+        WebhookView {
+            id: endpoint.id,
+            url: endpoint.url.clone(),
+            events: endpoint.events.clone(),
+            created_at: endpoint.created_at,
+        }
+    }
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct WebhookCreated {
+    webhook: WebhookView,
+    secret: String,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Debug, Deserialize)]
+pub struct DeliveryPage {
+    deliveries: Vec<Delivery>, // latest first
+    next_page: Option<u64>,
+}
+
+// HTTPS only, plain HTTP is accepted for a mock server on the local replica
+pub fn is_valid_url(url: &str) -> bool {
+    url.len() <= MAX_URL_LEN
+        && (url.starts_with("https://")
+            || url.starts_with("http://localhost")
+            || url.starts_with("http://127.0.0.1"))
+}
+
+pub async fn add_webhook(
+    url: String,
+    events: Vec<WebhookEvent>,
+    profile: Option<Principal>,
+) -> Result<WebhookCreated, WebhookError> {
+    let actor = staff::actor(profile, Permission::ManageSettings)?;
+
+    if !is_valid_url(&url) {
+        return Err(WebhookError::InvalidUrl);
+    }
+    let events = events
+        .into_iter()
+        .filter(|event| *event != WebhookEvent::Ping)
+        .fold(Vec::new(), |mut events, event| {
+            if !events.contains(&event) {
+                events.push(event);
+            }
+            events
+        });
+    if events.is_empty() {
+        return Err(WebhookError::NoEvents);
+    }
+
+    let (random,) = raw_rand()
+        .await
+        .map_err(|err| WebhookError::InterCanisterCall(format!("raw_rand failed {:?}", err)))?;
+
+    // Read after the call, another webhook could have been added meanwhile
+    let mut webhooks = Webhooks::get(&actor.business).unwrap_or_default();
+    if webhooks.endpoints.len() >= MAX_WEBHOOKS {
+        return Err(WebhookError::TooManyWebhooks { max: MAX_WEBHOOKS });
+    }
+
+    let endpoint = WebhookEndpoint {
+        id: webhooks.next_id,
+        url,
+        events,
+        secret: hex(&random),
+        created_at: time(),
+    };
+    webhooks.next_id += 1;
+    webhooks.endpoints.push(endpoint.clone());
+    Webhooks::insert(actor.business, webhooks);
+
+    actor.audit(AuditAction::WebhookAdded {
+        id: endpoint.id,
+        url: endpoint.url.clone(),
+    });
+    Ok(WebhookCreated {
+        webhook: WebhookView::from(&endpoint),
+        secret: endpoint.secret,
+    })
+}
+
+pub fn list_webhooks(profile: Option<Principal>) -> Result<Vec<WebhookView>, WebhookError> {
+    let actor = staff::actor(profile, Permission::ManageSettings)?;
+
+    Ok(Webhooks::get(&actor.business)
+        .unwrap_or_default()
+        .endpoints
+        .iter()
+        .map(WebhookView::from)
+        .collect())
+}
+
+// Pending deliveries of the webhook are still attempted
+pub fn remove_webhook(id: u64, profile: Option<Principal>) -> Result<(), WebhookError> {
+    let actor = staff::actor(profile, Permission::ManageSettings)?;
+
+    let mut webhooks = Webhooks::get(&actor.business).unwrap_or_default();
+    let index = match webhooks.endpoints.iter().position(|e| e.id == id) {
+        Some(index) => index,
+        None => return Err(WebhookError::WebhookNotFound),
+    };
+    webhooks.endpoints.remove(index);
+    Webhooks::insert(actor.business, webhooks);
+
+    actor.audit(AuditAction::WebhookRemoved { id });
+    Ok(())
+}
+
+// Every test costs an outcall, so a business can send one per TEST_INTERVAL
+pub fn send_test_webhook(id: u64, profile: Option<Principal>) -> Result<Delivery, WebhookError> {
+    let actor = staff::actor(profile, Permission::ManageSettings)?;
+
+    let mut webhooks = Webhooks::get(&actor.business).unwrap_or_default();
+    let endpoint = match webhooks.endpoints.iter().find(|e| e.id == id) {
+        Some(endpoint) => endpoint.clone(),
+        None => return Err(WebhookError::WebhookNotFound),
+    };
+    let now = time();
+    if let Some(retry_at) = webhooks
+        .last_test_at
+        .map(|at| at + TEST_INTERVAL)
+        .filter(|retry_at| *retry_at > now)
+    {
+        return Err(WebhookError::RateLimited { retry_at });
+    }
+    if pending_count(&actor.business) >= MAX_PENDING {
+        return Err(WebhookError::TooManyPending { max: MAX_PENDING });
+    }
+    webhooks.last_test_at = Some(now);
+
+    let delivery = new_delivery(
+        &actor.business,
+        &mut webhooks,
+        &endpoint,
+        WebhookEvent::Ping,
+        "{}",
+    );
+    Webhooks::insert(actor.business, webhooks);
+    timer::schedule_at(time());
+    Ok(delivery)
+}
+
+pub fn get_webhook_deliveries(
+    page: u64,
+    profile: Option<Principal>,
+) -> Result<DeliveryPage, WebhookError> {
+    let actor = staff::actor(profile, Permission::ManageSettings)?;
+    let delivery_len = Webhooks::get(&actor.business)
+        .unwrap_or_default()
+        .delivery_len;
+
+    let oldest = delivery_len.saturating_sub(MAX_DELIVERY_LOG);
+    let end = delivery_len
+        .saturating_sub(page * DELIVERY_PAGE_SIZE)
+        .max(oldest);
+    let start = end.saturating_sub(DELIVERY_PAGE_SIZE).max(oldest);
+    let deliveries = (start..end)
+        .rev()
+        .filter_map(|index| Delivery::get(&delivery_key(&actor.business, index)))
+        .collect();
+
+    Ok(DeliveryPage {
+        deliveries,
+        next_page: if start > oldest { Some(page + 1) } else { None },
+    })
+}
+
+/*
+ * Adds a delivery to the log of business, pruning the oldest past MAX_DELIVERY_LOG. Past MAX_PENDING
+ * waiting deliveries it's logged as Failed right away instead of queued.
+ */
+fn new_delivery(
+    business: &Principal,
+    webhooks: &mut Webhooks,
+    endpoint: &WebhookEndpoint,
+    event: WebhookEvent,
+    data: &str,
+) -> Delivery {
+    let id = delivery_key(business, webhooks.delivery_len);
+    let now = time();
+    let payload = format!(
+        "{{\"id\":{},\"event\":{},\"business\":{},\"created_at\":{},\"data\":{}}}",
+        json_string(&id),
+        json_string(event.name()),
+        json_string(&business.to_text()),
+        now,
+        data
+    );
+
+    let queued = pending_count(business) < MAX_PENDING;
+    let delivery = Delivery {
+        id: id.clone(),
+        business: *business,
+        webhook_id: endpoint.id,
+        url: endpoint.url.clone(),
+        event,
+        payload,
+        status: if queued {
+            DeliveryStatus::Pending
+        } else {
+            DeliveryStatus::Failed
+        },
+        attempts: 0,
+        last_status_code: None,
+        last_error: if queued {
+            None
+        } else {
+            Some("too many pending deliveries".to_string())
+        },
+        next_attempt_at: if queued { Some(now) } else { None },
+        created_at: now,
+        delivered_at: None,
+    };
+    webhooks.delivery_len += 1;
+    Delivery::insert(id.clone(), delivery.clone());
+    if queued {
+        set_pending(&id, now);
+    }
+
+    if let Some(index) = webhooks.delivery_len.checked_sub(MAX_DELIVERY_LOG + 1) {
+        let pruned = delivery_key(business, index);
+        DELIVERIES_MAP.with(|p| p.borrow_mut().remove(&pruned));
+        clear_pending(&pruned);
+    }
+    delivery
+}
+
+// Queues a delivery of data, a JSON value, to every webhook of business subscribed to event
+fn enqueue(business: &Principal, event: WebhookEvent, data: &str) {
+    let mut webhooks = match Webhooks::get(business) {
+        Some(webhooks) => webhooks,
+        None => return,
+    };
+
+    let endpoints = webhooks
+        .endpoints
+        .iter()
+        .filter(|endpoint| endpoint.events.contains(&event))
+        .cloned()
+        .collect::<Vec<_>>();
+    if endpoints.is_empty() {
+        return;
+    }
+
+    for endpoint in endpoints {
+        new_delivery(business, &mut webhooks, &endpoint, event, data);
+    }
+    Webhooks::insert(*business, webhooks);
+    timer::schedule_at(time());
+}
+
+// Called from Business::push_transaction
+pub fn record_entry(business: &Principal, entry: &TransactionEntry) {
+    let event = match entry.kind {
+        TxKind::SendsRefund { .. } | TxKind::ReceiveRefund { .. } => WebhookEvent::Refund,
+        _ if entry.kind.is_transfer() => WebhookEvent::PaymentRecorded,
+        _ => return,
+    };
+
+    enqueue(business, event, &json_row(&ExportRow::new(entry)));
+}
+
+// Called when a checkout session turns Paid
+pub fn record_checkout_paid(business: &Principal, data: &str) {
+    enqueue(business, WebhookEvent::CheckoutPaid, data);
+}
+
+// Earliest next attempt of the pending deliveries
+pub fn next_attempt_at() -> Option<u64> {
+    DELIVERY_SCHEDULE_MAP.with(|p| {
+        p.borrow()
+            .first_key_value()
+            .and_then(|(key, _)| key.split('/').next()?.parse().ok())
+    })
+}
+
+fn retry_delay(attempts: u32) -> u64 {
+    RETRY_BASE
+        .saturating_mul(1 << attempts.saturating_sub(1).min(20))
+        .min(MAX_RETRY_DELAY)
+}
+
+/*
+ * Signature header "t={seconds},v1={hex}", the HMAC-SHA256 with the webhook secret of "{t}.{body}".
+ * Receivers check it and that t is recent, like Stripe webhooks.
+ */
+fn signature(secret: &str, timestamp: u64, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(format!("{}.{}", timestamp, body).as_bytes());
+    format!("t={},v1={}", timestamp, hex(&mac.finalize().into_bytes()))
+}
+
+// Outcall cycles on a 13 node subnet, the unused part is refunded
+pub fn outcall_cycles(request_bytes: usize) -> u128 {
+    let nodes = 13u128;
+    (3_000_000 + 60_000 * nodes) * nodes
+        + 400 * nodes * request_bytes as u128
+        + 800 * nodes * MAX_RESPONSE_BYTES as u128
+}
+
+async fn attempt(mut delivery: Delivery) {
+    let secret = match Webhooks::get(&delivery.business).and_then(|webhooks| {
+        webhooks
+            .endpoints
+            .into_iter()
+            .find(|endpoint| endpoint.id == delivery.webhook_id)
+    }) {
+        Some(endpoint) => endpoint.secret,
+        None => {
+            // Removed webhook, its pending deliveries can't be signed anymore
+            delivery.status = DeliveryStatus::Failed;
+            delivery.last_error = Some("webhook removed".to_string());
+            delivery.next_attempt_at = None;
+            clear_pending(&delivery.id);
+            Delivery::insert(delivery.id.clone(), delivery);
+            return;
+        }
+    };
+
+    let now = time();
+    let request = CanisterHttpRequestArgument {
+        url: delivery.url.clone(),
+        max_response_bytes: Some(MAX_RESPONSE_BYTES),
+        method: HttpMethod::POST,
+        headers: vec![
+            HttpHeader {
+                name: "Content-Type".to_string(),
+                value: "application/json".to_string(),
+            },
+            HttpHeader {
+                name: "X-Rapido-Event".to_string(),
+                value: delivery.event.name().to_string(),
+            },
+            HttpHeader {
+                name: "X-Rapido-Delivery".to_string(),
+                value: delivery.id.clone(),
+            },
+            HttpHeader {
+                name: "X-Rapido-Signature".to_string(),
+                value: signature(&secret, now / 1_000_000_000, &delivery.payload),
+            },
+        ],
+        body: Some(delivery.payload.clone().into_bytes()),
+        transform: Some(TransformContext::from_name(
+            "transform_webhook_response".to_string(),
+            vec![],
+        )),
+    };
+    let cycles = outcall_cycles(delivery.payload.len() + delivery.url.len() + 512);
+
+    let result = http_request(request, cycles).await;
+
+    // Read again, only this attempt changes the delivery but the log entry must not go back in time.
+    // A delivery pruned from the log meanwhile isn't added back
+    let mut delivery = match Delivery::get(&delivery.id) {
+        Some(delivery) => delivery,
+        None => return,
+    };
+    delivery.attempts += 1;
+    match result {
+        Ok((response,)) => {
+            let status = u16::try_from(response.status.0).unwrap_or(0);
+            delivery.last_status_code = Some(status);
+            delivery.last_error = None;
+            if (200..300).contains(&status) {
+                delivery.status = DeliveryStatus::Delivered;
+                delivery.delivered_at = Some(time());
+            }
+        }
+        Err((code, message)) => {
+            let mut error = format!("{:?} {}", code, message);
+            error.truncate(MAX_ERROR_LEN);
+            delivery.last_error = Some(error);
+        }
+    }
+
+    if delivery.status == DeliveryStatus::Pending && delivery.attempts >= MAX_ATTEMPTS {
+        delivery.status = DeliveryStatus::Failed;
+    }
+    if delivery.status == DeliveryStatus::Pending {
+        let next = time() + retry_delay(delivery.attempts);
+        delivery.next_attempt_at = Some(next);
+        set_pending(&delivery.id, next);
+    } else {
+        delivery.next_attempt_at = None;
+        clear_pending(&delivery.id);
+    }
+    Delivery::insert(delivery.id.clone(), delivery);
+}
+
+// Attempts the deliveries that are due, called by the timer
+pub async fn deliver_due() {
+    let now = time();
+    let due = DELIVERY_SCHEDULE_MAP.with(|p| {
+        p.borrow()
+            .range(..format!("{:020}0", now))
+            .take(DELIVERIES_PER_TICK)
+            .map(|(_, key)| key)
+            .collect::<Vec<_>>()
+    });
+
+    let mut attempts = Vec::new();
+    for key in due {
+        // Not picked again by the next tick while its outcall is in flight
+        set_pending(&key, now + IN_FLIGHT_TIMEOUT);
+        if let Some(delivery) = Delivery::get(&key) {
+            attempts.push(attempt(delivery));
+        }
+    }
+
+    // The outcalls are made at the same time, a slow endpoint doesn't hold up the others
+    join_all(attempts).await;
+}
+
+// Replicas see different headers like dates, only the status is kept so they agree on the response
+pub fn transform_response(args: TransformArgs) -> HttpResponse {
+    HttpResponse {
+        status: args.response.status,
+        headers: Vec::new(),
+        body: Vec::new(),
+    }
+}
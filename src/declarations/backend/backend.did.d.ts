@@ -2,11 +2,119 @@ import type { Principal } from '@dfinity/principal';
 import type { ActorMethod } from '@dfinity/agent';
 import type { IDL } from '@dfinity/candid';
 
+export interface Account {
+  'owner' : Principal,
+  'subaccount' : [] | [Uint8Array | number[]],
+}
+export type AccountKind = { 'User' : null } |
+  { 'Business' : BusinessCategory };
 export type AddBusinessError = { 'AccountNotFound' : null } |
   { 'BusinessNotFound' : null };
-export type AddMessageErr = { 'AccountNotFound' : null } |
+export type AddMessageErr = { 'Blocked' : null } |
+  { 'ChatNotEncrypted' : null } |
+  { 'AccountNotFound' : null } |
+  { 'InvalidCiphertext' : null } |
   { 'ChatNotFound' : null } |
-  { 'NotAParticipant' : null };
+  { 'NotAParticipant' : null } |
+  { 'ReplyToNotFound' : null } |
+  { 'ChatEncrypted' : null };
+export interface AddPushSubscriptionArg {
+  'endpoint' : string,
+  'auth' : string,
+  'p256dh' : string,
+}
+export interface AllowedApp {
+  'name' : string,
+  'added_at' : bigint,
+  'canister' : Principal,
+}
+export type AnalyticsBucket = { 'Day' : null } |
+  { 'Week' : null } |
+  { 'Month' : null };
+export type AnalyticsError = { 'RangeTooLong' : { 'max_days' : bigint } } |
+  { 'AccountNotFound' : null } |
+  { 'InvalidRange' : null } |
+  { 'NotPermitted' : null };
+export interface AnalyticsPeriod {
+  'average_ticket' : bigint,
+  'revenue_out' : bigint,
+  'revenue_in' : bigint,
+  'tx_count_in' : bigint,
+  'start' : bigint,
+  'top_customers' : Array<TopCustomer>,
+  'unique_customers' : bigint,
+  'tx_count_out' : bigint,
+}
+export interface AnalyticsRange { 'to' : bigint, 'from' : bigint }
+export interface AppPaymentRequest {
+  'id' : bigint,
+  'app' : Principal,
+  'status' : AppRequestStatus,
+  'payment_memo' : Uint8Array | number[],
+  'memo' : [] | [string],
+  'callback_error' : [] | [string],
+  'user' : Principal,
+  'requested_at' : bigint,
+  'callback' : string,
+  'app_name' : string,
+  'payment_uri' : string,
+  'amount' : bigint,
+  'expires_at' : bigint,
+  'pay_id' : string,
+}
+export type AppRequestError = { 'NotAController' : null } |
+  { 'Blocked' : null } |
+  { 'AccountNotFound' : null } |
+  { 'InvalidAmount' : null } |
+  { 'InvalidCallback' : null } |
+  { 'NotAllowed' : null } |
+  { 'MemoTooLong' : { 'max' : bigint } } |
+  { 'InvalidName' : { 'max' : bigint } } |
+  { 'RequestNotFound' : null } |
+  { 'TooManyPending' : { 'max' : bigint } } |
+  { 'NotPending' : null };
+export interface AppRequestPage {
+  'next_page' : [] | [bigint],
+  'requests' : Array<AppPaymentRequest>,
+}
+export type AppRequestStatus = {
+    'Paid' : { 'tx_id' : bigint, 'paid_at' : bigint }
+  } |
+  { 'Declined' : { 'declined_at' : bigint } } |
+  { 'Expired' : null } |
+  { 'Pending' : null };
+export type ApprovalStatus = { 'Used' : { 'at' : bigint } } |
+  { 'Approved' : { 'at' : bigint } } |
+  { 'Rejected' : { 'at' : bigint } } |
+  { 'Pending' : null };
+export type AuditAction = {
+    'MemberAdded' : { 'principal' : Principal, 'role' : Role }
+  } |
+  { 'PaymentLinkCreated' : { 'id' : bigint } } |
+  { 'CheckoutSessionCreated' : { 'id' : bigint, 'amount' : bigint } } |
+  { 'MemberRoleChanged' : { 'principal' : Principal, 'role' : Role } } |
+  { 'CustomersRebuilt' : null } |
+  { 'WebhookRemoved' : { 'id' : bigint } } |
+  { 'PaymentLinkDisabled' : { 'id' : bigint } } |
+  { 'WebhookAdded' : { 'id' : bigint, 'url' : string } } |
+  { 'AnalyticsRebuilt' : null } |
+  { 'MemberRemoved' : { 'principal' : Principal } } |
+  { 'CustomerUpdated' : { 'pay_id' : string } };
+export interface AuditEntry {
+  'member' : Principal,
+  'action' : AuditAction,
+  'role' : Role,
+  'timestamp' : bigint,
+}
+export interface AuditPage {
+  'next_page' : [] | [bigint],
+  'entries' : Array<AuditEntry>,
+}
+export type BlockError = { 'NotBlocked' : null } |
+  { 'AccountNotFound' : null } |
+  { 'PayIdNotFound' : null } |
+  { 'NotPermitted' : null } |
+  { 'CannotBlockSelf' : null };
 export interface Business {
   'logo' : string,
   'name' : string,
@@ -15,6 +123,10 @@ export interface Business {
   'transactions' : Array<TransactionEntry>,
   'pay_id' : string,
 }
+export interface BusinessAnalytics {
+  'total' : AnalyticsPeriod,
+  'buckets' : Array<AnalyticsPeriod>,
+}
 export type BusinessCategory = { 'Healthcare' : null } |
   { 'RealEstate' : null } |
   { 'Food' : null } |
@@ -31,6 +143,10 @@ export type BusinessCategory = { 'Healthcare' : null } |
   { 'Hospitality' : null } |
   { 'Construction' : null } |
   { 'Manufacturing' : null };
+export interface BusinessDirectoryPage {
+  'next_page' : [] | [bigint],
+  'businesses' : Array<PayIdProfile>,
+}
 export interface BusinessInUser {
   'id' : string,
   'b_logo' : string,
@@ -41,6 +157,11 @@ export interface BusinessInUser {
   'b_principal' : Principal,
   'transactions' : Array<BusinessUserTx>,
 }
+export interface BusinessProfile {
+  'principal' : Principal,
+  'subaccount' : [] | [Uint8Array | number[]],
+  'business' : Business,
+}
 export interface BusinessSignUpArgs {
   'logo' : string,
   'name' : string,
@@ -58,27 +179,518 @@ export interface Chat {
   'id' : string,
   'participants' : Array<string>,
   'messages' : Array<MessageOrTransaction>,
+  'encrypted' : [] | [boolean],
   'last_activity' : bigint,
+  'last_read' : [] | [Array<[string, bigint]>],
 }
-export type CreateChatErr = { 'AccountNotFound' : null } |
+export type ChatEncryptionError = { 'ChatNotEncrypted' : null } |
+  { 'AccountNotFound' : null } |
+  { 'InterCanisterCall' : string } |
+  { 'ChatNotFound' : null } |
+  { 'NotAParticipant' : null } |
+  { 'RateLimited' : { 'retry_at' : bigint } } |
+  { 'InvalidTransportKey' : null };
+export interface ChatUpdate {
+  'id' : string,
+  'participants' : Array<string>,
+  'messages' : Array<[bigint, MessageOrTransaction]>,
+  'encrypted' : [] | [boolean],
+  'unread_count' : bigint,
+  'last_activity' : bigint,
+  'last_read' : [] | [Array<[string, bigint]>],
+}
+export type CheckoutError = { 'AccountNotFound' : null } |
+  { 'InvalidAmount' : null } |
+  { 'InterCanisterCall' : string } |
+  { 'SessionNotFound' : null } |
+  { 'NotPermitted' : null } |
+  { 'InvalidTtl' : { 'max' : bigint } } |
+  { 'ReferenceTooLong' : { 'max' : bigint } };
+export interface CheckoutSession {
+  'id' : bigint,
+  'status' : CheckoutStatus,
+  'memo' : Uint8Array | number[],
+  'reference' : string,
+  'created_at' : bigint,
+  'created_by' : Principal,
+  'business' : Principal,
+  'payment_uri' : string,
+  'amount' : bigint,
+  'expires_at' : bigint,
+  'start_block' : bigint,
+}
+export type CheckoutStatus = { 'Open' : null } |
+  { 'Paid' : { 'tx_id' : bigint, 'from' : Principal, 'paid_at' : bigint } } |
+  { 'Expired' : null };
+export interface Contact {
+  'principal' : Principal,
+  'nickname' : [] | [string],
+  'note' : [] | [string],
+  'added_at' : bigint,
+  'favorite' : boolean,
+}
+export type ContactError = { 'AccountNotFound' : null } |
+  { 'PayIdNotFound' : null } |
+  { 'ContactNotFound' : null } |
+  { 'NotPermitted' : null } |
+  { 'NicknameTooLong' : { 'max' : bigint } } |
+  { 'AnonymousPrincipal' : null } |
+  { 'CannotAddSelf' : null } |
+  { 'NoteTooLong' : { 'max' : bigint } };
+export interface ContactView {
+  'contact' : Contact,
+  'name' : string,
+  'pay_id' : [] | [string],
+}
+export type CreateChatErr = { 'Blocked' : null } |
+  { 'AccountNotFound' : null } |
   { 'ParticipantNotFound' : null } |
   { 'CallerAndParticipantSame' : null };
+export interface CreateEscrowArg {
+  'note' : [] | [string],
+  'chat_id' : string,
+  'amount' : bigint,
+  'timeout' : [] | [bigint],
+}
+export interface CreateMandateArg {
+  'starts_at' : [] | [bigint],
+  'interval' : bigint,
+  'ends_at' : bigint,
+  'note' : [] | [string],
+  'payee' : string,
+  'amount' : bigint,
+}
+export interface CreatePaymentLinkArg {
+  'title' : string,
+  'single_use' : boolean,
+  'amount' : [] | [bigint],
+  'expires_at' : [] | [bigint],
+}
+export interface Customer {
+  'total_paid_out' : bigint,
+  'principal' : Principal,
+  'name' : string,
+  'note' : [] | [string],
+  'tags' : Array<string>,
+  'total_paid' : bigint,
+  'added_business' : boolean,
+  'first_seen' : bigint,
+  'tx_count' : bigint,
+  'last_seen' : bigint,
+  'pay_id' : string,
+}
+export type CustomerError = { 'AccountNotFound' : null } |
+  { 'TagTooLong' : { 'max' : bigint } } |
+  { 'NotPermitted' : null } |
+  { 'CustomerNotFound' : null } |
+  { 'NoteTooLong' : { 'max' : bigint } } |
+  { 'TooManyTags' : { 'max' : bigint } };
+export interface CustomerPage {
+  'next_page' : [] | [bigint],
+  'customers' : Array<Customer>,
+}
 export type DataResponse = { 'User' : UserData } |
   { 'Business' : Business } |
+  { 'Profiles' : ProfilesData } |
   { 'NotSignUp' : null };
+export interface Delivery {
+  'id' : string,
+  'url' : string,
+  'last_error' : [] | [string],
+  'status' : DeliveryStatus,
+  'next_attempt_at' : [] | [bigint],
+  'attempts' : number,
+  'created_at' : bigint,
+  'event' : WebhookEvent,
+  'business' : Principal,
+  'last_status_code' : [] | [number],
+  'payload' : string,
+  'delivered_at' : [] | [bigint],
+  'webhook_id' : bigint,
+}
+export interface DeliveryPage {
+  'next_page' : [] | [bigint],
+  'deliveries' : Array<Delivery>,
+}
+export type DeliveryStatus = { 'Failed' : null } |
+  { 'Delivered' : null } |
+  { 'Pending' : null };
+export type DirectoryError = { 'AccountNotFound' : null } |
+  { 'NotPermitted' : null };
+export interface Dispute {
+  'by' : Principal,
+  'opened_at' : bigint,
+  'reason' : string,
+}
+export interface Escrow {
+  'id' : bigint,
+  'status' : EscrowStatus,
+  'deposit_account' : Account,
+  'note' : [] | [string],
+  'deposit_amount' : bigint,
+  'deadline' : [] | [bigint],
+  'created_at' : bigint,
+  'funded_at' : [] | [bigint],
+  'payment_uri' : string,
+  'dispute' : [] | [Dispute],
+  'payee' : Principal,
+  'payer' : Principal,
+  'chat_id' : string,
+  'amount' : bigint,
+  'timeout' : bigint,
+  'delivered_at' : [] | [bigint],
+  'payout' : [] | [PendingPayout],
+  'timed_out' : boolean,
+}
+export type EscrowError = { 'NotAController' : null } |
+  { 'AccountNotFound' : null } |
+  { 'InvalidAmount' : null } |
+  { 'PayeeNotFound' : null } |
+  { 'Policy' : PolicyError } |
+  { 'InterCanisterCall' : string } |
+  { 'InvalidTimeout' : { 'max' : bigint, 'min' : bigint } } |
+  { 'PayoutLookup' : null } |
+  { 'NotPermitted' : null } |
+  { 'ChatNotFound' : null } |
+  { 'NotAParticipant' : null } |
+  { 'EscrowNotFound' : null } |
+  { 'NotFunded' : { 'balance' : bigint } } |
+  { 'ReasonTooLong' : { 'max' : bigint } } |
+  { 'TransferFailed' : string } |
+  { 'NoteTooLong' : { 'max' : bigint } } |
+  { 'InvalidState' : null };
+export type EscrowEvent = { 'Disputed' : { 'reason' : string } } |
+  { 'Refunded' : { 'tx_id' : bigint } } |
+  { 'Released' : { 'tx_id' : bigint } } |
+  { 'Delivered' : null } |
+  { 'Funded' : null } |
+  { 'Cancelled' : null } |
+  { 'TimedOut' : null } |
+  { 'Created' : null };
+export interface EscrowMessage {
+  'read_by' : Array<string>,
+  'event' : EscrowEvent,
+  'sender_id' : string,
+  'timestamp' : bigint,
+  'escrow_id' : bigint,
+  'amount' : bigint,
+}
+export type EscrowResolution = { 'Release' : null } |
+  { 'Refund' : null };
+export type EscrowStatus = { 'Disputed' : null } |
+  { 'Refunded' : { 'tx_id' : bigint } } |
+  { 'Released' : { 'tx_id' : bigint } } |
+  { 'Delivered' : null } |
+  { 'Settling' : null } |
+  { 'Funded' : null } |
+  { 'AwaitingFunds' : null } |
+  { 'Cancelled' : null };
+export interface ExportChunk {
+  'total_entries' : bigint,
+  'data' : string,
+  'next_chunk' : [] | [bigint],
+}
+export type ExportError = { 'AccountNotFound' : null } |
+  { 'InvalidRange' : null } |
+  { 'NotPermitted' : null } |
+  { 'InvalidChunk' : null } |
+  { 'ChunkOutOfRange' : null };
+export type ExportFormat = { 'Csv' : null } |
+  { 'Ofx' : null } |
+  { 'Json' : null };
+export interface ExportRange { 'to' : [] | [bigint], 'from' : [] | [bigint] }
 export type FetchInitDataError = { 'AnonymousCaller' : null };
-export type GetBusinessError = { 'AccountNotFound' : null };
+export type FiatError = { 'NotAController' : null } |
+  { 'AccountNotFound' : null } |
+  { 'InvalidCurrency' : null } |
+  { 'NotPermitted' : null } |
+  { 'UnsupportedCurrency' : null };
+export interface FiatValue {
+  'decimals' : number,
+  'rate' : bigint,
+  'currency' : string,
+  'timestamp' : bigint,
+}
+export type GetBusinessError = { 'AccountNotFound' : null } |
+  { 'NotPermitted' : null };
+export interface HttpHeader { 'value' : string, 'name' : string }
+export interface HttpResponse {
+  'status' : bigint,
+  'body' : Uint8Array | number[],
+  'headers' : Array<HttpHeader>,
+}
+export interface LinkPayment {
+  'tx_id' : bigint,
+  'from' : Principal,
+  'timestamp' : bigint,
+  'amount' : bigint,
+}
+export interface LinkPaymentPage {
+  'next_page' : [] | [bigint],
+  'payments' : Array<LinkPayment>,
+}
+export type LinkStatus = { 'Paid' : null } |
+  { 'Disabled' : null } |
+  { 'PaidOpen' : null } |
+  { 'Unpaid' : null } |
+  { 'Expired' : null };
+export interface Mandate {
+  'id' : bigint,
+  'status' : MandateStatus,
+  'interval' : bigint,
+  'payments' : bigint,
+  'retry_at' : [] | [bigint],
+  'ends_at' : bigint,
+  'note' : [] | [string],
+  'attempts' : number,
+  'created_at' : bigint,
+  'events' : Array<MandateEvent>,
+  'payee_pay_id' : string,
+  'next_run_at' : bigint,
+  'payee' : Principal,
+  'payer' : Principal,
+  'amount' : bigint,
+}
+export type MandateError = { 'AccountNotFound' : null } |
+  { 'InvalidAmount' : null } |
+  { 'PayeeNotFound' : null } |
+  { 'NotActive' : null } |
+  { 'CannotPaySelf' : null } |
+  { 'InterCanisterCall' : string } |
+  { 'TooManyMandates' : { 'max' : bigint } } |
+  { 'InvalidStartDate' : null } |
+  { 'NotPermitted' : null } |
+  { 'InvalidEndDate' : null } |
+  { 'AllowanceTooLow' : { 'allowance' : bigint } } |
+  { 'InvalidInterval' : { 'max' : bigint, 'min' : bigint } } |
+  { 'MandateNotFound' : null } |
+  { 'NoteTooLong' : { 'max' : bigint } };
+export interface MandateEvent {
+  'run_at' : bigint,
+  'kind' : MandateEventKind,
+  'timestamp' : bigint,
+}
+export type MandateEventKind = { 'Skipped' : null } |
+  { 'Blocked' : { 'error' : PolicyError } } |
+  { 'Failed' : { 'attempt' : number, 'reason' : string } } |
+  { 'Paid' : { 'tx_id' : bigint } } |
+  { 'NotRecorded' : { 'tx_id' : bigint, 'error' : RecordTxErr } } |
+  { 'Cancelled' : null } |
+  { 'Completed' : null };
+export type MandateStatus = { 'Active' : null } |
+  { 'Cancelled' : { 'at' : bigint, 'by' : Principal } } |
+  { 'Completed' : null };
+export type MarkMessageReadErr = { 'AccountNotFound' : null } |
+  { 'ChatNotFound' : null } |
+  { 'NotAParticipant' : null };
+export interface Member {
+  'principal' : Principal,
+  'role' : Role,
+  'added_at' : bigint,
+  'added_by' : Principal,
+}
+export interface Membership {
+  'name' : string,
+  'role' : Role,
+  'business' : Principal,
+  'pay_id' : string,
+}
 export interface Message {
   'read_by' : Array<string>,
   'content' : string,
+  'ciphertext' : [] | [Uint8Array | number[]],
+  'reply_to' : [] | [bigint],
+  'edit_history' : [] | [Array<MessageEdit>],
   'sender_id' : string,
   'timestamp' : bigint,
+  'deleted_at' : [] | [bigint],
+  'reactions' : [] | [Array<Reaction>],
 }
-export type MessageOrTransaction = { 'RequestPayment' : RequestPayment } |
+export interface MessageEdit { 'content' : string, 'edited_at' : bigint }
+export type MessageOrTransaction = { 'Escrow' : EscrowMessage } |
+  { 'RequestPayment' : RequestPayment } |
   { 'Transaction' : Transaction } |
   { 'Message' : Message };
+export interface MyChat { 'chat' : Chat, 'unread_count' : bigint }
+export interface Notification {
+  'id' : bigint,
+  'read' : boolean,
+  'created_at' : bigint,
+  'event' : NotificationEvent,
+}
+export type NotificationEvent = {
+    'AppRequestReceived' : {
+      'app' : string,
+      'request_id' : bigint,
+      'memo' : [] | [string],
+      'amount' : bigint,
+    }
+  } |
+  {
+    'PaymentReceived' : { 'tx_id' : bigint, 'from' : string, 'amount' : bigint }
+  } |
+  {
+    'InvoiceDue' : {
+      'from' : string,
+      'chat_id' : string,
+      'message_id' : bigint,
+      'amount' : bigint,
+      'expires_at' : bigint,
+    }
+  } |
+  {
+    'RequestExpired' : {
+      'chat_id' : string,
+      'message_id' : bigint,
+      'amount' : bigint,
+    }
+  } |
+  {
+    'MandateProblem' : {
+      'mandate_id' : bigint,
+      'event' : MandateEventKind,
+      'payee' : string,
+      'amount' : bigint,
+    }
+  } |
+  {
+    'BusinessAdded' : { 'name' : string, 'role' : Role, 'business' : Principal }
+  } |
+  { 'Mention' : { 'by' : string, 'chat_id' : string, 'message_id' : bigint } } |
+  {
+    'RequestPaid' : {
+      'by' : string,
+      'tx_id' : bigint,
+      'chat_id' : string,
+      'message_id' : bigint,
+      'amount' : bigint,
+    }
+  } |
+  {
+    'RequestReceived' : {
+      'from' : string,
+      'chat_id' : string,
+      'message_id' : bigint,
+      'amount' : bigint,
+    }
+  } |
+  {
+    'AppRequestClosed' : {
+      'app' : string,
+      'request_id' : bigint,
+      'status' : AppRequestStatus,
+      'amount' : bigint,
+    }
+  };
+export type NotificationKind = { 'AppRequestReceived' : null } |
+  { 'PaymentReceived' : null } |
+  { 'InvoiceDue' : null } |
+  { 'RequestExpired' : null } |
+  { 'MandateProblem' : null } |
+  { 'BusinessAdded' : null } |
+  { 'Mention' : null } |
+  { 'RequestPaid' : null } |
+  { 'RequestReceived' : null } |
+  { 'AppRequestClosed' : null };
+export interface NotificationPage {
+  'notifications' : Array<Notification>,
+  'unread' : bigint,
+  'next_cursor' : [] | [bigint],
+}
 export type PayIdOrPrincipal = { 'PayId' : string } |
   { 'Principal' : Principal };
+export interface PayIdProfile {
+  'verified' : boolean,
+  'kind' : AccountKind,
+  'name' : string,
+  'pay_id' : string,
+  'avatar' : string,
+}
+export interface PaymentLink {
+  'id' : bigint,
+  'title' : string,
+  'payment_count' : bigint,
+  'disabled_at' : [] | [bigint],
+  'owner' : Principal,
+  'total_received' : bigint,
+  'created_at' : bigint,
+  'created_by' : Principal,
+  'last_paid_at' : [] | [bigint],
+  'single_use' : boolean,
+  'amount' : [] | [bigint],
+  'expires_at' : [] | [bigint],
+}
+export interface PaymentLinkDetails {
+  'link' : PaymentLink,
+  'view' : PaymentLinkView,
+}
+export type PaymentLinkError = { 'InvalidExpiry' : null } |
+  { 'AccountNotFound' : null } |
+  { 'InvalidAmount' : null } |
+  { 'TooManyLinks' : { 'max' : bigint } } |
+  { 'TitleTooLong' : { 'max' : bigint } } |
+  { 'NotPermitted' : null } |
+  { 'LinkNotFound' : null };
+export interface PaymentLinkView {
+  'id' : bigint,
+  'status' : LinkStatus,
+  'title' : string,
+  'owner_pay_id' : string,
+  'payment_uri' : string,
+  'single_use' : boolean,
+  'amount' : [] | [bigint],
+  'expires_at' : [] | [bigint],
+  'owner_name' : string,
+}
+export interface PendingPayout {
+  'to' : Principal,
+  'fee' : bigint,
+  'created_at_time' : bigint,
+  'amount' : bigint,
+  'lookup_from' : bigint,
+}
+export interface PolicyDecision {
+  'to' : Principal,
+  'send' : PolicySend,
+  'timestamp' : bigint,
+  'amount' : bigint,
+  'outcome' : PolicyOutcome,
+}
+export type PolicyError = {
+    'MonthlyLimit' : { 'cap' : bigint, 'spent' : bigint }
+  } |
+  { 'ApprovalRejected' : { 'approval_id' : bigint } } |
+  { 'ApprovalRequired' : { 'approval_id' : bigint } } |
+  { 'ApprovalPending' : { 'approval_id' : bigint } } |
+  { 'PerTransactionLimit' : { 'max' : bigint } } |
+  { 'DailyLimit' : { 'cap' : bigint, 'spent' : bigint } };
+export interface PolicyLogPage {
+  'next_page' : [] | [bigint],
+  'decisions' : Array<PolicyDecision>,
+}
+export type PolicyOutcome = { 'Allowed' : { 'approval_id' : [] | [bigint] } } |
+  { 'Denied' : { 'error' : PolicyError } };
+export type PolicySend = { 'EscrowRelease' : { 'escrow_id' : bigint } } |
+  { 'MandatePayment' : { 'run_at' : bigint, 'mandate_id' : bigint } };
+export interface ProfilesData {
+  'businesses' : Array<BusinessProfile>,
+  'user' : [] | [UserData],
+  'user_subaccount' : [] | [Uint8Array | number[]],
+}
+export interface PushSubscriptionView {
+  'id' : bigint,
+  'endpoint' : string,
+  'created_at' : bigint,
+}
+export interface Reaction { 'emoji' : string, 'pay_id' : string }
+export type RecordRefundErr = { 'PartiesNotReversed' : null } |
+  { 'RefundBeforeOriginal' : null } |
+  { 'AlreadyRecorded' : null } |
+  { 'InterCanisterCall' : string } |
+  { 'OriginalNotRecorded' : null } |
+  { 'NotPaidToBusiness' : null } |
+  { 'InvalidTransaction' : string } |
+  { 'ExceedsRefundable' : { 'refundable' : bigint } };
 export type RecordRegPayTxErr = { 'RequestPaymentNotFound' : null } |
   { 'AlreadyRecorded' : null } |
   { 'AccountNotFound' : null } |
@@ -93,9 +705,15 @@ export interface RecordReqPayArg {
   'message_index' : bigint,
 }
 export type RecordTxErr = { 'AlreadyRecorded' : null } |
+  { 'AccountNotFound' : Principal } |
   { 'InterCanisterCall' : string } |
   { 'InvalidTransaction' : string } |
   { 'BothAccountsNotFound' : { 'to' : Principal, 'from' : Principal } };
+export interface RefundState {
+  'refunded' : bigint,
+  'refund_tx_ids' : Array<bigint>,
+  'refundable' : bigint,
+}
 export interface ReqPayArg {
   'note' : [] | [string],
   'chat_id' : string,
@@ -111,31 +729,249 @@ export interface RequestPayment {
   'amount' : bigint,
   'expires_at' : bigint,
 }
+export type RequestPaymentError = { 'OnlyContactsCanRequest' : null } |
+  { 'Blocked' : null } |
+  { 'AccountNotFound' : null } |
+  { 'ChatNotFound' : null } |
+  { 'NotAParticipant' : null } |
+  { 'RateLimited' : { 'retry_at' : bigint } };
 export type Result = { 'Ok' : Message } |
   { 'Err' : AddMessageErr };
-export type Result_1 = { 'Ok' : Chat } |
-  { 'Err' : CreateChatErr };
-export type Result_2 = { 'Ok' : DataResponse } |
+export type Result_1 = { 'Ok' : Member } |
+  { 'Err' : StaffError };
+export type Result_10 = { 'Ok' : PaymentLinkDetails } |
+  { 'Err' : PaymentLinkError };
+export type Result_11 = { 'Ok' : SendApproval } |
+  { 'Err' : SpendingPolicyError };
+export type Result_12 = { 'Ok' : AppPaymentRequest } |
+  { 'Err' : AppRequestError };
+export type Result_13 = { 'Ok' : null } |
+  { 'Err' : UpdateMessageErr };
+export type Result_14 = { 'Ok' : Message } |
+  { 'Err' : UpdateMessageErr };
+export type Result_15 = { 'Ok' : Chat } |
+  { 'Err' : ChatEncryptionError };
+export type Result_16 = { 'Ok' : ExportChunk } |
+  { 'Err' : ExportError };
+export type Result_17 = { 'Ok' : DataResponse } |
   { 'Err' : FetchInitDataError };
-export type Result_3 = { 'Ok' : Business } |
+export type Result_18 = { 'Ok' : AppRequestPage } |
+  { 'Err' : AppRequestError };
+export type Result_19 = { 'Ok' : AuditPage } |
+  { 'Err' : StaffError };
+export type Result_2 = { 'Ok' : PushSubscriptionView } |
+  { 'Err' : WebPushError };
+export type Result_20 = { 'Ok' : Business } |
   { 'Err' : GetBusinessError };
+export type Result_21 = { 'Ok' : BusinessAnalytics } |
+  { 'Err' : AnalyticsError };
+export type Result_22 = { 'Ok' : Uint8Array | number[] } |
+  { 'Err' : ChatEncryptionError };
+export type Result_23 = { 'Ok' : ContactView } |
+  { 'Err' : ContactError };
+export type Result_24 = { 'Ok' : Array<ContactView> } |
+  { 'Err' : ContactError };
+export type Result_25 = { 'Ok' : Customer } |
+  { 'Err' : CustomerError };
+export type Result_26 = { 'Ok' : Array<Escrow> } |
+  { 'Err' : EscrowError };
+export type Result_27 = { 'Ok' : LinkPaymentPage } |
+  { 'Err' : PaymentLinkError };
+export type Result_28 = { 'Ok' : Array<Mandate> } |
+  { 'Err' : MandateError };
+export type Result_29 = { 'Ok' : Array<NotificationKind> } |
+  { 'Err' : DirectoryError };
+export type Result_3 = { 'Ok' : WebhookCreated } |
+  { 'Err' : WebhookError };
+export type Result_30 = { 'Ok' : NotificationPage } |
+  { 'Err' : GetBusinessError };
+export type Result_31 = { 'Ok' : PolicyLogPage } |
+  { 'Err' : SpendingPolicyError };
+export type Result_32 = { 'Ok' : string } |
+  { 'Err' : FiatError };
+export type Result_33 = { 'Ok' : Array<PushSubscriptionView> } |
+  { 'Err' : WebPushError };
+export type Result_34 = { 'Ok' : SafetySettings } |
+  { 'Err' : BlockError };
+export type Result_35 = { 'Ok' : Array<SendApproval> } |
+  { 'Err' : SpendingPolicyError };
+export type Result_36 = { 'Ok' : [] | [SpendingPolicyView] } |
+  { 'Err' : SpendingPolicyError };
+export type Result_37 = { 'Ok' : DeliveryPage } |
+  { 'Err' : WebhookError };
+export type Result_38 = { 'Ok' : CustomerPage } |
+  { 'Err' : CustomerError };
+export type Result_39 = { 'Ok' : Array<Member> } |
+  { 'Err' : StaffError };
 export type Result_4 = { 'Ok' : null } |
-  { 'Err' : AddMessageErr };
-export type Result_5 = { 'Ok' : RequestPayment } |
-  { 'Err' : AddMessageErr };
-export type Result_6 = { 'Ok' : null } |
+  { 'Err' : AppRequestError };
+export type Result_40 = { 'Ok' : Array<PaymentLinkDetails> } |
+  { 'Err' : PaymentLinkError };
+export type Result_41 = { 'Ok' : Array<WebhookView> } |
+  { 'Err' : WebhookError };
+export type Result_42 = { 'Ok' : null } |
+  { 'Err' : MarkMessageReadErr };
+export type Result_43 = { 'Ok' : bigint } |
+  { 'Err' : GetBusinessError };
+export type Result_44 = { 'Ok' : RequestPayment } |
+  { 'Err' : RequestPaymentError };
+export type Result_45 = { 'Ok' : null } |
+  { 'Err' : AnalyticsError };
+export type Result_46 = { 'Ok' : null } |
+  { 'Err' : CustomerError };
+export type Result_47 = { 'Ok' : null } |
+  { 'Err' : SearchError };
+export type Result_48 = { 'Ok' : RefundState } |
+  { 'Err' : RecordRefundErr };
+export type Result_49 = { 'Ok' : null } |
   { 'Err' : RecordRegPayTxErr };
-export type Result_7 = { 'Ok' : null } |
+export type Result_5 = { 'Ok' : null } |
+  { 'Err' : BlockError };
+export type Result_50 = { 'Ok' : null } |
   { 'Err' : RecordTxErr };
-export type Result_8 = { 'Ok' : null } |
+export type Result_51 = { 'Ok' : null } |
+  { 'Err' : ContactError };
+export type Result_52 = { 'Ok' : null } |
+  { 'Err' : StaffError };
+export type Result_53 = { 'Ok' : null } |
+  { 'Err' : WebPushError };
+export type Result_54 = { 'Ok' : null } |
+  { 'Err' : WebhookError };
+export type Result_55 = { 'Ok' : SearchResponse } |
+  { 'Err' : SearchError };
+export type Result_56 = { 'Ok' : Delivery } |
+  { 'Err' : WebhookError };
+export type Result_57 = { 'Ok' : null } |
+  { 'Err' : DirectoryError };
+export type Result_58 = { 'Ok' : null } |
+  { 'Err' : EscrowError };
+export type Result_59 = { 'Ok' : null } |
+  { 'Err' : FiatError };
+export type Result_6 = { 'Ok' : Escrow } |
+  { 'Err' : EscrowError };
+export type Result_60 = { 'Ok' : SpendingPolicyView } |
+  { 'Err' : SpendingPolicyError };
+export type Result_61 = { 'Ok' : null } |
+  { 'Err' : SetVerifiedError };
+export type Result_62 = { 'Ok' : string } |
+  { 'Err' : WebPushError };
+export type Result_63 = { 'Ok' : null } |
   { 'Err' : SignUpError };
-export type Result_9 = { 'Ok' : BusinessInUser } |
+export type Result_64 = { 'Ok' : SyncResponse } |
+  { 'Err' : SyncError };
+export type Result_65 = { 'Ok' : BusinessInUser } |
   { 'Err' : AddBusinessError };
+export type Result_7 = { 'Ok' : Mandate } |
+  { 'Err' : MandateError };
+export type Result_8 = { 'Ok' : Chat } |
+  { 'Err' : CreateChatErr };
+export type Result_9 = { 'Ok' : CheckoutSession } |
+  { 'Err' : CheckoutError };
+export type Role = { 'Viewer' : null } |
+  { 'Cashier' : null } |
+  { 'Admin' : null } |
+  { 'Owner' : null };
+export interface SafetySettings {
+  'blocked' : Array<Principal>,
+  'only_contacts_can_request' : boolean,
+}
+export interface SearchArgs {
+  'to' : [] | [bigint],
+  'min_amount' : [] | [bigint],
+  'from' : [] | [bigint],
+  'query' : string,
+  'offset' : bigint,
+  'limit' : bigint,
+  'max_amount' : [] | [bigint],
+}
+export type SearchError = { 'AccountNotFound' : null } |
+  { 'NotPermitted' : null } |
+  { 'EmptyQuery' : null } |
+  { 'InvalidLimit' : { 'max' : bigint } };
+export type SearchItem = { 'History' : TransactionEntry } |
+  {
+    'Message' : {
+      'message' : MessageOrTransaction,
+      'chat_id' : string,
+      'message_id' : bigint,
+    }
+  } |
+  { 'Business' : BusinessInUser } |
+  { 'Contact' : ContactView };
+export interface SearchResponse {
+  'total' : bigint,
+  'results' : Array<SearchResult>,
+}
+export interface SearchResult { 'item' : SearchItem, 'score' : number }
+export interface SendApproval {
+  'id' : bigint,
+  'to' : Principal,
+  'status' : ApprovalStatus,
+  'send' : PolicySend,
+  'created_at' : bigint,
+  'approver' : Principal,
+  'account' : Principal,
+  'amount' : bigint,
+  'expires_at' : bigint,
+}
+export type SetVerifiedError = { 'NotAController' : null } |
+  { 'PayIdNotFound' : null };
 export type SignUpArg = { 'User' : UserSignUpArgs } |
   { 'Business' : BusinessSignUpArgs };
-export type SignUpError = { 'AccountExist' : null } |
+export type SignUpError = { 'TooManyProfiles' : { 'max' : bigint } } |
+  { 'AccountExist' : null } |
   { 'PayIdExist' : null } |
   { 'AnonymousCaller' : null };
+export interface SpendingPolicyArg {
+  'approval_threshold' : [] | [bigint],
+  'daily_cap' : [] | [bigint],
+  'max_per_tx' : [] | [bigint],
+  'approver' : [] | [Principal],
+  'monthly_cap' : [] | [bigint],
+}
+export type SpendingPolicyError = { 'ApproverNotFound' : null } |
+  { 'AccountNotFound' : null } |
+  { 'ApprovalNotFound' : null } |
+  { 'ApproverRequired' : null } |
+  { 'InvalidApprover' : null } |
+  { 'NotPermitted' : null } |
+  { 'AlreadyDecided' : null };
+export interface SpendingPolicyView {
+  'spent_this_month' : bigint,
+  'updated_at' : bigint,
+  'approval_threshold' : [] | [bigint],
+  'daily_cap' : [] | [bigint],
+  'max_per_tx' : [] | [bigint],
+  'approver' : [] | [Principal],
+  'account' : Principal,
+  'spent_today' : bigint,
+  'monthly_cap' : [] | [bigint],
+}
+export type StaffError = { 'AlreadyAMember' : null } |
+  { 'AccountNotFound' : null } |
+  { 'IsABusiness' : null } |
+  { 'NotPermitted' : null } |
+  { 'AnonymousPrincipal' : null } |
+  { 'MemberNotFound' : null } |
+  { 'TooManyMembers' : { 'max' : bigint } };
+export type SyncError = { 'AccountNotFound' : null } |
+  { 'NotPermitted' : null };
+export interface SyncResponse {
+  'notifications' : Array<Notification>,
+  'chats' : Array<ChatUpdate>,
+  'app_requests' : Array<AppPaymentRequest>,
+  'history' : Array<TransactionEntry>,
+  'version' : bigint,
+  'business' : Array<BusinessInUser>,
+  'full_resync' : boolean,
+  'mandates' : Array<Mandate>,
+}
+export interface TopCustomer {
+  'name' : string,
+  'paid' : bigint,
+  'tx_count' : bigint,
+  'pay_id' : string,
+}
 export interface Transaction {
   'read_by' : Array<string>,
   'tx_id' : bigint,
@@ -145,16 +981,48 @@ export interface Transaction {
   'amount' : bigint,
 }
 export interface TransactionEntry {
+  'fee' : [] | [bigint],
   'tx_id' : bigint,
+  'fiat' : [] | [FiatValue],
   'kind' : TxKind,
   'name' : string,
   'note' : [] | [string],
   'timestamp' : bigint,
   'amount' : bigint,
   'pay_id' : string,
+  'refund' : [] | [RefundState],
+}
+export interface TransformArgs {
+  'context' : Uint8Array | number[],
+  'response' : HttpResponse,
 }
 export type TxKind = { 'Sends' : null } |
+  { 'Approve' : { 'expires_at' : [] | [bigint] } } |
+  { 'Burn' : null } |
+  { 'Mint' : null } |
+  { 'Allowance' : { 'expires_at' : [] | [bigint] } } |
+  { 'ReceiveRefund' : { 'original_tx_id' : bigint } } |
+  { 'SendsRefund' : { 'original_tx_id' : bigint } } |
   { 'Receive' : null };
+export interface UpdateContactArg {
+  'contact' : PayIdOrPrincipal,
+  'nickname' : [] | [string],
+  'note' : [] | [string],
+  'favorite' : boolean,
+}
+export interface UpdateCustomerArg {
+  'note' : [] | [string],
+  'tags' : Array<string>,
+  'pay_id' : string,
+}
+export type UpdateMessageErr = { 'AccountNotFound' : null } |
+  { 'MessageNotFound' : null } |
+  { 'ChatNotFound' : null } |
+  { 'NotAParticipant' : null } |
+  { 'InvalidReaction' : null } |
+  { 'NotMessageSender' : null } |
+  { 'MessageDeleted' : null } |
+  { 'ChatEncrypted' : null };
 export interface User {
   'my_chats' : Array<[bigint, string]>,
   'name' : string,
@@ -174,27 +1042,228 @@ export interface UserSignUpArgs {
   'profile_pic' : string,
   'pay_id' : string,
 }
+export type WebPushError = { 'NotAController' : null } |
+  { 'AccountNotFound' : null } |
+  { 'SubscriptionNotFound' : null } |
+  { 'InterCanisterCall' : string } |
+  { 'NotPermitted' : null } |
+  { 'KeyGeneration' : null } |
+  { 'KeyEncryption' : null } |
+  { 'InvalidSubject' : null } |
+  { 'InvalidKeys' : null } |
+  { 'RateLimited' : { 'retry_at' : bigint } } |
+  { 'NotConfigured' : null } |
+  { 'InvalidEndpoint' : null } |
+  { 'TooManySubscriptions' : { 'max' : bigint } };
+export interface WebhookCreated { 'secret' : string, 'webhook' : WebhookView }
+export type WebhookError = { 'AccountNotFound' : null } |
+  { 'InterCanisterCall' : string } |
+  { 'NoEvents' : null } |
+  { 'NotPermitted' : null } |
+  { 'InvalidUrl' : null } |
+  { 'RateLimited' : { 'retry_at' : bigint } } |
+  { 'TooManyWebhooks' : { 'max' : bigint } } |
+  { 'WebhookNotFound' : null } |
+  { 'TooManyPending' : { 'max' : bigint } };
+export type WebhookEvent = { 'Refund' : null } |
+  { 'Ping' : null } |
+  { 'CheckoutPaid' : null } |
+  { 'PaymentRecorded' : null };
+export interface WebhookView {
+  'id' : bigint,
+  'url' : string,
+  'created_at' : bigint,
+  'events' : Array<WebhookEvent>,
+}
 export interface _SERVICE {
+  'add_encrypted_message' : ActorMethod<
+    [string, Uint8Array | number[], [] | [bigint]],
+    Result
+  >,
+  'add_member' : ActorMethod<[Principal, Role, [] | [Principal]], Result_1>,
   'add_message' : ActorMethod<[string, string], Result>,
-  'create_chat' : ActorMethod<[PayIdOrPrincipal], Result_1>,
-  'fetch_data' : ActorMethod<[], Result_2>,
-  'fetch_initial_data' : ActorMethod<[], Result_2>,
+  'add_push_subscription' : ActorMethod<
+    [AddPushSubscriptionArg, [] | [Principal]],
+    Result_2
+  >,
+  'add_webhook' : ActorMethod<
+    [string, Array<WebhookEvent>, [] | [Principal]],
+    Result_3
+  >,
+  'allow_payment_requester' : ActorMethod<[Principal, string], Result_4>,
+  'block_account' : ActorMethod<[PayIdOrPrincipal, [] | [Principal]], Result_5>,
+  'cancel_escrow' : ActorMethod<[bigint], Result_6>,
+  'cancel_mandate' : ActorMethod<[bigint, [] | [Principal]], Result_7>,
+  'confirm_escrow_funding' : ActorMethod<[bigint], Result_6>,
+  'create_chat' : ActorMethod<[PayIdOrPrincipal], Result_8>,
+  'create_checkout_session' : ActorMethod<
+    [bigint, string, [] | [bigint], [] | [Principal]],
+    Result_9
+  >,
+  'create_escrow' : ActorMethod<[CreateEscrowArg], Result_6>,
+  'create_mandate' : ActorMethod<[CreateMandateArg], Result_7>,
+  'create_payment_link' : ActorMethod<
+    [CreatePaymentLinkArg, [] | [Principal]],
+    Result_10
+  >,
+  'decide_send_approval' : ActorMethod<[bigint, boolean], Result_11>,
+  'decline_app_payment_request' : ActorMethod<[bigint], Result_12>,
+  'delete_message' : ActorMethod<[string, bigint], Result_13>,
+  'disable_payment_link' : ActorMethod<[bigint, [] | [Principal]], Result_10>,
+  'dispute_escrow' : ActorMethod<[bigint, string], Result_6>,
+  'edit_message' : ActorMethod<[string, bigint, string], Result_14>,
+  'enable_chat_encryption' : ActorMethod<[string], Result_15>,
+  'export_transactions' : ActorMethod<
+    [ExportFormat, ExportRange, bigint, [] | [Principal]],
+    Result_16
+  >,
+  'fetch_data' : ActorMethod<[], Result_17>,
+  'fetch_initial_data' : ActorMethod<[], Result_17>,
   'get_account_from_pay_id' : ActorMethod<[string], [] | [Principal]>,
-  'get_business' : ActorMethod<[], Result_3>,
+  'get_app_payment_request' : ActorMethod<[bigint], Result_12>,
+  'get_app_payment_requests' : ActorMethod<[bigint], Result_18>,
+  'get_audit_log' : ActorMethod<[bigint, [] | [Principal]], Result_19>,
+  'get_business' : ActorMethod<[[] | [Principal]], Result_20>,
+  'get_business_analytics' : ActorMethod<
+    [AnalyticsRange, AnalyticsBucket, [] | [Principal]],
+    Result_21
+  >,
   'get_chat' : ActorMethod<[string], [] | [Chat]>,
-  'get_my_chats' : ActorMethod<[], Array<Chat>>,
+  'get_chat_key_public_key' : ActorMethod<[], Result_22>,
+  'get_checkout_status' : ActorMethod<[bigint, [] | [Principal]], Result_9>,
+  'get_contact' : ActorMethod<[PayIdOrPrincipal, [] | [Principal]], Result_23>,
+  'get_contacts' : ActorMethod<[[] | [Principal]], Result_24>,
+  'get_customer' : ActorMethod<[string, [] | [Principal]], Result_25>,
+  'get_disputed_escrows' : ActorMethod<[], Result_26>,
+  'get_encrypted_chat_key' : ActorMethod<
+    [string, Uint8Array | number[]],
+    Result_22
+  >,
+  'get_escrow' : ActorMethod<[bigint], Result_6>,
+  'get_escrows' : ActorMethod<[], Result_26>,
+  'get_ledger_account' : ActorMethod<[string], [] | [Account]>,
+  'get_link_payments' : ActorMethod<
+    [bigint, bigint, [] | [Principal]],
+    Result_27
+  >,
+  'get_mandates' : ActorMethod<[[] | [Principal]], Result_28>,
+  'get_membership' : ActorMethod<[], [] | [Membership]>,
+  'get_muted_notifications' : ActorMethod<[[] | [Principal]], Result_29>,
+  'get_my_chats' : ActorMethod<[], Array<MyChat>>,
   'get_new_business_transactions' : ActorMethod<
-    [bigint],
+    [bigint, [] | [Principal]],
     Array<TransactionEntry>
   >,
+  'get_notifications' : ActorMethod<
+    [[] | [bigint], [] | [Principal]],
+    Result_30
+  >,
+  'get_payment_link' : ActorMethod<[bigint], [] | [PaymentLinkView]>,
+  'get_policy_log' : ActorMethod<[bigint, [] | [Principal]], Result_31>,
+  'get_preferred_currency' : ActorMethod<[[] | [Principal]], Result_32>,
+  'get_push_subscriptions' : ActorMethod<[[] | [Principal]], Result_33>,
+  'get_safety_settings' : ActorMethod<[[] | [Principal]], Result_34>,
+  'get_send_approvals' : ActorMethod<[[] | [Principal]], Result_35>,
+  'get_spending_policy' : ActorMethod<[[] | [Principal]], Result_36>,
+  'get_unread_count' : ActorMethod<[], bigint>,
   'get_user' : ActorMethod<[], [] | [User]>,
+  'get_vapid_public_key' : ActorMethod<[], [] | [string]>,
+  'get_webhook_deliveries' : ActorMethod<[bigint, [] | [Principal]], Result_37>,
+  'import_recent_contacts' : ActorMethod<
+    [[] | [bigint], [] | [Principal]],
+    Result_24
+  >,
   'is_pay_id_available' : ActorMethod<[string], boolean>,
-  'mark_message_read' : ActorMethod<[string], Result_4>,
-  'payment_request_message' : ActorMethod<[ReqPayArg], Result_5>,
-  'record_request_payment' : ActorMethod<[RecordReqPayArg], Result_6>,
-  'record_xfer_transaction' : ActorMethod<[bigint, [] | [string]], Result_7>,
-  'sign_up' : ActorMethod<[SignUpArg], Result_8>,
-  'user_add_business' : ActorMethod<[PayIdOrPrincipal], Result_9>,
+  'list_businesses' : ActorMethod<
+    [[] | [BusinessCategory], [] | [string], bigint],
+    BusinessDirectoryPage
+  >,
+  'list_customers' : ActorMethod<
+    [[] | [string], bigint, [] | [Principal]],
+    Result_38
+  >,
+  'list_members' : ActorMethod<[[] | [Principal]], Result_39>,
+  'list_payment_links' : ActorMethod<[[] | [Principal]], Result_40>,
+  'list_payment_requesters' : ActorMethod<[], Array<AllowedApp>>,
+  'list_webhooks' : ActorMethod<[[] | [Principal]], Result_41>,
+  'lookup_pay_id' : ActorMethod<[string], [] | [PayIdProfile]>,
+  'mark_escrow_delivered' : ActorMethod<[bigint], Result_6>,
+  'mark_message_read' : ActorMethod<[string], Result_42>,
+  'mark_notifications_read' : ActorMethod<
+    [[] | [bigint], [] | [Principal]],
+    Result_43
+  >,
+  'payment_request_message' : ActorMethod<[ReqPayArg], Result_44>,
+  'react_to_message' : ActorMethod<[string, bigint, [] | [string]], Result_14>,
+  'rebuild_business_analytics' : ActorMethod<[[] | [Principal]], Result_45>,
+  'rebuild_customers' : ActorMethod<[[] | [Principal]], Result_46>,
+  'rebuild_search_index' : ActorMethod<[[] | [Principal]], Result_47>,
+  'record_refund' : ActorMethod<[bigint, bigint, [] | [string]], Result_48>,
+  'record_request_payment' : ActorMethod<[RecordReqPayArg], Result_49>,
+  'record_xfer_transaction' : ActorMethod<[bigint, [] | [string]], Result_50>,
+  'refund_escrow' : ActorMethod<[bigint], Result_6>,
+  'release_escrow' : ActorMethod<[bigint], Result_6>,
+  'remove_contact' : ActorMethod<
+    [PayIdOrPrincipal, [] | [Principal]],
+    Result_51
+  >,
+  'remove_member' : ActorMethod<[Principal, [] | [Principal]], Result_52>,
+  'remove_payment_requester' : ActorMethod<[Principal], Result_4>,
+  'remove_push_subscription' : ActorMethod<
+    [bigint, [] | [Principal]],
+    Result_53
+  >,
+  'remove_webhook' : ActorMethod<[bigint, [] | [Principal]], Result_54>,
+  'reply_to_message' : ActorMethod<[string, bigint, string], Result>,
+  'request_payment_from' : ActorMethod<
+    [string, bigint, [] | [string], string],
+    Result_12
+  >,
+  'resolve_escrow' : ActorMethod<[bigint, EscrowResolution], Result_6>,
+  'search' : ActorMethod<[SearchArgs, [] | [Principal]], Result_55>,
+  'send_test_push' : ActorMethod<[[] | [Principal]], Result_53>,
+  'send_test_webhook' : ActorMethod<[bigint, [] | [Principal]], Result_56>,
+  'set_directory_visibility' : ActorMethod<
+    [boolean, [] | [Principal]],
+    Result_57
+  >,
+  'set_escrow_arbiter' : ActorMethod<[Principal], Result_58>,
+  'set_exchange_rate_canister' : ActorMethod<[Principal], Result_59>,
+  'set_muted_notifications' : ActorMethod<
+    [Array<NotificationKind>, [] | [Principal]],
+    Result_29
+  >,
+  'set_only_contacts_can_request' : ActorMethod<
+    [boolean, [] | [Principal]],
+    Result_5
+  >,
+  'set_preferred_currency' : ActorMethod<[string, [] | [Principal]], Result_59>,
+  'set_spending_policy' : ActorMethod<
+    [SpendingPolicyArg, [] | [Principal]],
+    Result_60
+  >,
+  'set_verified' : ActorMethod<[string, boolean], Result_61>,
+  'setup_web_push' : ActorMethod<[string], Result_62>,
+  'sign_up' : ActorMethod<[SignUpArg], Result_63>,
+  'sync' : ActorMethod<[bigint, [] | [Principal]], Result_64>,
+  'transform_webhook_response' : ActorMethod<[TransformArgs], HttpResponse>,
+  'unblock_account' : ActorMethod<
+    [PayIdOrPrincipal, [] | [Principal]],
+    Result_5
+  >,
+  'update_contact' : ActorMethod<
+    [UpdateContactArg, [] | [Principal]],
+    Result_23
+  >,
+  'update_customer' : ActorMethod<
+    [UpdateCustomerArg, [] | [Principal]],
+    Result_25
+  >,
+  'update_member_role' : ActorMethod<
+    [Principal, Role, [] | [Principal]],
+    Result_1
+  >,
+  'user_add_business' : ActorMethod<[PayIdOrPrincipal], Result_65>,
 }
 export declare const idlFactory: IDL.InterfaceFactory;
 export declare const init: (args: { IDL: typeof IDL }) => IDL.Type[];
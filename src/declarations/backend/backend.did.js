@@ -1,20 +1,291 @@
 export const idlFactory = ({ IDL }) => {
+  const MessageEdit = IDL.Record({
+    'content' : IDL.Text,
+    'edited_at' : IDL.Nat64,
+  });
+  const Reaction = IDL.Record({ 'emoji' : IDL.Text, 'pay_id' : IDL.Text });
   const Message = IDL.Record({
     'read_by' : IDL.Vec(IDL.Text),
     'content' : IDL.Text,
+    'ciphertext' : IDL.Opt(IDL.Vec(IDL.Nat8)),
+    'reply_to' : IDL.Opt(IDL.Nat64),
+    'edit_history' : IDL.Opt(IDL.Vec(MessageEdit)),
     'sender_id' : IDL.Text,
     'timestamp' : IDL.Nat64,
+    'deleted_at' : IDL.Opt(IDL.Nat64),
+    'reactions' : IDL.Opt(IDL.Vec(Reaction)),
   });
   const AddMessageErr = IDL.Variant({
+    'Blocked' : IDL.Null,
+    'ChatNotEncrypted' : IDL.Null,
     'AccountNotFound' : IDL.Null,
+    'InvalidCiphertext' : IDL.Null,
     'ChatNotFound' : IDL.Null,
     'NotAParticipant' : IDL.Null,
+    'ReplyToNotFound' : IDL.Null,
+    'ChatEncrypted' : IDL.Null,
   });
   const Result = IDL.Variant({ 'Ok' : Message, 'Err' : AddMessageErr });
+  const Role = IDL.Variant({
+    'Viewer' : IDL.Null,
+    'Cashier' : IDL.Null,
+    'Admin' : IDL.Null,
+    'Owner' : IDL.Null,
+  });
+  const Member = IDL.Record({
+    'principal' : IDL.Principal,
+    'role' : Role,
+    'added_at' : IDL.Nat64,
+    'added_by' : IDL.Principal,
+  });
+  const StaffError = IDL.Variant({
+    'AlreadyAMember' : IDL.Null,
+    'AccountNotFound' : IDL.Null,
+    'IsABusiness' : IDL.Null,
+    'NotPermitted' : IDL.Null,
+    'AnonymousPrincipal' : IDL.Null,
+    'MemberNotFound' : IDL.Null,
+    'TooManyMembers' : IDL.Record({ 'max' : IDL.Nat64 }),
+  });
+  const Result_1 = IDL.Variant({ 'Ok' : Member, 'Err' : StaffError });
+  const AddPushSubscriptionArg = IDL.Record({
+    'endpoint' : IDL.Text,
+    'auth' : IDL.Text,
+    'p256dh' : IDL.Text,
+  });
+  const PushSubscriptionView = IDL.Record({
+    'id' : IDL.Nat64,
+    'endpoint' : IDL.Text,
+    'created_at' : IDL.Nat64,
+  });
+  const WebPushError = IDL.Variant({
+    'NotAController' : IDL.Null,
+    'AccountNotFound' : IDL.Null,
+    'SubscriptionNotFound' : IDL.Null,
+    'InterCanisterCall' : IDL.Text,
+    'NotPermitted' : IDL.Null,
+    'KeyGeneration' : IDL.Null,
+    'KeyEncryption' : IDL.Null,
+    'InvalidSubject' : IDL.Null,
+    'InvalidKeys' : IDL.Null,
+    'RateLimited' : IDL.Record({ 'retry_at' : IDL.Nat64 }),
+    'NotConfigured' : IDL.Null,
+    'InvalidEndpoint' : IDL.Null,
+    'TooManySubscriptions' : IDL.Record({ 'max' : IDL.Nat64 }),
+  });
+  const Result_2 = IDL.Variant({
+    'Ok' : PushSubscriptionView,
+    'Err' : WebPushError,
+  });
+  const WebhookEvent = IDL.Variant({
+    'Refund' : IDL.Null,
+    'Ping' : IDL.Null,
+    'CheckoutPaid' : IDL.Null,
+    'PaymentRecorded' : IDL.Null,
+  });
+  const WebhookView = IDL.Record({
+    'id' : IDL.Nat64,
+    'url' : IDL.Text,
+    'created_at' : IDL.Nat64,
+    'events' : IDL.Vec(WebhookEvent),
+  });
+  const WebhookCreated = IDL.Record({
+    'secret' : IDL.Text,
+    'webhook' : WebhookView,
+  });
+  const WebhookError = IDL.Variant({
+    'AccountNotFound' : IDL.Null,
+    'InterCanisterCall' : IDL.Text,
+    'NoEvents' : IDL.Null,
+    'NotPermitted' : IDL.Null,
+    'InvalidUrl' : IDL.Null,
+    'RateLimited' : IDL.Record({ 'retry_at' : IDL.Nat64 }),
+    'TooManyWebhooks' : IDL.Record({ 'max' : IDL.Nat64 }),
+    'WebhookNotFound' : IDL.Null,
+    'TooManyPending' : IDL.Record({ 'max' : IDL.Nat64 }),
+  });
+  const Result_3 = IDL.Variant({ 'Ok' : WebhookCreated, 'Err' : WebhookError });
+  const AppRequestError = IDL.Variant({
+    'NotAController' : IDL.Null,
+    'Blocked' : IDL.Null,
+    'AccountNotFound' : IDL.Null,
+    'InvalidAmount' : IDL.Null,
+    'InvalidCallback' : IDL.Null,
+    'NotAllowed' : IDL.Null,
+    'MemoTooLong' : IDL.Record({ 'max' : IDL.Nat64 }),
+    'InvalidName' : IDL.Record({ 'max' : IDL.Nat64 }),
+    'RequestNotFound' : IDL.Null,
+    'TooManyPending' : IDL.Record({ 'max' : IDL.Nat64 }),
+    'NotPending' : IDL.Null,
+  });
+  const Result_4 = IDL.Variant({ 'Ok' : IDL.Null, 'Err' : AppRequestError });
   const PayIdOrPrincipal = IDL.Variant({
     'PayId' : IDL.Text,
     'Principal' : IDL.Principal,
   });
+  const BlockError = IDL.Variant({
+    'NotBlocked' : IDL.Null,
+    'AccountNotFound' : IDL.Null,
+    'PayIdNotFound' : IDL.Null,
+    'NotPermitted' : IDL.Null,
+    'CannotBlockSelf' : IDL.Null,
+  });
+  const Result_5 = IDL.Variant({ 'Ok' : IDL.Null, 'Err' : BlockError });
+  const EscrowStatus = IDL.Variant({
+    'Disputed' : IDL.Null,
+    'Refunded' : IDL.Record({ 'tx_id' : IDL.Nat }),
+    'Released' : IDL.Record({ 'tx_id' : IDL.Nat }),
+    'Delivered' : IDL.Null,
+    'Settling' : IDL.Null,
+    'Funded' : IDL.Null,
+    'AwaitingFunds' : IDL.Null,
+    'Cancelled' : IDL.Null,
+  });
+  const Account = IDL.Record({
+    'owner' : IDL.Principal,
+    'subaccount' : IDL.Opt(IDL.Vec(IDL.Nat8)),
+  });
+  const Dispute = IDL.Record({
+    'by' : IDL.Principal,
+    'opened_at' : IDL.Nat64,
+    'reason' : IDL.Text,
+  });
+  const PendingPayout = IDL.Record({
+    'to' : IDL.Principal,
+    'fee' : IDL.Nat,
+    'created_at_time' : IDL.Nat64,
+    'amount' : IDL.Nat,
+    'lookup_from' : IDL.Nat64,
+  });
+  const Escrow = IDL.Record({
+    'id' : IDL.Nat64,
+    'status' : EscrowStatus,
+    'deposit_account' : Account,
+    'note' : IDL.Opt(IDL.Text),
+    'deposit_amount' : IDL.Nat,
+    'deadline' : IDL.Opt(IDL.Nat64),
+    'created_at' : IDL.Nat64,
+    'funded_at' : IDL.Opt(IDL.Nat64),
+    'payment_uri' : IDL.Text,
+    'dispute' : IDL.Opt(Dispute),
+    'payee' : IDL.Principal,
+    'payer' : IDL.Principal,
+    'chat_id' : IDL.Text,
+    'amount' : IDL.Nat,
+    'timeout' : IDL.Nat64,
+    'delivered_at' : IDL.Opt(IDL.Nat64),
+    'payout' : IDL.Opt(PendingPayout),
+    'timed_out' : IDL.Bool,
+  });
+  const PolicyError = IDL.Variant({
+    'MonthlyLimit' : IDL.Record({ 'cap' : IDL.Nat, 'spent' : IDL.Nat }),
+    'ApprovalRejected' : IDL.Record({ 'approval_id' : IDL.Nat64 }),
+    'ApprovalRequired' : IDL.Record({ 'approval_id' : IDL.Nat64 }),
+    'ApprovalPending' : IDL.Record({ 'approval_id' : IDL.Nat64 }),
+    'PerTransactionLimit' : IDL.Record({ 'max' : IDL.Nat }),
+    'DailyLimit' : IDL.Record({ 'cap' : IDL.Nat, 'spent' : IDL.Nat }),
+  });
+  const EscrowError = IDL.Variant({
+    'NotAController' : IDL.Null,
+    'AccountNotFound' : IDL.Null,
+    'InvalidAmount' : IDL.Null,
+    'PayeeNotFound' : IDL.Null,
+    'Policy' : PolicyError,
+    'InterCanisterCall' : IDL.Text,
+    'InvalidTimeout' : IDL.Record({ 'max' : IDL.Nat64, 'min' : IDL.Nat64 }),
+    'PayoutLookup' : IDL.Null,
+    'NotPermitted' : IDL.Null,
+    'ChatNotFound' : IDL.Null,
+    'NotAParticipant' : IDL.Null,
+    'EscrowNotFound' : IDL.Null,
+    'NotFunded' : IDL.Record({ 'balance' : IDL.Nat }),
+    'ReasonTooLong' : IDL.Record({ 'max' : IDL.Nat64 }),
+    'TransferFailed' : IDL.Text,
+    'NoteTooLong' : IDL.Record({ 'max' : IDL.Nat64 }),
+    'InvalidState' : IDL.Null,
+  });
+  const Result_6 = IDL.Variant({ 'Ok' : Escrow, 'Err' : EscrowError });
+  const MandateStatus = IDL.Variant({
+    'Active' : IDL.Null,
+    'Cancelled' : IDL.Record({ 'at' : IDL.Nat64, 'by' : IDL.Principal }),
+    'Completed' : IDL.Null,
+  });
+  const RecordTxErr = IDL.Variant({
+    'AlreadyRecorded' : IDL.Null,
+    'AccountNotFound' : IDL.Principal,
+    'InterCanisterCall' : IDL.Text,
+    'InvalidTransaction' : IDL.Text,
+    'BothAccountsNotFound' : IDL.Record({
+      'to' : IDL.Principal,
+      'from' : IDL.Principal,
+    }),
+  });
+  const MandateEventKind = IDL.Variant({
+    'Skipped' : IDL.Null,
+    'Blocked' : IDL.Record({ 'error' : PolicyError }),
+    'Failed' : IDL.Record({ 'attempt' : IDL.Nat32, 'reason' : IDL.Text }),
+    'Paid' : IDL.Record({ 'tx_id' : IDL.Nat }),
+    'NotRecorded' : IDL.Record({ 'tx_id' : IDL.Nat, 'error' : RecordTxErr }),
+    'Cancelled' : IDL.Null,
+    'Completed' : IDL.Null,
+  });
+  const MandateEvent = IDL.Record({
+    'run_at' : IDL.Nat64,
+    'kind' : MandateEventKind,
+    'timestamp' : IDL.Nat64,
+  });
+  const Mandate = IDL.Record({
+    'id' : IDL.Nat64,
+    'status' : MandateStatus,
+    'interval' : IDL.Nat64,
+    'payments' : IDL.Nat64,
+    'retry_at' : IDL.Opt(IDL.Nat64),
+    'ends_at' : IDL.Nat64,
+    'note' : IDL.Opt(IDL.Text),
+    'attempts' : IDL.Nat32,
+    'created_at' : IDL.Nat64,
+    'events' : IDL.Vec(MandateEvent),
+    'payee_pay_id' : IDL.Text,
+    'next_run_at' : IDL.Nat64,
+    'payee' : IDL.Principal,
+    'payer' : IDL.Principal,
+    'amount' : IDL.Nat,
+  });
+  const MandateError = IDL.Variant({
+    'AccountNotFound' : IDL.Null,
+    'InvalidAmount' : IDL.Null,
+    'PayeeNotFound' : IDL.Null,
+    'NotActive' : IDL.Null,
+    'CannotPaySelf' : IDL.Null,
+    'InterCanisterCall' : IDL.Text,
+    'TooManyMandates' : IDL.Record({ 'max' : IDL.Nat64 }),
+    'InvalidStartDate' : IDL.Null,
+    'NotPermitted' : IDL.Null,
+    'InvalidEndDate' : IDL.Null,
+    'AllowanceTooLow' : IDL.Record({ 'allowance' : IDL.Nat }),
+    'InvalidInterval' : IDL.Record({ 'max' : IDL.Nat64, 'min' : IDL.Nat64 }),
+    'MandateNotFound' : IDL.Null,
+    'NoteTooLong' : IDL.Record({ 'max' : IDL.Nat64 }),
+  });
+  const Result_7 = IDL.Variant({ 'Ok' : Mandate, 'Err' : MandateError });
+  const EscrowEvent = IDL.Variant({
+    'Disputed' : IDL.Record({ 'reason' : IDL.Text }),
+    'Refunded' : IDL.Record({ 'tx_id' : IDL.Nat }),
+    'Released' : IDL.Record({ 'tx_id' : IDL.Nat }),
+    'Delivered' : IDL.Null,
+    'Funded' : IDL.Null,
+    'Cancelled' : IDL.Null,
+    'TimedOut' : IDL.Null,
+    'Created' : IDL.Null,
+  });
+  const EscrowMessage = IDL.Record({
+    'read_by' : IDL.Vec(IDL.Text),
+    'event' : EscrowEvent,
+    'sender_id' : IDL.Text,
+    'timestamp' : IDL.Nat64,
+    'escrow_id' : IDL.Nat64,
+    'amount' : IDL.Nat,
+  });
   const RequestPayment = IDL.Record({
     'read_by' : IDL.Vec(IDL.Text),
     'tx_id' : IDL.Opt(IDL.Nat),
@@ -34,6 +305,7 @@ export const idlFactory = ({ IDL }) => {
     'amount' : IDL.Nat,
   });
   const MessageOrTransaction = IDL.Variant({
+    'Escrow' : EscrowMessage,
     'RequestPayment' : RequestPayment,
     'Transaction' : Transaction,
     'Message' : Message,
@@ -42,14 +314,228 @@ export const idlFactory = ({ IDL }) => {
     'id' : IDL.Text,
     'participants' : IDL.Vec(IDL.Text),
     'messages' : IDL.Vec(MessageOrTransaction),
+    'encrypted' : IDL.Opt(IDL.Bool),
     'last_activity' : IDL.Nat64,
+    'last_read' : IDL.Opt(IDL.Vec(IDL.Tuple(IDL.Text, IDL.Nat64))),
   });
   const CreateChatErr = IDL.Variant({
+    'Blocked' : IDL.Null,
     'AccountNotFound' : IDL.Null,
     'ParticipantNotFound' : IDL.Null,
     'CallerAndParticipantSame' : IDL.Null,
   });
-  const Result_1 = IDL.Variant({ 'Ok' : Chat, 'Err' : CreateChatErr });
+  const Result_8 = IDL.Variant({ 'Ok' : Chat, 'Err' : CreateChatErr });
+  const CheckoutStatus = IDL.Variant({
+    'Open' : IDL.Null,
+    'Paid' : IDL.Record({
+      'tx_id' : IDL.Nat,
+      'from' : IDL.Principal,
+      'paid_at' : IDL.Nat64,
+    }),
+    'Expired' : IDL.Null,
+  });
+  const CheckoutSession = IDL.Record({
+    'id' : IDL.Nat64,
+    'status' : CheckoutStatus,
+    'memo' : IDL.Vec(IDL.Nat8),
+    'reference' : IDL.Text,
+    'created_at' : IDL.Nat64,
+    'created_by' : IDL.Principal,
+    'business' : IDL.Principal,
+    'payment_uri' : IDL.Text,
+    'amount' : IDL.Nat,
+    'expires_at' : IDL.Nat64,
+    'start_block' : IDL.Nat64,
+  });
+  const CheckoutError = IDL.Variant({
+    'AccountNotFound' : IDL.Null,
+    'InvalidAmount' : IDL.Null,
+    'InterCanisterCall' : IDL.Text,
+    'SessionNotFound' : IDL.Null,
+    'NotPermitted' : IDL.Null,
+    'InvalidTtl' : IDL.Record({ 'max' : IDL.Nat64 }),
+    'ReferenceTooLong' : IDL.Record({ 'max' : IDL.Nat64 }),
+  });
+  const Result_9 = IDL.Variant({
+    'Ok' : CheckoutSession,
+    'Err' : CheckoutError,
+  });
+  const CreateEscrowArg = IDL.Record({
+    'note' : IDL.Opt(IDL.Text),
+    'chat_id' : IDL.Text,
+    'amount' : IDL.Nat,
+    'timeout' : IDL.Opt(IDL.Nat64),
+  });
+  const CreateMandateArg = IDL.Record({
+    'starts_at' : IDL.Opt(IDL.Nat64),
+    'interval' : IDL.Nat64,
+    'ends_at' : IDL.Nat64,
+    'note' : IDL.Opt(IDL.Text),
+    'payee' : IDL.Text,
+    'amount' : IDL.Nat,
+  });
+  const CreatePaymentLinkArg = IDL.Record({
+    'title' : IDL.Text,
+    'single_use' : IDL.Bool,
+    'amount' : IDL.Opt(IDL.Nat),
+    'expires_at' : IDL.Opt(IDL.Nat64),
+  });
+  const PaymentLink = IDL.Record({
+    'id' : IDL.Nat64,
+    'title' : IDL.Text,
+    'payment_count' : IDL.Nat64,
+    'disabled_at' : IDL.Opt(IDL.Nat64),
+    'owner' : IDL.Principal,
+    'total_received' : IDL.Nat,
+    'created_at' : IDL.Nat64,
+    'created_by' : IDL.Principal,
+    'last_paid_at' : IDL.Opt(IDL.Nat64),
+    'single_use' : IDL.Bool,
+    'amount' : IDL.Opt(IDL.Nat),
+    'expires_at' : IDL.Opt(IDL.Nat64),
+  });
+  const LinkStatus = IDL.Variant({
+    'Paid' : IDL.Null,
+    'Disabled' : IDL.Null,
+    'PaidOpen' : IDL.Null,
+    'Unpaid' : IDL.Null,
+    'Expired' : IDL.Null,
+  });
+  const PaymentLinkView = IDL.Record({
+    'id' : IDL.Nat64,
+    'status' : LinkStatus,
+    'title' : IDL.Text,
+    'owner_pay_id' : IDL.Text,
+    'payment_uri' : IDL.Text,
+    'single_use' : IDL.Bool,
+    'amount' : IDL.Opt(IDL.Nat),
+    'expires_at' : IDL.Opt(IDL.Nat64),
+    'owner_name' : IDL.Text,
+  });
+  const PaymentLinkDetails = IDL.Record({
+    'link' : PaymentLink,
+    'view' : PaymentLinkView,
+  });
+  const PaymentLinkError = IDL.Variant({
+    'InvalidExpiry' : IDL.Null,
+    'AccountNotFound' : IDL.Null,
+    'InvalidAmount' : IDL.Null,
+    'TooManyLinks' : IDL.Record({ 'max' : IDL.Nat64 }),
+    'TitleTooLong' : IDL.Record({ 'max' : IDL.Nat64 }),
+    'NotPermitted' : IDL.Null,
+    'LinkNotFound' : IDL.Null,
+  });
+  const Result_10 = IDL.Variant({
+    'Ok' : PaymentLinkDetails,
+    'Err' : PaymentLinkError,
+  });
+  const ApprovalStatus = IDL.Variant({
+    'Used' : IDL.Record({ 'at' : IDL.Nat64 }),
+    'Approved' : IDL.Record({ 'at' : IDL.Nat64 }),
+    'Rejected' : IDL.Record({ 'at' : IDL.Nat64 }),
+    'Pending' : IDL.Null,
+  });
+  const PolicySend = IDL.Variant({
+    'EscrowRelease' : IDL.Record({ 'escrow_id' : IDL.Nat64 }),
+    'MandatePayment' : IDL.Record({
+      'run_at' : IDL.Nat64,
+      'mandate_id' : IDL.Nat64,
+    }),
+  });
+  const SendApproval = IDL.Record({
+    'id' : IDL.Nat64,
+    'to' : IDL.Principal,
+    'status' : ApprovalStatus,
+    'send' : PolicySend,
+    'created_at' : IDL.Nat64,
+    'approver' : IDL.Principal,
+    'account' : IDL.Principal,
+    'amount' : IDL.Nat,
+    'expires_at' : IDL.Nat64,
+  });
+  const SpendingPolicyError = IDL.Variant({
+    'ApproverNotFound' : IDL.Null,
+    'AccountNotFound' : IDL.Null,
+    'ApprovalNotFound' : IDL.Null,
+    'ApproverRequired' : IDL.Null,
+    'InvalidApprover' : IDL.Null,
+    'NotPermitted' : IDL.Null,
+    'AlreadyDecided' : IDL.Null,
+  });
+  const Result_11 = IDL.Variant({
+    'Ok' : SendApproval,
+    'Err' : SpendingPolicyError,
+  });
+  const AppRequestStatus = IDL.Variant({
+    'Paid' : IDL.Record({ 'tx_id' : IDL.Nat, 'paid_at' : IDL.Nat64 }),
+    'Declined' : IDL.Record({ 'declined_at' : IDL.Nat64 }),
+    'Expired' : IDL.Null,
+    'Pending' : IDL.Null,
+  });
+  const AppPaymentRequest = IDL.Record({
+    'id' : IDL.Nat64,
+    'app' : IDL.Principal,
+    'status' : AppRequestStatus,
+    'payment_memo' : IDL.Vec(IDL.Nat8),
+    'memo' : IDL.Opt(IDL.Text),
+    'callback_error' : IDL.Opt(IDL.Text),
+    'user' : IDL.Principal,
+    'requested_at' : IDL.Nat64,
+    'callback' : IDL.Text,
+    'app_name' : IDL.Text,
+    'payment_uri' : IDL.Text,
+    'amount' : IDL.Nat,
+    'expires_at' : IDL.Nat64,
+    'pay_id' : IDL.Text,
+  });
+  const Result_12 = IDL.Variant({
+    'Ok' : AppPaymentRequest,
+    'Err' : AppRequestError,
+  });
+  const UpdateMessageErr = IDL.Variant({
+    'AccountNotFound' : IDL.Null,
+    'MessageNotFound' : IDL.Null,
+    'ChatNotFound' : IDL.Null,
+    'NotAParticipant' : IDL.Null,
+    'InvalidReaction' : IDL.Null,
+    'NotMessageSender' : IDL.Null,
+    'MessageDeleted' : IDL.Null,
+    'ChatEncrypted' : IDL.Null,
+  });
+  const Result_13 = IDL.Variant({ 'Ok' : IDL.Null, 'Err' : UpdateMessageErr });
+  const Result_14 = IDL.Variant({ 'Ok' : Message, 'Err' : UpdateMessageErr });
+  const ChatEncryptionError = IDL.Variant({
+    'ChatNotEncrypted' : IDL.Null,
+    'AccountNotFound' : IDL.Null,
+    'InterCanisterCall' : IDL.Text,
+    'ChatNotFound' : IDL.Null,
+    'NotAParticipant' : IDL.Null,
+    'RateLimited' : IDL.Record({ 'retry_at' : IDL.Nat64 }),
+    'InvalidTransportKey' : IDL.Null,
+  });
+  const Result_15 = IDL.Variant({ 'Ok' : Chat, 'Err' : ChatEncryptionError });
+  const ExportFormat = IDL.Variant({
+    'Csv' : IDL.Null,
+    'Ofx' : IDL.Null,
+    'Json' : IDL.Null,
+  });
+  const ExportRange = IDL.Record({
+    'to' : IDL.Opt(IDL.Nat64),
+    'from' : IDL.Opt(IDL.Nat64),
+  });
+  const ExportChunk = IDL.Record({
+    'total_entries' : IDL.Nat64,
+    'data' : IDL.Text,
+    'next_chunk' : IDL.Opt(IDL.Nat64),
+  });
+  const ExportError = IDL.Variant({
+    'AccountNotFound' : IDL.Null,
+    'InvalidRange' : IDL.Null,
+    'NotPermitted' : IDL.Null,
+    'InvalidChunk' : IDL.Null,
+    'ChunkOutOfRange' : IDL.Null,
+  });
+  const Result_16 = IDL.Variant({ 'Ok' : ExportChunk, 'Err' : ExportError });
   const User = IDL.Record({
     'my_chats' : IDL.Vec(IDL.Tuple(IDL.Nat64, IDL.Text)),
     'name' : IDL.Text,
@@ -58,15 +544,38 @@ export const idlFactory = ({ IDL }) => {
     'profile_pic' : IDL.Text,
     'pay_id' : IDL.Text,
   });
-  const TxKind = IDL.Variant({ 'Sends' : IDL.Null, 'Receive' : IDL.Null });
+  const FiatValue = IDL.Record({
+    'decimals' : IDL.Nat32,
+    'rate' : IDL.Nat64,
+    'currency' : IDL.Text,
+    'timestamp' : IDL.Nat64,
+  });
+  const TxKind = IDL.Variant({
+    'Sends' : IDL.Null,
+    'Approve' : IDL.Record({ 'expires_at' : IDL.Opt(IDL.Nat64) }),
+    'Burn' : IDL.Null,
+    'Mint' : IDL.Null,
+    'Allowance' : IDL.Record({ 'expires_at' : IDL.Opt(IDL.Nat64) }),
+    'ReceiveRefund' : IDL.Record({ 'original_tx_id' : IDL.Nat }),
+    'SendsRefund' : IDL.Record({ 'original_tx_id' : IDL.Nat }),
+    'Receive' : IDL.Null,
+  });
+  const RefundState = IDL.Record({
+    'refunded' : IDL.Nat,
+    'refund_tx_ids' : IDL.Vec(IDL.Nat),
+    'refundable' : IDL.Nat,
+  });
   const TransactionEntry = IDL.Record({
+    'fee' : IDL.Opt(IDL.Nat),
     'tx_id' : IDL.Nat,
+    'fiat' : IDL.Opt(FiatValue),
     'kind' : TxKind,
     'name' : IDL.Text,
     'note' : IDL.Opt(IDL.Text),
     'timestamp' : IDL.Nat64,
     'amount' : IDL.Nat,
     'pay_id' : IDL.Text,
+    'refund' : IDL.Opt(RefundState),
   });
   const BusinessCategory = IDL.Variant({
     'Healthcare' : IDL.Null,
@@ -117,27 +626,446 @@ export const idlFactory = ({ IDL }) => {
     'transactions' : IDL.Vec(TransactionEntry),
     'pay_id' : IDL.Text,
   });
+  const BusinessProfile = IDL.Record({
+    'principal' : IDL.Principal,
+    'subaccount' : IDL.Opt(IDL.Vec(IDL.Nat8)),
+    'business' : Business,
+  });
+  const ProfilesData = IDL.Record({
+    'businesses' : IDL.Vec(BusinessProfile),
+    'user' : IDL.Opt(UserData),
+    'user_subaccount' : IDL.Opt(IDL.Vec(IDL.Nat8)),
+  });
   const DataResponse = IDL.Variant({
     'User' : UserData,
     'Business' : Business,
+    'Profiles' : ProfilesData,
     'NotSignUp' : IDL.Null,
   });
   const FetchInitDataError = IDL.Variant({ 'AnonymousCaller' : IDL.Null });
-  const Result_2 = IDL.Variant({
+  const Result_17 = IDL.Variant({
     'Ok' : DataResponse,
     'Err' : FetchInitDataError,
   });
-  const GetBusinessError = IDL.Variant({ 'AccountNotFound' : IDL.Null });
-  const Result_3 = IDL.Variant({ 'Ok' : Business, 'Err' : GetBusinessError });
-  const Result_4 = IDL.Variant({ 'Ok' : IDL.Null, 'Err' : AddMessageErr });
+  const AppRequestPage = IDL.Record({
+    'next_page' : IDL.Opt(IDL.Nat64),
+    'requests' : IDL.Vec(AppPaymentRequest),
+  });
+  const Result_18 = IDL.Variant({
+    'Ok' : AppRequestPage,
+    'Err' : AppRequestError,
+  });
+  const AuditAction = IDL.Variant({
+    'MemberAdded' : IDL.Record({ 'principal' : IDL.Principal, 'role' : Role }),
+    'PaymentLinkCreated' : IDL.Record({ 'id' : IDL.Nat64 }),
+    'CheckoutSessionCreated' : IDL.Record({
+      'id' : IDL.Nat64,
+      'amount' : IDL.Nat,
+    }),
+    'MemberRoleChanged' : IDL.Record({
+      'principal' : IDL.Principal,
+      'role' : Role,
+    }),
+    'CustomersRebuilt' : IDL.Null,
+    'WebhookRemoved' : IDL.Record({ 'id' : IDL.Nat64 }),
+    'PaymentLinkDisabled' : IDL.Record({ 'id' : IDL.Nat64 }),
+    'WebhookAdded' : IDL.Record({ 'id' : IDL.Nat64, 'url' : IDL.Text }),
+    'AnalyticsRebuilt' : IDL.Null,
+    'MemberRemoved' : IDL.Record({ 'principal' : IDL.Principal }),
+    'CustomerUpdated' : IDL.Record({ 'pay_id' : IDL.Text }),
+  });
+  const AuditEntry = IDL.Record({
+    'member' : IDL.Principal,
+    'action' : AuditAction,
+    'role' : Role,
+    'timestamp' : IDL.Nat64,
+  });
+  const AuditPage = IDL.Record({
+    'next_page' : IDL.Opt(IDL.Nat64),
+    'entries' : IDL.Vec(AuditEntry),
+  });
+  const Result_19 = IDL.Variant({ 'Ok' : AuditPage, 'Err' : StaffError });
+  const GetBusinessError = IDL.Variant({
+    'AccountNotFound' : IDL.Null,
+    'NotPermitted' : IDL.Null,
+  });
+  const Result_20 = IDL.Variant({ 'Ok' : Business, 'Err' : GetBusinessError });
+  const AnalyticsRange = IDL.Record({ 'to' : IDL.Nat64, 'from' : IDL.Nat64 });
+  const AnalyticsBucket = IDL.Variant({
+    'Day' : IDL.Null,
+    'Week' : IDL.Null,
+    'Month' : IDL.Null,
+  });
+  const TopCustomer = IDL.Record({
+    'name' : IDL.Text,
+    'paid' : IDL.Nat,
+    'tx_count' : IDL.Nat64,
+    'pay_id' : IDL.Text,
+  });
+  const AnalyticsPeriod = IDL.Record({
+    'average_ticket' : IDL.Nat,
+    'revenue_out' : IDL.Nat,
+    'revenue_in' : IDL.Nat,
+    'tx_count_in' : IDL.Nat64,
+    'start' : IDL.Nat64,
+    'top_customers' : IDL.Vec(TopCustomer),
+    'unique_customers' : IDL.Nat64,
+    'tx_count_out' : IDL.Nat64,
+  });
+  const BusinessAnalytics = IDL.Record({
+    'total' : AnalyticsPeriod,
+    'buckets' : IDL.Vec(AnalyticsPeriod),
+  });
+  const AnalyticsError = IDL.Variant({
+    'RangeTooLong' : IDL.Record({ 'max_days' : IDL.Nat64 }),
+    'AccountNotFound' : IDL.Null,
+    'InvalidRange' : IDL.Null,
+    'NotPermitted' : IDL.Null,
+  });
+  const Result_21 = IDL.Variant({
+    'Ok' : BusinessAnalytics,
+    'Err' : AnalyticsError,
+  });
+  const Result_22 = IDL.Variant({
+    'Ok' : IDL.Vec(IDL.Nat8),
+    'Err' : ChatEncryptionError,
+  });
+  const Contact = IDL.Record({
+    'principal' : IDL.Principal,
+    'nickname' : IDL.Opt(IDL.Text),
+    'note' : IDL.Opt(IDL.Text),
+    'added_at' : IDL.Nat64,
+    'favorite' : IDL.Bool,
+  });
+  const ContactView = IDL.Record({
+    'contact' : Contact,
+    'name' : IDL.Text,
+    'pay_id' : IDL.Opt(IDL.Text),
+  });
+  const ContactError = IDL.Variant({
+    'AccountNotFound' : IDL.Null,
+    'PayIdNotFound' : IDL.Null,
+    'ContactNotFound' : IDL.Null,
+    'NotPermitted' : IDL.Null,
+    'NicknameTooLong' : IDL.Record({ 'max' : IDL.Nat64 }),
+    'AnonymousPrincipal' : IDL.Null,
+    'CannotAddSelf' : IDL.Null,
+    'NoteTooLong' : IDL.Record({ 'max' : IDL.Nat64 }),
+  });
+  const Result_23 = IDL.Variant({ 'Ok' : ContactView, 'Err' : ContactError });
+  const Result_24 = IDL.Variant({
+    'Ok' : IDL.Vec(ContactView),
+    'Err' : ContactError,
+  });
+  const Customer = IDL.Record({
+    'total_paid_out' : IDL.Nat,
+    'principal' : IDL.Principal,
+    'name' : IDL.Text,
+    'note' : IDL.Opt(IDL.Text),
+    'tags' : IDL.Vec(IDL.Text),
+    'total_paid' : IDL.Nat,
+    'added_business' : IDL.Bool,
+    'first_seen' : IDL.Nat64,
+    'tx_count' : IDL.Nat64,
+    'last_seen' : IDL.Nat64,
+    'pay_id' : IDL.Text,
+  });
+  const CustomerError = IDL.Variant({
+    'AccountNotFound' : IDL.Null,
+    'TagTooLong' : IDL.Record({ 'max' : IDL.Nat64 }),
+    'NotPermitted' : IDL.Null,
+    'CustomerNotFound' : IDL.Null,
+    'NoteTooLong' : IDL.Record({ 'max' : IDL.Nat64 }),
+    'TooManyTags' : IDL.Record({ 'max' : IDL.Nat64 }),
+  });
+  const Result_25 = IDL.Variant({ 'Ok' : Customer, 'Err' : CustomerError });
+  const Result_26 = IDL.Variant({
+    'Ok' : IDL.Vec(Escrow),
+    'Err' : EscrowError,
+  });
+  const LinkPayment = IDL.Record({
+    'tx_id' : IDL.Nat,
+    'from' : IDL.Principal,
+    'timestamp' : IDL.Nat64,
+    'amount' : IDL.Nat,
+  });
+  const LinkPaymentPage = IDL.Record({
+    'next_page' : IDL.Opt(IDL.Nat64),
+    'payments' : IDL.Vec(LinkPayment),
+  });
+  const Result_27 = IDL.Variant({
+    'Ok' : LinkPaymentPage,
+    'Err' : PaymentLinkError,
+  });
+  const Result_28 = IDL.Variant({
+    'Ok' : IDL.Vec(Mandate),
+    'Err' : MandateError,
+  });
+  const Membership = IDL.Record({
+    'name' : IDL.Text,
+    'role' : Role,
+    'business' : IDL.Principal,
+    'pay_id' : IDL.Text,
+  });
+  const NotificationKind = IDL.Variant({
+    'AppRequestReceived' : IDL.Null,
+    'PaymentReceived' : IDL.Null,
+    'InvoiceDue' : IDL.Null,
+    'RequestExpired' : IDL.Null,
+    'MandateProblem' : IDL.Null,
+    'BusinessAdded' : IDL.Null,
+    'Mention' : IDL.Null,
+    'RequestPaid' : IDL.Null,
+    'RequestReceived' : IDL.Null,
+    'AppRequestClosed' : IDL.Null,
+  });
+  const DirectoryError = IDL.Variant({
+    'AccountNotFound' : IDL.Null,
+    'NotPermitted' : IDL.Null,
+  });
+  const Result_29 = IDL.Variant({
+    'Ok' : IDL.Vec(NotificationKind),
+    'Err' : DirectoryError,
+  });
+  const MyChat = IDL.Record({ 'chat' : Chat, 'unread_count' : IDL.Nat64 });
+  const NotificationEvent = IDL.Variant({
+    'AppRequestReceived' : IDL.Record({
+      'app' : IDL.Text,
+      'request_id' : IDL.Nat64,
+      'memo' : IDL.Opt(IDL.Text),
+      'amount' : IDL.Nat,
+    }),
+    'PaymentReceived' : IDL.Record({
+      'tx_id' : IDL.Nat,
+      'from' : IDL.Text,
+      'amount' : IDL.Nat,
+    }),
+    'InvoiceDue' : IDL.Record({
+      'from' : IDL.Text,
+      'chat_id' : IDL.Text,
+      'message_id' : IDL.Nat64,
+      'amount' : IDL.Nat,
+      'expires_at' : IDL.Nat64,
+    }),
+    'RequestExpired' : IDL.Record({
+      'chat_id' : IDL.Text,
+      'message_id' : IDL.Nat64,
+      'amount' : IDL.Nat,
+    }),
+    'MandateProblem' : IDL.Record({
+      'mandate_id' : IDL.Nat64,
+      'event' : MandateEventKind,
+      'payee' : IDL.Text,
+      'amount' : IDL.Nat,
+    }),
+    'BusinessAdded' : IDL.Record({
+      'name' : IDL.Text,
+      'role' : Role,
+      'business' : IDL.Principal,
+    }),
+    'Mention' : IDL.Record({
+      'by' : IDL.Text,
+      'chat_id' : IDL.Text,
+      'message_id' : IDL.Nat64,
+    }),
+    'RequestPaid' : IDL.Record({
+      'by' : IDL.Text,
+      'tx_id' : IDL.Nat,
+      'chat_id' : IDL.Text,
+      'message_id' : IDL.Nat64,
+      'amount' : IDL.Nat,
+    }),
+    'RequestReceived' : IDL.Record({
+      'from' : IDL.Text,
+      'chat_id' : IDL.Text,
+      'message_id' : IDL.Nat64,
+      'amount' : IDL.Nat,
+    }),
+    'AppRequestClosed' : IDL.Record({
+      'app' : IDL.Text,
+      'request_id' : IDL.Nat64,
+      'status' : AppRequestStatus,
+      'amount' : IDL.Nat,
+    }),
+  });
+  const Notification = IDL.Record({
+    'id' : IDL.Nat64,
+    'read' : IDL.Bool,
+    'created_at' : IDL.Nat64,
+    'event' : NotificationEvent,
+  });
+  const NotificationPage = IDL.Record({
+    'notifications' : IDL.Vec(Notification),
+    'unread' : IDL.Nat64,
+    'next_cursor' : IDL.Opt(IDL.Nat64),
+  });
+  const Result_30 = IDL.Variant({
+    'Ok' : NotificationPage,
+    'Err' : GetBusinessError,
+  });
+  const PolicyOutcome = IDL.Variant({
+    'Allowed' : IDL.Record({ 'approval_id' : IDL.Opt(IDL.Nat64) }),
+    'Denied' : IDL.Record({ 'error' : PolicyError }),
+  });
+  const PolicyDecision = IDL.Record({
+    'to' : IDL.Principal,
+    'send' : PolicySend,
+    'timestamp' : IDL.Nat64,
+    'amount' : IDL.Nat,
+    'outcome' : PolicyOutcome,
+  });
+  const PolicyLogPage = IDL.Record({
+    'next_page' : IDL.Opt(IDL.Nat64),
+    'decisions' : IDL.Vec(PolicyDecision),
+  });
+  const Result_31 = IDL.Variant({
+    'Ok' : PolicyLogPage,
+    'Err' : SpendingPolicyError,
+  });
+  const FiatError = IDL.Variant({
+    'NotAController' : IDL.Null,
+    'AccountNotFound' : IDL.Null,
+    'InvalidCurrency' : IDL.Null,
+    'NotPermitted' : IDL.Null,
+    'UnsupportedCurrency' : IDL.Null,
+  });
+  const Result_32 = IDL.Variant({ 'Ok' : IDL.Text, 'Err' : FiatError });
+  const Result_33 = IDL.Variant({
+    'Ok' : IDL.Vec(PushSubscriptionView),
+    'Err' : WebPushError,
+  });
+  const SafetySettings = IDL.Record({
+    'blocked' : IDL.Vec(IDL.Principal),
+    'only_contacts_can_request' : IDL.Bool,
+  });
+  const Result_34 = IDL.Variant({ 'Ok' : SafetySettings, 'Err' : BlockError });
+  const Result_35 = IDL.Variant({
+    'Ok' : IDL.Vec(SendApproval),
+    'Err' : SpendingPolicyError,
+  });
+  const SpendingPolicyView = IDL.Record({
+    'spent_this_month' : IDL.Nat,
+    'updated_at' : IDL.Nat64,
+    'approval_threshold' : IDL.Opt(IDL.Nat),
+    'daily_cap' : IDL.Opt(IDL.Nat),
+    'max_per_tx' : IDL.Opt(IDL.Nat),
+    'approver' : IDL.Opt(IDL.Principal),
+    'account' : IDL.Principal,
+    'spent_today' : IDL.Nat,
+    'monthly_cap' : IDL.Opt(IDL.Nat),
+  });
+  const Result_36 = IDL.Variant({
+    'Ok' : IDL.Opt(SpendingPolicyView),
+    'Err' : SpendingPolicyError,
+  });
+  const DeliveryStatus = IDL.Variant({
+    'Failed' : IDL.Null,
+    'Delivered' : IDL.Null,
+    'Pending' : IDL.Null,
+  });
+  const Delivery = IDL.Record({
+    'id' : IDL.Text,
+    'url' : IDL.Text,
+    'last_error' : IDL.Opt(IDL.Text),
+    'status' : DeliveryStatus,
+    'next_attempt_at' : IDL.Opt(IDL.Nat64),
+    'attempts' : IDL.Nat32,
+    'created_at' : IDL.Nat64,
+    'event' : WebhookEvent,
+    'business' : IDL.Principal,
+    'last_status_code' : IDL.Opt(IDL.Nat16),
+    'payload' : IDL.Text,
+    'delivered_at' : IDL.Opt(IDL.Nat64),
+    'webhook_id' : IDL.Nat64,
+  });
+  const DeliveryPage = IDL.Record({
+    'next_page' : IDL.Opt(IDL.Nat64),
+    'deliveries' : IDL.Vec(Delivery),
+  });
+  const Result_37 = IDL.Variant({ 'Ok' : DeliveryPage, 'Err' : WebhookError });
+  const AccountKind = IDL.Variant({
+    'User' : IDL.Null,
+    'Business' : BusinessCategory,
+  });
+  const PayIdProfile = IDL.Record({
+    'verified' : IDL.Bool,
+    'kind' : AccountKind,
+    'name' : IDL.Text,
+    'pay_id' : IDL.Text,
+    'avatar' : IDL.Text,
+  });
+  const BusinessDirectoryPage = IDL.Record({
+    'next_page' : IDL.Opt(IDL.Nat64),
+    'businesses' : IDL.Vec(PayIdProfile),
+  });
+  const CustomerPage = IDL.Record({
+    'next_page' : IDL.Opt(IDL.Nat64),
+    'customers' : IDL.Vec(Customer),
+  });
+  const Result_38 = IDL.Variant({ 'Ok' : CustomerPage, 'Err' : CustomerError });
+  const Result_39 = IDL.Variant({ 'Ok' : IDL.Vec(Member), 'Err' : StaffError });
+  const Result_40 = IDL.Variant({
+    'Ok' : IDL.Vec(PaymentLinkDetails),
+    'Err' : PaymentLinkError,
+  });
+  const AllowedApp = IDL.Record({
+    'name' : IDL.Text,
+    'added_at' : IDL.Nat64,
+    'canister' : IDL.Principal,
+  });
+  const Result_41 = IDL.Variant({
+    'Ok' : IDL.Vec(WebhookView),
+    'Err' : WebhookError,
+  });
+  const MarkMessageReadErr = IDL.Variant({
+    'AccountNotFound' : IDL.Null,
+    'ChatNotFound' : IDL.Null,
+    'NotAParticipant' : IDL.Null,
+  });
+  const Result_42 = IDL.Variant({
+    'Ok' : IDL.Null,
+    'Err' : MarkMessageReadErr,
+  });
+  const Result_43 = IDL.Variant({ 'Ok' : IDL.Nat64, 'Err' : GetBusinessError });
   const ReqPayArg = IDL.Record({
     'note' : IDL.Opt(IDL.Text),
     'chat_id' : IDL.Text,
     'amount' : IDL.Nat,
   });
-  const Result_5 = IDL.Variant({
+  const RequestPaymentError = IDL.Variant({
+    'OnlyContactsCanRequest' : IDL.Null,
+    'Blocked' : IDL.Null,
+    'AccountNotFound' : IDL.Null,
+    'ChatNotFound' : IDL.Null,
+    'NotAParticipant' : IDL.Null,
+    'RateLimited' : IDL.Record({ 'retry_at' : IDL.Nat64 }),
+  });
+  const Result_44 = IDL.Variant({
     'Ok' : RequestPayment,
-    'Err' : AddMessageErr,
+    'Err' : RequestPaymentError,
+  });
+  const Result_45 = IDL.Variant({ 'Ok' : IDL.Null, 'Err' : AnalyticsError });
+  const Result_46 = IDL.Variant({ 'Ok' : IDL.Null, 'Err' : CustomerError });
+  const SearchError = IDL.Variant({
+    'AccountNotFound' : IDL.Null,
+    'NotPermitted' : IDL.Null,
+    'EmptyQuery' : IDL.Null,
+    'InvalidLimit' : IDL.Record({ 'max' : IDL.Nat64 }),
+  });
+  const Result_47 = IDL.Variant({ 'Ok' : IDL.Null, 'Err' : SearchError });
+  const RecordRefundErr = IDL.Variant({
+    'PartiesNotReversed' : IDL.Null,
+    'RefundBeforeOriginal' : IDL.Null,
+    'AlreadyRecorded' : IDL.Null,
+    'InterCanisterCall' : IDL.Text,
+    'OriginalNotRecorded' : IDL.Null,
+    'NotPaidToBusiness' : IDL.Null,
+    'InvalidTransaction' : IDL.Text,
+    'ExceedsRefundable' : IDL.Record({ 'refundable' : IDL.Nat }),
+  });
+  const Result_48 = IDL.Variant({
+    'Ok' : RefundState,
+    'Err' : RecordRefundErr,
   });
   const RecordReqPayArg = IDL.Record({
     'tx_id' : IDL.Nat,
@@ -157,17 +1085,62 @@ export const idlFactory = ({ IDL }) => {
       'from' : IDL.Principal,
     }),
   });
-  const Result_6 = IDL.Variant({ 'Ok' : IDL.Null, 'Err' : RecordRegPayTxErr });
-  const RecordTxErr = IDL.Variant({
-    'AlreadyRecorded' : IDL.Null,
-    'InterCanisterCall' : IDL.Text,
-    'InvalidTransaction' : IDL.Text,
-    'BothAccountsNotFound' : IDL.Record({
-      'to' : IDL.Principal,
-      'from' : IDL.Principal,
+  const Result_49 = IDL.Variant({ 'Ok' : IDL.Null, 'Err' : RecordRegPayTxErr });
+  const Result_50 = IDL.Variant({ 'Ok' : IDL.Null, 'Err' : RecordTxErr });
+  const Result_51 = IDL.Variant({ 'Ok' : IDL.Null, 'Err' : ContactError });
+  const Result_52 = IDL.Variant({ 'Ok' : IDL.Null, 'Err' : StaffError });
+  const Result_53 = IDL.Variant({ 'Ok' : IDL.Null, 'Err' : WebPushError });
+  const Result_54 = IDL.Variant({ 'Ok' : IDL.Null, 'Err' : WebhookError });
+  const EscrowResolution = IDL.Variant({
+    'Release' : IDL.Null,
+    'Refund' : IDL.Null,
+  });
+  const SearchArgs = IDL.Record({
+    'to' : IDL.Opt(IDL.Nat64),
+    'min_amount' : IDL.Opt(IDL.Nat),
+    'from' : IDL.Opt(IDL.Nat64),
+    'query' : IDL.Text,
+    'offset' : IDL.Nat64,
+    'limit' : IDL.Nat64,
+    'max_amount' : IDL.Opt(IDL.Nat),
+  });
+  const SearchItem = IDL.Variant({
+    'History' : TransactionEntry,
+    'Message' : IDL.Record({
+      'message' : MessageOrTransaction,
+      'chat_id' : IDL.Text,
+      'message_id' : IDL.Nat64,
     }),
+    'Business' : BusinessInUser,
+    'Contact' : ContactView,
   });
-  const Result_7 = IDL.Variant({ 'Ok' : IDL.Null, 'Err' : RecordTxErr });
+  const SearchResult = IDL.Record({ 'item' : SearchItem, 'score' : IDL.Nat32 });
+  const SearchResponse = IDL.Record({
+    'total' : IDL.Nat64,
+    'results' : IDL.Vec(SearchResult),
+  });
+  const Result_55 = IDL.Variant({ 'Ok' : SearchResponse, 'Err' : SearchError });
+  const Result_56 = IDL.Variant({ 'Ok' : Delivery, 'Err' : WebhookError });
+  const Result_57 = IDL.Variant({ 'Ok' : IDL.Null, 'Err' : DirectoryError });
+  const Result_58 = IDL.Variant({ 'Ok' : IDL.Null, 'Err' : EscrowError });
+  const Result_59 = IDL.Variant({ 'Ok' : IDL.Null, 'Err' : FiatError });
+  const SpendingPolicyArg = IDL.Record({
+    'approval_threshold' : IDL.Opt(IDL.Nat),
+    'daily_cap' : IDL.Opt(IDL.Nat),
+    'max_per_tx' : IDL.Opt(IDL.Nat),
+    'approver' : IDL.Opt(IDL.Principal),
+    'monthly_cap' : IDL.Opt(IDL.Nat),
+  });
+  const Result_60 = IDL.Variant({
+    'Ok' : SpendingPolicyView,
+    'Err' : SpendingPolicyError,
+  });
+  const SetVerifiedError = IDL.Variant({
+    'NotAController' : IDL.Null,
+    'PayIdNotFound' : IDL.Null,
+  });
+  const Result_61 = IDL.Variant({ 'Ok' : IDL.Null, 'Err' : SetVerifiedError });
+  const Result_62 = IDL.Variant({ 'Ok' : IDL.Text, 'Err' : WebPushError });
   const UserSignUpArgs = IDL.Record({
     'name' : IDL.Text,
     'profile_pic' : IDL.Text,
@@ -184,49 +1157,416 @@ export const idlFactory = ({ IDL }) => {
     'Business' : BusinessSignUpArgs,
   });
   const SignUpError = IDL.Variant({
+    'TooManyProfiles' : IDL.Record({ 'max' : IDL.Nat64 }),
     'AccountExist' : IDL.Null,
     'PayIdExist' : IDL.Null,
     'AnonymousCaller' : IDL.Null,
   });
-  const Result_8 = IDL.Variant({ 'Ok' : IDL.Null, 'Err' : SignUpError });
+  const Result_63 = IDL.Variant({ 'Ok' : IDL.Null, 'Err' : SignUpError });
+  const ChatUpdate = IDL.Record({
+    'id' : IDL.Text,
+    'participants' : IDL.Vec(IDL.Text),
+    'messages' : IDL.Vec(IDL.Tuple(IDL.Nat64, MessageOrTransaction)),
+    'encrypted' : IDL.Opt(IDL.Bool),
+    'unread_count' : IDL.Nat64,
+    'last_activity' : IDL.Nat64,
+    'last_read' : IDL.Opt(IDL.Vec(IDL.Tuple(IDL.Text, IDL.Nat64))),
+  });
+  const SyncResponse = IDL.Record({
+    'notifications' : IDL.Vec(Notification),
+    'chats' : IDL.Vec(ChatUpdate),
+    'app_requests' : IDL.Vec(AppPaymentRequest),
+    'history' : IDL.Vec(TransactionEntry),
+    'version' : IDL.Nat64,
+    'business' : IDL.Vec(BusinessInUser),
+    'full_resync' : IDL.Bool,
+    'mandates' : IDL.Vec(Mandate),
+  });
+  const SyncError = IDL.Variant({
+    'AccountNotFound' : IDL.Null,
+    'NotPermitted' : IDL.Null,
+  });
+  const Result_64 = IDL.Variant({ 'Ok' : SyncResponse, 'Err' : SyncError });
+  const HttpHeader = IDL.Record({ 'value' : IDL.Text, 'name' : IDL.Text });
+  const HttpResponse = IDL.Record({
+    'status' : IDL.Nat,
+    'body' : IDL.Vec(IDL.Nat8),
+    'headers' : IDL.Vec(HttpHeader),
+  });
+  const TransformArgs = IDL.Record({
+    'context' : IDL.Vec(IDL.Nat8),
+    'response' : HttpResponse,
+  });
+  const UpdateContactArg = IDL.Record({
+    'contact' : PayIdOrPrincipal,
+    'nickname' : IDL.Opt(IDL.Text),
+    'note' : IDL.Opt(IDL.Text),
+    'favorite' : IDL.Bool,
+  });
+  const UpdateCustomerArg = IDL.Record({
+    'note' : IDL.Opt(IDL.Text),
+    'tags' : IDL.Vec(IDL.Text),
+    'pay_id' : IDL.Text,
+  });
   const AddBusinessError = IDL.Variant({
     'AccountNotFound' : IDL.Null,
     'BusinessNotFound' : IDL.Null,
   });
-  const Result_9 = IDL.Variant({
+  const Result_65 = IDL.Variant({
     'Ok' : BusinessInUser,
     'Err' : AddBusinessError,
   });
   return IDL.Service({
+    'add_encrypted_message' : IDL.Func(
+        [IDL.Text, IDL.Vec(IDL.Nat8), IDL.Opt(IDL.Nat64)],
+        [Result],
+        [],
+      ),
+    'add_member' : IDL.Func(
+        [IDL.Principal, Role, IDL.Opt(IDL.Principal)],
+        [Result_1],
+        [],
+      ),
     'add_message' : IDL.Func([IDL.Text, IDL.Text], [Result], []),
-    'create_chat' : IDL.Func([PayIdOrPrincipal], [Result_1], []),
-    'fetch_data' : IDL.Func([], [Result_2], ['query']),
-    'fetch_initial_data' : IDL.Func([], [Result_2], ['query']),
+    'add_push_subscription' : IDL.Func(
+        [AddPushSubscriptionArg, IDL.Opt(IDL.Principal)],
+        [Result_2],
+        [],
+      ),
+    'add_webhook' : IDL.Func(
+        [IDL.Text, IDL.Vec(WebhookEvent), IDL.Opt(IDL.Principal)],
+        [Result_3],
+        [],
+      ),
+    'allow_payment_requester' : IDL.Func(
+        [IDL.Principal, IDL.Text],
+        [Result_4],
+        [],
+      ),
+    'block_account' : IDL.Func(
+        [PayIdOrPrincipal, IDL.Opt(IDL.Principal)],
+        [Result_5],
+        [],
+      ),
+    'cancel_escrow' : IDL.Func([IDL.Nat64], [Result_6], []),
+    'cancel_mandate' : IDL.Func(
+        [IDL.Nat64, IDL.Opt(IDL.Principal)],
+        [Result_7],
+        [],
+      ),
+    'confirm_escrow_funding' : IDL.Func([IDL.Nat64], [Result_6], []),
+    'create_chat' : IDL.Func([PayIdOrPrincipal], [Result_8], []),
+    'create_checkout_session' : IDL.Func(
+        [IDL.Nat, IDL.Text, IDL.Opt(IDL.Nat64), IDL.Opt(IDL.Principal)],
+        [Result_9],
+        [],
+      ),
+    'create_escrow' : IDL.Func([CreateEscrowArg], [Result_6], []),
+    'create_mandate' : IDL.Func([CreateMandateArg], [Result_7], []),
+    'create_payment_link' : IDL.Func(
+        [CreatePaymentLinkArg, IDL.Opt(IDL.Principal)],
+        [Result_10],
+        [],
+      ),
+    'decide_send_approval' : IDL.Func([IDL.Nat64, IDL.Bool], [Result_11], []),
+    'decline_app_payment_request' : IDL.Func([IDL.Nat64], [Result_12], []),
+    'delete_message' : IDL.Func([IDL.Text, IDL.Nat64], [Result_13], []),
+    'disable_payment_link' : IDL.Func(
+        [IDL.Nat64, IDL.Opt(IDL.Principal)],
+        [Result_10],
+        [],
+      ),
+    'dispute_escrow' : IDL.Func([IDL.Nat64, IDL.Text], [Result_6], []),
+    'edit_message' : IDL.Func([IDL.Text, IDL.Nat64, IDL.Text], [Result_14], []),
+    'enable_chat_encryption' : IDL.Func([IDL.Text], [Result_15], []),
+    'export_transactions' : IDL.Func(
+        [ExportFormat, ExportRange, IDL.Nat64, IDL.Opt(IDL.Principal)],
+        [Result_16],
+        ['query'],
+      ),
+    'fetch_data' : IDL.Func([], [Result_17], ['query']),
+    'fetch_initial_data' : IDL.Func([], [Result_17], ['query']),
     'get_account_from_pay_id' : IDL.Func(
         [IDL.Text],
         [IDL.Opt(IDL.Principal)],
         ['query'],
       ),
-    'get_business' : IDL.Func([], [Result_3], ['query']),
+    'get_app_payment_request' : IDL.Func([IDL.Nat64], [Result_12], ['query']),
+    'get_app_payment_requests' : IDL.Func([IDL.Nat64], [Result_18], ['query']),
+    'get_audit_log' : IDL.Func(
+        [IDL.Nat64, IDL.Opt(IDL.Principal)],
+        [Result_19],
+        ['query'],
+      ),
+    'get_business' : IDL.Func([IDL.Opt(IDL.Principal)], [Result_20], ['query']),
+    'get_business_analytics' : IDL.Func(
+        [AnalyticsRange, AnalyticsBucket, IDL.Opt(IDL.Principal)],
+        [Result_21],
+        ['query'],
+      ),
     'get_chat' : IDL.Func([IDL.Text], [IDL.Opt(Chat)], ['query']),
-    'get_my_chats' : IDL.Func([], [IDL.Vec(Chat)], ['query']),
+    'get_chat_key_public_key' : IDL.Func([], [Result_22], []),
+    'get_checkout_status' : IDL.Func(
+        [IDL.Nat64, IDL.Opt(IDL.Principal)],
+        [Result_9],
+        ['query'],
+      ),
+    'get_contact' : IDL.Func(
+        [PayIdOrPrincipal, IDL.Opt(IDL.Principal)],
+        [Result_23],
+        ['query'],
+      ),
+    'get_contacts' : IDL.Func([IDL.Opt(IDL.Principal)], [Result_24], ['query']),
+    'get_customer' : IDL.Func(
+        [IDL.Text, IDL.Opt(IDL.Principal)],
+        [Result_25],
+        ['query'],
+      ),
+    'get_disputed_escrows' : IDL.Func([], [Result_26], ['query']),
+    'get_encrypted_chat_key' : IDL.Func(
+        [IDL.Text, IDL.Vec(IDL.Nat8)],
+        [Result_22],
+        [],
+      ),
+    'get_escrow' : IDL.Func([IDL.Nat64], [Result_6], ['query']),
+    'get_escrows' : IDL.Func([], [Result_26], ['query']),
+    'get_ledger_account' : IDL.Func([IDL.Text], [IDL.Opt(Account)], ['query']),
+    'get_link_payments' : IDL.Func(
+        [IDL.Nat64, IDL.Nat64, IDL.Opt(IDL.Principal)],
+        [Result_27],
+        ['query'],
+      ),
+    'get_mandates' : IDL.Func([IDL.Opt(IDL.Principal)], [Result_28], ['query']),
+    'get_membership' : IDL.Func([], [IDL.Opt(Membership)], ['query']),
+    'get_muted_notifications' : IDL.Func(
+        [IDL.Opt(IDL.Principal)],
+        [Result_29],
+        ['query'],
+      ),
+    'get_my_chats' : IDL.Func([], [IDL.Vec(MyChat)], ['query']),
     'get_new_business_transactions' : IDL.Func(
-        [IDL.Nat64],
+        [IDL.Nat64, IDL.Opt(IDL.Principal)],
         [IDL.Vec(TransactionEntry)],
         ['query'],
       ),
+    'get_notifications' : IDL.Func(
+        [IDL.Opt(IDL.Nat64), IDL.Opt(IDL.Principal)],
+        [Result_30],
+        ['query'],
+      ),
+    'get_payment_link' : IDL.Func(
+        [IDL.Nat64],
+        [IDL.Opt(PaymentLinkView)],
+        ['query'],
+      ),
+    'get_policy_log' : IDL.Func(
+        [IDL.Nat64, IDL.Opt(IDL.Principal)],
+        [Result_31],
+        ['query'],
+      ),
+    'get_preferred_currency' : IDL.Func(
+        [IDL.Opt(IDL.Principal)],
+        [Result_32],
+        ['query'],
+      ),
+    'get_push_subscriptions' : IDL.Func(
+        [IDL.Opt(IDL.Principal)],
+        [Result_33],
+        ['query'],
+      ),
+    'get_safety_settings' : IDL.Func(
+        [IDL.Opt(IDL.Principal)],
+        [Result_34],
+        ['query'],
+      ),
+    'get_send_approvals' : IDL.Func(
+        [IDL.Opt(IDL.Principal)],
+        [Result_35],
+        ['query'],
+      ),
+    'get_spending_policy' : IDL.Func(
+        [IDL.Opt(IDL.Principal)],
+        [Result_36],
+        ['query'],
+      ),
+    'get_unread_count' : IDL.Func([], [IDL.Nat64], ['query']),
     'get_user' : IDL.Func([], [IDL.Opt(User)], ['query']),
+    'get_vapid_public_key' : IDL.Func([], [IDL.Opt(IDL.Text)], ['query']),
+    'get_webhook_deliveries' : IDL.Func(
+        [IDL.Nat64, IDL.Opt(IDL.Principal)],
+        [Result_37],
+        ['query'],
+      ),
+    'import_recent_contacts' : IDL.Func(
+        [IDL.Opt(IDL.Nat64), IDL.Opt(IDL.Principal)],
+        [Result_24],
+        [],
+      ),
     'is_pay_id_available' : IDL.Func([IDL.Text], [IDL.Bool], ['query']),
-    'mark_message_read' : IDL.Func([IDL.Text], [Result_4], []),
-    'payment_request_message' : IDL.Func([ReqPayArg], [Result_5], []),
-    'record_request_payment' : IDL.Func([RecordReqPayArg], [Result_6], []),
+    'list_businesses' : IDL.Func(
+        [IDL.Opt(BusinessCategory), IDL.Opt(IDL.Text), IDL.Nat64],
+        [BusinessDirectoryPage],
+        ['query'],
+      ),
+    'list_customers' : IDL.Func(
+        [IDL.Opt(IDL.Text), IDL.Nat64, IDL.Opt(IDL.Principal)],
+        [Result_38],
+        ['query'],
+      ),
+    'list_members' : IDL.Func([IDL.Opt(IDL.Principal)], [Result_39], ['query']),
+    'list_payment_links' : IDL.Func(
+        [IDL.Opt(IDL.Principal)],
+        [Result_40],
+        ['query'],
+      ),
+    'list_payment_requesters' : IDL.Func([], [IDL.Vec(AllowedApp)], ['query']),
+    'list_webhooks' : IDL.Func(
+        [IDL.Opt(IDL.Principal)],
+        [Result_41],
+        ['query'],
+      ),
+    'lookup_pay_id' : IDL.Func([IDL.Text], [IDL.Opt(PayIdProfile)], ['query']),
+    'mark_escrow_delivered' : IDL.Func([IDL.Nat64], [Result_6], []),
+    'mark_message_read' : IDL.Func([IDL.Text], [Result_42], []),
+    'mark_notifications_read' : IDL.Func(
+        [IDL.Opt(IDL.Nat64), IDL.Opt(IDL.Principal)],
+        [Result_43],
+        [],
+      ),
+    'payment_request_message' : IDL.Func([ReqPayArg], [Result_44], []),
+    'react_to_message' : IDL.Func(
+        [IDL.Text, IDL.Nat64, IDL.Opt(IDL.Text)],
+        [Result_14],
+        [],
+      ),
+    'rebuild_business_analytics' : IDL.Func(
+        [IDL.Opt(IDL.Principal)],
+        [Result_45],
+        [],
+      ),
+    'rebuild_customers' : IDL.Func([IDL.Opt(IDL.Principal)], [Result_46], []),
+    'rebuild_search_index' : IDL.Func(
+        [IDL.Opt(IDL.Principal)],
+        [Result_47],
+        [],
+      ),
+    'record_refund' : IDL.Func(
+        [IDL.Nat, IDL.Nat, IDL.Opt(IDL.Text)],
+        [Result_48],
+        [],
+      ),
+    'record_request_payment' : IDL.Func([RecordReqPayArg], [Result_49], []),
     'record_xfer_transaction' : IDL.Func(
         [IDL.Nat, IDL.Opt(IDL.Text)],
-        [Result_7],
+        [Result_50],
+        [],
+      ),
+    'refund_escrow' : IDL.Func([IDL.Nat64], [Result_6], []),
+    'release_escrow' : IDL.Func([IDL.Nat64], [Result_6], []),
+    'remove_contact' : IDL.Func(
+        [PayIdOrPrincipal, IDL.Opt(IDL.Principal)],
+        [Result_51],
+        [],
+      ),
+    'remove_member' : IDL.Func(
+        [IDL.Principal, IDL.Opt(IDL.Principal)],
+        [Result_52],
+        [],
+      ),
+    'remove_payment_requester' : IDL.Func([IDL.Principal], [Result_4], []),
+    'remove_push_subscription' : IDL.Func(
+        [IDL.Nat64, IDL.Opt(IDL.Principal)],
+        [Result_53],
+        [],
+      ),
+    'remove_webhook' : IDL.Func(
+        [IDL.Nat64, IDL.Opt(IDL.Principal)],
+        [Result_54],
+        [],
+      ),
+    'reply_to_message' : IDL.Func(
+        [IDL.Text, IDL.Nat64, IDL.Text],
+        [Result],
+        [],
+      ),
+    'request_payment_from' : IDL.Func(
+        [IDL.Text, IDL.Nat, IDL.Opt(IDL.Text), IDL.Text],
+        [Result_12],
+        [],
+      ),
+    'resolve_escrow' : IDL.Func([IDL.Nat64, EscrowResolution], [Result_6], []),
+    'search' : IDL.Func(
+        [SearchArgs, IDL.Opt(IDL.Principal)],
+        [Result_55],
+        ['query'],
+      ),
+    'send_test_push' : IDL.Func([IDL.Opt(IDL.Principal)], [Result_53], []),
+    'send_test_webhook' : IDL.Func(
+        [IDL.Nat64, IDL.Opt(IDL.Principal)],
+        [Result_56],
+        [],
+      ),
+    'set_directory_visibility' : IDL.Func(
+        [IDL.Bool, IDL.Opt(IDL.Principal)],
+        [Result_57],
+        [],
+      ),
+    'set_escrow_arbiter' : IDL.Func([IDL.Principal], [Result_58], []),
+    'set_exchange_rate_canister' : IDL.Func([IDL.Principal], [Result_59], []),
+    'set_muted_notifications' : IDL.Func(
+        [IDL.Vec(NotificationKind), IDL.Opt(IDL.Principal)],
+        [Result_29],
+        [],
+      ),
+    'set_only_contacts_can_request' : IDL.Func(
+        [IDL.Bool, IDL.Opt(IDL.Principal)],
+        [Result_5],
+        [],
+      ),
+    'set_preferred_currency' : IDL.Func(
+        [IDL.Text, IDL.Opt(IDL.Principal)],
+        [Result_59],
+        [],
+      ),
+    'set_spending_policy' : IDL.Func(
+        [SpendingPolicyArg, IDL.Opt(IDL.Principal)],
+        [Result_60],
+        [],
+      ),
+    'set_verified' : IDL.Func([IDL.Text, IDL.Bool], [Result_61], []),
+    'setup_web_push' : IDL.Func([IDL.Text], [Result_62], []),
+    'sign_up' : IDL.Func([SignUpArg], [Result_63], []),
+    'sync' : IDL.Func(
+        [IDL.Nat64, IDL.Opt(IDL.Principal)],
+        [Result_64],
+        ['query'],
+      ),
+    'transform_webhook_response' : IDL.Func(
+        [TransformArgs],
+        [HttpResponse],
+        ['query'],
+      ),
+    'unblock_account' : IDL.Func(
+        [PayIdOrPrincipal, IDL.Opt(IDL.Principal)],
+        [Result_5],
+        [],
+      ),
+    'update_contact' : IDL.Func(
+        [UpdateContactArg, IDL.Opt(IDL.Principal)],
+        [Result_23],
+        [],
+      ),
+    'update_customer' : IDL.Func(
+        [UpdateCustomerArg, IDL.Opt(IDL.Principal)],
+        [Result_25],
+        [],
+      ),
+    'update_member_role' : IDL.Func(
+        [IDL.Principal, Role, IDL.Opt(IDL.Principal)],
+        [Result_1],
         [],
       ),
-    'sign_up' : IDL.Func([SignUpArg], [Result_8], []),
-    'user_add_business' : IDL.Func([PayIdOrPrincipal], [Result_9], []),
+    'user_add_business' : IDL.Func([PayIdOrPrincipal], [Result_65], []),
   });
 };
 export const init = ({ IDL }) => { return []; };
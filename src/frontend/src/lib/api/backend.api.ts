@@ -19,7 +19,7 @@ import type {
 import type { CommonCanisterApiFunctionParams } from '$lib/types/canister';
 import { BACKEND_CANISTER_ID } from '@constants/app.constants';
 import type {
-	Chat,
+	MyChat,
 	PayIdOrPrincipal,
 	RecordReqPayArg,
 	SignUpArg,
@@ -75,7 +75,7 @@ export const fetchInitialData = async ({
 export const getMyChats = async ({
 	identity,
 	certified = false
-}: CommonCanisterApiFunctionParams<QueryParams>): Promise<Chat[]> => {
+}: CommonCanisterApiFunctionParams<QueryParams>): Promise<MyChat[]> => {
 	const { getMyChats } = await backendCanister({ identity });
 
 	return getMyChats({ certified });
@@ -20,7 +20,7 @@ import type { CreateCanisterOptions } from '$lib/types/canister';
 import { idlFactory as idlFactoryBackend } from '@declarations/backend';
 import type {
 	_SERVICE as BackendService,
-	Chat,
+	MyChat,
 	PayIdOrPrincipal,
 	RecordReqPayArg,
 	ReqPayArg,
@@ -67,7 +67,7 @@ export class BackendCanister extends Canister<BackendService> {
 		return fetch_initial_data();
 	};
 
-	getMyChats = ({ certified }: QueryParams): Promise<Chat[]> => {
+	getMyChats = ({ certified }: QueryParams): Promise<MyChat[]> => {
 		const { get_my_chats } = this.caller({ certified });
 
 		return get_my_chats();